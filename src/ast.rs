@@ -1,27 +1,68 @@
 use serde::{Deserialize, Serialize};
-use simple_error::SimpleError;
 
 use shapes::*;
 use std::collections::HashMap;
 
-#[derive(Debug, Clone)]
+/**
+* The span of source something was parsed from, x and y where it starts and end_x and end_y just past where it
+* ends. Lines and columns are counted from 1.
+*/
+#[derive(Debug, Clone, PartialEq)]
 pub struct Location {
   pub src: String,
   pub x: usize,
   pub y: usize,
+  pub end_x: usize,
+  pub end_y: usize,
 }
 
 impl Location {
+  /**
+  * An empty span at x and y.
+  */
+  pub fn point(src: &str, x: usize, y: usize) -> Location {
+    Location { src: String::from(src), x, y, end_x: x, end_y: y }
+  }
+
   pub fn pretty(&self) -> String {
-    format!("at file: {}, line: {}, column: {}", self.src, self.y, self.x)
+    if self.end_y > self.y {
+      format!("at file: {}, line: {}, column: {} to line: {}, column: {}", self.src, self.y, self.x, self.end_y, self.end_x.saturating_sub(1).max(1))
+    } else if self.end_x > self.x + 1 {
+      format!("at file: {}, line: {}, columns: {}-{}", self.src, self.y, self.x, self.end_x - 1)
+    } else {
+      format!("at file: {}, line: {}, column: {}", self.src, self.y, self.x)
+    }
+  }
+
+  /**
+  * From the start of this span to the end of end.
+  */
+  pub fn to(&self, end: &Location) -> Location {
+    Location { end_x: end.end_x, end_y: end.end_y, ..self.clone() }
   }
 
-  pub fn fail<T>(&self, message: &str) -> Result<T, SimpleError> {
-    Err(SimpleError::new(format!("{} {}", message, self.pretty())))
+  /**
+  * From the start of this span to the end of text written starting there.
+  */
+  pub fn spanning(&self, text: &str) -> Location {
+    let mut end = (self.x, self.y);
+
+    for ch in text.chars() {
+      if ch == '\n' {
+        end = (1, end.1 + 1);
+      } else {
+        end = (end.0 + 1, end.1);
+      }
+    }
+
+    Location { end_x: end.0, end_y: end.1, ..self.clone() }
   }
 
-  pub fn error(&self, message: &str) -> SimpleError {
-    SimpleError::new(format!("{} {}", message, self.pretty()))
+  /**
+  * Whether the character at line and column is inside the span.
+  */
+  pub fn contains(&self, line: usize, column: usize) -> bool {
+    (line, column) >= (self.y, self.x) && (line, column) < (self.end_y, self.end_x)
   }
 }
 
@@ -30,14 +71,27 @@ pub enum Expression {
   Import(Box<ImportEx>),
   FunctionDeclaration(Box<FunctionDeclarationEx>),
   Assignment(Box<AssignmentEx>),
+  Reassignment(Box<ReassignmentEx>),
   Variable(Box<VariableEx>),
   BinaryOp(Box<BinaryOpEx>),
   Call(Box<CallEx>),
   If(Box<IfEx>),
+  While(Box<WhileEx>),
   Block(Box<BlockEx>),
+  Tuple(Box<TupleEx>),
+  List(Box<ListEx>),
   StringLiteral(Box<StringLiteralEx>),
   NumberLiteral(Box<NumberLiteralEx>),
   BooleanLiteral(Location, bool),
+  UnitLiteral(Location),
+  Hole(Box<HoleEx>),
+  Variant(Box<VariantEx>),
+  Try(Box<TryEx>),
+  Index(Box<IndexEx>),
+  Field(Box<FieldEx>),
+  Panic(Box<PanicEx>),
+  Return(Box<ReturnEx>),
+  Assert(Box<AssertEx>),
 }
 
 impl Expression {
@@ -47,14 +101,27 @@ impl Expression {
       Expression::Import(ex) => &ex.loc,
       Expression::FunctionDeclaration(ex) => &ex.loc,
       Expression::Assignment(ex) => &ex.loc,
+      Expression::Reassignment(ex) => &ex.loc,
       Expression::Variable(ex) => &ex.loc,
       Expression::BinaryOp(ex) => &ex.loc,
       Expression::Call(ex) => &ex.loc,
       Expression::If(ex) => &ex.loc,
+      Expression::While(ex) => &ex.loc,
       Expression::Block(ex) => &ex.loc,
+      Expression::Tuple(ex) => &ex.loc,
+      Expression::List(ex) => &ex.loc,
       Expression::StringLiteral(ex) => &ex.loc,
       Expression::NumberLiteral(ex) => &ex.loc,
       Expression::BooleanLiteral(loc, _) => loc,
+      Expression::UnitLiteral(loc) => loc,
+      Expression::Hole(ex) => &ex.loc,
+      Expression::Variant(ex) => &ex.loc,
+      Expression::Try(ex) => &ex.loc,
+      Expression::Index(ex) => &ex.loc,
+      Expression::Field(ex) => &ex.loc,
+      Expression::Panic(ex) => &ex.loc,
+      Expression::Return(ex) => &ex.loc,
+      Expression::Assert(ex) => &ex.loc,
     }
   }
 
@@ -64,14 +131,27 @@ impl Expression {
       Expression::Import(ex) => shape_unit(),
       Expression::FunctionDeclaration(ex) => ex.shape(),
       Expression::Assignment(ex) => ex.shape.clone(),
+      Expression::Reassignment(_) => shape_unit(),
       Expression::Variable(ex) => ex.shape.clone(),
       Expression::BinaryOp(ex) => ex.shape.clone(),
       Expression::Call(ex) => ex.shape.clone(),
       Expression::If(ex) => ex.shape.clone(),
+      Expression::While(ex) => ex.shape.clone(),
       Expression::Block(ex) => ex.shape.clone(),
+      Expression::Tuple(ex) => ex.shape.clone(),
+      Expression::List(ex) => ex.shape.clone(),
       Expression::StringLiteral(ex) => ex.shape.clone(),
       Expression::NumberLiteral(ex) => ex.shape.clone(),
       Expression::BooleanLiteral(..) => shape_boolean(),
+      Expression::UnitLiteral(_) => shape_unit(),
+      Expression::Hole(ex) => ex.shape.clone(),
+      Expression::Variant(ex) => ex.shape.clone(),
+      Expression::Try(ex) => ex.shape.clone(),
+      Expression::Index(ex) => ex.shape.clone(),
+      Expression::Field(ex) => ex.shape.clone(),
+      Expression::Panic(ex) => ex.shape.clone(),
+      Expression::Return(ex) => ex.shape.clone(),
+      Expression::Assert(ex) => ex.shape.clone(),
     }
   }
 }
@@ -79,6 +159,8 @@ pub struct FunctionContext {
   pub is_lambda: bool,
   pub is_local: bool,
   pub is_recursive: bool,
+  /** A local function declared at the start of its block, so functions above it in a mutually recursive group can call it. */
+  pub is_forward: bool,
   pub closures: Vec<Parameter>,
 }
 
@@ -88,6 +170,7 @@ impl FunctionContext {
       is_local,
       is_lambda,
       is_recursive: false,
+      is_forward: false,
       closures: Vec::new(),
     }
   }
@@ -97,6 +180,7 @@ impl FunctionContext {
       is_local: self.is_local,
       is_lambda: self.is_lambda,
       is_recursive: self.is_recursive,
+      is_forward: self.is_forward,
       closures,
     }
   }
@@ -106,6 +190,17 @@ impl FunctionContext {
       is_local: self.is_local,
       is_lambda: self.is_lambda,
       is_recursive,
+      is_forward: self.is_forward,
+      closures: self.closures.clone(),
+    }
+  }
+
+  pub fn set_is_forward(&self, is_forward: bool) -> FunctionContext {
+    FunctionContext {
+      is_local: self.is_local,
+      is_lambda: self.is_lambda,
+      is_recursive: self.is_recursive,
+      is_forward,
       closures: self.closures.clone(),
     }
   }
@@ -130,18 +225,82 @@ pub struct FunctionDeclarationEx {
   pub loc: Location,
   pub id: String,
   pub args: Vec<Parameter>,
+  /** One for each of args, filled in at any call that leaves that argument out. */
+  pub defaults: Vec<Option<Literal>>,
   pub body: Expression,
   pub context: FunctionContext,
 }
 
+/**
+* A parameter's default value. Only literals are allowed, so one can be copied into every call without surprises.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+  Number(f64),
+  String(String),
+  Boolean(bool),
+  Unit,
+}
+
+impl Literal {
+
+  pub fn shape(&self) -> Shape {
+    match self {
+      Literal::Number(_) => shape_float(),
+      Literal::String(_) => shape_string(),
+      Literal::Boolean(_) => shape_boolean(),
+      Literal::Unit => shape_unit(),
+    }
+  }
+
+  pub fn to_expression(&self, loc: &Location) -> Expression {
+    match self {
+      Literal::Number(value) => NumberLiteralEx { shape: shape_float(), loc: loc.clone(), value: *value }.wrap(),
+      Literal::String(value) => StringLiteralEx { shape: shape_string(), loc: loc.clone(), value: value.clone() }.wrap(),
+      Literal::Boolean(value) => Expression::BooleanLiteral(loc.clone(), *value),
+      Literal::Unit => Expression::UnitLiteral(loc.clone()),
+    }
+  }
+}
+
 pub struct AssignmentEx {
   pub shape: Shape,
   pub loc: Location,
 
+  pub pattern: Pattern,
+  pub body: Expression,
+  /** Declared with let mut, so the names it binds can be reassigned. */
+  pub mutable: bool,
+}
+
+/**
+* Stores a new value into a variable declared with let mut.
+*/
+pub struct ReassignmentEx {
+  pub loc: Location,
+
   pub id: String,
   pub body: Expression,
 }
 
+/**
+* Left hand side of a let. Tuple patterns may nest.
+*/
+#[derive(Debug, Clone)]
+pub enum Pattern {
+  Id(String),
+  Tuple(Vec<Pattern>),
+}
+
+impl Pattern {
+  pub fn ids(&self) -> Vec<String> {
+    match self {
+      Pattern::Id(id) => vec![id.clone()],
+      Pattern::Tuple(items) => items.iter().flat_map(|item| item.ids()).collect(),
+    }
+  }
+}
+
 pub struct VariableEx {
   pub shape: Shape,
   pub loc: Location,
@@ -164,6 +323,10 @@ pub struct CallEx {
 
   pub func: Expression,
   pub args: Vec<Expression>,
+  /** Arguments passed as `name = value`, always after args. The typechecker moves them into args, in parameter order. */
+  pub named: Vec<(String, Expression)>,
+  /** Written as `first |> func(rest)`. Only the formatter cares, it's an ordinary call everywhere else. */
+  pub piped: bool,
 }
 
 pub struct IfEx {
@@ -175,6 +338,14 @@ pub struct IfEx {
   pub else_block: Expression,
 }
 
+pub struct WhileEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub condition: Expression,
+  pub body: Expression,
+}
+
 pub struct BlockEx {
   pub shape: Shape,
   pub loc: Location,
@@ -182,6 +353,23 @@ pub struct BlockEx {
   pub body: Vec<Expression>,
 }
 
+pub struct TupleEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub items: Vec<Expression>,
+}
+
+/**
+* [a, b, c], a list of the values in order. Every item must have the same shape.
+*/
+pub struct ListEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub items: Vec<Expression>,
+}
+
 pub struct StringLiteralEx {
   pub shape: Shape,
   pub loc: Location,
@@ -196,11 +384,127 @@ pub struct NumberLiteralEx {
   pub value: f64,
 }
 
+/**
+* The `???` placeholder. Takes whatever shape is expected where it's written, and fails if it's ever run.
+*/
+pub struct HoleEx {
+  pub shape: Shape,
+  pub loc: Location,
+}
+
+/** Tags of the builtin Option and Result variants. */
+pub const VARIANT_SOME: &'static str = "Some";
+pub const VARIANT_NONE: &'static str = "None";
+pub const VARIANT_OK: &'static str = "Ok";
+pub const VARIANT_ERR: &'static str = "Err";
+
+/**
+* Builds one of the builtin variants, Some(value), None, Ok(value) or Err(error).
+*/
+pub struct VariantEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub tag: String,
+  pub values: Vec<Expression>,
+}
+
+/**
+* The postfix ? operator. Unwraps a Some or Ok, or returns a None or Err from the enclosing function as it is.
+*/
+pub struct TryEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub body: Expression,
+}
+
+/**
+* xs[i], the element of a list at a position. An index past the end panics.
+*/
+pub struct IndexEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub list: Expression,
+  pub index: Expression,
+}
+
+/**
+* box.value, one field of a data type. The typechecker fills in index, the field's position among the type's fields.
+*/
+pub struct FieldEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub value: Expression,
+  pub field: String,
+  pub index: usize,
+}
+
+/**
+* panic("message"), stops the program with a runtime error that Core.tryCall can catch. Like a hole it takes whatever
+* shape is expected of it.
+*/
+pub struct PanicEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub message: String,
+}
+
+/**
+* return value, leaves the enclosing function early with value. A bare return is from a Unit function and returns ().
+* It never finishes, so like panic it takes whatever shape is expected of it.
+*/
+pub struct ReturnEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub body: Expression,
+}
+
+/**
+* The assert statement. Fails with the condition as written and where it is if the condition is false.
+*/
+pub struct AssertEx {
+  pub shape: Shape,
+  pub loc: Location,
+
+  pub condition: Expression,
+}
+
 pub struct AstModule {
   pub package: String,
   pub name: String,
   pub functions: Vec<AstFunctionDeclaration>,
   pub imports: Vec<ImportEx>,
+  pub aliases: Vec<TypeAliasEx>,
+  pub datas: Vec<DataEx>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeAliasEx {
+  pub loc: Location,
+  pub visibility: Visibility,
+
+  pub id: String,
+  pub shape: Shape,
+}
+
+/**
+* `data Box[T](value: T)`, a new type with a parameter for each name in brackets, and a function of the same name that
+* builds one from its fields. The typechecker fills in shape, the GenericShapeConstructor that Box[Float] applies.
+*/
+#[derive(Debug, Clone)]
+pub struct DataEx {
+  pub loc: Location,
+  pub visibility: Visibility,
+
+  pub id: String,
+  pub params: Vec<String>,
+  pub fields: Vec<Parameter>,
+  pub shape: Shape,
 }
 
 #[derive(Debug, Clone)]
@@ -216,6 +520,7 @@ pub struct AstFunctionDeclaration {
   pub ex: FunctionDeclarationEx,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Visibility {
   Private,
   Protected,
@@ -223,6 +528,30 @@ pub enum Visibility {
   Public
 }
 
+impl Visibility {
+
+  /**
+  * Whether a module can import a declaration with this visibility from another module, following visiblity.md.
+  * Module names use '.' for directories, so protected compares everything before the last '.'.
+  */
+  pub fn is_exported(&self, from_package: &str, from_module: &str, to_package: &str, to_module: &str) -> bool {
+    match self {
+      Visibility::Public => true,
+      Visibility::Internal => from_package == to_package,
+      Visibility::Protected => from_package == to_package && is_same_or_child_dir(module_dir(from_module), module_dir(to_module)),
+      Visibility::Private => false,
+    }
+  }
+}
+
+fn module_dir(module: &str) -> &str {
+  module.rfind('.').map(|dot| &module[..dot]).unwrap_or("")
+}
+
+fn is_same_or_child_dir(dir: &str, child: &str) -> bool {
+  dir.is_empty() || child == dir || child.starts_with(&format!("{}.", dir))
+}
+
 impl FunctionDeclarationEx {
   pub fn wrap(self) -> Expression {
     Expression::FunctionDeclaration(Box::new(self))
@@ -239,6 +568,12 @@ impl AssignmentEx {
   }
 }
 
+impl ReassignmentEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Reassignment(Box::new(self))
+  }
+}
+
 impl VariableEx {
   pub fn wrap(self) -> Expression {
     Expression::Variable(Box::new(self))
@@ -263,12 +598,30 @@ impl IfEx {
   }
 }
 
+impl WhileEx {
+  pub fn wrap(self) -> Expression {
+    Expression::While(Box::new(self))
+  }
+}
+
 impl BlockEx {
   pub fn wrap(self) -> Expression {
     Expression::Block(Box::new(self))
   }
 }
 
+impl TupleEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Tuple(Box::new(self))
+  }
+}
+
+impl ListEx {
+  pub fn wrap(self) -> Expression {
+    Expression::List(Box::new(self))
+  }
+}
+
 impl StringLiteralEx {
   pub fn wrap(self) -> Expression {
     Expression::StringLiteral(Box::new(self))
@@ -281,6 +634,61 @@ impl NumberLiteralEx {
   }
 }
 
+impl HoleEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Hole(Box::new(self))
+  }
+
+  /**
+  * Message raised when the hole is run, naming the shape it stands in for.
+  */
+  pub fn message(&self) -> String {
+    format!("Hole has type {} {}", self.shape.pretty(), self.loc.pretty())
+  }
+}
+
+impl VariantEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Variant(Box::new(self))
+  }
+}
+
+impl TryEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Try(Box::new(self))
+  }
+}
+
+impl IndexEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Index(Box::new(self))
+  }
+}
+
+impl FieldEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Field(Box::new(self))
+  }
+}
+
+impl PanicEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Panic(Box::new(self))
+  }
+}
+
+impl ReturnEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Return(Box::new(self))
+  }
+}
+
+impl AssertEx {
+  pub fn wrap(self) -> Expression {
+    Expression::Assert(Box::new(self))
+  }
+}
+
 impl ImportEx {
   pub fn wrap(self) -> Expression {
     Expression::Import(Box::new(self))
@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+use bytecode::{BitApplication, BitPackage};
+use compiler::compile_source_module;
+use error::LetError;
+use interpreter::{Backend, Dispatch, Machine};
+use optimize::{OptLevel, Optimizer};
+use runtime::Value;
+use typechecker::AppShapes;
+use {SOURCE_MODULE, SOURCE_PACKAGE};
+
+/**
+* A program to time. Each one is run by calling its run function with no arguments.
+*/
+pub struct Program {
+  pub name: &'static str,
+  pub src: &'static str,
+}
+
+pub const PROGRAMS: [Program; 3] = [
+  Program { name: "fib", src: include_str!("../bench/fib.let") },
+  Program { name: "fold", src: include_str!("../bench/fold.let") },
+  Program { name: "closures", src: include_str!("../bench/closures.let") },
+];
+
+const ENTRY: &'static str = "run";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Config {
+  pub level: OptLevel,
+  pub backend: Backend,
+  pub dispatch: Dispatch,
+}
+
+impl Config {
+
+  pub fn name(&self) -> String {
+    let level = match self.level {
+      OptLevel::O0 => "O0",
+      OptLevel::O1 => "O1",
+      OptLevel::O2 => "O2",
+    };
+
+    let runner = match (self.backend, self.dispatch) {
+      (Backend::Register, _) => "register",
+      (Backend::Stack, Dispatch::Match) => "match",
+      (Backend::Stack, Dispatch::Threaded) => "threaded",
+    };
+
+    format!("{}/{}", level, runner)
+  }
+}
+
+/**
+* Every optimization level with each way of running the bytecode.
+*/
+pub fn configs() -> Vec<Config> {
+  let runners = [(Backend::Stack, Dispatch::Match), (Backend::Stack, Dispatch::Threaded), (Backend::Register, Dispatch::Match)];
+
+  [OptLevel::O0, OptLevel::O1, OptLevel::O2].iter()
+    .flat_map(|level| runners.iter().map(move |&(backend, dispatch)| Config { level: *level, backend, dispatch }))
+    .collect()
+}
+
+pub struct Measurement {
+  pub program: &'static str,
+  pub config: Config,
+  pub result: Value,
+  pub fastest: Duration,
+  pub mean: Duration,
+}
+
+/**
+* Times every program under every config, iterations times each after one untimed run to warm up. Compiling and
+* loading are left out, only the call to run is timed.
+*/
+pub fn run_benchmarks(iterations: u32) -> Result<Vec<Measurement>, LetError> {
+  let mut measurements = Vec::new();
+
+  for program in PROGRAMS.iter() {
+    for config in configs() {
+      let mut app = BitApplication::library();
+      app.packages.insert(String::from(SOURCE_PACKAGE), compile(program, config.level)?);
+
+      let mut machine = Machine::new(app);
+      machine.set_backend(config.backend);
+      machine.set_dispatch(config.dispatch);
+
+      let result = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, ENTRY, vec![])?;
+      let mut times = Vec::with_capacity(iterations as usize);
+
+      for _ in 0..iterations {
+        let start = Instant::now();
+        machine.call(SOURCE_PACKAGE, SOURCE_MODULE, ENTRY, vec![])?;
+        times.push(start.elapsed());
+      }
+
+      measurements.push(Measurement {
+        program: program.name,
+        config,
+        result,
+        fastest: times.iter().min().cloned().unwrap_or_default(),
+        mean: times.iter().sum::<Duration>() / iterations.max(1),
+      });
+    }
+  }
+
+  Ok(measurements)
+}
+
+fn compile(program: &Program, level: OptLevel) -> Result<BitPackage, LetError> {
+  let module = compile_source_module(SOURCE_PACKAGE, SOURCE_MODULE, program.src, &Optimizer::with_level(level), &AppShapes::new())?;
+  let mut package = BitPackage::new();
+
+  package.modules.insert(String::from(SOURCE_MODULE), module);
+  Ok(package)
+}
+
+pub fn report(measurements: &[Measurement]) -> String {
+  let mut lines = vec![format!("{:<10} {:<12} {:>12} {:>12}", "program", "config", "fastest (us)", "mean (us)")];
+
+  for measurement in measurements {
+    lines.push(format!("{:<10} {:<12} {:>12} {:>12}", measurement.program, measurement.config.name(), micros(measurement.fastest), micros(measurement.mean)));
+  }
+
+  lines.join("\n")
+}
+
+fn micros(time: Duration) -> u64 {
+  time.as_secs() * 1_000_000 + u64::from(time.subsec_micros())
+}
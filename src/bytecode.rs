@@ -1,13 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs::File;
-use std::io::{self, ErrorKind, Write};
+use std::io::{self, ErrorKind, Read, Write};
 use std::rc::Rc;
 
-use simple_error::SimpleError;
+use bincode::{deserialize_from, serialize_into};
 use serde::{Serialize, Deserialize};
 
-use interpreter::RunFunction;
+use ast::Location;
+use error::RuntimeError;
+use interpreter::{Machine, NativeFunction, RunFunction};
 use runtime::Value;
 use shapes::BaseShapeKind;
 use shapes::Shape;
@@ -18,7 +20,7 @@ pub type ConstantId = u32;
 
 pub struct BitApplication {
   pub packages: HashMap<String, BitPackage>,
-  pub main: FunctionRef,
+  pub main: Option<FunctionRef>,
 }
 
 impl BitApplication {
@@ -26,21 +28,115 @@ impl BitApplication {
   pub fn new(main: FunctionRef) -> BitApplication {
     BitApplication {
       packages: HashMap::new(),
-      main
+      main: Some(main)
     }
   }
 
-  pub fn lookup_module(&self, func: &FunctionRef) -> Result<&BitModule, SimpleError> {
+  /**
+  * An application without an entry point, for embedders that only ever call functions directly.
+  */
+  pub fn library() -> BitApplication {
+    BitApplication {
+      packages: HashMap::new(),
+      main: None
+    }
+  }
+
+  /**
+  * Adds a host function the letLang code can import as package::module and call as module.name.
+  * Use AppShapes::for_application when compiling so the typechecker can see it too.
+  */
+  pub fn register_native<Op: Fn(&Machine, Vec<Value>) -> Result<Value, RuntimeError> + 'static>(&mut self, package: &str, module: &str, name: &str, shape: Shape, op: Op) -> Result<(), RuntimeError> {
+    let bit_module = self.packages.entry(String::from(package))
+      .or_insert_with(BitPackage::new)
+      .modules.entry(String::from(module))
+      .or_insert_with(BitModule::new);
+
+    if bit_module.functions.contains_key(name) {
+      return Err(RuntimeError::AlreadyDeclared { package: String::from(package), module: String::from(module), name: String::from(name) });
+    }
+
+    let func_ref = FunctionRef {
+      package: String::from(package),
+      module: String::from(module),
+      name: String::from(name),
+
+      shape,
+    };
+
+    bit_module.functions.insert(String::from(name), NativeFunction { func: Box::new(op), func_ref }.wrap());
+    Ok(())
+  }
+
+  pub fn lookup_module(&self, func: &FunctionRef) -> Result<&BitModule, RuntimeError> {
     self.packages.get(&func.package)
       .and_then(|package| package.modules.get(&func.module))
-      .ok_or_else(|| SimpleError::new("FunctionRef Module lookup failed"))
+      .ok_or_else(|| RuntimeError::NoSuchFunction { func: Box::new(func.clone()) })
   }
 
-  pub fn lookup_function(&self, func: &FunctionRef) -> Result<&RunFunction, SimpleError> {
+  pub fn lookup_function(&self, func: &FunctionRef) -> Result<&RunFunction, RuntimeError> {
     self.packages.get(&func.package)
       .and_then(|package| package.modules.get(&func.module))
       .and_then(|module| module.functions.get(&func.name))
-      .ok_or_else(|| SimpleError::new("FunctionRef Module lookup failed"))
+      .ok_or_else(|| RuntimeError::NoSuchFunction { func: Box::new(func.clone()) })
+  }
+
+  /**
+  * Checks every function ref in every module against the function it names, and main if there is one, so bytecode
+  * compiled against a signature that has since changed fails up front instead of misbehaving once it's called.
+  * Every problem is reported at once, each ref only the first time it's seen.
+  */
+  pub fn link(&self) -> Result<(), RuntimeError> {
+    let mut checked: Vec<&FunctionRef> = Vec::new();
+    let mut errors = Vec::new();
+
+    let mut packages: Vec<&String> = self.packages.keys().collect();
+    packages.sort();
+
+    for package in packages {
+      let modules = &self.packages[package].modules;
+      let mut names: Vec<&String> = modules.keys().collect();
+      names.sort();
+
+      for name in names {
+        for func in &modules[name].function_refs {
+          if checked.iter().any(|prev| *prev == func && prev.shape == func.shape) {
+            continue;
+          }
+
+          checked.push(func);
+
+          if let Err(err) = self.link_ref(func) {
+            errors.push(err);
+          }
+        }
+      }
+    }
+
+    if let Some(main) = &self.main {
+      if let Err(err) = self.link_ref(main) {
+        errors.push(err);
+      }
+    }
+
+    if errors.is_empty() {
+      Ok(())
+    } else {
+      Err(RuntimeError::LinkErrors(errors))
+    }
+  }
+
+  fn link_ref(&self, func: &FunctionRef) -> Result<(), RuntimeError> {
+    let found = match self.lookup_function(func)? {
+      RunFunction::BitFunction(target) => &target.func_ref,
+      RunFunction::NativeFunction(target) => &target.func_ref,
+    };
+
+    if found.shape != func.shape {
+      return Err(RuntimeError::SignatureMismatch { func: Box::new(func.clone()), expected: func.shape.clone(), found: found.shape.clone() });
+    }
+
+    Ok(())
   }
 }
 
@@ -56,44 +152,52 @@ impl BitPackage {
 }
 
 pub struct BitModule {
-  pub string_constants: Vec<String>,
+  /** Interned once when the module is built or loaded, so loading a constant only bumps a reference count. */
+  pub string_constants: Vec<Rc<String>>,
   pub function_refs: Vec<FunctionRef>,
-  pub functions: HashMap<String, RunFunction>,
+  /** Ordered by name, so everything written out of a module is the same from one build to the next. */
+  pub functions: BTreeMap<String, RunFunction>,
   pub shape_refs: Vec<Shape>,
 }
 
 impl BitModule {
 
-  pub fn lookup_string(&self, id: ConstantId) -> Result<String, SimpleError> {
-    Ok(self.string_constants.get(id as usize)
-      .ok_or_else(|| SimpleError::new("Invalid bytecode. Invalid String constant id"))?
-      .clone())
+  pub fn new() -> BitModule {
+    BitModule {
+      string_constants: Vec::new(),
+      function_refs: Vec::new(),
+      functions: BTreeMap::new(),
+      shape_refs: Vec::new(),
+    }
   }
 
-  pub fn lookup_function(&self, id: ConstantId) -> Result<FunctionRef, SimpleError> {
+  pub fn lookup_string(&self, id: ConstantId) -> Result<&Rc<String>, RuntimeError> {
+    self.string_constants.get(id as usize)
+      .ok_or_else(|| RuntimeError::InvalidConstant { kind: "String", id: id as usize })
+  }
+
+  pub fn lookup_function(&self, id: ConstantId) -> Result<FunctionRef, RuntimeError> {
     Ok(self.function_refs.get(id as usize)
-      .ok_or_else(|| SimpleError::new("Invalid bytecode. Invalid Function constant id"))?
+      .ok_or_else(|| RuntimeError::InvalidConstant { kind: "Function", id: id as usize })?
       .clone())
   }
 
-  pub fn lookup_shape(&self, id: ConstantId) -> Result<Shape, SimpleError> {
+  pub fn lookup_shape(&self, id: ConstantId) -> Result<Shape, RuntimeError> {
     Ok(self.shape_refs.get(id as usize)
-      .ok_or_else(|| SimpleError::new("Invalid bytecode. Invalid Shape constant id"))?
+      .ok_or_else(|| RuntimeError::InvalidConstant { kind: "Shape", id: id as usize })?
       .clone())
   }
 
-  pub fn debug(&self) -> Result<(), SimpleError> {
+  pub fn debug(&self) -> Result<(), RuntimeError> {
     for raw in self.functions.values() {
       match raw {
         RunFunction::BitFunction(func) => func.debug(self)?,
         RunFunction::NativeFunction(func) => {
           let mut writer = io::stderr();
 
-          writer.write_all(format!("{}: {}\n", func.func_ref.pretty(), func.func_ref.shape.pretty()).as_bytes())
-            .map_err(|err| SimpleError::from(err))?;
+          writer.write_all(format!("{}: {}\n", func.func_ref.pretty(), func.func_ref.shape.pretty()).as_bytes())?;
 
-          writer.write_all(b"  <native code>\n")
-            .map_err(|err| SimpleError::from(err))?
+          writer.write_all(b"  <native code>\n")?
         }
       }
     }
@@ -101,6 +205,88 @@ impl BitModule {
     Ok(())
   }
 
+  /**
+  * Writes the constant pools followed by every function, sorted by name so the output is stable.
+  */
+  pub fn disassemble<Writer: Write>(&self, writer: &mut Writer) -> Result<(), RuntimeError> {
+    writer.write_all(b"strings:\n")?;
+    for (id, value) in self.string_constants.iter().enumerate() {
+      writer.write_all(format!("  #{}: {:?}\n", id, value).as_bytes())?;
+    }
+
+    writer.write_all(b"\nfunction refs:\n")?;
+    for (id, func) in self.function_refs.iter().enumerate() {
+      writer.write_all(format!("  #{}: {}: {}\n", id, func.pretty(), func.shape.pretty()).as_bytes())?;
+    }
+
+    writer.write_all(b"\nshapes:\n")?;
+    for (id, shape) in self.shape_refs.iter().enumerate() {
+      writer.write_all(format!("  #{}: {}\n", id, shape.pretty()).as_bytes())?;
+    }
+
+    for func in self.functions.values() {
+      writer.write_all(b"\n")?;
+
+      match func {
+        RunFunction::BitFunction(func) => func.disassemble(self, writer)?,
+        RunFunction::NativeFunction(func) => {
+          writer.write_all(format!("{}: {}\n", func.func_ref.pretty(), func.func_ref.shape.pretty()).as_bytes())?;
+          writer.write_all(b"  <native code>\n")?
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+}
+
+/**
+* Borrowed view of a BitModule in the order it is written out. Native functions live in the host and are skipped.
+*/
+#[derive(Serialize)]
+struct ModuleView<'a> {
+  string_constants: Vec<&'a str>,
+  function_refs: &'a Vec<FunctionRef>,
+  shape_refs: &'a Vec<Shape>,
+  functions: Vec<&'a BitFunction>,
+}
+
+#[derive(Deserialize)]
+struct ModuleData {
+  string_constants: Vec<String>,
+  function_refs: Vec<FunctionRef>,
+  shape_refs: Vec<Shape>,
+  functions: Vec<BitFunction>,
+}
+
+pub fn serialize_bit_module<Writer: Write>(writer: &mut Writer, module: &BitModule) -> bincode::Result<()> {
+  let functions = module.functions.values()
+    .filter_map(|func| match func {
+      RunFunction::BitFunction(func) => Some(func),
+      RunFunction::NativeFunction(_) => None,
+    })
+    .collect();
+
+  serialize_into(writer, &ModuleView {
+    string_constants: module.string_constants.iter().map(|value| value.as_str()).collect(),
+    function_refs: &module.function_refs,
+    shape_refs: &module.shape_refs,
+    functions,
+  })
+}
+
+pub fn deserialize_bit_module<Reader: Read>(reader: &mut Reader) -> bincode::Result<BitModule> {
+  let data: ModuleData = deserialize_from(reader)?;
+
+  Ok(BitModule {
+    string_constants: data.string_constants.into_iter().map(Rc::new).collect(),
+    function_refs: data.function_refs,
+    shape_refs: data.shape_refs,
+    functions: data.functions.into_iter()
+      .map(|func| (func.func_ref.name.clone(), func.wrap()))
+      .collect(),
+  })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -142,36 +328,47 @@ impl PartialEq for FunctionRef {
 
 impl Eq for FunctionRef {}
 
+#[derive(Serialize, Deserialize)]
 pub struct BitFunction {
   pub func_ref: FunctionRef,
 
   pub max_locals: LocalId,
+  /** The deepest the operand stack gets, so it can be allocated once up front. */
+  pub max_stack: LocalId,
   pub body: Vec<Instruction>,
   pub source: Vec<SourcePoint>,
 }
 
 impl BitFunction {
 
-  pub fn debug(&self, module: &BitModule) -> Result<(), SimpleError> {
+  pub fn debug(&self, module: &BitModule) -> Result<(), RuntimeError> {
     let mut writer = io::stderr();
 
-    writer.write_all(format!("{}: {}\n", self.func_ref.pretty(), self.func_ref.shape.pretty()).as_bytes())
-      .map_err(|err| SimpleError::from(err))?;
+    writer.write_all(format!("{}: {}\n", self.func_ref.pretty(), self.func_ref.shape.pretty()).as_bytes())?;
 
     Instruction::pretty_print(module, &self.body, &mut writer)?;
 
-    writer.write_all(b"\n")
-      .map_err(|err| SimpleError::from(err))
+    writer.write_all(b"\n")?;
+    Ok(())
+  }
+
+  pub fn disassemble<Writer: Write>(&self, module: &BitModule, writer: &mut Writer) -> Result<(), RuntimeError> {
+    writer.write_all(format!("{}: {}\n", self.func_ref.pretty(), self.func_ref.shape.pretty()).as_bytes())?;
+    writer.write_all(format!("  locals: {}\n", self.max_locals).as_bytes())?;
+    writer.write_all(format!("  stack: {}\n", self.max_stack).as_bytes())?;
+
+    Instruction::pretty_print(module, &self.body, writer)
   }
 
 }
 
+#[derive(Serialize, Deserialize)]
 pub enum Instruction {
   NoOp, // 0 is an error to hopefully crash early on invalid bytecode.
   Duplicate,
   Pop,
   Swap,
-  LoadConstNull,
+  LoadConstUnit,
   LoadConstTrue,
   LoadConstFalse,
   LoadConstString {const_id: ConstantId},
@@ -188,6 +385,25 @@ pub enum Instruction {
   CallStatic {
     func_id: ConstantId,
   },
+  /** Arithmetic and comparison on the two Floats on top of the stack, run inline instead of calling Core. */
+  AddFloat,
+  SubFloat,
+  MulFloat,
+  DivFloat,
+  LessFloat,
+  LessEqualFloat,
+  GreaterFloat,
+  GreaterEqualFloat,
+  /** LoadValue(left), LoadValue(right), AddFloat fused by the peephole pass. */
+  AddLocals {
+    left: LocalId,
+    right: LocalId,
+  },
+  /** LoadConstFloat(value), StoreValue(local) fused by the peephole pass. */
+  StoreConstFloat {
+    value: f64,
+    local: LocalId,
+  },
   CallDynamic {
     param_count: LocalId,
   },
@@ -196,53 +412,124 @@ pub enum Instruction {
     func_id: ConstantId,
   },
   BuildRecursiveFunction,
+  BuildTuple {
+    size: LocalId,
+  },
+  LoadElement {
+    index: LocalId,
+  },
+  BuildVariant {
+    const_id: ConstantId,
+    size: LocalId,
+  },
+  IsVariant {
+    const_id: ConstantId,
+  },
+  LoadVariantValue {
+    index: LocalId,
+  },
+  /** Pops count values and pushes a list of them, the first pushed first in the list. */
+  BuildList {
+    count: LocalId,
+  },
+  /** Pops an index then a list, and pushes that element. Panics when out of bounds. */
+  ListGet,
+  NewCell,
+  LoadCell,
+  StoreCell,
   Return,
   Branch{jump: i32},
   Jump{jump: i32},
   Debug,
-  Error
+  Error {
+    const_id: ConstantId,
+  },
+  Panic {
+    const_id: ConstantId,
+  },
+  Assert {
+    const_id: ConstantId,
+    point: SourcePoint,
+  },
 }
 
 impl Instruction {
 
-  fn pretty_print<Writer: Write>(module: &BitModule, block: &Vec<Instruction>, writer: &mut Writer) -> Result<(), SimpleError> {
+  fn pretty_print<Writer: Write>(module: &BitModule, block: &Vec<Instruction>, writer: &mut Writer) -> Result<(), RuntimeError> {
 
     for (index, next) in block.iter().enumerate() {
-      writer.write_all(format!("  {}: ", index).as_bytes()).map_err(|err| SimpleError::from(err))?;
-
-      match next {
-        Instruction::NoOp => writer.write_all(b"NoOp"),
-        Instruction::Duplicate => writer.write_all(b"Duplicate"),
-        Instruction::Pop => writer.write_all(b"Pop"),
-        Instruction::Swap => writer.write_all(b"Swap"),
-        Instruction::LoadConstNull => writer.write_all(b"LoadConstNull"),
-        Instruction::LoadConstTrue => writer.write_all(b"LoadConstTrue"),
-        Instruction::LoadConstFalse => writer.write_all(b"LoadConstFalse"),
-        Instruction::LoadConstString {const_id} => writer.write_all(format!("LoadConstString('{}')", module.lookup_string(*const_id)?).as_bytes()),
-        Instruction::LoadConstFunction {const_id} => writer.write_all(format!("LoadConstFunction('{}')", module.lookup_function(*const_id)?.pretty()).as_bytes()),
-        Instruction::LoadConstFloat {value} => writer.write_all(format!("LoadConstFloat({})", value).as_bytes()),
-        Instruction::LoadValue {local} => writer.write_all(format!("LoadValue({})", local).as_bytes()),
-        Instruction::StoreValue {local} => writer.write_all(format!("StoreValue({})", local).as_bytes()),
-        Instruction::CallStatic {func_id} => writer.write_all(format!("CallStatic('{}')", module.lookup_function(*func_id)?.pretty()).as_bytes()),
-        Instruction::CallDynamic {param_count} => writer.write_all(format!("CallDynamic({})", param_count).as_bytes()),
-        Instruction::BuildClosure {param_count, func_id} => writer.write_all(format!("BuildClosure({}, '{}')", param_count, module.lookup_function(*func_id)?.pretty()).as_bytes()),
-        Instruction::BuildRecursiveFunction => writer.write_all(b"BuildRecursiveFunction"),
-        Instruction::Return => writer.write_all(b"Return"),
-        Instruction::Branch{jump} => writer.write_all(format!("Branch({})", jump).as_bytes()),
-        Instruction::Jump{jump} => writer.write_all(format!("Jump({})", jump).as_bytes()),
-        Instruction::Debug => writer.write_all(b"Debug"),
-        Instruction::Error => writer.write_all(b"Error"),
-      }.map_err(|err| SimpleError::from(err))?;
-
-      writer.write_all(b"\n").map_err(|err| SimpleError::from(err))?;
+      writer.write_all(format!("  {}: {}\n", index, next.pretty(module)?).as_bytes())?;
     }
 
     Ok(())
   }
 
+  /**
+  * Single line form of this instruction with any constants it refers to looked up in module.
+  */
+  pub fn pretty(&self, module: &BitModule) -> Result<String, RuntimeError> {
+    Ok(match self {
+      Instruction::NoOp => String::from("NoOp"),
+      Instruction::Duplicate => String::from("Duplicate"),
+      Instruction::Pop => String::from("Pop"),
+      Instruction::Swap => String::from("Swap"),
+      Instruction::LoadConstUnit => String::from("LoadConstUnit"),
+      Instruction::LoadConstTrue => String::from("LoadConstTrue"),
+      Instruction::LoadConstFalse => String::from("LoadConstFalse"),
+      Instruction::LoadConstString {const_id} => format!("LoadConstString('{}')", module.lookup_string(*const_id)?),
+      Instruction::LoadConstFunction {const_id} => format!("LoadConstFunction('{}')", module.lookup_function(*const_id)?.pretty()),
+      Instruction::LoadConstFloat {value} => format!("LoadConstFloat({})", value),
+      Instruction::LoadValue {local} => format!("LoadValue({})", local),
+      Instruction::StoreValue {local} => format!("StoreValue({})", local),
+      Instruction::CallStatic {func_id} => format!("CallStatic('{}')", module.lookup_function(*func_id)?.pretty()),
+      Instruction::CallDynamic {param_count} => format!("CallDynamic({})", param_count),
+      Instruction::AddFloat => String::from("AddFloat"),
+      Instruction::SubFloat => String::from("SubFloat"),
+      Instruction::MulFloat => String::from("MulFloat"),
+      Instruction::DivFloat => String::from("DivFloat"),
+      Instruction::LessFloat => String::from("LessFloat"),
+      Instruction::LessEqualFloat => String::from("LessEqualFloat"),
+      Instruction::GreaterFloat => String::from("GreaterFloat"),
+      Instruction::GreaterEqualFloat => String::from("GreaterEqualFloat"),
+      Instruction::AddLocals {left, right} => format!("AddLocals({}, {})", left, right),
+      Instruction::StoreConstFloat {value, local} => format!("StoreConstFloat({}, {})", value, local),
+      Instruction::BuildClosure {param_count, func_id} => format!("BuildClosure({}, '{}')", param_count, module.lookup_function(*func_id)?.pretty()),
+      Instruction::BuildRecursiveFunction => String::from("BuildRecursiveFunction"),
+      Instruction::BuildTuple {size} => format!("BuildTuple({})", size),
+      Instruction::LoadElement {index} => format!("LoadElement({})", index),
+      Instruction::BuildVariant {const_id, size} => format!("BuildVariant('{}', {})", module.lookup_string(*const_id)?, size),
+      Instruction::IsVariant {const_id} => format!("IsVariant('{}')", module.lookup_string(*const_id)?),
+      Instruction::LoadVariantValue {index} => format!("LoadVariantValue({})", index),
+      Instruction::BuildList {count} => format!("BuildList({})", count),
+      Instruction::ListGet => String::from("ListGet"),
+      Instruction::NewCell => String::from("NewCell"),
+      Instruction::LoadCell => String::from("LoadCell"),
+      Instruction::StoreCell => String::from("StoreCell"),
+      Instruction::Return => String::from("Return"),
+      Instruction::Branch{jump} => format!("Branch({})", jump),
+      Instruction::Jump{jump} => format!("Jump({})", jump),
+      Instruction::Debug => String::from("Debug"),
+      Instruction::Error {const_id} => format!("Error('{}')", module.lookup_string(*const_id)?),
+      Instruction::Panic {const_id} => format!("Panic('{}')", module.lookup_string(*const_id)?),
+      Instruction::Assert {const_id, point} => format!("Assert('{}', {})", module.lookup_string(*const_id)?, point.pretty()),
+    })
+  }
+
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct SourcePoint {
   pub line: u32,
   pub column: u32,
 }
+
+impl SourcePoint {
+
+  pub fn from_location(loc: &Location) -> SourcePoint {
+    SourcePoint { line: loc.y as u32, column: loc.x as u32 }
+  }
+
+  pub fn pretty(&self) -> String {
+    format!("line: {}, column: {}", self.line, self.column)
+  }
+}
@@ -0,0 +1,196 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, create_dir_all};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+use bincode::{deserialize_from, serialize_into};
+use serde::{Deserialize, Serialize};
+
+use ast::{AstModule, Visibility};
+use ir::IrModule;
+use shapes::Shape;
+use typechecker::AppShapes;
+
+/**
+* Default location of the cache, relative to the working directory so it lives beside cargo's own output.
+*/
+pub const DEFAULT_CACHE_DIR: &'static str = "target/letcache";
+
+/**
+* Layout of a cache entry. Bump it whenever IrModule, Shape or ModuleExports change how they serialize, so entries
+* written by an older compiler are ignored instead of misread.
+*/
+pub const CACHE_FORMAT_VERSION: u32 = 2;
+
+/**
+* What other modules can see of a module, enough to register it in AppShapes without checking it again.
+*/
+#[derive(Serialize, Deserialize)]
+pub struct ModuleExports {
+  pub functions: Vec<(String, Shape, Visibility)>,
+  pub types: Vec<(String, Shape)>,
+  pub fields: Vec<(String, Vec<(String, Shape)>)>,
+}
+
+impl ModuleExports {
+
+  pub fn of(module: &AstModule) -> ModuleExports {
+    ModuleExports {
+      functions: module.functions.iter()
+        .map(|dec| (dec.ex.id.clone(), dec.ex.shape(), dec.visibility.clone()))
+        .collect(),
+      types: module.aliases.iter()
+        .filter(|alias| alias.visibility == Visibility::Public)
+        .map(|alias| (alias.id.clone(), alias.shape.clone()))
+        // A data type's visibility is only that of its constructor, the shapes of other functions may still name it.
+        .chain(module.datas.iter().map(|data| (data.id.clone(), data.shape.clone())))
+        .collect(),
+      fields: module.datas.iter()
+        .map(|data| (data.id.clone(), data.fields.iter().map(|field| (field.id.clone(), field.shape.clone())).collect()))
+        .collect(),
+    }
+  }
+}
+
+/**
+* A module read back from the cache, still to be optimized and compiled to bytecode.
+*/
+pub struct CachedModule {
+  pub exports: ModuleExports,
+  pub ir: IrModule,
+}
+
+#[derive(Serialize)]
+struct EntryView<'a> {
+  version: u32,
+  source_hash: u64,
+  dependencies: Vec<(String, String, u64)>,
+  exports: &'a ModuleExports,
+  ir: &'a IrModule,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+  version: u32,
+  source_hash: u64,
+  dependencies: Vec<(String, String, u64)>,
+  exports: ModuleExports,
+  ir: IrModule,
+}
+
+/**
+* Saves the Ir of each module keyed by a hash of its source. An entry is only used while the source, the
+* CACHE_FORMAT_VERSION and the shapes of everything the module imports are all unchanged.
+*/
+pub struct BuildCache {
+  dir: PathBuf,
+}
+
+impl BuildCache {
+
+  pub fn new<P: Into<PathBuf>>(dir: P) -> BuildCache {
+    BuildCache { dir: dir.into() }
+  }
+
+  /**
+  * None on any kind of miss, including an unreadable or outdated file.
+  */
+  pub fn load(&self, package: &str, module: &str, source: &[u8], shapes: &AppShapes) -> Option<CachedModule> {
+    let entry = self.read(package, module, source)?;
+
+    for (dep_package, dep_module, hash) in &entry.dependencies {
+      if interface_hash(shapes, dep_package, dep_module) != Some(*hash) {
+        return None;
+      }
+    }
+
+    Some(CachedModule { exports: entry.exports, ir: entry.ir })
+  }
+
+  /**
+  * The modules a cached module imports, as long as its source is unchanged. Lets the compiler order modules without
+  * parsing them again.
+  */
+  pub fn imports(&self, package: &str, module: &str, source: &[u8]) -> Option<Vec<(String, String)>> {
+    let entry = self.read(package, module, source)?;
+
+    Some(entry.dependencies.into_iter().map(|(dep_package, dep_module, _)| (dep_package, dep_module)).collect())
+  }
+
+  fn read(&self, package: &str, module: &str, source: &[u8]) -> Option<Entry> {
+    let file = File::open(self.path(package, module)).ok()?;
+    let entry: Entry = deserialize_from(file).ok()?;
+
+    if entry.version != CACHE_FORMAT_VERSION || entry.source_hash != hash_source(source) {
+      return None;
+    }
+
+    Some(entry)
+  }
+
+  /**
+  * Call once the module's imports are registered in shapes. The cache only saves time, so failing to write is ignored.
+  */
+  pub fn store(&self, module: &AstModule, source: &[u8], exports: &ModuleExports, ir: &IrModule, shapes: &AppShapes) {
+    let dependencies = module.imports.iter()
+      .filter_map(|imp| interface_hash(shapes, &imp.package, &imp.module).map(|hash| (imp.package.clone(), imp.module.clone(), hash)))
+      .collect();
+
+    let entry = EntryView {
+      version: CACHE_FORMAT_VERSION,
+      source_hash: hash_source(source),
+      dependencies,
+      exports,
+      ir,
+    };
+
+    let path = self.path(&module.package, &module.name);
+
+    let written = path.parent()
+      .map_or(Ok(()), |parent| create_dir_all(parent))
+      .and_then(|()| File::create(&path))
+      .map_err(|err| err.to_string())
+      .and_then(|file| serialize_into(file, &entry).map_err(|err| err.to_string()));
+
+    if written.is_err() {
+      let _ = fs::remove_file(&path);
+    }
+  }
+
+  fn path(&self, package: &str, module: &str) -> PathBuf {
+    self.dir.join(package).join(format!("{}.cache", module))
+  }
+}
+
+fn hash_source(source: &[u8]) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  source.hash(&mut hasher);
+  hasher.finish()
+}
+
+/**
+* Hash of everything an importer can see of a module. None if the module isn't known yet.
+*/
+fn interface_hash(shapes: &AppShapes, package: &str, module: &str) -> Option<u64> {
+  let module = shapes.lookup_module(package, module)?;
+  let mut hasher = DefaultHasher::new();
+
+  let mut values = module.list_values();
+  values.sort();
+
+  for name in values {
+    name.hash(&mut hasher);
+    module.lookup(&name).map(|shape| shape.pretty()).hash(&mut hasher);
+    format!("{:?}", module.visibility(&name)).hash(&mut hasher);
+  }
+
+  let mut types = module.list_types();
+  types.sort();
+
+  for name in types {
+    name.hash(&mut hasher);
+    module.lookup_type(&name).map(|shape| shape.pretty()).hash(&mut hasher);
+  }
+
+  Some(hasher.finish())
+}
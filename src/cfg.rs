@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+
+use ir::{Ir, IrFunction};
+
+pub type BlockId = usize;
+
+/**
+* How control leaves a basic block. Stop is a block ending in Error or Panic, or one nothing ever reaches.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exit {
+  Jump(BlockId),
+  /** Pops the condition and goes to then_block if it's true. */
+  Branch { then_block: BlockId, else_block: BlockId },
+  Return,
+  Stop,
+}
+
+/**
+* A run of Ir with no control flow inside it. Return, Error and Panic can only be last.
+*/
+#[derive(Clone)]
+pub struct BasicBlock {
+  pub body: Vec<Ir>,
+  pub exit: Exit,
+}
+
+/**
+* An IrFunction as basic blocks with explicit edges, for analyses that would otherwise have to follow every kind of
+* nested block themselves. Block 0 is the entry.
+*
+* Blocks are made in a fixed order walking the Ir tree, so once the analyses are done raise puts each block's body
+* back where it came from. Passes may rewrite the instructions in a block but not the edges between them.
+*/
+pub struct Cfg {
+  pub blocks: Vec<BasicBlock>,
+}
+
+impl Cfg {
+
+  pub fn lower(func: &IrFunction) -> Cfg {
+    let mut cfg = Cfg { blocks: Vec::new() };
+    let entry = cfg.new_block();
+
+    cfg.lower_block(&func.body, entry);
+    cfg
+  }
+
+  /**
+  * Writes every block's body back into the tree func was lowered from.
+  */
+  pub fn raise(self, func: &mut IrFunction) {
+    let mut blocks = self.blocks.into_iter().map(|block| Some(block.body)).collect();
+    let mut next = 1;
+
+    func.body = raise_block(&func.body, 0, &mut blocks, &mut next);
+  }
+
+  pub fn successors(&self, id: BlockId) -> Vec<BlockId> {
+    match self.blocks[id].exit {
+      Exit::Jump(next) => vec![next],
+      Exit::Branch { then_block, else_block } => vec![then_block, else_block],
+      Exit::Return | Exit::Stop => vec![],
+    }
+  }
+
+  /**
+  * The locals live at the end of each block, that is read on some path before they're stored again.
+  */
+  pub fn live_out(&self) -> Vec<HashSet<String>> {
+    let mut live_in: Vec<HashSet<String>> = vec![HashSet::new(); self.blocks.len()];
+    let mut changed = true;
+
+    while changed {
+      changed = false;
+
+      for id in (0..self.blocks.len()).rev() {
+        let live = live_before(&self.blocks[id].body, self.live_after(id, &live_in));
+
+        if live != live_in[id] {
+          live_in[id] = live;
+          changed = true;
+        }
+      }
+    }
+
+    (0..self.blocks.len()).map(|id| self.live_after(id, &live_in)).collect()
+  }
+
+  fn live_after(&self, id: BlockId, live_in: &[HashSet<String>]) -> HashSet<String> {
+    self.successors(id).into_iter().flat_map(|next| live_in[next].iter().cloned()).collect()
+  }
+
+  fn new_block(&mut self) -> BlockId {
+    self.blocks.push(BasicBlock { body: Vec::new(), exit: Exit::Stop });
+    self.blocks.len() - 1
+  }
+
+  /**
+  * Adds body to the end of block current, giving the block control ends up in once body is done.
+  */
+  fn lower_block(&mut self, body: &[Ir], mut current: BlockId) -> BlockId {
+    for ir in body {
+      match ir {
+        Ir::Branch { then_block, else_block } => {
+          let then_start = self.new_block();
+          let else_start = self.new_block();
+          self.blocks[current].exit = Exit::Branch { then_block: then_start, else_block: else_start };
+
+          let then_end = self.lower_block(then_block, then_start);
+          let else_end = self.lower_block(else_block, else_start);
+
+          current = self.new_block();
+          self.blocks[then_end].exit = Exit::Jump(current);
+          self.blocks[else_end].exit = Exit::Jump(current);
+        }
+        Ir::Cond { arms, else_block } => {
+          let mut ends = Vec::new();
+
+          for (condition, then_block) in arms {
+            let condition_start = self.new_block();
+            self.blocks[current].exit = Exit::Jump(condition_start);
+
+            let condition_end = self.lower_block(condition, condition_start);
+            let then_start = self.new_block();
+            ends.push(self.lower_block(then_block, then_start));
+
+            current = self.new_block();
+            self.blocks[condition_end].exit = Exit::Branch { then_block: then_start, else_block: current };
+          }
+
+          ends.push(self.lower_block(else_block, current));
+
+          current = self.new_block();
+
+          for end in ends {
+            self.blocks[end].exit = Exit::Jump(current);
+          }
+        }
+        Ir::Loop { condition, body } => {
+          let head = self.new_block();
+          self.blocks[current].exit = Exit::Jump(head);
+
+          let condition_end = self.lower_block(condition, head);
+          let body_start = self.new_block();
+          let body_end = self.lower_block(body, body_start);
+
+          current = self.new_block();
+          self.blocks[condition_end].exit = Exit::Branch { then_block: body_start, else_block: current };
+          self.blocks[body_end].exit = Exit::Jump(head);
+        }
+        Ir::Assert { condition, .. } => {
+          // Failing stops the program, so as far as locals go it's a straight line through the condition.
+          let condition_start = self.new_block();
+          self.blocks[current].exit = Exit::Jump(condition_start);
+
+          let condition_end = self.lower_block(condition, condition_start);
+
+          current = self.new_block();
+          self.blocks[condition_end].exit = Exit::Jump(current);
+        }
+        Ir::Return | Ir::Error { .. } | Ir::Panic { .. } => {
+          self.blocks[current].body.push(ir.clone());
+          self.blocks[current].exit = match ir {
+            Ir::Return => Exit::Return,
+            _ => Exit::Stop,
+          };
+
+          // Anything after is unreachable, but still has to go somewhere.
+          current = self.new_block();
+        }
+        _ => self.blocks[current].body.push(ir.clone()),
+      }
+    }
+
+    current
+  }
+}
+
+/**
+* Mirrors lower_block, making blocks in the same order so each id picks up the body it was given.
+*/
+fn raise_block(body: &[Ir], mut current: BlockId, blocks: &mut Vec<Option<Vec<Ir>>>, next: &mut BlockId) -> Vec<Ir> {
+  let mut raised = Vec::new();
+
+  for ir in body {
+    match ir {
+      Ir::Branch { then_block, else_block } => {
+        raised.extend(take(blocks, current));
+        let then_start = new_block(next);
+        let else_start = new_block(next);
+
+        let then_block = raise_block(then_block, then_start, blocks, next);
+        let else_block = raise_block(else_block, else_start, blocks, next);
+
+        raised.push(Ir::Branch { then_block, else_block });
+        current = new_block(next);
+      }
+      Ir::Cond { arms, else_block } => {
+        raised.extend(take(blocks, current));
+        let mut raised_arms = Vec::new();
+
+        for (condition, then_block) in arms {
+          let condition_start = new_block(next);
+          let condition = raise_block(condition, condition_start, blocks, next);
+          let then_start = new_block(next);
+          let then_block = raise_block(then_block, then_start, blocks, next);
+
+          raised_arms.push((condition, then_block));
+          current = new_block(next);
+        }
+
+        let else_block = raise_block(else_block, current, blocks, next);
+
+        raised.push(Ir::Cond { arms: raised_arms, else_block });
+        current = new_block(next);
+      }
+      Ir::Loop { condition, body } => {
+        raised.extend(take(blocks, current));
+        let head = new_block(next);
+        let condition = raise_block(condition, head, blocks, next);
+        let body_start = new_block(next);
+        let body = raise_block(body, body_start, blocks, next);
+
+        raised.push(Ir::Loop { condition, body });
+        current = new_block(next);
+      }
+      Ir::Assert { condition, message, point } => {
+        raised.extend(take(blocks, current));
+        let condition_start = new_block(next);
+        let condition = raise_block(condition, condition_start, blocks, next);
+
+        raised.push(Ir::Assert { condition, message: message.clone(), point: *point });
+        current = new_block(next);
+      }
+      Ir::Return | Ir::Error { .. } | Ir::Panic { .. } => {
+        // The block already holds this as its last instruction.
+        raised.extend(take(blocks, current));
+        current = new_block(next);
+      }
+      _ => {}
+    }
+  }
+
+  raised.extend(take(blocks, current));
+  raised
+}
+
+fn new_block(next: &mut BlockId) -> BlockId {
+  *next += 1;
+  *next - 1
+}
+
+fn take(blocks: &mut Vec<Option<Vec<Ir>>>, id: BlockId) -> Vec<Ir> {
+  blocks[id].take().unwrap_or_default()
+}
+
+fn live_before(body: &[Ir], mut live: HashSet<String>) -> HashSet<String> {
+  for ir in body.iter().rev() {
+    match ir {
+      Ir::LoadValue { local } => {
+        live.insert(local.clone());
+      }
+      Ir::StoreValue { local } => {
+        live.remove(local);
+      }
+      Ir::Return | Ir::Error { .. } | Ir::Panic { .. } => live.clear(),
+      _ => {}
+    }
+  }
+
+  live
+}
@@ -1,12 +1,11 @@
 use core::borrow::BorrowMut;
 use std::cmp::max;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
 use std::fs::{self, DirEntry, File, create_dir_all};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-use simple_error::SimpleError;
-
 use ast::{AssignmentEx, FunctionContext, Parameter};
 use ast::BinaryOpEx;
 use ast::BlockEx;
@@ -14,35 +13,276 @@ use ast::CallEx;
 use ast::Expression;
 use ast::FunctionDeclarationEx;
 use ast::Location;
-use ast::AstModule;
 use ast::NumberLiteralEx;
 use ast::StringLiteralEx;
 use ast::VariableEx;
-use bytecode::{BitModule, BitPackage};
+use bytecode::{BitApplication, BitModule, BitPackage};
+use cache::{BuildCache, ModuleExports};
 use bytecode::BitFunction;
 use bytecode::ConstantId;
 use bytecode::FunctionRef;
 use bytecode::Instruction;
 use bytecode::LocalId;
+use error::CompileError;
 use interpreter::RunFunction;
 use ir::{compile_ir_module, Ir, IrFunction, IrModule};
 use optimize::Optimizer;
-use parser::parse;
+use parser::{SCRIPT_MAIN, parse, parse_script, parse_str};
 use shapes::Shape;
 use shapes::shape_float;
 use typechecker;
+use typechecker::AppShapes;
+
+/**
+* The package a script's module is compiled into.
+*/
+pub const SCRIPT_PACKAGE: &'static str = "script";
+
+/**
+* Each module's functions and public types are registered into shapes once it is checked, so later modules can import it.
+*/
+pub fn compile_package(name: &str, base_dir: &str, optimizer: &Optimizer, shapes: &mut AppShapes) -> Result<BitPackage, CompileError> {
+  compile_modules(name, base_dir, optimizer, shapes, None)
+}
+
+/**
+* Same as compile_package, but unchanged modules are read back from cache instead of being parsed and checked again.
+*/
+pub fn compile_package_cached(name: &str, base_dir: &str, optimizer: &Optimizer, shapes: &mut AppShapes, cache: &BuildCache) -> Result<BitPackage, CompileError> {
+  compile_modules(name, base_dir, optimizer, shapes, Some(cache))
+}
+
+/**
+* File that marks a directory as a package, see Manifest.
+*/
+pub const MANIFEST_FILE: &'static str = "package.let.toml";
+
+/**
+* A package's package.let.toml, a small subset of TOML with only string values:
+*
+* ```toml
+* [package]
+* name = "app"
+* source = "src"       # directory of .let files, relative to the manifest, defaults to the manifest's own
+* main = "Main.main"   # optional entry point, module and function
+*
+* [dependencies]
+* util = "../util"     # directory holding the util package's own manifest
+* ```
+*/
+#[derive(Debug, Clone)]
+pub struct Manifest {
+  pub name: String,
+  pub source: PathBuf,
+  pub main: Option<(String, String)>,
+  pub dependencies: Vec<(String, PathBuf)>,
+}
+
+pub fn read_manifest(dir: &Path) -> Result<Manifest, CompileError> {
+  let path = dir.join(MANIFEST_FILE);
+  let src = fs::read_to_string(&path).map_err(|err| io_error(&path, err))?;
+
+  parse_manifest(&src, dir).map_err(|message| CompileError::Manifest { path: path.display().to_string(), message })
+}
+
+/**
+* Relative paths are resolved against dir.
+*/
+pub fn parse_manifest(src: &str, dir: &Path) -> Result<Manifest, String> {
+  let mut section = String::new();
+  let mut name = None;
+  let mut source = None;
+  let mut main = None;
+  let mut dependencies = Vec::new();
+
+  for (index, raw) in src.lines().enumerate() {
+    let line = strip_comment(raw).trim();
+
+    if line.is_empty() {
+      continue;
+    }
+
+    if line.starts_with('[') && line.ends_with(']') {
+      section = String::from(line[1..line.len() - 1].trim());
+      continue;
+    }
+
+    let (key, value) = parse_manifest_entry(line).ok_or_else(|| format!("line {}: expected key = \"value\"", index + 1))?;
+
+    match (section.as_str(), key) {
+      ("package", "name") => name = Some(value),
+      ("package", "source") => source = Some(dir.join(value)),
+      ("package", "main") => {
+        let dot = value.rfind('.').ok_or_else(|| format!("line {}: main must be Module.function", index + 1))?;
+        main = Some((String::from(&value[..dot]), String::from(&value[dot + 1..])));
+      }
+      ("dependencies", dep) => dependencies.push((String::from(dep), dir.join(value))),
+      (section, key) => return Err(format!("line {}: unknown key {} in [{}]", index + 1, key, section)),
+    }
+  }
+
+  Ok(Manifest {
+    name: name.ok_or_else(|| String::from("missing name in [package]"))?,
+    source: source.unwrap_or_else(|| dir.to_path_buf()),
+    main,
+    dependencies,
+  })
+}
+
+fn strip_comment(line: &str) -> &str {
+  let mut in_string = false;
+
+  for (index, ch) in line.char_indices() {
+    match ch {
+      '"' => in_string = !in_string,
+      '#' if !in_string => return &line[..index],
+      _ => {}
+    }
+  }
+
+  line
+}
+
+fn parse_manifest_entry(line: &str) -> Option<(&str, String)> {
+  let equals = line.find('=')?;
+  let key = line[..equals].trim();
+  let value = line[equals + 1..].trim();
+
+  if key.is_empty() || value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+    return None;
+  }
+
+  Some((key, String::from(&value[1..value.len() - 1])))
+}
+
+/**
+* Compiles the package whose manifest is in dir along with every package it depends on, dependencies first, so
+* imports across packages are typechecked like any other.
+*/
+pub fn compile_application(dir: &Path, optimizer: &Optimizer, cache: Option<&BuildCache>, shapes: &mut AppShapes) -> Result<BitApplication, CompileError> {
+  let mut manifests = Vec::new();
+  load_manifests(dir, &mut Vec::new(), &mut manifests)?;
+
+  let mut app = BitApplication::library();
+
+  for manifest in &manifests {
+    let source = manifest.source.to_str().ok_or_else(|| io_error(&manifest.source, "Invalid path"))?;
+    let package = compile_modules(&manifest.name, source, optimizer, shapes, cache)?;
+    app.packages.insert(manifest.name.clone(), package);
+  }
+
+  let root = manifests.last().expect("The root manifest is always loaded");
+
+  if let Some((module, name)) = &root.main {
+    let shape = shapes.lookup(&root.name, module, name)
+      .ok_or_else(|| CompileError::Manifest { path: dir.join(MANIFEST_FILE).display().to_string(), message: format!("no main function {}.{}", module, name) })?;
 
-pub fn compile_package(name: &str, base_dir: &str) -> Result<BitPackage, SimpleError> {
-  let raw_modules = find_modules(base_dir, name)?;
+    app.main = Some(FunctionRef { package: root.name.clone(), module: module.clone(), name: name.clone(), shape });
+  }
+
+  Ok(app)
+}
+
+/**
+* Depth first, so every manifest comes after the ones it depends on. Visiting holds the chain of packages being loaded.
+*/
+fn load_manifests(dir: &Path, visiting: &mut Vec<String>, loaded: &mut Vec<Manifest>) -> Result<(), CompileError> {
+  let manifest = read_manifest(dir)?;
+
+  if let Some(start) = visiting.iter().position(|prev| *prev == manifest.name) {
+    let mut cycle = visiting[start..].to_vec();
+    cycle.push(manifest.name);
+    return Err(CompileError::CircularDependency { cycle });
+  }
+
+  if loaded.iter().any(|prev| prev.name == manifest.name) {
+    return Ok(());
+  }
+
+  visiting.push(manifest.name.clone());
+
+  for (dep_name, dep_dir) in &manifest.dependencies {
+    let dep = read_manifest(dep_dir)?;
+
+    if dep.name != *dep_name {
+      let path = dep_dir.join(MANIFEST_FILE).display().to_string();
+      return Err(CompileError::Manifest { path, message: format!("expected package {} but found {}", dep_name, dep.name) });
+    }
+
+    load_manifests(dep_dir, visiting, loaded)?;
+  }
+
+  visiting.pop();
+  loaded.push(manifest);
+  Ok(())
+}
+
+fn compile_modules(name: &str, base_dir: &str, optimizer: &Optimizer, shapes: &mut AppShapes, cache: Option<&BuildCache>) -> Result<BitPackage, CompileError> {
+  let mut sources = HashMap::new();
+  let mut parsed = HashMap::new();
+  let mut imports = HashMap::new();
+
+  for (module_name, path) in find_modules(base_dir)? {
+    let source = fs::read(&path).map_err(|err| io_error(&path, err))?;
+
+    let module_imports = match cache.and_then(|cache| cache.imports(name, &module_name, &source)) {
+      Some(cached) => cached,
+      None => {
+        let module = parse(&path, name, &module_name)?;
+        let module_imports = module.imports.iter().map(|imp| (imp.package.clone(), imp.module.clone())).collect();
+        parsed.insert(module_name.clone(), module);
+        module_imports
+      }
+    };
+
+    // Only modules of this package need ordering, anything else is already registered or doesn't exist.
+    let local = module_imports.into_iter()
+      .filter(|(package, _)| package == name)
+      .map(|(_, module)| module)
+      .collect();
+
+    imports.insert(module_name.clone(), local);
+    sources.insert(module_name, (path, source));
+  }
 
   let mut modules = HashMap::new();
 
-  for parsed in raw_modules {
-    let checked = typechecker::check_module(parsed)?;
-    let compiled = compile_ir_module(&checked)?;
-    let bytecode = compile(compiled)?;
+  for module_name in sort_modules(name, &imports)? {
+    let (path, source) = &sources[&module_name];
+
+    let cached = if parsed.contains_key(&module_name) {
+      None
+    } else {
+      cache.and_then(|cache| cache.load(name, &module_name, source, shapes))
+    };
+
+    let compiled = match cached {
+      Some(cached) => {
+        register_module(name, &module_name, &cached.exports, shapes)?;
+        cached.ir
+      }
+      None => {
+        let module = match parsed.remove(&module_name) {
+          Some(module) => module,
+          None => parse(path, name, &module_name)?,
+        };
+
+        let checked = typechecker::check_module(module, shapes)?;
+        let exports = ModuleExports::of(&checked);
+        register_module(name, &module_name, &exports, shapes)?;
+        let compiled = compile_ir_module(&checked, shapes)?;
+
+        if let Some(cache) = cache {
+          cache.store(&checked, source, &exports, &compiled, shapes);
+        }
+
+        compiled
+      }
+    };
+
+    let bytecode = compile(compiled, optimizer)?;
     bytecode.debug();
-    modules.insert(checked.name.clone(), bytecode);
+    modules.insert(module_name, bytecode);
   }
 
   Ok(BitPackage {
@@ -50,30 +290,127 @@ pub fn compile_package(name: &str, base_dir: &str) -> Result<BitPackage, SimpleE
   })
 }
 
-fn find_modules(base: &str, package: &str) -> Result<Vec<AstModule>, SimpleError> {
+/**
+* Orders modules so each comes after everything it imports, breaking ties by name so builds are repeatable.
+*/
+fn sort_modules(package: &str, imports: &HashMap<String, Vec<String>>) -> Result<Vec<String>, CompileError> {
+  let mut names: Vec<&String> = imports.keys().collect();
+  names.sort();
+
+  let mut sorted = Vec::with_capacity(names.len());
+  let mut visiting = Vec::new();
+
+  for name in names {
+    visit_module(package, name, imports, &mut visiting, &mut sorted)?;
+  }
+
+  Ok(sorted)
+}
+
+fn visit_module(package: &str, name: &String, imports: &HashMap<String, Vec<String>>, visiting: &mut Vec<String>, sorted: &mut Vec<String>) -> Result<(), CompileError> {
+  if sorted.contains(name) {
+    return Ok(());
+  }
+
+  if let Some(start) = visiting.iter().position(|prev| prev == name) {
+    let mut cycle = visiting[start..].to_vec();
+    cycle.push(name.clone());
+    return Err(CompileError::CircularImport { package: String::from(package), cycle });
+  }
+
+  let mut deps: Vec<&String> = imports[name].iter().filter(|dep| imports.contains_key(*dep)).collect();
+  deps.sort();
+
+  visiting.push(name.clone());
+
+  for dep in deps {
+    visit_module(package, dep, imports, visiting, sorted)?;
+  }
+
+  visiting.pop();
+  sorted.push(name.clone());
+  Ok(())
+}
+
+fn register_module(package: &str, module: &str, exports: &ModuleExports, shapes: &mut AppShapes) -> Result<(), CompileError> {
+  for (name, shape, visibility) in &exports.functions {
+    shapes.register_with_visibility(package, module, name, shape.clone(), visibility.clone())?;
+  }
+
+  for (name, shape) in &exports.types {
+    shapes.register_type(package, module, name, shape.clone())?;
+  }
+
+  for (name, fields) in &exports.fields {
+    shapes.register_fields(package, module, name, fields.clone())?;
+  }
+
+  Ok(())
+}
+
+/**
+* Compiles a single module held in memory rather than read from a package directory.
+*/
+pub fn compile_source_module(package: &str, module: &str, src: &str, optimizer: &Optimizer, shapes: &AppShapes) -> Result<BitModule, CompileError> {
+  let parsed = parse_str(src, package, module)?;
+  let checked = typechecker::check_module(parsed, shapes)?;
+  let compiled = compile_ir_module(&checked, shapes)?;
+  compile(compiled, optimizer)
+}
+
+/**
+* Compiles a single script file into an application of its own, whose main is the script's top level statements.
+*/
+pub fn compile_script(path: &Path, optimizer: &Optimizer, shapes: &AppShapes) -> Result<BitApplication, CompileError> {
+  let module = path.file_stem()
+    .and_then(|stem| stem.to_str())
+    .ok_or_else(|| io_error(path, "Invalid path"))?;
+
+  let parsed = parse_script(path, SCRIPT_PACKAGE, module)?;
+  let checked = typechecker::check_module(parsed, shapes)?;
+
+  let main = checked.functions.iter()
+    .find(|dec| dec.ex.id == SCRIPT_MAIN)
+    .map(|dec| FunctionRef { package: String::from(SCRIPT_PACKAGE), module: String::from(module), name: dec.ex.id.clone(), shape: dec.ex.shape() });
+
+  let compiled = compile(compile_ir_module(&checked, shapes)?, optimizer)?;
+
+  let mut package = BitPackage::new();
+  package.modules.insert(String::from(module), compiled);
+
+  let mut app = BitApplication::library();
+  app.packages.insert(String::from(SCRIPT_PACKAGE), package);
+  app.main = main;
+
+  Ok(app)
+}
+
+/**
+* Every .let file under base, with the module name its path gives it.
+*/
+fn find_modules(base: &str) -> Result<Vec<(String, PathBuf)>, CompileError> {
   let mut modules = Vec::new();
   let mut dirs = vec![Path::new(base).to_path_buf()];
 
   while !dirs.is_empty() {
     let next_dir = dirs.pop().unwrap();
 
-    for entry in fs::read_dir(next_dir).map_err(|err| SimpleError::from(err))? {
-      let entry = entry.map_err(|err| SimpleError::from(err))?;
+    for entry in fs::read_dir(&next_dir).map_err(|err| io_error(&next_dir, err))? {
+      let entry = entry.map_err(|err| io_error(&next_dir, err))?;
       let path = entry.path();
       if path.is_dir() {
         dirs.push(path.clone())
       } else if path.extension().and_then(|ex| ex.to_str()).filter(|ex| *ex == "let").is_some() {
-        let full_module = path.strip_prefix(base).map_err(|err| SimpleError::from(err))?
+        let full_module = path.strip_prefix(base).map_err(|err| io_error(&path, err))?
           .to_str()
-          .ok_or_else(|| SimpleError::new("Invalid path"))?
+          .ok_or_else(|| io_error(&path, "Invalid path"))?
           .replace("/", ".") // handle both *nix and windows paths
           .replace("\\", ".");
 
         // remove .let at the end
-        let module = &full_module[..full_module.len() - 4];
+        let module = String::from(&full_module[..full_module.len() - 4]);
 
-        let parsed = parse(&path, package, module)?;
-        modules.push(parsed);
+        modules.push((module, path));
       }
     }
   }
@@ -81,18 +418,26 @@ fn find_modules(base: &str, package: &str) -> Result<Vec<AstModule>, SimpleError
   Ok(modules)
 }
 
-pub fn compile(mut module: IrModule) -> Result<BitModule, SimpleError> {
+fn io_error<E: ToString>(path: &Path, err: E) -> CompileError {
+  CompileError::Io { path: path.display().to_string(), message: err.to_string() }
+}
+
+pub fn compile(mut module: IrModule, optimizer: &Optimizer) -> Result<BitModule, CompileError> {
   let mut context = ModuleContext::new();
-  let optimizer = Optimizer::new();
-  let mut functions = HashMap::<String, RunFunction>::new();
+  let mut functions = BTreeMap::<String, RunFunction>::new();
+
+  optimizer.optimize_module(&mut module);
 
-  for (name, mut raw_func) in module.functions {
-    optimizer.optimize(&mut raw_func);
+  for (name, raw_func) in module.functions {
 //    raw_func.debug();
 
     let mut func_context = FuncContext::new(&raw_func.args);
 
-    let body = compile_block(&mut context, &mut func_context, &raw_func.body);
+    let mut body = compile_block(&mut context, &mut func_context, &raw_func.body);
+
+    if optimizer.peephole() {
+      body = peephole_opt(body);
+    }
 
     functions.insert(name.clone(), BitFunction {
       func_ref: FunctionRef {
@@ -100,10 +445,11 @@ pub fn compile(mut module: IrModule) -> Result<BitModule, SimpleError> {
         module: module.name.clone(),
         name: name.clone(),
 
-        shape: raw_func.shape.clone(),
+        shape: raw_func.func_ref.shape.clone(),
       },
 
       max_locals: func_context.max_locals,
+      max_stack: max_stack(&body, &context.function_refs.values),
       body,
       source: Vec::new(),
     }.wrap());
@@ -112,9 +458,9 @@ pub fn compile(mut module: IrModule) -> Result<BitModule, SimpleError> {
   let ModuleContext{function_refs, shape_refs, string_constants} = context;
 
   Ok(BitModule {
-    string_constants,
-    function_refs,
-    shape_refs,
+    string_constants: string_constants.values.into_iter().map(Rc::new).collect(),
+    function_refs: function_refs.values,
+    shape_refs: shape_refs.values,
     functions,
   })
 }
@@ -128,7 +474,7 @@ fn compile_block(context: &mut ModuleContext, func: &mut FuncContext, block: &Ve
       Ir::Duplicate => body.push(Instruction::Duplicate),
       Ir::Pop => body.push(Instruction::Pop),
       Ir::Swap => body.push(Instruction::Swap),
-      Ir::LoadConstNull => body.push(Instruction::LoadConstNull),
+      Ir::LoadConstUnit => body.push(Instruction::LoadConstUnit),
       Ir::LoadConstTrue => body.push(Instruction::LoadConstTrue),
       Ir::LoadConstFalse => body.push(Instruction::LoadConstFalse),
       Ir::LoadConstString { value } => body.push(Instruction::LoadConstString{const_id: context.lookup_string_constant(value)}),
@@ -138,8 +484,26 @@ fn compile_block(context: &mut ModuleContext, func: &mut FuncContext, block: &Ve
       Ir::StoreValue { local } => body.push(Instruction::StoreValue {local: func.lookup_local(local)}),
       Ir::CallStatic { func } => body.push(Instruction::CallStatic {func_id: context.lookup_function_ref(func) }),
       Ir::CallDynamic { param_count } => body.push(Instruction::CallDynamic {param_count: *param_count}),
+      Ir::AddFloat => body.push(Instruction::AddFloat),
+      Ir::SubFloat => body.push(Instruction::SubFloat),
+      Ir::MulFloat => body.push(Instruction::MulFloat),
+      Ir::DivFloat => body.push(Instruction::DivFloat),
+      Ir::LessFloat => body.push(Instruction::LessFloat),
+      Ir::LessEqualFloat => body.push(Instruction::LessEqualFloat),
+      Ir::GreaterFloat => body.push(Instruction::GreaterFloat),
+      Ir::GreaterEqualFloat => body.push(Instruction::GreaterEqualFloat),
       Ir::BuildClosure { param_count, func } => body.push(Instruction::BuildClosure {param_count: *param_count, func_id: context.lookup_function_ref(func) }),
       Ir::BuildRecursiveFunction => body.push(Instruction::BuildRecursiveFunction),
+      Ir::BuildTuple { size } => body.push(Instruction::BuildTuple {size: *size}),
+      Ir::LoadElement { index } => body.push(Instruction::LoadElement {index: *index}),
+      Ir::BuildVariant { tag, size } => body.push(Instruction::BuildVariant {const_id: context.lookup_string_constant(tag), size: *size}),
+      Ir::IsVariant { tag } => body.push(Instruction::IsVariant {const_id: context.lookup_string_constant(tag)}),
+      Ir::LoadVariantValue { index } => body.push(Instruction::LoadVariantValue {index: *index}),
+      Ir::BuildList { count } => body.push(Instruction::BuildList {count: *count}),
+      Ir::ListGet => body.push(Instruction::ListGet),
+      Ir::NewCell => body.push(Instruction::NewCell),
+      Ir::LoadCell => body.push(Instruction::LoadCell),
+      Ir::StoreCell => body.push(Instruction::StoreCell),
       Ir::Return => body.push(Instruction::Return),
       Ir::Branch{then_block, else_block} => {
 
@@ -158,8 +522,53 @@ fn compile_block(context: &mut ModuleContext, func: &mut FuncContext, block: &Ve
         body.append(&mut then_body);
         body.append(&mut else_body);
       },
+      Ir::Cond{arms, else_block} => {
+        // Compile in source order so locals are freed in the right order, then build from the last arm back so
+        // each arm knows how far its Jump has to go to get past every arm after it.
+        let compiled: Vec<(Vec<Instruction>, Vec<Instruction>)> = arms.iter()
+          .map(|(condition, then_block)| (compile_block(context, func, condition), compile_block(context, func, then_block)))
+          .collect();
+
+        let mut rest = compile_block(context, func, else_block);
+
+        for (mut condition_body, mut then_body) in compiled.into_iter().rev() {
+          if !rest.is_empty() {
+            if let Some(Instruction::Return) = then_body.last() {
+
+            } else {
+              then_body.push(Instruction::Jump { jump: rest.len() as i32 });
+            }
+          }
+
+          condition_body.push(Instruction::Branch {jump: then_body.len() as i32});
+          condition_body.append(&mut then_body);
+          condition_body.append(&mut rest);
+          rest = condition_body;
+        }
+
+        body.append(&mut rest);
+      },
+      Ir::Loop{condition, body: loop_block} => {
+        let mut condition_body = compile_block(context, func, condition);
+        let mut loop_body = compile_block(context, func, loop_block);
+
+        // Jump back over the body, the Branch and the condition to re-test it.
+        let back = condition_body.len() + loop_body.len() + 2;
+
+        body.append(&mut condition_body);
+        body.push(Instruction::Branch {jump: (loop_body.len() + 1) as i32});
+        body.append(&mut loop_body);
+        body.push(Instruction::Jump {jump: -(back as i32)});
+      },
       Ir::Debug => body.push(Instruction::Debug),
-      Ir::Error => body.push(Instruction::Error),
+      Ir::Error { message } => body.push(Instruction::Error {const_id: context.lookup_string_constant(message)}),
+      Ir::Panic { message } => body.push(Instruction::Panic {const_id: context.lookup_string_constant(message)}),
+      Ir::Assert { condition, message, point } => {
+        let mut condition_body = compile_block(context, func, condition);
+
+        body.append(&mut condition_body);
+        body.push(Instruction::Assert {const_id: context.lookup_string_constant(message), point: *point});
+      },
       Ir::FreeLocal {local} => func.free(local),
     }
   }
@@ -167,39 +576,202 @@ fn compile_block(context: &mut ModuleContext, func: &mut FuncContext, block: &Ve
   body
 }
 
+/**
+* Fuses common runs of instructions into one, so hot loops spend less time in dispatch:
+*
+* LoadValue(a), LoadValue(b), AddFloat becomes AddLocals(a, b)
+* LoadConstFloat(x), StoreValue(a) becomes StoreConstFloat(x, a)
+*
+* A run is never fused if anything jumps into the middle of it. Afterwards every jump is moved to the new index of
+* its target, and a Branch or Jump that lands on a Jump goes straight to where that one goes.
+*/
+pub fn peephole_opt(body: Vec<Instruction>) -> Vec<Instruction> {
+  let targets: Vec<Option<usize>> = body.iter().enumerate().map(|(index, instruction)| jump_target(instruction, index)).collect();
+  let unconditional: Vec<bool> = body.iter().map(|instruction| if let Instruction::Jump { .. } = instruction { true } else { false }).collect();
+  let is_target = |index: usize| targets.contains(&Some(index));
+
+  let old_len = body.len();
+  let mut old: Vec<Option<Instruction>> = body.into_iter().map(Some).collect();
+  let mut fused: Vec<(Instruction, Option<usize>)> = Vec::with_capacity(old_len);
+  // Where each old instruction ended up, with one extra for the end of the function.
+  let mut new_index = Vec::with_capacity(old_len + 1);
+  let mut index = 0;
+
+  while index < old_len {
+    let superinstruction = match (&old[index], old.get(index + 1), old.get(index + 2)) {
+      (Some(Instruction::LoadValue { local: left }), Some(Some(Instruction::LoadValue { local: right })), Some(Some(Instruction::AddFloat)))
+        if !is_target(index + 1) && !is_target(index + 2) => Some((Instruction::AddLocals { left: *left, right: *right }, 3)),
+      (Some(Instruction::LoadConstFloat { value }), Some(Some(Instruction::StoreValue { local })), _)
+        if !is_target(index + 1) => Some((Instruction::StoreConstFloat { value: *value, local: *local }, 2)),
+      _ => None,
+    };
+
+    let (instruction, size) = superinstruction.unwrap_or_else(|| (old[index].take().unwrap(), 1));
+
+    for _ in 0..size {
+      new_index.push(fused.len());
+    }
+
+    fused.push((instruction, targets[index]));
+    index += size;
+  }
+
+  new_index.push(fused.len());
+
+  // Follow a chain of Jumps to where it finally lands, giving up if it loops back on itself.
+  let resolve = |start: usize| {
+    let mut target = start;
+
+    for _ in 0..old_len {
+      match targets.get(target) {
+        Some(Some(next)) if unconditional[target] => target = *next,
+        _ => break,
+      }
+    }
+
+    new_index[target]
+  };
+
+  fused.into_iter()
+    .enumerate()
+    .map(|(index, (instruction, target))| {
+      let jump = |target: usize| resolve(target) as i32 - index as i32 - 1;
+
+      match (instruction, target) {
+        (Instruction::Branch { .. }, Some(target)) => Instruction::Branch { jump: jump(target) },
+        (Instruction::Jump { .. }, Some(target)) => Instruction::Jump { jump: jump(target) },
+        (instruction, _) => instruction,
+      }
+    })
+    .collect()
+}
+
+/**
+* How deep the operand stack can get running body. Code is laid out so every instruction is reached by falling
+* through or by a forward Branch or Jump, loops only jump backwards to a depth already seen, so one pass in order
+* is enough.
+*/
+fn max_stack(body: &[Instruction], function_refs: &[FunctionRef]) -> LocalId {
+  let mut depth_at: HashMap<usize, usize> = HashMap::new();
+  let mut depth = 0usize;
+  let mut max_depth = 0usize;
+  let mut reachable = true;
+
+  for (index, instruction) in body.iter().enumerate() {
+    if let Some(jumped) = depth_at.get(&index) {
+      depth = if reachable { max(depth, *jumped) } else { *jumped };
+      reachable = true;
+    }
+
+    if !reachable {
+      continue;
+    }
+
+    let (pops, pushes) = match instruction {
+      Instruction::NoOp | Instruction::Swap | Instruction::BuildRecursiveFunction | Instruction::Debug => (0, 0),
+      Instruction::LoadElement { .. } | Instruction::IsVariant { .. } | Instruction::LoadVariantValue { .. } => (1, 1),
+      Instruction::NewCell | Instruction::LoadCell => (1, 1),
+      Instruction::Duplicate => (1, 2),
+      Instruction::Pop | Instruction::StoreValue { .. } | Instruction::Branch { .. } | Instruction::Assert { .. } => (1, 0),
+      Instruction::LoadConstUnit | Instruction::LoadConstTrue | Instruction::LoadConstFalse | Instruction::LoadConstString { .. }
+        | Instruction::LoadConstFunction { .. } | Instruction::LoadConstFloat { .. } | Instruction::LoadValue { .. }
+        | Instruction::AddLocals { .. } => (0, 1),
+      Instruction::StoreConstFloat { .. } | Instruction::Jump { .. } | Instruction::Error { .. } | Instruction::Panic { .. } => (0, 0),
+      Instruction::CallStatic { func_id } => {
+        let args = match function_refs.get(*func_id as usize).map(|func| &func.shape) {
+          Some(Shape::SimpleFunctionShape { args, .. }) => args.len(),
+          _ => 0,
+        };
+
+        (args, 1)
+      }
+      Instruction::CallDynamic { param_count } => (*param_count as usize + 1, 1),
+      Instruction::BuildClosure { param_count, .. } => (*param_count as usize, 1),
+      Instruction::BuildTuple { size } | Instruction::BuildVariant { size, .. } => (*size as usize, 1),
+      Instruction::BuildList { count } => (*count as usize, 1),
+      Instruction::ListGet | Instruction::AddFloat | Instruction::SubFloat | Instruction::MulFloat | Instruction::DivFloat
+        | Instruction::LessFloat | Instruction::LessEqualFloat | Instruction::GreaterFloat | Instruction::GreaterEqualFloat => (2, 1),
+      Instruction::StoreCell => (2, 0),
+      Instruction::Return => (1, 0),
+    };
+
+    depth = depth.saturating_sub(pops) + pushes;
+    max_depth = max(max_depth, depth);
+
+    if let Some(target) = jump_target(instruction, index) {
+      let jumped = depth_at.entry(target).or_insert(depth);
+      *jumped = max(*jumped, depth);
+    }
+
+    match instruction {
+      Instruction::Jump { .. } | Instruction::Return | Instruction::Error { .. } | Instruction::Panic { .. } => reachable = false,
+      _ => {}
+    }
+  }
+
+  max_depth as LocalId
+}
+
+fn jump_target(instruction: &Instruction, index: usize) -> Option<usize> {
+  match instruction {
+    Instruction::Branch { jump } | Instruction::Jump { jump } => Some((index as i64 + 1 + *jump as i64) as usize),
+    _ => None,
+  }
+}
+
 struct ModuleContext {
-  function_refs: Vec<FunctionRef>,
-  shape_refs: Vec<Shape>,
-  string_constants: Vec<String>,
+  function_refs: ConstantPool<FunctionRef>,
+  shape_refs: ConstantPool<Shape>,
+  string_constants: ConstantPool<String>,
 }
 
 impl ModuleContext {
   fn new() -> ModuleContext {
     ModuleContext {
-      function_refs: Vec::new(),
-      shape_refs: Vec::new(),
-      string_constants: Vec::new(),
+      function_refs: ConstantPool::new(),
+      shape_refs: ConstantPool::new(),
+      string_constants: ConstantPool::new(),
     }
   }
 
   fn lookup_function_ref(&mut self, func: &FunctionRef) -> ConstantId {
-    ModuleContext::lookup(&mut self.function_refs, func) as ConstantId
+    self.function_refs.lookup(func)
   }
 
   fn lookup_string_constant(&mut self, s: &String) -> ConstantId {
-    ModuleContext::lookup(&mut self.string_constants, s) as ConstantId
+    self.string_constants.lookup(s)
   }
 
   fn lookup_shape(&mut self, shape: &Shape) -> ConstantId {
-    ModuleContext::lookup(&mut self.shape_refs, shape) as ConstantId
+    self.shape_refs.lookup(shape)
   }
+}
+
+/**
+* Constants in the order they were first used, each stored once, with an index to find a constant's id again.
+*/
+struct ConstantPool<T: Eq + Hash + Clone> {
+  values: Vec<T>,
+  ids: HashMap<T, ConstantId>,
+}
+
+impl<T: Eq + Hash + Clone> ConstantPool<T> {
+  fn new() -> ConstantPool<T> {
+    ConstantPool {
+      values: Vec::new(),
+      ids: HashMap::new(),
+    }
+  }
+
+  fn lookup(&mut self, next: &T) -> ConstantId {
+    if let Some(id) = self.ids.get(next) {
+      return *id;
+    }
 
-  fn lookup<T: Eq + Clone>(col: &mut Vec<T>, next: &T) -> usize {
-    col.iter().position(|other| *other == *next)
-      .or_else(move || {
-        col.push(next.clone());
-        Some(col.len() - 1)
-      }).unwrap()
+    let id = self.values.len() as ConstantId;
+    self.values.push(next.clone());
+    self.ids.insert(next.clone(), id);
+    id
   }
 }
 
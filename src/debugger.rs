@@ -0,0 +1,179 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+
+use bytecode::{BitModule, FunctionRef, Instruction};
+use interpreter::RunFunction;
+use runtime::Value;
+use shapes::shape_unknown;
+
+/**
+* What the Machine should do after the debugger returns from a pause.
+*/
+pub enum DebugAction {
+  /** Pause again before the next instruction, following calls into other functions. */
+  Step,
+  /** Run until the next breakpoint. */
+  Continue,
+  /** Stop the program, the Machine returns RuntimeError::Aborted. */
+  Abort,
+}
+
+/**
+* State of the function the Machine is paused in, just before it runs instruction.
+*/
+pub struct Frame<'a> {
+  pub func: &'a FunctionRef,
+  pub module: &'a BitModule,
+  pub index: usize,
+  pub instruction: &'a Instruction,
+  pub stack: &'a Vec<Value>,
+  pub locals: &'a Vec<Value>,
+}
+
+/**
+* Implemented by callers that want to drive execution. The Machine calls pause at every breakpoint, and at every
+* instruction while stepping. Breakpoints can be changed while paused.
+*/
+pub trait Debugger {
+  fn pause(&mut self, frame: &Frame, breakpoints: &mut Breakpoints) -> DebugAction;
+}
+
+/**
+* Instruction indexes to pause at, per function. Functions compare by name only so a FunctionRef with any shape works.
+*/
+pub struct Breakpoints {
+  points: HashMap<FunctionRef, HashSet<usize>>,
+}
+
+impl Breakpoints {
+
+  pub fn new() -> Breakpoints {
+    Breakpoints { points: HashMap::new() }
+  }
+
+  pub fn add(&mut self, func: FunctionRef, index: usize) {
+    self.points.entry(func).or_insert_with(HashSet::new).insert(index);
+  }
+
+  /**
+  * Returns false if there was no such breakpoint.
+  */
+  pub fn remove(&mut self, func: &FunctionRef, index: usize) -> bool {
+    self.points.get_mut(func).map(|points| points.remove(&index)).unwrap_or(false)
+  }
+
+  pub fn contains(&self, func: &FunctionRef, index: usize) -> bool {
+    self.points.get(func).map(|points| points.contains(&index)).unwrap_or(false)
+  }
+
+  pub fn list(&self) -> Vec<(FunctionRef, usize)> {
+    let mut all: Vec<(FunctionRef, usize)> = self.points.iter()
+      .flat_map(|(func, points)| points.iter().map(move |index| (func.clone(), *index)))
+      .collect();
+
+    all.sort_by(|left, right| (left.0.pretty(), left.1).cmp(&(right.0.pretty(), right.1)));
+    all
+  }
+}
+
+/**
+* Line based debugger on stdin and stdout, used by the debug subcommand.
+*/
+pub struct ConsoleDebugger;
+
+const CONSOLE_HELP: &'static str = "Commands:
+  s, step                   run one instruction
+  c, continue               run to the next breakpoint
+  b, break <function> <n>   pause before instruction n of package::Module.function
+  d, delete <function> <n>  remove a breakpoint
+  l, list                   show breakpoints
+  stack                     show the operand stack
+  locals                    show local variables
+  code                      disassemble the current function
+  q, quit                   stop the program
+";
+
+impl Debugger for ConsoleDebugger {
+
+  fn pause(&mut self, frame: &Frame, breakpoints: &mut Breakpoints) -> DebugAction {
+    let instruction = frame.instruction.pretty(frame.module).unwrap_or_else(|err| err.to_string());
+    println!("{} {}: {}", frame.func.pretty(), frame.index, instruction);
+
+    let stdin = io::stdin();
+
+    loop {
+      print!("(debug) ");
+      if io::stdout().flush().is_err() {
+        return DebugAction::Abort;
+      }
+
+      let mut line = String::new();
+
+      match stdin.lock().read_line(&mut line) {
+        Ok(0) | Err(_) => return DebugAction::Abort,
+        Ok(_) => {}
+      }
+
+      let words: Vec<&str> = line.split_whitespace().collect();
+
+      match words.as_slice() {
+        [] | ["s"] | ["step"] => return DebugAction::Step,
+        ["c"] | ["continue"] => return DebugAction::Continue,
+        ["q"] | ["quit"] => return DebugAction::Abort,
+        ["b", func, index] | ["break", func, index] => match parse_breakpoint(func, index) {
+          Some((func, index)) => breakpoints.add(func, index),
+          None => println!("Expected package::Module.function and an instruction index"),
+        }
+        ["d", func, index] | ["delete", func, index] => match parse_breakpoint(func, index) {
+          Some((func, index)) => if !breakpoints.remove(&func, index) {
+            println!("No breakpoint at {} {}", func.pretty(), index);
+          }
+          None => println!("Expected package::Module.function and an instruction index"),
+        }
+        ["l"] | ["list"] => {
+          for (func, index) in breakpoints.list() {
+            println!("  {} {}", func.pretty(), index);
+          }
+        }
+        ["stack"] => {
+          for (index, value) in frame.stack.iter().enumerate() {
+            println!("  {}: {:?}", index, value);
+          }
+        }
+        ["locals"] => {
+          for (index, value) in frame.locals.iter().enumerate() {
+            println!("  {}: {:?}", index, value);
+          }
+        }
+        ["code"] => {
+          let func = frame.module.functions.get(&frame.func.name);
+
+          if let Some(RunFunction::BitFunction(func)) = func {
+            if let Err(err) = func.disassemble(frame.module, &mut io::stdout()) {
+              println!("Error: {}", err);
+            }
+          }
+        }
+        _ => print!("{}", CONSOLE_HELP),
+      }
+    }
+  }
+}
+
+/**
+* Reads package::Module.function and an index. The shape is left unknown, breakpoints don't look at it.
+*/
+fn parse_breakpoint(func: &str, index: &str) -> Option<(FunctionRef, usize)> {
+  let index = index.parse::<usize>().ok()?;
+  let split = func.find("::")?;
+  let (package, rest) = (&func[..split], &func[split + 2..]);
+  let dot = rest.rfind('.')?;
+
+  Some((FunctionRef {
+    package: String::from(package),
+    module: String::from(&rest[..dot]),
+    name: String::from(&rest[dot + 1..]),
+
+    shape: shape_unknown(),
+  }, index))
+}
@@ -0,0 +1,458 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use ast::Location;
+use bytecode::{FunctionRef, SourcePoint};
+use shapes::Shape;
+
+/**
+* Failure to read source text.
+*/
+#[derive(Debug)]
+pub enum LexError {
+  Io { src: String, message: String },
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+  Lex(LexError),
+  UnexpectedToken { loc: Location, found: String, expected: String },
+  UnexpectedEof { loc: Location },
+  InvalidNumber { loc: Location, value: String },
+  ReservedWord { loc: Location, word: String },
+  NumberTooBig { loc: Location, value: String },
+}
+
+#[derive(Debug)]
+pub enum TypeError {
+  NoSuchModule { loc: Box<Location>, package: String, module: String },
+  UnknownType { loc: Box<Location>, name: String },
+  CyclicAlias { loc: Box<Location>, cycle: Vec<String> },
+  TypeRedeclaration { loc: Box<Location>, name: String },
+  /** A data type given the wrong number of type arguments, Box[Float, Float] for data Box[T]. */
+  TypeArgumentCount { loc: Box<Location>, name: String, expected: usize, found: usize },
+  Redeclaration { loc: Box<Location>, id: String },
+  /** A let hiding a binding that's still visible, only an error when shadowing is denied. */
+  Shadowed { loc: Box<Location>, id: String, original: Location },
+  /** A statement before the end of a block whose value is thrown away, only ever a warning. */
+  DiscardedValue { loc: Box<Location>, shape: Shape },
+  UndeclaredVariable { loc: Box<Location>, id: String },
+  /** The imported module has a function by this name, but doesn't export it. */
+  PrivateFunction { loc: Box<Location>, id: String },
+  /** Assignment to a variable that wasn't declared with let mut. */
+  Immutable { loc: Box<Location>, id: String },
+  UnknownShape { loc: Box<Location> },
+  Mismatch { loc: Box<Location>, declared: Shape, found: Shape },
+  InvalidOperands { loc: Box<Location>, op: String, left: Shape, right: Shape },
+  /** func is what's called as the call site names it, shape is its shape. */
+  ArgumentCount { loc: Box<Location>, func: String, shape: Shape, expected: usize, found: usize },
+  /** The argument at index, counted from 0, doesn't fit the parameter it's passed to. */
+  ArgumentMismatch { loc: Box<Location>, func: String, index: usize, expected: Shape, found: Shape },
+  NotAFunction { loc: Box<Location>, func: String, shape: Shape },
+  NotATuple { loc: Box<Location>, shape: Shape },
+  NotAList { loc: Box<Location>, shape: Shape },
+  /** value.name(args) where the module owning value's shape has no function name, or isn't imported. */
+  NoSuchMethod { loc: Box<Location>, shape: Shape, name: String },
+  NoSuchField { loc: Box<Location>, shape: Shape, field: String },
+  /** A function whose result was left out and can't be worked out from its body. */
+  AmbiguousResult { loc: Box<Location>, id: String },
+  /** An operator declared with other than the two operands it's applied to. */
+  OperatorArity { loc: Box<Location>, op: String, found: usize },
+  /** An operator declared for builtin shapes only, which would change what it does on plain values. */
+  PrimitiveOperator { loc: Box<Location>, op: String, left: Shape, right: Shape },
+  /** Named arguments passed to a function without a known parameter list, like a lambda or an import. */
+  NamedArguments { loc: Box<Location>, func: String },
+  UnknownArgument { loc: Box<Location>, func: String, id: String },
+  DuplicateArgument { loc: Box<Location>, func: String, id: String },
+  /** A parameter without a default that the call left out. */
+  MissingArgument { loc: Box<Location>, func: String, id: String },
+  PatternArity { loc: Box<Location>, shape: Shape, expected: usize, found: usize },
+  /** The ? operator on something that isn't an Option or Result, or in a function that doesn't return the same kind. */
+  InvalidTry { loc: Box<Location>, found: Shape, result: Shape },
+  /** A map key of a shape that can't be hashed, like a function. */
+  NotHashable { loc: Box<Location>, shape: Shape },
+  /** A function or type registered twice into AppShapes. */
+  AlreadyDeclared { name: String },
+  /** Compiled modules have a fixed set of functions and can't be registered into. */
+  ReadOnlyModule { package: String, module: String, name: String },
+  Internal { message: String },
+}
+
+#[derive(Debug)]
+pub enum CompileError {
+  Io { path: String, message: String },
+  Parse(ParseError),
+  /** Every syntax error found in a module. */
+  ParseErrors(Vec<ParseError>),
+  Type(TypeError),
+  /** Every error the typechecker found in a module. */
+  TypeErrors(Vec<TypeError>),
+  NoSuchModule { package: String, module: String },
+  /** Modules that import each other in a loop, the first module is repeated at the end. */
+  CircularImport { package: String, cycle: Vec<String> },
+  /** Packages whose manifests depend on each other in a loop, the first package is repeated at the end. */
+  CircularDependency { cycle: Vec<String> },
+  Manifest { path: String, message: String },
+  NotAFunction { loc: Location },
+  MissingOperator { loc: Location, op: String },
+  UnknownLocal { loc: Location, name: String },
+  /** A local read on some path where nothing was ever stored in it. */
+  Uninitialized { func: Box<FunctionRef>, local: String },
+}
+
+#[derive(Debug)]
+pub enum RuntimeError {
+  Io { message: String },
+  NoMain,
+  /** A main that takes something other than nothing or the args as a List[String]. */
+  InvalidMain { func: Box<FunctionRef> },
+  NoSuchFunction { func: Box<FunctionRef> },
+  SignatureMismatch { func: Box<FunctionRef>, expected: Shape, found: Shape },
+  /** Every ref the linker couldn't resolve, or that resolved to a function with a different signature. */
+  LinkErrors(Vec<RuntimeError>),
+  NoSuchName { package: String, module: String, name: String },
+  AlreadyDeclared { package: String, module: String, name: String },
+  ArgumentCount { func: Box<FunctionRef>, expected: usize, found: usize },
+  InvalidBytecode { func: Box<FunctionRef>, message: String },
+  InvalidConstant { kind: &'static str, id: usize },
+  /** Raised by native functions, usually for bad arguments. */
+  Native { message: String },
+  /** The program ran an Error instruction, such as one left by a `???` hole. */
+  Raised { func: Box<FunctionRef>, message: String },
+  /** The program ran a panic. The only error Core.tryCall turns into a value. */
+  Panic { func: Box<FunctionRef>, message: String },
+  /** An assert statement's condition was false. */
+  AssertionFailed { func: Box<FunctionRef>, point: SourcePoint, condition: String },
+  /** A call into a module the Machine's SecurityConfig has turned off. */
+  Disabled { func: Box<FunctionRef>, capability: &'static str },
+  /** A Debugger asked the Machine to stop. */
+  Aborted,
+  /** The program ran past one of the Machine's ExecutionLimits. */
+  LimitExceeded { func: Box<FunctionRef>, limit: Limit },
+  /**
+  * Calls nested deeper than the Machine's max_call_depth. The chain runs from the innermost call outwards, with
+  * repeated calls to the same function collapsed into a count. Only the first few functions are kept.
+  */
+  StackOverflow { limit: usize, chain: Vec<(FunctionRef, usize)> },
+  /** Running the bytecode after a serialize and deserialize gave a different result than before. */
+  RoundTripMismatch { func: Box<FunctionRef>, original: String, restored: String },
+}
+
+/**
+* Which of the ExecutionLimits was hit, along with its configured value.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Limit {
+  Instructions(u64),
+  Timeout(Duration),
+}
+
+/**
+* Any error the toolchain can produce, for callers like the repl that both compile and run.
+*/
+#[derive(Debug)]
+pub enum LetError {
+  Compile(CompileError),
+  Runtime(RuntimeError),
+}
+
+impl RuntimeError {
+  pub fn native<S: Into<String>>(message: S) -> RuntimeError {
+    RuntimeError::Native { message: message.into() }
+  }
+
+  pub fn invalid_bytecode(func: &FunctionRef, message: &str) -> RuntimeError {
+    RuntimeError::InvalidBytecode { func: Box::new(func.clone()), message: String::from(message) }
+  }
+}
+
+impl ParseError {
+  pub fn loc(&self) -> Option<&Location> {
+    match self {
+      ParseError::Lex(_) => None,
+      ParseError::UnexpectedToken { loc, .. } | ParseError::UnexpectedEof { loc } | ParseError::InvalidNumber { loc, .. }
+        | ParseError::ReservedWord { loc, .. } | ParseError::NumberTooBig { loc, .. } => Some(loc),
+    }
+  }
+}
+
+impl TypeError {
+  /**
+  * Where in the source the error is, for every error that comes from checking a particular expression.
+  */
+  pub fn loc(&self) -> Option<&Location> {
+    match self {
+      TypeError::NoSuchModule { loc, .. } |
+      TypeError::UnknownType { loc, .. } |
+      TypeError::CyclicAlias { loc, .. } |
+      TypeError::TypeRedeclaration { loc, .. } |
+      TypeError::TypeArgumentCount { loc, .. } |
+      TypeError::Redeclaration { loc, .. } |
+      TypeError::Shadowed { loc, .. } |
+      TypeError::DiscardedValue { loc, .. } |
+      TypeError::UndeclaredVariable { loc, .. } |
+      TypeError::PrivateFunction { loc, .. } |
+      TypeError::Immutable { loc, .. } |
+      TypeError::UnknownShape { loc, .. } |
+      TypeError::Mismatch { loc, .. } |
+      TypeError::InvalidOperands { loc, .. } |
+      TypeError::ArgumentCount { loc, .. } |
+      TypeError::ArgumentMismatch { loc, .. } |
+      TypeError::NotAFunction { loc, .. } |
+      TypeError::NotATuple { loc, .. } |
+      TypeError::NotAList { loc, .. } |
+      TypeError::NoSuchMethod { loc, .. } |
+      TypeError::NoSuchField { loc, .. } |
+      TypeError::AmbiguousResult { loc, .. } |
+      TypeError::OperatorArity { loc, .. } |
+      TypeError::PrimitiveOperator { loc, .. } |
+      TypeError::NamedArguments { loc, .. } |
+      TypeError::UnknownArgument { loc, .. } |
+      TypeError::DuplicateArgument { loc, .. } |
+      TypeError::MissingArgument { loc, .. } |
+      TypeError::PatternArity { loc, .. } |
+      TypeError::InvalidTry { loc, .. } |
+      TypeError::NotHashable { loc, .. } => Some(loc),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for LexError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LexError::Io { src, message } => write!(f, "Could not read {}: {}", src, message),
+    }
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ParseError::Lex(err) => err.fmt(f),
+      ParseError::UnexpectedToken { loc, found, expected } => write!(f, "Unexpected '{}' found {}. Expected: {}", found, loc.pretty(), expected),
+      ParseError::UnexpectedEof { loc } => write!(f, "Unexpected <EOF> {}", loc.pretty()),
+      ParseError::InvalidNumber { loc, value } => write!(f, "Invalid float literal '{}' {}", value, loc.pretty()),
+      ParseError::NumberTooBig { loc, value } => write!(f, "Integer literal '{}' is bigger than 2^53 so can't be an exact Float {}", value, loc.pretty()),
+      ParseError::ReservedWord { loc, word } => write!(f, "'{}' is a reserved word and can't be used as a name {}", word, loc.pretty()),
+    }
+  }
+}
+
+impl fmt::Display for TypeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      TypeError::NoSuchModule { loc, package, module } => write!(f, "No such module {}::{} {}", package, module, loc.pretty()),
+      TypeError::UnknownType { loc, name } => write!(f, "Could not find type: {}, {}", name, loc.pretty()),
+      TypeError::CyclicAlias { loc, cycle } => write!(f, "Cyclic type alias: {} {}", cycle.join(" -> "), loc.pretty()),
+      TypeError::TypeRedeclaration { loc, name } => write!(f, "Redeclaration of type: {} {}", name, loc.pretty()),
+      TypeError::TypeArgumentCount { loc, name, expected, found } => write!(f, "Type {} expects {} type arguments but found {} {}", name, expected, found, loc.pretty()),
+      TypeError::Redeclaration { loc, id } => write!(f, "Redeclaration of variable: {} {}", id, loc.pretty()),
+      TypeError::Shadowed { loc, id, original } => write!(f, "Variable {} shadows the one declared {} {}", id, original.pretty(), loc.pretty()),
+      TypeError::DiscardedValue { loc, shape } => write!(f, "Value of shape {} is never used {}", shape.pretty(), loc.pretty()),
+      TypeError::UndeclaredVariable { loc, id } => write!(f, "Undeclared variable: {} {}", id, loc.pretty()),
+      TypeError::PrivateFunction { loc, id } => write!(f, "Function {} is private {}", id, loc.pretty()),
+      TypeError::Immutable { loc, id } => write!(f, "Cannot assign to {}, it was not declared with let mut {}", id, loc.pretty()),
+      TypeError::UnknownShape { loc } => write!(f, "Unknown shape {}", loc.pretty()),
+      TypeError::Mismatch { loc, declared, found } => write!(f, "Incompatible types! Declared: {}, but found: {} {}", declared.pretty(), found.pretty(), loc.pretty()),
+      TypeError::InvalidOperands { loc, op, left, right } => write!(f, "Incompatible types! Cannot perform operation '{}' on distinct types '{}' and '{}' {}", op, left.pretty(), right.pretty(), loc.pretty()),
+      TypeError::ArgumentCount { loc, func, shape, expected, found } => write!(f, "Incorrect number of arguments for call to {}: {}, expected {} but found {} {}", func, shape.pretty(), expected, found, loc.pretty()),
+      TypeError::ArgumentMismatch { loc, func, index, expected, found } => write!(f, "Invalid argument types for call to {}, argument {} expected {} but found {} {}", func, index + 1, expected.pretty(), found.pretty(), loc.pretty()),
+      TypeError::NotAFunction { loc, func, shape } => write!(f, "Attempt to call {}, a non-function of type {} {}", func, shape.pretty(), loc.pretty()),
+      TypeError::NotATuple { loc, shape } => write!(f, "Cannot destructure non tuple type {} {}", shape.pretty(), loc.pretty()),
+      TypeError::NotAList { loc, shape } => write!(f, "Cannot index into non list type {} {}", shape.pretty(), loc.pretty()),
+      TypeError::NoSuchMethod { loc, shape, name } => write!(f, "No function {} for type {}, is its module imported? {}", name, shape.pretty(), loc.pretty()),
+      TypeError::NoSuchField { loc, shape, field } => write!(f, "No field {} on type {} {}", field, shape.pretty(), loc.pretty()),
+      TypeError::AmbiguousResult { loc, id } => write!(f, "Can't infer what {} returns, it only returns the result of calling itself. Give it a result type {}", id, loc.pretty()),
+      TypeError::OperatorArity { loc, op, found } => write!(f, "Operator {} must take exactly two arguments but takes {} {}", op, found, loc.pretty()),
+      TypeError::PrimitiveOperator { loc, op, left, right } => write!(f, "Operator {} can't be declared for builtin shapes {} and {}, one of them must be a declared type {}", op, left.pretty(), right.pretty(), loc.pretty()),
+      TypeError::NamedArguments { loc, func } => write!(f, "Named arguments can only be passed to functions declared in this module, not to {} {}", func, loc.pretty()),
+      TypeError::UnknownArgument { loc, func, id } => write!(f, "No parameter named {} in call to {} {}", id, func, loc.pretty()),
+      TypeError::DuplicateArgument { loc, func, id } => write!(f, "Argument {} is passed more than once in call to {} {}", id, func, loc.pretty()),
+      TypeError::MissingArgument { loc, func, id } => write!(f, "No argument passed for parameter {} in call to {} {}", id, func, loc.pretty()),
+      TypeError::NotHashable { loc, shape } => write!(f, "Can't use {} as a map key, it isn't hashable {}", shape.pretty(), loc.pretty()),
+      TypeError::InvalidTry { loc, found, result } => write!(f, "Can't use ? on {} in a function returning {} {}", found.pretty(), result.pretty(), loc.pretty()),
+      TypeError::PatternArity { loc, shape, expected, found } => write!(f, "Pattern has {} elements but type {} has {} {}", expected, shape.pretty(), found, loc.pretty()),
+      TypeError::AlreadyDeclared { name } => write!(f, "{} is already declared", name),
+      TypeError::ReadOnlyModule { package, module, name } => write!(f, "Cannot register {} into compiled module {}::{}", name, package, module),
+      TypeError::Internal { message } => write!(f, "{}", message),
+    }
+  }
+}
+
+impl fmt::Display for CompileError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CompileError::Io { path, message } => write!(f, "Could not read {}: {}", path, message),
+      CompileError::Parse(err) => err.fmt(f),
+      CompileError::ParseErrors(errs) => {
+        let lines: Vec<String> = errs.iter().map(|err| err.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+      }
+      CompileError::Type(err) => err.fmt(f),
+      CompileError::TypeErrors(errs) => {
+        let lines: Vec<String> = errs.iter().map(|err| err.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+      }
+      CompileError::NoSuchModule { package, module } => write!(f, "Can't find module {}::{}", package, module),
+      CompileError::CircularImport { package, cycle } => write!(f, "Circular import in package {}: {}", package, cycle.join(" -> ")),
+      CompileError::CircularDependency { cycle } => write!(f, "Circular dependency between packages: {}", cycle.join(" -> ")),
+      CompileError::Manifest { path, message } => write!(f, "Invalid manifest {}: {}", path, message),
+      CompileError::NotAFunction { loc } => write!(f, "Function does not have function shape {}", loc.pretty()),
+      CompileError::MissingOperator { loc, op } => write!(f, "Could not look up Core operator function {} {}", op, loc.pretty()),
+      CompileError::UnknownLocal { loc, name } => write!(f, "Variable '{}' not found in IrCompiler scope {}", name, loc.pretty()),
+      CompileError::Uninitialized { func, local } => write!(f, "Local '{}' may be read before it's stored in {}", local, func.pretty()),
+    }
+  }
+}
+
+impl fmt::Display for RuntimeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RuntimeError::Io { message } => write!(f, "IO error: {}", message),
+      RuntimeError::NoMain => write!(f, "Application has no main function"),
+      RuntimeError::InvalidMain { func } => write!(f, "Main function {} must take no arguments or a List[String], not {}", func.pretty(), func.shape.pretty()),
+      RuntimeError::NoSuchFunction { func } => write!(f, "No such function {}", func.pretty()),
+      RuntimeError::SignatureMismatch { func, expected, found } => write!(f, "Signature mismatch for {}, compiled against {} but found {}", func.pretty(), expected.pretty(), found.pretty()),
+      RuntimeError::LinkErrors(errs) => {
+        let lines: Vec<String> = errs.iter().map(|err| err.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+      }
+      RuntimeError::NoSuchName { package, module, name } => write!(f, "No such function {}::{}.{}", package, module, name),
+      RuntimeError::AlreadyDeclared { package, module, name } => write!(f, "Function {}::{}.{} is already declared", package, module, name),
+      RuntimeError::ArgumentCount { func, expected, found } => write!(f, "{} expects {} arguments but was given {}", func.pretty(), expected, found),
+      RuntimeError::InvalidBytecode { func, message } => write!(f, "Invalid bytecode in {}. {}", func.pretty(), message),
+      RuntimeError::InvalidConstant { kind, id } => write!(f, "Invalid bytecode. Invalid {} constant id {}", kind, id),
+      RuntimeError::Native { message } => write!(f, "{}", message),
+      RuntimeError::Raised { func, message } => write!(f, "{} in {}", message, func.pretty()),
+      RuntimeError::Panic { func, message } => write!(f, "Panic: {} in {}", message, func.pretty()),
+      RuntimeError::AssertionFailed { func, point, condition } => write!(f, "Assertion failed: {} in {} at {}", condition, func.pretty(), point.pretty()),
+      RuntimeError::Disabled { func, capability } => write!(f, "{} is not allowed, the {} capability is disabled", func.pretty(), capability),
+      RuntimeError::Aborted => write!(f, "Execution stopped by debugger"),
+      RuntimeError::LimitExceeded { func, limit } => write!(f, "{} in {}", limit, func.pretty()),
+      RuntimeError::RoundTripMismatch { func, original, restored } => write!(f, "{} gave {} as compiled but {} after a serialize round trip", func.pretty(), original, restored),
+      RuntimeError::StackOverflow { limit, chain } => {
+        write!(f, "Stack overflow, exceeded call depth limit of {}", limit)?;
+
+        for (func, count) in chain {
+          if *count > 1 {
+            write!(f, "\n  in {} ({} times)", func.pretty(), count)?;
+          } else {
+            write!(f, "\n  in {}", func.pretty())?;
+          }
+        }
+
+        if chain.iter().map(|(_, count)| count).sum::<usize>() < *limit {
+          write!(f, "\n  ...")?;
+        }
+
+        Ok(())
+      }
+    }
+  }
+}
+
+impl fmt::Display for Limit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Limit::Instructions(max) => write!(f, "Exceeded limit of {} instructions", max),
+      Limit::Timeout(max) => write!(f, "Exceeded time limit of {}ms", max.as_secs() * 1000 + u64::from(max.subsec_millis())),
+    }
+  }
+}
+
+impl fmt::Display for LetError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LetError::Compile(err) => err.fmt(f),
+      LetError::Runtime(err) => err.fmt(f),
+    }
+  }
+}
+
+impl Error for LexError {}
+impl Error for ParseError {}
+impl Error for TypeError {}
+impl Error for CompileError {}
+impl Error for RuntimeError {}
+impl Error for LetError {}
+
+impl From<LexError> for ParseError {
+  fn from(err: LexError) -> ParseError {
+    ParseError::Lex(err)
+  }
+}
+
+impl From<ParseError> for CompileError {
+  fn from(err: ParseError) -> CompileError {
+    CompileError::Parse(err)
+  }
+}
+
+impl From<Vec<ParseError>> for CompileError {
+  fn from(errs: Vec<ParseError>) -> CompileError {
+    CompileError::ParseErrors(errs)
+  }
+}
+
+impl From<TypeError> for CompileError {
+  fn from(err: TypeError) -> CompileError {
+    CompileError::Type(err)
+  }
+}
+
+impl From<Vec<TypeError>> for CompileError {
+  fn from(errs: Vec<TypeError>) -> CompileError {
+    CompileError::TypeErrors(errs)
+  }
+}
+
+impl From<io::Error> for RuntimeError {
+  fn from(err: io::Error) -> RuntimeError {
+    RuntimeError::Io { message: err.to_string() }
+  }
+}
+
+impl From<CompileError> for LetError {
+  fn from(err: CompileError) -> LetError {
+    LetError::Compile(err)
+  }
+}
+
+impl From<ParseError> for LetError {
+  fn from(err: ParseError) -> LetError {
+    LetError::Compile(CompileError::Parse(err))
+  }
+}
+
+impl From<Vec<ParseError>> for LetError {
+  fn from(errs: Vec<ParseError>) -> LetError {
+    LetError::Compile(CompileError::ParseErrors(errs))
+  }
+}
+
+impl From<TypeError> for LetError {
+  fn from(err: TypeError) -> LetError {
+    LetError::Compile(CompileError::Type(err))
+  }
+}
+
+impl From<Vec<TypeError>> for LetError {
+  fn from(errs: Vec<TypeError>) -> LetError {
+    LetError::Compile(CompileError::TypeErrors(errs))
+  }
+}
+
+impl From<RuntimeError> for LetError {
+  fn from(err: RuntimeError) -> LetError {
+    LetError::Runtime(err)
+  }
+}
+
+impl From<io::Error> for LetError {
+  fn from(err: io::Error) -> LetError {
+    LetError::Runtime(RuntimeError::from(err))
+  }
+}
@@ -0,0 +1,364 @@
+use ast::*;
+use parser::RECEIVER_PREFIX;
+use shapes::Shape;
+
+const INDENT: &'static str = "  ";
+
+/**
+* Prints a parsed module back out as canonical letLang source.
+*
+* Imports come first, then type aliases, then data types, then functions in the order they were declared. Parentheses are only
+* emitted where precedence needs them. The lexer drops comments, so there are none to keep yet.
+*/
+pub fn format_module(module: &AstModule) -> String {
+  let mut sections = Vec::new();
+
+  if !module.imports.is_empty() {
+    let imports: Vec<String> = module.imports.iter()
+      .map(|imp| format!("import {}::{}", imp.package, imp.module))
+      .collect();
+
+    sections.push(imports.join("\n"));
+  }
+
+  if !module.aliases.is_empty() {
+    let aliases: Vec<String> = module.aliases.iter()
+      .map(|alias| format!("{}type {} = {}", format_visibility(&alias.visibility), alias.id, alias.shape.pretty()))
+      .collect();
+
+    sections.push(aliases.join("\n"));
+  }
+
+  if !module.datas.is_empty() {
+    let datas: Vec<String> = module.datas.iter()
+      .map(|data| format!("{}data {}{}", format_visibility(&data.visibility), data.id, format_data(data)))
+      .collect();
+
+    sections.push(datas.join("\n"));
+  }
+
+  for func in &module.functions {
+    sections.push(format!("{}{}", format_visibility(&func.visibility), format_function(&func.ex, 0)));
+  }
+
+  let mut result = sections.join("\n\n");
+  result.push('\n');
+  result
+}
+
+fn format_visibility(visibility: &Visibility) -> &'static str {
+  match visibility {
+    Visibility::Public => "public ",
+    Visibility::Internal => "internal ",
+    Visibility::Protected => "protected ",
+    Visibility::Private => "",
+  }
+}
+
+fn format_data(data: &DataEx) -> String {
+  let params = if data.params.is_empty() { String::new() } else { format!("[{}]", data.params.join(", ")) };
+  let fields: Vec<String> = data.fields.iter().map(|field| format!("{}: {}", field.id, field.shape.pretty())).collect();
+
+  format!("{}({})", params, fields.join(", "))
+}
+
+fn format_function(ex: &FunctionDeclarationEx, depth: usize) -> String {
+  let args: Vec<String> = ex.args.iter().zip(&ex.defaults)
+    .map(|(arg, default)| {
+      let head = if arg.shape == Shape::UnknownShape { arg.id.clone() } else { format!("{}: {}", arg.id, arg.shape.pretty()) };
+
+      match default {
+        Some(default) => format!("{} = {}", head, format_expression(&default.to_expression(&ex.loc), depth)),
+        None => head,
+      }
+    })
+    .collect();
+
+  let result = if ex.result == Shape::UnknownShape { String::new() } else { format!(": {}", ex.result.pretty()) };
+
+  format!("fun {}({}){} = {}", ex.id, args.join(", "), result, format_expression(&ex.body, depth))
+}
+
+fn format_lambda(ex: &FunctionDeclarationEx, depth: usize) -> String {
+  let args: Vec<String> = ex.args.iter()
+    .map(|arg| if arg.shape == Shape::UnknownShape {
+      arg.id.clone()
+    } else {
+      format!("{}: {}", arg.id, arg.shape.pretty())
+    })
+    .collect();
+
+  let mut head = args.join(", ");
+
+  if ex.result != Shape::UnknownShape {
+    if !head.is_empty() {
+      head.push(' ');
+    }
+
+    head.push_str(&format!("-> {}", ex.result.pretty()));
+  }
+
+  if !head.is_empty() {
+    head.push(' ');
+  }
+
+  let statements = match &ex.body {
+    Expression::Block(block) => format_statements(&block.body, depth + 1),
+    Expression::NoOp(_) => Vec::new(),
+    other => vec![format_expression(other, depth + 1)],
+  };
+
+  // Short lambdas stay on one line, as they usually do when passed as arguments.
+  if statements.len() == 1 && !statements[0].contains('\n') {
+    format!("{{{}=> {}}}", head, statements[0])
+  } else {
+    format!("{{{}=>\n{}\n{}}}", head, indent_lines(&statements, depth + 1), indent(depth))
+  }
+}
+
+/**
+* Newlines don't end statements, so one that starts with '(' would be read as a call on the statement before it.
+* A ';' after that earlier statement keeps them apart.
+*/
+fn format_statements(body: &Vec<Expression>, depth: usize) -> Vec<String> {
+  let mut statements: Vec<String> = body.iter().map(|ex| format_expression(ex, depth)).collect();
+
+  for index in 1..statements.len() {
+    if statements[index].starts_with('(') {
+      statements[index - 1].push(';');
+    }
+  }
+
+  statements
+}
+
+fn format_arguments(call: &CallEx, depth: usize) -> String {
+  let args: Vec<String> = call.args.iter().map(|arg| format_expression(arg, depth))
+    .chain(call.named.iter().map(|(id, arg)| format!("{} = {}", id, format_expression(arg, depth))))
+    .collect();
+
+  args.join(", ")
+}
+
+/**
+* The receiver, method name and call of a block the parser made from `value.name(args)`.
+*/
+fn method_call(block: &BlockEx) -> Option<(&Expression, &str, &CallEx)> {
+  match block.body.as_slice() {
+    [Expression::Assignment(store), Expression::Call(call)] => match (&store.pattern, &call.func) {
+      (Pattern::Id(local), Expression::Variable(func)) if local.starts_with(RECEIVER_PREFIX) && func.id.starts_with(&format!("{}.", local)) => {
+        Some((&store.body, &func.id[local.len() + 1..], call))
+      }
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+fn format_block(body: &Vec<Expression>, depth: usize) -> String {
+  if body.is_empty() {
+    return String::from("{}");
+  }
+
+  format!("{{\n{}\n{}}}", indent_lines(&format_statements(body, depth + 1), depth + 1), indent(depth))
+}
+
+fn indent(depth: usize) -> String {
+  INDENT.repeat(depth)
+}
+
+fn indent_lines(statements: &Vec<String>, depth: usize) -> String {
+  let prefix = indent(depth);
+
+  statements.iter()
+    .map(|statement| format!("{}{}", prefix, statement))
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+fn format_pattern(pattern: &Pattern) -> String {
+  match pattern {
+    Pattern::Id(id) => id.clone(),
+    Pattern::Tuple(items) => format!("({})", items.iter().map(format_pattern).collect::<Vec<String>>().join(", ")),
+  }
+}
+
+fn format_string(value: &str) -> String {
+  let mut result = String::from("\"");
+
+  for ch in value.chars() {
+    match ch {
+      '"' => result.push_str("\\\""),
+      '\\' => result.push_str("\\\\"),
+      '\n' => result.push_str("\\n"),
+      '\t' => result.push_str("\\t"),
+      other => result.push(other),
+    }
+  }
+
+  result.push('"');
+  result
+}
+
+/**
+* Binding strength of each binary operator, matching the order parse_ops tries them in.
+*/
+fn precedence(op: &str) -> u8 {
+  match op {
+    "*" | "/" => 5,
+    "+" | "-" => 4,
+    ".." => 3,
+    "<" | ">" | "<=" | ">=" => 2,
+    _ => 1,
+  }
+}
+
+/**
+* Wraps an operand in parentheses when it would otherwise bind differently. Operators are left associative, so the
+* right side also needs them at equal precedence. An if or while would swallow the rest of the expression.
+*/
+fn format_operand(ex: &Expression, parent: u8, is_right: bool, depth: usize) -> String {
+  let text = format_expression(ex, depth);
+
+  let needs_parens = match ex {
+    Expression::Call(call) if call.piped => true,
+    Expression::BinaryOp(op) => {
+      let own = precedence(&op.op);
+      own < parent || (is_right && own == parent)
+    }
+    Expression::If(_) | Expression::While(_) | Expression::Assignment(_) | Expression::Reassignment(_) | Expression::Assert(_) | Expression::Return(_) => true,
+    _ => false,
+  };
+
+  if needs_parens {
+    format!("({})", text)
+  } else {
+    text
+  }
+}
+
+pub fn format_expression(ex: &Expression, depth: usize) -> String {
+  match ex {
+    Expression::NoOp(_) => String::from("{}"),
+    Expression::Import(imp) => format!("import {}::{}", imp.package, imp.module),
+    Expression::FunctionDeclaration(func) => {
+      if func.context.is_lambda {
+        format_lambda(func, depth)
+      } else {
+        format_function(func, depth)
+      }
+    }
+    Expression::Assignment(assign) => {
+      let annotation = if assign.shape == Shape::UnknownShape {
+        String::new()
+      } else {
+        format!(": {}", assign.shape.pretty())
+      };
+
+      let keyword = if assign.mutable { "let mut" } else { "let" };
+
+      format!("{} {}{} = {}", keyword, format_pattern(&assign.pattern), annotation, format_expression(&assign.body, depth))
+    }
+    Expression::Reassignment(assign) => format!("{} = {}", assign.id, format_expression(&assign.body, depth)),
+    Expression::Variable(var) => var.id.clone(),
+    Expression::BinaryOp(op) => {
+      let parent = precedence(&op.op);
+
+      let spacing = if op.op == ".." { "" } else { " " };
+
+      format!("{}{}{}{}{}", format_operand(&op.left, parent, false, depth), spacing, op.op, spacing, format_operand(&op.right, parent, true, depth))
+    }
+    Expression::Call(call) if call.piped => {
+      // Pipes chain left to right, so only the leftmost can go without parentheses.
+      let first = match &call.args[0] {
+        Expression::Call(inner) if inner.piped => format_expression(&call.args[0], depth),
+        other => format_operand(other, 1, false, depth),
+      };
+      let func = format_expression(&call.func, depth);
+
+      if call.args.len() == 1 && call.named.is_empty() {
+        format!("{} |> {}", first, func)
+      } else {
+        let rest: Vec<String> = call.args[1..].iter().map(|arg| format_expression(arg, depth))
+          .chain(call.named.iter().map(|(id, arg)| format!("{} = {}", id, format_expression(arg, depth))))
+          .collect();
+        format!("{} |> {}({})", first, func, rest.join(", "))
+      }
+    }
+    Expression::Call(call) => {
+      let func = match &call.func {
+        Expression::Variable(_) | Expression::FunctionDeclaration(_) | Expression::Tuple(_) => format_expression(&call.func, depth),
+        other => format!("({})", format_expression(other, depth)),
+      };
+
+      format!("{}({})", func, format_arguments(call, depth))
+    }
+    Expression::If(if_ex) => {
+      let condition = format_expression(&if_ex.condition, depth);
+      let then_block = format_expression(&if_ex.then_block, depth);
+
+      if let Expression::NoOp(_) = if_ex.else_block {
+        format!("if ({}) {}", condition, then_block)
+      } else {
+        format!("if ({}) {} else {}", condition, then_block, format_expression(&if_ex.else_block, depth))
+      }
+    }
+    Expression::While(while_ex) => {
+      let body = match &while_ex.body {
+        Expression::Block(block) => format_block(&block.body, depth),
+        Expression::NoOp(_) => String::from("{}"),
+        other => format!("{{ {} }}", format_expression(other, depth)),
+      };
+
+      format!("while {} {}", format_expression(&while_ex.condition, depth), body)
+    }
+    Expression::Block(block) => match method_call(block) {
+      Some((receiver, name, call)) => format!("{}.{}({})", format_operand(receiver, u8::max_value(), false, depth), name, format_arguments(call, depth)),
+      None => format_block(&block.body, depth),
+    },
+    Expression::Tuple(tuple) => {
+      let items: Vec<String> = tuple.items.iter().map(|item| format_expression(item, depth)).collect();
+      format!("({})", items.join(", "))
+    }
+    Expression::List(list) => {
+      let items: Vec<String> = list.items.iter().map(|item| format_expression(item, depth)).collect();
+      format!("[{}]", items.join(", "))
+    }
+    Expression::StringLiteral(string) => format_string(&string.value),
+    Expression::NumberLiteral(number) => format!("{}", number.value),
+    Expression::BooleanLiteral(_, value) => format!("{}", value),
+    Expression::UnitLiteral(_) => String::from("()"),
+    Expression::Hole(_) => String::from("???"),
+    Expression::Panic(panic) => format!("panic({})", format_string(&panic.message)),
+    Expression::Return(ret) => match &ret.body {
+      Expression::UnitLiteral(_) => String::from("return"),
+      body => format!("return {}", format_expression(body, depth)),
+    },
+    Expression::Assert(assert) => format!("assert {}", format_expression(&assert.condition, depth)),
+    Expression::Variant(variant) if variant.values.is_empty() => variant.tag.clone(),
+    Expression::Variant(variant) => {
+      let values: Vec<String> = variant.values.iter().map(|value| format_expression(value, depth)).collect();
+      format!("{}({})", variant.tag, values.join(", "))
+    }
+    Expression::Try(try_ex) => match &try_ex.body {
+      Expression::Variable(_) | Expression::Call(_) | Expression::Variant(_) | Expression::Try(_) | Expression::Index(_) | Expression::Field(_) | Expression::Tuple(_) | Expression::List(_) => format!("{}?", format_expression(&try_ex.body, depth)),
+      other => format!("({})?", format_expression(other, depth)),
+    },
+    Expression::Index(index) => {
+      let list = match &index.list {
+        Expression::Variable(_) | Expression::Call(_) | Expression::Index(_) | Expression::Field(_) | Expression::Tuple(_) | Expression::List(_) => format_expression(&index.list, depth),
+        other => format!("({})", format_expression(other, depth)),
+      };
+
+      format!("{}[{}]", list, format_expression(&index.index, depth))
+    }
+    Expression::Field(field) => {
+      let value = match &field.value {
+        Expression::Variable(_) | Expression::Call(_) | Expression::Index(_) | Expression::Field(_) | Expression::Tuple(_) | Expression::List(_) => format_expression(&field.value, depth),
+        other => format!("({})", format_expression(other, depth)),
+      };
+
+      format!("{}.{}", value, field.field)
+    }
+  }
+}
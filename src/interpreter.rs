@@ -1,16 +1,25 @@
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fmt::Debug;
 use std::fmt::Error;
 use std::fmt::Formatter;
-use std::rc::Rc;
+use std::io::{self, BufRead, Write};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
 
-use simple_error::SimpleError;
 
 use bytecode::*;
-use runtime::Value;
+use debugger::{Breakpoints, DebugAction, Debugger, Frame};
+use error::{Limit, RuntimeError, TypeError};
+use runtime::{ListValue, Value, VariantValue};
 use shapes::*;
 use shapes::Shape::SimpleFunctionShape;
 use lib_core::core_runtime;
+use profiler::Profile;
+use register::{RegCache, RegFunction, RegInstruction, float, to_registers};
+use threaded::{Control, ThreadFrame, ThreadedCache, ThreadedFunction, decode};
+use typechecker::AppShapes;
 
 pub enum RunFunction {
   BitFunction(BitFunction),
@@ -18,8 +27,25 @@ pub enum RunFunction {
 }
 
 
+/**
+* Something callable. A call's locals are whatever the handle captured, followed by the arguments, and are built
+* straight into the frame the function runs with.
+*/
 pub trait FunctionHandle {
-  fn with(&self, args: Vec<Value>) -> (&FunctionRef, Vec<Value>);
+  /**
+  * How many locals enter writes ahead of the arguments.
+  */
+  fn captured(&self) -> usize;
+
+  /**
+  * Writes the captured locals into frame and gives the function to run with it once the arguments are added.
+  */
+  fn enter(&self, frame: &mut Vec<Value>) -> &FunctionRef;
+
+  /**
+  * The function this handle calls, without the closures it would pass along.
+  */
+  fn func_ref(&self) -> &FunctionRef;
 }
 
 impl Debug for FunctionHandle {
@@ -28,60 +54,568 @@ impl Debug for FunctionHandle {
   }
 }
 
+/**
+* Bounds on a single run of the Machine, so a runaway program fails instead of hanging the host. By default only the
+* call depth is limited, to DEFAULT_MAX_CALL_DEPTH.
+*/
+#[derive(Debug, Clone)]
+pub struct ExecutionLimits {
+  pub max_instructions: Option<u64>,
+  pub max_call_depth: Option<usize>,
+  pub timeout: Option<Duration>,
+}
+
+/**
+* Each call nests a Rust frame, so the host thread needs roughly STACK_SIZE_PER_CALL bytes of stack per level. The
+* main thread usually has far less than this default needs, run the Machine on a thread with a bigger stack.
+*/
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+/**
+* The stack one call uses in an unoptimized build, which measures at up to about 62KB for a call through the register
+* backend or threaded dispatch, with some room to spare. An optimized build uses far less.
+*/
+pub const STACK_SIZE_PER_CALL: usize = 80 * 1024;
+
+/**
+* How many distinct functions a StackOverflow error keeps while unwinding.
+*/
+const STACK_OVERFLOW_CHAIN: usize = 16;
+
+impl Default for ExecutionLimits {
+  fn default() -> ExecutionLimits {
+    ExecutionLimits {
+      max_instructions: None,
+      max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+      timeout: None,
+    }
+  }
+}
+
+impl ExecutionLimits {
+
+  /**
+  * No limits at all. Deep enough recursion will then overflow the host's stack and abort the process.
+  */
+  pub fn none() -> ExecutionLimits {
+    ExecutionLimits { max_instructions: None, max_call_depth: None, timeout: None }
+  }
+
+  fn is_limited(&self) -> bool {
+    self.max_instructions.is_some() || self.timeout.is_some()
+  }
+}
+
+/**
+* Which host resources letLang code may reach. The default is safe for untrusted scripts, with everything off.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct SecurityConfig {
+  /** Allows Core::File. When off its functions fail when called, not when the program is compiled. */
+  pub enable_fs: bool,
+}
+
+impl SecurityConfig {
+
+  /**
+  * Everything on, for programs the user runs themselves.
+  */
+  pub fn trusted() -> SecurityConfig {
+    SecurityConfig { enable_fs: true }
+  }
+}
+
+/**
+* How the Machine runs BitFunctions. Register converts each function to three address code the first time it's
+* called, any it can't convert still run on the stack. It's an experiment, Stack is the default.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Backend {
+  Stack,
+  Register,
+}
+
+/**
+* How the stack interpreter picks the code for each instruction. Threaded decodes each function into a closure per
+* instruction as it's loaded, so running it skips the match. Functions it can't decode still go through the match.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Dispatch {
+  Match,
+  Threaded,
+}
+
+/**
+* The clock is only read this often, it's much slower than counting.
+*/
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
 pub struct Machine {
   app: BitApplication,
+  output: RefCell<Box<Write>>,
+  input: RefCell<Option<Box<BufRead>>>, // None reads straight from stdin, sharing its buffer with the host
+  args: Vec<String>,
+  env: Option<HashMap<String, String>>, // None reads the host process's environment
+  debugger: Option<RefCell<Box<Debugger>>>,
+  breakpoints: RefCell<Breakpoints>,
+  stepping: Cell<bool>,
+  limits: ExecutionLimits,
+  security: SecurityConfig,
+  instructions: Cell<u64>,
+  depth: Cell<usize>,
+  started: Cell<Option<Instant>>,
+  profile: Option<RefCell<Profile>>,
+  backend: Backend,
+  registers: RefCell<RegCache>,
+  dispatch: Dispatch,
+  threaded: RefCell<ThreadedCache>,
+}
+
+/**
+* How a function run outside the match loop finished. A tail call is handed back so it doesn't nest.
+*/
+enum Exit {
+  Return(Value),
+  TailCall(FunctionRef, Vec<Value>),
 }
 
 impl Machine {
   pub fn new(mut app: BitApplication) -> Machine {
     app.packages.insert(String::from("Core"), core_runtime());
-    Machine { app }
+    Machine {
+      app,
+      output: RefCell::new(Box::new(io::stdout())),
+      input: RefCell::new(None),
+      args: Vec::new(),
+      env: None,
+      debugger: None,
+      breakpoints: RefCell::new(Breakpoints::new()),
+      stepping: Cell::new(false),
+      limits: ExecutionLimits::default(),
+      security: SecurityConfig::default(),
+      instructions: Cell::new(0),
+      depth: Cell::new(0),
+      started: Cell::new(None),
+      profile: None,
+      backend: Backend::Stack,
+      registers: RefCell::new(RegCache::new()),
+      dispatch: Dispatch::Match,
+      threaded: RefCell::new(ThreadedCache::new()),
+    }
+  }
+
+  /**
+  * Starts recording calls, instructions and time per function. Read the results with profile.
+  */
+  pub fn enable_profiling(&mut self) {
+    self.profile = Some(RefCell::new(Profile::new()));
+  }
+
+  /**
+  * Everything recorded since profiling was enabled, or None if it never was.
+  */
+  pub fn profile(&self) -> Option<Profile> {
+    self.profile.as_ref().map(|profile| profile.borrow().clone())
   }
 
-  pub fn run_main(&self) -> Result<Value, SimpleError> {
-    self.execute(self.app.main.clone(), vec![])
+  /**
+  * Limits apply to each outermost call, counting everything it calls in turn.
+  */
+  pub fn set_limits(&mut self, limits: ExecutionLimits) {
+    self.limits = limits;
   }
 
-  pub fn execute_handle(&self, handle: Rc<FunctionHandle>, locals: Vec<Value>) -> Result<Value, SimpleError> {
-    let (func, params) = handle.with(locals);
-    self.execute(func.clone(), params)
+  /**
+  * The debugger only knows stack bytecode, so while one is set everything runs on the stack.
+  */
+  pub fn set_backend(&mut self, backend: Backend) {
+    self.backend = backend;
+  }
+
+  /**
+  * Switching to Threaded decodes everything already loaded, modules loaded later are decoded as they come in.
+  */
+  pub fn set_dispatch(&mut self, dispatch: Dispatch) {
+    self.dispatch = dispatch;
+    self.threaded.borrow_mut().clear();
+
+    if dispatch == Dispatch::Threaded {
+      for package in self.app.packages.values() {
+        package.modules.values().for_each(|module| self.decode_module(module));
+      }
+    }
   }
 
-  pub fn execute(&self, mut src_func_ref: FunctionRef, mut locals: Vec<Value>) -> Result<Value, SimpleError> {
+  pub fn set_security(&mut self, security: SecurityConfig) {
+    self.security = security;
+  }
+
+  /**
+  * Hands control to debugger before the first instruction that runs, then at each breakpoint or step.
+  */
+  pub fn set_debugger(&mut self, debugger: Box<Debugger>) {
+    self.debugger = Some(RefCell::new(debugger));
+    self.stepping.set(true);
+  }
+
+  /**
+  * Pauses before instruction index of func. Only has an effect once a debugger is set.
+  * BitFunctions don't carry source positions yet, so there is no way to break on a line.
+  */
+  pub fn add_breakpoint(&self, func: FunctionRef, index: usize) {
+    self.breakpoints.borrow_mut().add(func, index);
+  }
+
+  pub fn remove_breakpoint(&self, func: &FunctionRef, index: usize) -> bool {
+    self.breakpoints.borrow_mut().remove(func, index)
+  }
+
+  /**
+  * Replaces stdout as the destination of Console.print and Console.println.
+  */
+  pub fn set_output(&mut self, output: Box<Write>) {
+    self.output = RefCell::new(output);
+  }
+
+  /**
+  * Replaces stdin as the source of Console.readLine.
+  */
+  pub fn set_input(&mut self, input: Box<BufRead>) {
+    self.input = RefCell::new(Some(input));
+  }
+
+  /**
+  * The arguments Env.args returns, usually whatever followed the program on the command line.
+  */
+  pub fn set_args(&mut self, args: Vec<String>) {
+    self.args = args;
+  }
+
+  /**
+  * Replaces the host process's environment as the source of Env.getVar.
+  */
+  pub fn set_env(&mut self, env: HashMap<String, String>) {
+    self.env = Some(env);
+  }
+
+  pub fn args(&self) -> &Vec<String> {
+    &self.args
+  }
+
+  /**
+  * The args as the List[String] letLang code sees them.
+  */
+  pub fn args_list(&self) -> Value {
+    let contents = self.args.iter().map(|arg| Value::String(Rc::new(arg.clone()))).collect();
+    Value::List(Rc::new(ListValue { contents, shape: shape_string() }))
+  }
+
+  pub fn env_var(&self, name: &str) -> Option<String> {
+    match &self.env {
+      Some(env) => env.get(name).cloned(),
+      None => env::var(name).ok(),
+    }
+  }
+
+  pub fn write(&self, text: &str) -> Result<(), RuntimeError> {
+    let mut output = self.output.borrow_mut();
+
+    output.write_all(text.as_bytes()).map_err(RuntimeError::from)?;
+    output.flush().map_err(RuntimeError::from)
+  }
+
+  /**
+  * Reads a single line without the trailing newline. Returns an empty string at the end of input.
+  */
+  pub fn read_line(&self) -> Result<String, RuntimeError> {
+    let mut line = String::new();
+
+    match self.input.borrow_mut().as_mut() {
+      Some(input) => input.read_line(&mut line),
+      None => io::stdin().read_line(&mut line),
+    }.map_err(RuntimeError::from)?;
+
+    if line.ends_with('\n') {
+      line.pop();
+
+      if line.ends_with('\r') {
+        line.pop();
+      }
+    }
+
+    Ok(line)
+  }
+
+  /**
+  * Same as BitApplication::register_native, for functions added after the Machine is created.
+  */
+  pub fn register_native<Op: Fn(&Machine, Vec<Value>) -> Result<Value, RuntimeError> + 'static>(&mut self, package: &str, module: &str, name: &str, shape: Shape, op: Op) -> Result<(), RuntimeError> {
+    self.app.register_native(package, module, name, shape, op)
+  }
+
+  /**
+  * Same as BitApplication::link, once the core runtime is loaded alongside everything else.
+  */
+  pub fn link(&self) -> Result<(), RuntimeError> {
+    self.app.link()
+  }
+
+  /**
+  * Shapes of everything loaded into this Machine, for typechecking code that will run on it.
+  */
+  pub fn shapes(&self) -> Result<AppShapes, TypeError> {
+    AppShapes::for_application(&self.app)
+  }
+
+  /**
+  * Adds a module to a running Machine, creating the package if needed. Used by the repl to load each line.
+  */
+  pub fn load_module(&mut self, package: &str, name: &str, module: BitModule) {
+    self.app.packages.entry(String::from(package))
+      .or_insert_with(BitPackage::new)
+      .modules.insert(String::from(name), module);
+
+    if self.dispatch == Dispatch::Threaded {
+      if let Some(module) = self.app.packages.get(package).and_then(|package| package.modules.get(name)) {
+        self.decode_module(module);
+      }
+    }
+  }
+
+  /**
+  * Runs main, which either takes nothing or takes the args as a List[String], and can return anything.
+  */
+  pub fn run_main(&self) -> Result<Value, RuntimeError> {
+    let main = self.app.main.clone()
+      .ok_or_else(|| RuntimeError::NoMain)?;
+
+    let args = match &main.shape {
+      Shape::SimpleFunctionShape { args, .. } if args.is_empty() => vec![],
+      Shape::SimpleFunctionShape { args, .. } if *args == vec![shape_list(shape_string())] => vec![self.args_list()],
+      _ => return Err(RuntimeError::InvalidMain { func: Box::new(main.clone()) }),
+    };
+
+    self.execute(main, args)
+  }
+
+  /**
+  * Looks up and runs any loaded function by name. The number of arguments must match the function's shape.
+  */
+  pub fn call(&self, package: &str, module: &str, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let func_ref = self.app.packages.get(package)
+      .and_then(|pack| pack.modules.get(module))
+      .and_then(|module| module.functions.get(name))
+      .map(|func| func.func_ref().clone())
+      .ok_or_else(|| RuntimeError::NoSuchName { package: String::from(package), module: String::from(module), name: String::from(name) })?;
+
+    if let Shape::SimpleFunctionShape { args: ref expected, .. } = func_ref.shape {
+      if expected.len() != args.len() {
+        return Err(RuntimeError::ArgumentCount { func: Box::new(func_ref.clone()), expected: expected.len(), found: args.len() });
+      }
+    }
+
+    self.execute(func_ref, args)
+  }
+
+  pub fn execute_handle(&self, handle: Rc<FunctionHandle>, locals: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (func, frame) = build_frame(&*handle, locals.into_iter());
+    self.execute(func.clone(), frame)
+  }
+
+  pub fn execute(&self, func_ref: FunctionRef, locals: Vec<Value>) -> Result<Value, RuntimeError> {
+    let depth = self.depth.get();
+
+    if depth == 0 {
+      self.instructions.set(0);
+      self.started.set(Some(Instant::now()));
+    }
+
+    if let Some(max) = self.limits.max_call_depth {
+      if depth >= max {
+        return Err(RuntimeError::StackOverflow { limit: max, chain: vec![(func_ref, 1)] });
+      }
+    }
+
+    self.depth.set(depth + 1);
+
+    // Tail calls replace current, so an error names the function that was really running.
+    let mut current = func_ref;
+
+    let result = if let Some(profile) = &self.profile {
+      let entry = current.clone();
+      let start = Instant::now();
+      let result = self.run(&mut current, locals);
+      profile.borrow_mut().record_time(&entry, start.elapsed());
+      result
+    } else {
+      self.run(&mut current, locals)
+    };
+
+    self.depth.set(depth);
+    result.map_err(|err| match err {
+      RuntimeError::StackOverflow { limit, chain } => RuntimeError::StackOverflow { limit, chain: extend_chain(chain, current) },
+      other => other,
+    })
+  }
+
+  fn check_limits(&self, func_ref: &FunctionRef) -> Result<(), RuntimeError> {
+    let count = self.instructions.get() + 1;
+    self.instructions.set(count);
+
+    if let Some(max) = self.limits.max_instructions {
+      if count > max {
+        return Err(RuntimeError::LimitExceeded { func: Box::new(func_ref.clone()), limit: Limit::Instructions(max) });
+      }
+    }
+
+    if let (Some(max), Some(started)) = (self.limits.timeout, self.started.get()) {
+      if count % TIMEOUT_CHECK_INTERVAL == 0 && started.elapsed() > max {
+        return Err(RuntimeError::LimitExceeded { func: Box::new(func_ref.clone()), limit: Limit::Timeout(max) });
+      }
+    }
+
+    Ok(())
+  }
+
+  /**
+  * Fails if func belongs to a module the SecurityConfig has turned off.
+  */
+  fn check_security(&self, func: &FunctionRef) -> Result<(), RuntimeError> {
+    if func.package == "Core" && func.module == "File" && !self.security.enable_fs {
+      return Err(RuntimeError::Disabled { func: Box::new(func.clone()), capability: "fs" });
+    }
+
+    Ok(())
+  }
+
+  fn run(&self, src_func_ref: &mut FunctionRef, mut locals: Vec<Value>) -> Result<Value, RuntimeError> {
     'outer: loop {
-      match self.app.lookup_function(&src_func_ref)? {
+      if let Some(profile) = &self.profile {
+        profile.borrow_mut().record_call(src_func_ref);
+      }
+
+      self.check_security(src_func_ref)?;
+
+      match self.app.lookup_function(src_func_ref)? {
         RunFunction::BitFunction(func) => {
-          let module = self.app.lookup_module(&src_func_ref)?;
+          let module = self.app.lookup_module(src_func_ref)?;
+
+          if let Some(reg_func) = self.register_function(func, module) {
+            match self.run_registers(&reg_func, src_func_ref, locals)? {
+              Exit::Return(value) => return Ok(value),
+              Exit::TailCall(func_ref, params) => {
+                *src_func_ref = func_ref;
+                locals = params;
+                continue 'outer;
+              }
+            }
+          }
+
+          if let Some(threaded) = self.threaded_function(src_func_ref) {
+            match self.run_threaded(&threaded, src_func_ref, locals)? {
+              Exit::Return(value) => return Ok(value),
+              Exit::TailCall(func_ref, params) => {
+                *src_func_ref = func_ref;
+                locals = params;
+                continue 'outer;
+              }
+            }
+          }
 
           let mut index = 0usize;
-          let mut stack: Vec<Value> = Vec::new();
-          locals.resize(func.max_locals as usize, Value::Null);
+          let mut stack: Vec<Value> = Vec::with_capacity(func.max_stack as usize);
+          // Every slot is stored to before it's loaded, Unit only fills the gap until then.
+          locals.resize(func.max_locals as usize, Value::Unit);
 
           while index < func.body.len() {
+            if self.limits.is_limited() {
+              self.check_limits(src_func_ref)?;
+            }
+
+            if let Some(profile) = &self.profile {
+              profile.borrow_mut().record_instruction(src_func_ref);
+            }
+
+            if let Some(debugger) = &self.debugger {
+              if self.stepping.get() || self.breakpoints.borrow().contains(src_func_ref, index) {
+                let frame = Frame { func: src_func_ref, module, index, instruction: &func.body[index], stack: &stack, locals: &locals };
+
+                match debugger.borrow_mut().pause(&frame, &mut self.breakpoints.borrow_mut()) {
+                  DebugAction::Step => self.stepping.set(true),
+                  DebugAction::Continue => self.stepping.set(false),
+                  DebugAction::Abort => return Err(RuntimeError::Aborted),
+                }
+              }
+            }
+
             match func.body[index] {
               Instruction::NoOp => {}
+              Instruction::AddFloat => {
+                let (left, right) = pop_floats(&mut stack, src_func_ref, "AddFloat")?;
+                stack.push(Value::Float(left + right));
+              }
+              Instruction::AddLocals { left, right } => {
+                match (locals.get(left as usize), locals.get(right as usize)) {
+                  (Some(Value::Float(left)), Some(Value::Float(right))) => stack.push(Value::Float(left + right)),
+                  _ => return Err(RuntimeError::invalid_bytecode(src_func_ref, "AddLocals needs two float locals")),
+                }
+              }
+              Instruction::StoreConstFloat { value, local } => {
+                let slot = locals.get_mut(local as usize)
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "StoreConstFloat of local that doesn't exist"))?;
+
+                *slot = Value::Float(value);
+              }
+              Instruction::SubFloat => {
+                let (left, right) = pop_floats(&mut stack, src_func_ref, "SubFloat")?;
+                stack.push(Value::Float(left - right));
+              }
+              Instruction::MulFloat => {
+                let (left, right) = pop_floats(&mut stack, src_func_ref, "MulFloat")?;
+                stack.push(Value::Float(left * right));
+              }
+              Instruction::DivFloat => {
+                let (left, right) = pop_floats(&mut stack, src_func_ref, "DivFloat")?;
+                stack.push(Value::Float(left / right));
+              }
+              Instruction::LessFloat => {
+                let (left, right) = pop_floats(&mut stack, src_func_ref, "LessFloat")?;
+                stack.push(if left < right { Value::True } else { Value::False });
+              }
+              Instruction::LessEqualFloat => {
+                let (left, right) = pop_floats(&mut stack, src_func_ref, "LessEqualFloat")?;
+                stack.push(if left <= right { Value::True } else { Value::False });
+              }
+              Instruction::GreaterFloat => {
+                let (left, right) = pop_floats(&mut stack, src_func_ref, "GreaterFloat")?;
+                stack.push(if left > right { Value::True } else { Value::False });
+              }
+              Instruction::GreaterEqualFloat => {
+                let (left, right) = pop_floats(&mut stack, src_func_ref, "GreaterEqualFloat")?;
+                stack.push(if left >= right { Value::True } else { Value::False });
+              }
               Instruction::Duplicate => {
                 let last = stack.last()
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. Attempt to duplicate empty stack"))?
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to duplicate empty stack"))?
                   .clone();
                 stack.push(last);
               }
               Instruction::Pop => {
                 stack.pop()
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode in module. Attempt to pop empty stack"))?;
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to pop empty stack"))?;
               }
               Instruction::Swap => {
                 let first = stack.pop()
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. Attempt to swap empty stack"))?;
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to swap empty stack"))?;
 
                 let second = stack.pop()
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. Attempt to swap stack of 1"))?;
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to swap stack of 1"))?;
 
                 stack.push(first);
                 stack.push(second);
               }
-              Instruction::LoadConstNull => {
-                stack.push(Value::Null);
+              Instruction::LoadConstUnit => {
+                stack.push(Value::Unit);
               }
               Instruction::LoadConstTrue => {
                 stack.push(Value::True);
@@ -90,7 +624,7 @@ impl Machine {
                 stack.push(Value::False);
               }
               Instruction::LoadConstString { const_id } => {
-                stack.push(Value::String(Rc::new(module.lookup_string(const_id)?)));
+                stack.push(Value::String(module.lookup_string(const_id)?.clone()));
               }
               Instruction::LoadConstFunction { const_id } => {
                 let func_ref = module.lookup_function(const_id)?;
@@ -102,7 +636,7 @@ impl Machine {
                 let index = local as usize;
 
                 let local: &Value = locals.get(index)
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. LoadValue of local that doesn't exist"))?;
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "LoadValue of local that doesn't exist"))?;
 
                 stack.push(local.clone());
               }
@@ -110,13 +644,13 @@ impl Machine {
                 let index = local as usize;
 
                 let value = stack.pop()
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. Attempt to StoreValue of empty stack"))?;
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to StoreValue of empty stack"))?;
 
                 locals[index] = value;
               }
               Instruction::CallStatic { func_id } => {
                 let func_ref = module.function_refs.get(func_id as usize)
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. Invalid function id"))?
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Invalid function id"))?
                   .clone();
 
                 if let Shape::SimpleFunctionShape { args, result: _ } = func_ref.shape.clone() {
@@ -125,7 +659,7 @@ impl Machine {
 
                   for i in 0..size {
                     let param = stack.pop()
-                      .ok_or_else(|| SimpleError::new("Invalid bytecode. Not enough args for function"))?;
+                      .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Not enough args for function"))?;
 
                     params.push(param);
                   }
@@ -133,7 +667,7 @@ impl Machine {
                   params.reverse();
 
                   if let Instruction::Return = func.body[index + 1] {
-                    src_func_ref = func_ref;
+                    *src_func_ref = func_ref;
                     locals = params;
                     continue 'outer;
                   } else {
@@ -141,48 +675,40 @@ impl Machine {
                     stack.push(result);
                   }
                 } else {
-                  return Err(SimpleError::new("Invalid bytecode. CallStatic is not function"));
+                  return Err(RuntimeError::invalid_bytecode(src_func_ref, "CallStatic is not function"));
                 }
               }
               Instruction::CallDynamic { param_count } => {
-                let mut params: Vec<Value> = Vec::with_capacity(param_count as usize);
-
-                for i in 0..param_count {
-                  let param = stack.pop()
-                    .ok_or_else(|| SimpleError::new("Invalid bytecode. Not enough args for function"))?;
+                let first = stack.len().checked_sub(param_count as usize + 1)
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Not enough args for function"))?;
 
-                  params.push(param);
-                }
-
-                params.reverse();
-
-                let maybe_func: Value = stack.pop()
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. Invalid built in function id"))?;
-
-                if let Value::Function(handle) = maybe_func {
-                  let (func_ref, new_locals) = handle.with(params);
+                let handle = match &stack[first] {
+                  Value::Function(handle) => handle.clone(),
+                  _ => return Err(RuntimeError::invalid_bytecode(src_func_ref, "CallDynamic is not function")),
+                };
 
-                  if let Instruction::Return = func.body[index + 1] {
-                    src_func_ref = func_ref.clone();
-                    locals = new_locals;
-                    continue 'outer;
-                  }
+                // The arguments move straight from the stack into the new frame, then the function goes too.
+                let (func_ref, frame) = build_frame(&*handle, stack.drain(first + 1..));
+                stack.pop();
 
-                  let result = self.execute(func_ref.clone(), new_locals)?;
-                  stack.push(result);
-                } else {
-                  return Err(SimpleError::new("Invalid bytecode. CallDynamic is not function"));
+                if let Instruction::Return = func.body[index + 1] {
+                  *src_func_ref = func_ref.clone();
+                  locals = frame;
+                  continue 'outer;
                 }
+
+                let result = self.execute(func_ref.clone(), frame)?;
+                stack.push(result);
               }
               Instruction::BuildClosure { param_count, func_id } => {
                 let func = module.function_refs.get(func_id as usize)
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. Invalid function id"))?;
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Invalid function id"))?;
 
                 let mut params = Vec::with_capacity(param_count as usize);
 
                 for _ in 0..param_count {
                   let param = stack.pop()
-                    .ok_or_else(|| SimpleError::new("Invalid bytecode. Not enough args for closure"))?;
+                    .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Not enough args for closure"))?;
                   params.push(param);
                 }
 
@@ -196,43 +722,181 @@ impl Machine {
                 stack.push(Value::Function(Rc::new(closure)));
               }
               Instruction::BuildRecursiveFunction => {
-                let maybe_func = stack.pop().ok_or_else(|| SimpleError::new("Invalid bytecode. Attempt to BuildRecursiveFunction of empty stack"))?;
+                let maybe_func = stack.pop().ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to BuildRecursiveFunction of empty stack"))?;
 
                 if let Value::Function(func) = maybe_func {
-                  stack.push(Value::Function(Rc::new(RecursiveHandle { func })));
+                  stack.push(Value::Function(RecursiveHandle::new(func)));
+                } else {
+                  return Err(RuntimeError::invalid_bytecode(src_func_ref, "BuildRecursiveFunction is not function"));
+                }
+              }
+              Instruction::BuildTuple { size } => {
+                let mut items = Vec::with_capacity(size as usize);
+
+                for _ in 0..size {
+                  let item = stack.pop()
+                    .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Not enough values for tuple"))?;
+                  items.push(item);
+                }
+
+                items.reverse();
+
+                stack.push(Value::Tuple(Rc::new(items)));
+              }
+              Instruction::LoadElement { index } => {
+                let maybe_tuple = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to LoadElement of empty stack"))?;
+
+                if let Value::Tuple(items) = maybe_tuple {
+                  let item = items.get(index as usize)
+                    .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "LoadElement index out of bounds"))?;
+
+                  stack.push(item.clone());
+                } else {
+                  return Err(RuntimeError::invalid_bytecode(src_func_ref, "LoadElement is not tuple"));
+                }
+              }
+              Instruction::BuildVariant { const_id, size } => {
+                let mut values = Vec::with_capacity(size as usize);
+
+                for _ in 0..size {
+                  let value = stack.pop()
+                    .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Not enough values for variant"))?;
+                  values.push(value);
+                }
+
+                values.reverse();
+
+                stack.push(Value::Variant(Rc::new(VariantValue { tag: module.lookup_string(const_id)?.to_string(), values })));
+              }
+              Instruction::IsVariant { const_id } => {
+                let maybe_variant = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to IsVariant of empty stack"))?;
+
+                if let Value::Variant(variant) = maybe_variant {
+                  stack.push(if variant.tag == **module.lookup_string(const_id)? { Value::True } else { Value::False });
+                } else {
+                  return Err(RuntimeError::invalid_bytecode(src_func_ref, "IsVariant is not variant"));
+                }
+              }
+              Instruction::LoadVariantValue { index } => {
+                let maybe_variant = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to LoadVariantValue of empty stack"))?;
+
+                if let Value::Variant(variant) = maybe_variant {
+                  let value = variant.values.get(index as usize)
+                    .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "LoadVariantValue index out of bounds"))?;
+
+                  stack.push(value.clone());
+                } else {
+                  return Err(RuntimeError::invalid_bytecode(src_func_ref, "LoadVariantValue is not variant"));
+                }
+              }
+              Instruction::BuildList { count } => {
+                let first = stack.len().checked_sub(count as usize)
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Not enough values for list"))?;
+
+                let contents = stack.drain(first..).collect();
+
+                stack.push(Value::List(Rc::new(ListValue { contents, shape: Shape::UnknownShape })));
+              }
+              Instruction::ListGet => {
+                let maybe_index = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to ListGet of empty stack"))?;
+                let maybe_list = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to ListGet of empty stack"))?;
+
+                if let (Value::List(list), Value::Float(index)) = (maybe_list, maybe_index) {
+                  // Out of bounds is the program's fault, not the bytecode's, so it panics where tryCall can catch it.
+                  let item = if index >= 0.0 && index.fract() == 0.0 { list.contents.get(index as usize) } else { None };
+
+                  match item {
+                    Some(item) => stack.push(item.clone()),
+                    None => return Err(RuntimeError::Panic {
+                      func: Box::new(src_func_ref.clone()),
+                      message: format!("Index {} out of bounds for list of length {}", index, list.contents.len()),
+                    }),
+                  }
+                } else {
+                  return Err(RuntimeError::invalid_bytecode(src_func_ref, "ListGet is not list and float"));
+                }
+              }
+              Instruction::NewCell => {
+                let value = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to NewCell of empty stack"))?;
+
+                stack.push(Value::Cell(Rc::new(RefCell::new(value))));
+              }
+              Instruction::LoadCell => {
+                let maybe_cell = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to LoadCell of empty stack"))?;
+
+                if let Value::Cell(cell) = maybe_cell {
+                  stack.push(cell.borrow().clone());
+                } else {
+                  return Err(RuntimeError::invalid_bytecode(src_func_ref, "LoadCell is not cell"));
+                }
+              }
+              Instruction::StoreCell => {
+                let value = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to StoreCell of empty stack"))?;
+
+                let maybe_cell = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to StoreCell without a cell"))?;
+
+                if let Value::Cell(cell) = maybe_cell {
+                  *cell.borrow_mut() = value;
                 } else {
-                  return Err(SimpleError::new("Invalid bytecode. BuildRecursiveFunction is not function"));
+                  return Err(RuntimeError::invalid_bytecode(src_func_ref, "StoreCell is not cell"));
                 }
               }
               Instruction::Return => {
                 return stack.pop()
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. Attempt to return empty stack"));
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to return empty stack"));
               }
               Instruction::Branch { jump } => {
                 let first = stack.pop()
-                  .ok_or_else(|| SimpleError::new("Invalid bytecode. Attempt to Branch empty stack"))?;
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to Branch empty stack"))?;
 
                 match first {
                   Value::True => {}
-                  Value::False => index = Machine::calculate_jump(index, jump),
-                  _ => return Err(SimpleError::new("Invalid bytecode. Attempt to Branch on non boolean"))
+                  Value::False => {
+                    index = Machine::calculate_jump(index, jump);
+                    continue;
+                  }
+                  _ => return Err(RuntimeError::invalid_bytecode(src_func_ref, "Attempt to Branch on non boolean"))
                 }
               }
               Instruction::Jump { jump } => {
                 index = Machine::calculate_jump(index, jump);
+                continue;
               }
               Instruction::Debug => {
                 println!("Debug: \n  Stack: {:#?}\n  Locals: {:#?}\n  Function: ", &stack, &locals);
                 func.debug(module)?;
               }
+              Instruction::Error { const_id } => {
+                return Err(RuntimeError::Raised { func: Box::new(src_func_ref.clone()), message: module.lookup_string(const_id)?.to_string() });
+              }
+              Instruction::Assert { const_id, point } => {
+                let condition = stack.pop()
+                  .ok_or_else(|| RuntimeError::invalid_bytecode(src_func_ref, "Attempt to Assert of empty stack"))?;
 
-              _ => unimplemented!()
+                match condition {
+                  Value::True => {}
+                  Value::False => return Err(RuntimeError::AssertionFailed { func: Box::new(src_func_ref.clone()), point, condition: module.lookup_string(const_id)?.to_string() }),
+                  _ => return Err(RuntimeError::invalid_bytecode(src_func_ref, "Assert condition is not boolean")),
+                }
+              }
+              Instruction::Panic { const_id } => {
+                return Err(RuntimeError::Panic { func: Box::new(src_func_ref.clone()), message: module.lookup_string(const_id)?.to_string() });
+              }
             }
 
             index += 1;
           }
 
-          return Err(SimpleError::new(format!("Overflowed function body")));
+          return Err(RuntimeError::invalid_bytecode(src_func_ref, "Overflowed function body"));
         }
         RunFunction::NativeFunction(native) => {
           return (native.func)(self, locals);
@@ -241,14 +905,176 @@ impl Machine {
     }
   }
 
+  /**
+  * The register form of func, if the register backend is on and func could be converted.
+  */
+  fn register_function(&self, func: &BitFunction, module: &BitModule) -> Option<Rc<RegFunction>> {
+    if self.backend != Backend::Register || self.debugger.is_some() {
+      return None;
+    }
+
+    self.registers.borrow_mut()
+      .entry(func.func_ref.clone())
+      .or_insert_with(|| to_registers(func, module).map(Rc::new))
+      .clone()
+  }
+
+  fn run_registers(&self, func: &RegFunction, src_func_ref: &FunctionRef, mut regs: Vec<Value>) -> Result<Exit, RuntimeError> {
+    regs.resize(func.registers as usize, Value::Unit);
+
+    let mut index = 0usize;
+
+    while index < func.body.len() {
+      if self.limits.is_limited() {
+        self.check_limits(src_func_ref)?;
+      }
+
+      if let Some(profile) = &self.profile {
+        profile.borrow_mut().record_instruction(src_func_ref);
+      }
+
+      match &func.body[index] {
+        RegInstruction::Move { dst, src } => regs[*dst as usize] = regs[*src as usize].clone(),
+        RegInstruction::LoadConst { dst, value } => regs[*dst as usize] = value.clone(),
+        RegInstruction::Float { op, dst, left, right } => {
+          let result = match (&regs[*left as usize], &regs[*right as usize]) {
+            (Value::Float(left), Value::Float(right)) => float(*op, *left, *right),
+            _ => return Err(RuntimeError::invalid_bytecode(src_func_ref, "Float operation on non float registers")),
+          };
+
+          regs[*dst as usize] = result;
+        }
+        RegInstruction::Call { dst, func: callee, args } => {
+          let params: Vec<Value> = args.iter().map(|arg| regs[*arg as usize].clone()).collect();
+
+          if let Some(RegInstruction::Return { src }) = func.body.get(index + 1) {
+            if src == dst {
+              return Ok(Exit::TailCall(callee.clone(), params));
+            }
+          }
+
+          regs[*dst as usize] = self.execute(callee.clone(), params)?;
+        }
+        RegInstruction::BranchFalse { condition, target } => match regs[*condition as usize] {
+          Value::True => {}
+          Value::False => {
+            index = *target;
+            continue;
+          }
+          _ => return Err(RuntimeError::invalid_bytecode(src_func_ref, "Attempt to Branch on non boolean")),
+        },
+        RegInstruction::Jump { target } => {
+          index = *target;
+          continue;
+        }
+        RegInstruction::Return { src } => return Ok(Exit::Return(::std::mem::replace(&mut regs[*src as usize], Value::Unit))),
+      }
+
+      index += 1;
+    }
+
+    Err(RuntimeError::invalid_bytecode(src_func_ref, "Overflowed function body"))
+  }
+
+  fn decode_module(&self, module: &BitModule) {
+    let mut threaded = self.threaded.borrow_mut();
+
+    for func in module.functions.values() {
+      if let RunFunction::BitFunction(func) = func {
+        threaded.insert(func.func_ref.clone(), decode(func, module).map(Rc::new));
+      }
+    }
+  }
+
+  /**
+  * The decoded form of func, if threaded dispatch is on and func could be decoded.
+  */
+  fn threaded_function(&self, func_ref: &FunctionRef) -> Option<Rc<ThreadedFunction>> {
+    if self.dispatch != Dispatch::Threaded || self.debugger.is_some() {
+      return None;
+    }
+
+    self.threaded.borrow().get(func_ref).cloned().and_then(|func| func)
+  }
+
+  fn run_threaded(&self, func: &ThreadedFunction, src_func_ref: &FunctionRef, mut locals: Vec<Value>) -> Result<Exit, RuntimeError> {
+    locals.resize(func.max_locals as usize, Value::Unit);
+
+    let mut frame = ThreadFrame { machine: self, func: src_func_ref, stack: Vec::with_capacity(func.max_stack as usize), locals };
+    let mut index = 0usize;
+
+    while index < func.ops.len() {
+      if self.limits.is_limited() {
+        self.check_limits(src_func_ref)?;
+      }
+
+      if let Some(profile) = &self.profile {
+        profile.borrow_mut().record_instruction(src_func_ref);
+      }
+
+      match (func.ops[index])(&mut frame)? {
+        Control::Next => index += 1,
+        Control::Jump(target) => index = target,
+        Control::Return(value) => return Ok(Exit::Return(value)),
+        Control::TailCall(func_ref, params) => return Ok(Exit::TailCall(func_ref, params)),
+      }
+    }
+
+    Err(RuntimeError::invalid_bytecode(src_func_ref, "Overflowed function body"))
+  }
+
+  /**
+  * Jumps are relative to the instruction after the jump, so a jump of 0 is a no-op.
+  */
   fn calculate_jump(index: usize, jump: i32) -> usize {
+    let next = index + 1;
+
     if jump >= 0 {
-      return index + (jump as usize);
+      return next + (jump as usize);
     } else {
       let rel = (0 - jump) as usize;
-      return index - rel;
+      return next - rel;
+    }
+  }
+}
+
+/**
+* Adds an enclosing call to a StackOverflow chain while it unwinds, counting a repeat of the last function instead.
+*/
+/**
+* Pops the right then left operand of a float instruction.
+*/
+fn pop_floats(stack: &mut Vec<Value>, func: &FunctionRef, name: &str) -> Result<(f64, f64), RuntimeError> {
+  match (stack.pop(), stack.pop()) {
+    (Some(Value::Float(right)), Some(Value::Float(left))) => Ok((left, right)),
+    _ => Err(RuntimeError::invalid_bytecode(func, &format!("{} needs two floats on the stack", name))),
+  }
+}
+
+fn extend_chain(mut chain: Vec<(FunctionRef, usize)>, func: FunctionRef) -> Vec<(FunctionRef, usize)> {
+  if let Some((last, count)) = chain.last_mut() {
+    if *last == func {
+      *count += 1;
+      return chain;
+    }
+  }
+
+  if chain.len() < STACK_OVERFLOW_CHAIN {
+    chain.push((func, 1));
+  }
+
+  chain
+}
+
+impl RunFunction {
+
+  pub fn func_ref(&self) -> &FunctionRef {
+    match self {
+      RunFunction::BitFunction(func) => &func.func_ref,
+      RunFunction::NativeFunction(func) => &func.func_ref,
     }
   }
+
 }
 
 impl BitFunction {
@@ -259,9 +1085,27 @@ impl BitFunction {
 
 }
 
+/**
+* The locals for a call of handle, allocated once with room for its captures and args.
+*/
+fn build_frame<'a, I: ExactSizeIterator<Item = Value>>(handle: &'a FunctionHandle, args: I) -> (&'a FunctionRef, Vec<Value>) {
+  let mut frame = Vec::with_capacity(handle.captured() + args.len());
+  let func_ref = handle.enter(&mut frame);
+  frame.extend(args);
+  (func_ref, frame)
+}
+
 impl FunctionHandle for FunctionRef {
-  fn with(&self, args: Vec<Value>) -> (&FunctionRef, Vec<Value>) {
-    (&self, args)
+  fn captured(&self) -> usize {
+    0
+  }
+
+  fn enter(&self, _frame: &mut Vec<Value>) -> &FunctionRef {
+    self
+  }
+
+  fn func_ref(&self) -> &FunctionRef {
+    self
   }
 }
 
@@ -271,28 +1115,57 @@ struct ClosureHandle {
 }
 
 impl FunctionHandle for ClosureHandle {
-  fn with(&self, mut args: Vec<Value>) -> (&FunctionRef, Vec<Value>) {
-    let mut locals = self.closures.clone();
-    locals.append(&mut args);
-    (&self.func, locals)
+  fn captured(&self) -> usize {
+    self.closures.len()
+  }
+
+  fn enter(&self, frame: &mut Vec<Value>) -> &FunctionRef {
+    frame.extend(self.closures.iter().cloned());
+    &self.func
+  }
+
+  fn func_ref(&self) -> &FunctionRef {
+    &self.func
   }
 }
 
+/**
+* A function that refers to itself, passed in as its own first argument. It only holds itself weakly, so it isn't
+* kept alive by a cycle, and every call sees the same handle rather than allocating a new one.
+*/
 struct RecursiveHandle {
   func: Rc<FunctionHandle>,
+  me: Weak<RecursiveHandle>,
+}
+
+impl RecursiveHandle {
+  fn new(func: Rc<FunctionHandle>) -> Rc<RecursiveHandle> {
+    Rc::new_cyclic(|me| RecursiveHandle { func, me: me.clone() })
+  }
 }
 
 impl FunctionHandle for RecursiveHandle {
-  fn with(&self, mut args: Vec<Value>) -> (&FunctionRef, Vec<Value>) {
-    let mut locals = Vec::with_capacity(args.len() + 1);
-    locals.push(Value::Function(Rc::new(RecursiveHandle { func: self.func.clone() })));
-    locals.append(&mut args);
-    self.func.with(locals)
+  fn captured(&self) -> usize {
+    self.func.captured() + 1
+  }
+
+  fn enter(&self, frame: &mut Vec<Value>) -> &FunctionRef {
+    // Whoever is calling holds a strong reference, so this can only fail if called outside of an Rc.
+    let me = self.me.upgrade().unwrap_or_else(|| RecursiveHandle::new(self.func.clone()));
+
+    // The wrapped function's captures come first, then itself as the first argument.
+    let func_ref = self.func.enter(frame);
+    frame.push(Value::Function(me));
+    func_ref
+  }
+
+  fn func_ref(&self) -> &FunctionRef {
+    self.func.func_ref()
   }
 }
 
 pub struct NativeFunction {
-  pub func: Box<Fn(&Machine, Vec<Value>) -> Result<Value, SimpleError>>,
+  pub func: Box<Fn(&Machine, Vec<Value>) -> Result<Value, RuntimeError>>,
   pub func_ref: FunctionRef,
 }
 
@@ -303,3 +1176,42 @@ impl NativeFunction {
   }
 
 }
+
+/**
+* Collects host functions into a BitModule that can be loaded into a Machine with load_module.
+*/
+pub struct NativeModuleBuilder {
+  package: String,
+  module: String,
+  functions: BTreeMap<String, RunFunction>,
+}
+
+impl NativeModuleBuilder {
+
+  pub fn new(package: &str, module: &str) -> NativeModuleBuilder {
+    NativeModuleBuilder {
+      package: String::from(package),
+      module: String::from(module),
+      functions: BTreeMap::new(),
+    }
+  }
+
+  pub fn function<Op: Fn(&Machine, Vec<Value>) -> Result<Value, RuntimeError> + 'static>(mut self, name: &str, shape: Shape, op: Op) -> NativeModuleBuilder {
+    let func_ref = FunctionRef {
+      package: self.package.clone(),
+      module: self.module.clone(),
+      name: String::from(name),
+
+      shape,
+    };
+
+    self.functions.insert(String::from(name), NativeFunction { func: Box::new(op), func_ref }.wrap());
+    self
+  }
+
+  pub fn build(self) -> BitModule {
+    let mut module = BitModule::new();
+    module.functions = self.functions;
+    module
+  }
+}
@@ -1,30 +1,31 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
 use std::io;
 use std::io::{BufWriter, Error, Read, Write};
 
 use bincode::{deserialize_from, serialize_into};
 use serde::{Deserialize, Serialize};
-use simple_error::SimpleError;
 
-use ast::{AssignmentEx, AstModule, BinaryOpEx, BlockEx, CallEx, Expression, FunctionDeclarationEx, IfEx, Location, NumberLiteralEx, Parameter, StringLiteralEx, VariableEx};
-use bytecode::{FunctionRef, LocalId};
-use ir::ScopeLookup::Local;
-use shapes::{Shape, shape_boolean, shape_float, shape_list};
+use ast::{AssertEx, AssignmentEx, AstModule, BinaryOpEx, BlockEx, CallEx, Expression, FunctionDeclarationEx, FieldEx, IfEx, IndexEx, ListEx, Location, NumberLiteralEx, Parameter, Pattern, ReassignmentEx, StringLiteralEx, TryEx, TupleEx, VariableEx, VariantEx, WhileEx, VARIANT_ERR, VARIANT_NONE};
+use bytecode::{FunctionRef, LocalId, SourcePoint};
+use format::format_expression;
+use error::CompileError;
+use shapes::{Shape, shape_boolean, shape_float, shape_list, shape_unit};
+use typechecker::AppShapes;
 
 #[derive(Serialize, Deserialize)]
 pub struct IrModule {
   pub package: String,
   pub name: String,
-  pub functions: HashMap<String, IrFunction>,
+  /** Ordered by name, so the compiler fills the constant pools in the same order every build. */
+  pub functions: BTreeMap<String, IrFunction>,
 }
 
 impl IrModule {
-  pub fn debug(&self) -> Result<(), SimpleError> {
+  pub fn debug(&self) -> io::Result<()> {
     let mut writer = io::stderr();
 
-    writer.write_all(format!("{}::{} \n", self.package, self.name).as_bytes())
-      .map_err(|err| SimpleError::from(err))?;
+    writer.write_all(format!("{}::{} \n", self.package, self.name).as_bytes())?;
 
     for func in self.functions.values() {
       func.pretty_print(&mut writer)?;
@@ -34,7 +35,7 @@ impl IrModule {
   }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct IrFunction {
   pub func_ref: FunctionRef,
   pub args: Vec<Parameter>,
@@ -49,28 +50,26 @@ impl IrFunction {
     self.pretty_print(&mut writer).unwrap();
   }
 
-  pub fn pretty_print<Writer: Write>(&self, writer: &mut Writer) -> Result<(), SimpleError> {
+  pub fn pretty_print<Writer: Write>(&self, writer: &mut Writer) -> io::Result<()> {
     let args: Vec<String> = self.args.iter().map(|param| param.pretty()).collect();
 
-    writer.write_all(format!("  {}({}): {}\n", self.func_ref.name, args.join(", "), self.func_ref.result().pretty()).as_bytes())
-      .map_err(|err| SimpleError::from(err))?;
+    writer.write_all(format!("  {}({}): {}\n", self.func_ref.name, args.join(", "), self.func_ref.result().pretty()).as_bytes())?;
 
 
     Ir::pretty_print(&self.body, "    ", writer)?;
 
     writer.write_all(b"\n")
-      .map_err(|err| SimpleError::from(err))
   }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Ir {
   NoOp,
   // 0 is an error to hopefully crash early on invalid bytecode.
   Duplicate,
   Pop,
   Swap,
-  LoadConstNull,
+  LoadConstUnit,
   LoadConstTrue,
   LoadConstFalse,
   LoadConstString {
@@ -91,6 +90,14 @@ pub enum Ir {
   CallStatic {
     func: FunctionRef,
   },
+  AddFloat,
+  SubFloat,
+  MulFloat,
+  DivFloat,
+  LessFloat,
+  LessEqualFloat,
+  GreaterFloat,
+  GreaterEqualFloat,
   CallDynamic {
     param_count: LocalId,
   },
@@ -99,29 +106,78 @@ pub enum Ir {
     func: FunctionRef,
   },
   BuildRecursiveFunction,
+  BuildTuple {
+    size: LocalId,
+  },
+  LoadElement {
+    index: LocalId,
+  },
+  BuildVariant {
+    tag: String,
+    size: LocalId,
+  },
+  IsVariant {
+    tag: String,
+  },
+  LoadVariantValue {
+    index: LocalId,
+  },
+  BuildList {
+    count: LocalId,
+  },
+  ListGet,
+  NewCell,
+  LoadCell,
+  StoreCell,
   Return,
   Branch {
     then_block: Vec<Ir>,
     else_block: Vec<Ir>,
   },
+  /**
+  * An else if chain. Each arm's condition runs in turn until one is true and only that arm's block runs, or the
+  * else_block if none are.
+  */
+  Cond {
+    arms: Vec<(Vec<Ir>, Vec<Ir>)>,
+    else_block: Vec<Ir>,
+  },
+  Loop {
+    condition: Vec<Ir>,
+    body: Vec<Ir>,
+  },
   Debug,
-  Error,
+  Error {
+    message: String,
+  },
+  Panic {
+    message: String,
+  },
+  /**
+  * Runs condition and fails with message, the condition as written, if it's false. Kept apart from the code around
+  * it so the whole check can be stripped.
+  */
+  Assert {
+    condition: Vec<Ir>,
+    message: String,
+    point: SourcePoint,
+  },
   FreeLocal {
     local: String,
   }
 }
 
 impl Ir {
-  pub fn pretty_print<Writer: Write>(block: &Vec<Ir>, indent: &str, writer: &mut Writer) -> Result<(), SimpleError> {
+  pub fn pretty_print<Writer: Write>(block: &Vec<Ir>, indent: &str, writer: &mut Writer) -> io::Result<()> {
     for (index, next) in block.iter().enumerate() {
-      writer.write_all(format!("{}{}: ", indent, index).as_bytes()).map_err(|err| SimpleError::from(err))?;
+      writer.write_all(format!("{}{}: ", indent, index).as_bytes())?;
 
       match next {
         Ir::NoOp => writer.write_all(b"NoOp"),
         Ir::Duplicate => writer.write_all(b"Duplicate"),
         Ir::Pop => writer.write_all(b"Pop"),
         Ir::Swap => writer.write_all(b"Swap"),
-        Ir::LoadConstNull => writer.write_all(b"LoadConstNull"),
+        Ir::LoadConstUnit => writer.write_all(b"LoadConstUnit"),
         Ir::LoadConstTrue => writer.write_all(b"LoadConstTrue"),
         Ir::LoadConstFalse => writer.write_all(b"LoadConstFalse"),
         Ir::LoadConstString { value } => writer.write_all(format!("LoadConstString('{}')", value).as_bytes()),
@@ -131,47 +187,95 @@ impl Ir {
         Ir::StoreValue { local } => writer.write_all(format!("StoreValue({})", local).as_bytes()),
         Ir::CallStatic { func } => writer.write_all(format!("CallStatic({})", func.pretty()).as_bytes()),
         Ir::CallDynamic { param_count } => writer.write_all(format!("CallDynamic({})", param_count).as_bytes()),
+        Ir::AddFloat => writer.write_all(b"AddFloat"),
+        Ir::SubFloat => writer.write_all(b"SubFloat"),
+        Ir::MulFloat => writer.write_all(b"MulFloat"),
+        Ir::DivFloat => writer.write_all(b"DivFloat"),
+        Ir::LessFloat => writer.write_all(b"LessFloat"),
+        Ir::LessEqualFloat => writer.write_all(b"LessEqualFloat"),
+        Ir::GreaterFloat => writer.write_all(b"GreaterFloat"),
+        Ir::GreaterEqualFloat => writer.write_all(b"GreaterEqualFloat"),
         Ir::BuildClosure { param_count, func } => writer.write_all(format!("BuildClosure({}, '{}')", *param_count, func.pretty()).as_bytes()),
         Ir::BuildRecursiveFunction => writer.write_all(b"BuildRecursiveFunction"),
+        Ir::BuildTuple { size } => writer.write_all(format!("BuildTuple({})", size).as_bytes()),
+        Ir::LoadElement { index } => writer.write_all(format!("LoadElement({})", index).as_bytes()),
+        Ir::BuildVariant { tag, size } => writer.write_all(format!("BuildVariant('{}', {})", tag, size).as_bytes()),
+        Ir::IsVariant { tag } => writer.write_all(format!("IsVariant('{}')", tag).as_bytes()),
+        Ir::LoadVariantValue { index } => writer.write_all(format!("LoadVariantValue({})", index).as_bytes()),
+        Ir::BuildList { count } => writer.write_all(format!("BuildList({})", count).as_bytes()),
+        Ir::ListGet => writer.write_all(b"ListGet"),
+        Ir::NewCell => writer.write_all(b"NewCell"),
+        Ir::LoadCell => writer.write_all(b"LoadCell"),
+        Ir::StoreCell => writer.write_all(b"StoreCell"),
         Ir::Return => writer.write_all(b"Return"),
         Ir::Branch{then_block, else_block} => {
           let inner_indent = format!("{}    ", indent);
-          writer.write_all(format!("Branch\n{}  then_block:\n", indent).as_bytes())
-            .map_err(|err| SimpleError::from(err))?;
+          writer.write_all(format!("Branch\n{}  then_block:\n", indent).as_bytes())?;
           Ir::pretty_print(then_block, &inner_indent, writer)?;
-          writer.write_all(format!("{}  else_block:\n", indent).as_bytes())
-            .map_err(|err| SimpleError::from(err))?;
+          writer.write_all(format!("{}  else_block:\n", indent).as_bytes())?;
           Ir::pretty_print(else_block, &inner_indent, writer)?;
           Ok(())
         },
+        Ir::Cond{arms, else_block} => {
+          let inner_indent = format!("{}    ", indent);
+          writer.write_all(b"Cond\n")?;
+
+          for (condition, then_block) in arms {
+            writer.write_all(format!("{}  condition:\n", indent).as_bytes())?;
+            Ir::pretty_print(condition, &inner_indent, writer)?;
+            writer.write_all(format!("{}  then_block:\n", indent).as_bytes())?;
+            Ir::pretty_print(then_block, &inner_indent, writer)?;
+          }
+
+          writer.write_all(format!("{}  else_block:\n", indent).as_bytes())?;
+          Ir::pretty_print(else_block, &inner_indent, writer)?;
+          Ok(())
+        },
+        Ir::Loop{condition, body} => {
+          let inner_indent = format!("{}    ", indent);
+          writer.write_all(format!("Loop\n{}  condition:\n", indent).as_bytes())?;
+          Ir::pretty_print(condition, &inner_indent, writer)?;
+          writer.write_all(format!("{}  body:\n", indent).as_bytes())?;
+          Ir::pretty_print(body, &inner_indent, writer)?;
+          Ok(())
+        },
         Ir::Debug => writer.write_all(b"Debug"),
-        Ir::Error => writer.write_all(b"Error"),
+        Ir::Error { message } => writer.write_all(format!("Error('{}')", message).as_bytes()),
+        Ir::Panic { message } => writer.write_all(format!("Panic('{}')", message).as_bytes()),
+        Ir::Assert{condition, message, point} => {
+          let inner_indent = format!("{}    ", indent);
+          writer.write_all(format!("Assert('{}', {})\n{}  condition:\n", message, point.pretty(), indent).as_bytes())?;
+          Ir::pretty_print(condition, &inner_indent, writer)?;
+          Ok(())
+        },
         Ir::FreeLocal {local} => writer.write_all(format!("FreeLocal({})", local).as_bytes())
-      }.map_err(|err| SimpleError::from(err))?;
+      }?;
 
-      writer.write_all(b"\n").map_err(|err| SimpleError::from(err))?;
+      writer.write_all(b"\n")?;
     }
 
     Ok(())
   }
 }
 
-pub fn compile_ir_module(module: &AstModule) -> Result<IrModule, SimpleError> {
+pub fn compile_ir_module(module: &AstModule, shapes: &AppShapes) -> Result<IrModule, CompileError> {
   let mut context = IrModuleContext::new(module.package.clone(), module.name.clone());
 
-  let core_lib = CoreLibContext::new();
-
   for imp in &module.imports {
-    if &imp.package == "Core" {
-      let lib = core_lib.scope.get(&imp.module)
-        .ok_or_else(|| SimpleError::new(format!("Can't find function {} in core lib.", &imp.module)))?;
+    let lib = shapes.lookup_module(&imp.package, &imp.module)
+      .ok_or_else(|| CompileError::NoSuchModule { package: imp.package.clone(), module: imp.module.clone() })?;
 
-      for lookup in lib {
-        match lookup {
-          ScopeLookup::Static(fun) => context.declared_functions.insert(format!("{}.{}", &imp.module, fun.name), lookup.clone()),
-          ScopeLookup::Local => return Err(SimpleError::new(format!("Can't find function {} in core lib.", &imp.module)))
-        };
-      }
+    for name in lib.list_values() {
+      let shape = lib.lookup(&name).expect("Invalid impl");
+      let func_ref = FunctionRef {
+        package: imp.package.clone(),
+        module: imp.module.clone(),
+        name: name.clone(),
+
+        shape,
+      };
+
+      context.declared_functions.insert(format!("{}.{}", &imp.module, name), ScopeLookup::Static(func_ref));
     }
   }
 
@@ -187,7 +291,11 @@ pub fn compile_ir_module(module: &AstModule) -> Result<IrModule, SimpleError> {
   }
 
   for func in &module.functions {
-    compile_ir_function(&func.ex, &mut context)?;
+    compile_ir_function(&func.ex, &HashSet::new(), &mut context)?;
+  }
+
+  for func in context.functions.values() {
+    check_initialized(func)?;
   }
 
   Ok(IrModule {
@@ -198,38 +306,146 @@ pub fn compile_ir_module(module: &AstModule) -> Result<IrModule, SimpleError> {
   })
 }
 
-fn compile_ir_function(ex: &FunctionDeclarationEx, context: &mut IrModuleContext) -> Result<FunctionRef, SimpleError> {
-  context.push_function();
+/**
+* Closures named in cells were captured from a local that was already boxed, and arrive as the cell itself.
+*/
+fn compile_ir_function(ex: &FunctionDeclarationEx, cells: &HashSet<String>, context: &mut IrModuleContext) -> Result<FunctionRef, CompileError> {
+  let mut captured = HashSet::new();
+  find_captured(&ex.body, &mut captured);
+
+  context.push_function(captured);
 
   for closure in &ex.context.closures {
-    context.store(closure.id.clone());
+    if cells.contains(&closure.id) {
+      context.store_cell(closure.id.clone());
+    } else {
+      context.store(closure.id.clone());
+    }
   }
 
+  let mut params: Vec<String> = ex.args.iter().map(|arg| arg.id.clone()).collect();
+
   if ex.context.is_recursive {
-    context.store(ex.id.clone());
+    params.insert(0, ex.id.clone());
   }
 
-  for arg in &ex.args {
-    context.store(arg.id.clone());
+  // A captured parameter is boxed into a cell in its own local, it isn't shadowing anything.
+  for param in params {
+    if context.is_captured(&param) {
+      context.append(Ir::LoadValue { local: param.clone() });
+      context.append(Ir::NewCell);
+      context.store_cell(param.clone());
+      context.append(Ir::StoreValue { local: param });
+    } else {
+      context.store(param);
+    }
   }
 
   compile_ir_expression(&ex.body, context)?;
 
+  // Nothing after a body that always returns or panics would ever run.
+  if diverges(&ex.body) {
+    return Ok(context.pop_function(ex));
+  }
+
+  // A body ending in a declaration or assignment has nothing to return, it's Unit. So is a Unit function whatever it ends in.
+  let discards = ex.result == shape_unit() && ex.body.shape() != shape_unit();
+
+  if pushes_value(&ex.body) && discards {
+    context.append(Ir::Pop);
+  }
+
+  if !pushes_value(&ex.body) || discards {
+    context.append(Ir::LoadConstUnit);
+  }
+
   context.append(Ir::Return);
 
   return Ok(context.pop_function(ex));
 }
 
-fn compile_ir_expression(ex: &Expression, context: &mut IrModuleContext) -> Result<(), SimpleError> {
+/**
+* Collects every name an inner function captures, at any depth. Those locals are boxed in a Value::Cell so the
+* function that declared them and every closure share one copy.
+*/
+fn find_captured(ex: &Expression, captured: &mut HashSet<String>) {
   match ex {
-    Expression::NoOp(_) => Ok(()),
+    Expression::FunctionDeclaration(func) => {
+      for closure in &func.context.closures {
+        captured.insert(closure.id.clone());
+      }
+
+      find_captured(&func.body, captured);
+    }
+    Expression::Assignment(ex) => find_captured(&ex.body, captured),
+    Expression::Reassignment(ex) => find_captured(&ex.body, captured),
+    Expression::BinaryOp(ex) => {
+      find_captured(&ex.left, captured);
+      find_captured(&ex.right, captured);
+    }
+    Expression::Call(ex) => {
+      find_captured(&ex.func, captured);
+
+      for arg in &ex.args {
+        find_captured(arg, captured);
+      }
+    }
+    Expression::If(ex) => {
+      find_captured(&ex.condition, captured);
+      find_captured(&ex.then_block, captured);
+      find_captured(&ex.else_block, captured);
+    }
+    Expression::While(ex) => {
+      find_captured(&ex.condition, captured);
+      find_captured(&ex.body, captured);
+    }
+    Expression::Block(ex) => {
+      for next in &ex.body {
+        find_captured(next, captured);
+      }
+    }
+    Expression::Tuple(ex) => {
+      for item in &ex.items {
+        find_captured(item, captured);
+      }
+    }
+    Expression::List(ex) => {
+      for item in &ex.items {
+        find_captured(item, captured);
+      }
+    }
+    Expression::Variant(ex) => {
+      for value in &ex.values {
+        find_captured(value, captured);
+      }
+    }
+    Expression::Try(ex) => find_captured(&ex.body, captured),
+    Expression::Return(ex) => find_captured(&ex.body, captured),
+    Expression::Index(ex) => {
+      find_captured(&ex.list, captured);
+      find_captured(&ex.index, captured);
+    }
+    Expression::Field(ex) => find_captured(&ex.value, captured),
+    Expression::Assert(ex) => find_captured(&ex.condition, captured),
+    Expression::NoOp(_) | Expression::Import(_) | Expression::Variable(_) | Expression::StringLiteral(_)
+      | Expression::NumberLiteral(_) | Expression::BooleanLiteral(..) | Expression::UnitLiteral(_) | Expression::Hole(_) | Expression::Panic(_) => {}
+  }
+}
+
+fn compile_ir_expression(ex: &Expression, context: &mut IrModuleContext) -> Result<(), CompileError> {
+  match ex {
+    Expression::NoOp(_) => Ok(context.append(Ir::LoadConstUnit)),
     Expression::FunctionDeclaration(ex) => ex.compile_ir(context),
     Expression::Assignment(ex) => ex.compile_ir(context),
+    Expression::Reassignment(ex) => ex.compile_ir(context),
     Expression::Variable(ex) => ex.compile_ir(context),
     Expression::BinaryOp(ex) => ex.compile_ir(context),
     Expression::Call(ex) => ex.compile_ir(context),
     Expression::If(ex) => ex.compile_ir(context),
+    Expression::While(ex) => ex.compile_ir(context),
     Expression::Block(ex) => ex.compile_ir(context),
+    Expression::Tuple(ex) => ex.compile_ir(context),
+    Expression::List(ex) => ex.compile_ir(context),
     Expression::StringLiteral(ex) => ex.compile_ir(context),
     Expression::NumberLiteral(ex) => ex.compile_ir(context),
     Expression::BooleanLiteral(_, value) => {
@@ -240,39 +456,68 @@ fn compile_ir_expression(ex: &Expression, context: &mut IrModuleContext) -> Resu
       }
       Ok(())
     }
+    Expression::UnitLiteral(_) => Ok(context.append(Ir::LoadConstUnit)),
+    Expression::Hole(ex) => Ok(context.append(Ir::Error { message: ex.message() })),
+    Expression::Panic(ex) => Ok(context.append(Ir::Panic { message: ex.message.clone() })),
+    Expression::Variant(ex) => ex.compile_ir(context),
+    Expression::Try(ex) => ex.compile_ir(context),
+    Expression::Return(ex) => {
+      compile_ir_expression(&ex.body, context)?;
+      Ok(context.append(Ir::Return))
+    }
+    Expression::Index(ex) => ex.compile_ir(context),
+    Expression::Field(ex) => ex.compile_ir(context),
+    Expression::Assert(ex) => ex.compile_ir(context),
 
     _ => unimplemented!()
   }
 }
 
 trait IrCompilable {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError>;
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError>;
 }
 
 impl IrCompilable for StringLiteralEx {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError> {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
     Ok(context.append(Ir::LoadConstString { value: self.value.clone() }))
   }
 }
 
 impl IrCompilable for NumberLiteralEx {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError> {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
     Ok(context.append(Ir::LoadConstFloat { value: self.value }))
   }
 }
 
 impl IrCompilable for BlockEx {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError> {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    context.push_scope();
+
     for ex in &self.body {
+      if let Expression::FunctionDeclaration(func) = ex {
+        if func.context.is_forward {
+          context.declare_forward(func.id.clone());
+        }
+      }
+    }
+
+    // Only the last statement's value is the block's, any before it are thrown away. Even a Unit one is pushed.
+    for (index, ex) in self.body.iter().enumerate() {
       compile_ir_expression(ex, context)?;
+
+      if index + 1 < self.body.len() && pushes_value(ex) {
+        context.discard();
+      }
     }
+
+    context.pop_scope();
     Ok(())
   }
 }
 
 impl IrCompilable for CallEx {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError> {
-    let CallEx { shape, loc, func, args } = self;
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    let CallEx { shape, loc, func, args, .. } = self;
 
     if let Expression::Variable(var) = func {
       if let ScopeLookup::Static(func_ref) = context.lookup(&var.id, loc)? {
@@ -291,10 +536,16 @@ impl IrCompilable for CallEx {
       compile_ir_expression(arg, context)?;
     }
 
-    if let Shape::SimpleFunctionShape {args, ..} = func.shape() {
+    if let Shape::SimpleFunctionShape {args, result} = func.shape() {
       context.append(Ir::CallDynamic { param_count: args.len() as LocalId });
+
+      // A function of any result can be passed as one whose result is Unit, so whatever it returned is swapped for ().
+      if *result == shape_unit() {
+        context.append(Ir::Pop);
+        context.append(Ir::LoadConstUnit);
+      }
     } else {
-      return self.loc.fail("Function does not have function shape");
+      return Err(CompileError::NotAFunction { loc: self.loc.clone() });
     }
 
     Ok(())
@@ -302,57 +553,334 @@ impl IrCompilable for CallEx {
 }
 
 impl IrCompilable for IfEx {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError> {
-    let IfEx{shape: raw_shape, loc, condition, then_block: raw_then_block, else_block: raw_else_block} = self;
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    // An else if chain becomes the arms of one Cond, rather than Branches nested in each other's else_block.
+    let mut arms: Vec<&IfEx> = vec![self];
+    let mut last = &self.else_block;
+
+    while let Expression::If(next) = last {
+      arms.push(next);
+      last = &next.else_block;
+    }
+
+    // Without a final else nothing is pushed when every condition is false, so each arm drops any Unit its block
+    // leaves and the whole if pushes one Unit after.
+    let is_unit = if let Expression::NoOp(_) = last { true } else { false };
+
+    let mut compiled = Vec::new();
+
+    for arm in arms {
+      context.push_block();
+      compile_ir_expression(&arm.condition, context)?;
+      let condition = context.pop_block();
 
-    compile_ir_expression(condition, context)?;
+      context.push_block();
+      compile_ir_expression(&arm.then_block, context)?;
 
+      if is_unit && pushes_value(&arm.then_block) {
+        context.append(Ir::Pop);
+      }
+
+      if !is_unit {
+        push_unit_if_empty(&arm.then_block, context);
+      }
+
+      compiled.push((condition, context.pop_block()));
+    }
+
+    let else_block = if is_unit {
+      Vec::new()
+    } else {
+      context.push_block();
+      compile_ir_expression(last, context)?;
+      push_unit_if_empty(last, context);
+      context.pop_block()
+    };
+
+    if compiled.len() == 1 {
+      let (condition, then_block) = compiled.pop().unwrap();
+
+      for next in condition {
+        context.append(next);
+      }
+
+      context.append(Ir::Branch {then_block, else_block});
+    } else {
+      context.append(Ir::Cond {arms: compiled, else_block});
+    }
+
+    if is_unit {
+      context.append(Ir::LoadConstUnit);
+    }
+
+    Ok(())
+  }
+}
+
+impl IrCompilable for VariantEx {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    for value in &self.values {
+      compile_ir_expression(value, context)?;
+    }
+
+    context.append(Ir::BuildVariant { tag: self.tag.clone(), size: self.values.len() as LocalId });
+    Ok(())
+  }
+}
+
+/**
+* Desugars to an early return. A copy of the value is tested for the failing variant, which is returned from the
+* function as it is, otherwise the value inside is unwrapped in its place.
+*/
+impl IrCompilable for TryEx {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    let failure = if self.body.shape().result_args().is_some() {
+      VARIANT_ERR
+    } else {
+      VARIANT_NONE
+    };
+
+    compile_ir_expression(&self.body, context)?;
+
+    context.append(Ir::Duplicate);
+    context.append(Ir::IsVariant { tag: String::from(failure) });
+    context.append(Ir::Branch {
+      then_block: vec![Ir::Return],
+      else_block: vec![Ir::LoadVariantValue { index: 0 }],
+    });
+
+    Ok(())
+  }
+}
+
+impl IrCompilable for ListEx {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    for item in &self.items {
+      compile_ir_expression(item, context)?;
+    }
+
+    Ok(context.append(Ir::BuildList { count: self.items.len() as LocalId }))
+  }
+}
+
+impl IrCompilable for IndexEx {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    compile_ir_expression(&self.list, context)?;
+    compile_ir_expression(&self.index, context)?;
+    context.append(Ir::ListGet);
+    Ok(())
+  }
+}
+
+impl IrCompilable for FieldEx {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    compile_ir_expression(&self.value, context)?;
+    context.append(Ir::LoadVariantValue { index: self.index as LocalId });
+    Ok(())
+  }
+}
+
+impl IrCompilable for AssertEx {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
     context.push_block();
-    compile_ir_expression(raw_then_block, context)?;
-    let then_block = context.pop_block();
+    compile_ir_expression(&self.condition, context)?;
+    let condition = context.pop_block();
+
+    context.append(Ir::Assert {
+      condition,
+      message: format_expression(&self.condition, 0),
+      point: SourcePoint::from_location(&self.loc),
+    });
+    context.append(Ir::LoadConstUnit);
+    Ok(())
+  }
+}
+
+impl IrCompilable for WhileEx {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    let WhileEx{shape, loc, condition: raw_condition, body: raw_body} = self;
+
+    context.push_block();
+    compile_ir_expression(raw_condition, context)?;
+    let condition = context.pop_block();
 
     context.push_block();
-    compile_ir_expression(raw_else_block, context)?;
-    let else_block = context.pop_block();
+    compile_ir_expression(raw_body, context)?;
 
-    context.append(Ir::Branch {then_block, else_block});
+    // Each iteration's result is thrown away, the loop as a whole is Unit.
+    if pushes_value(raw_body) {
+      context.append(Ir::Pop);
+    }
+
+    let body = context.pop_block();
+
+    context.append(Ir::Loop {condition, body});
+    context.append(Ir::LoadConstUnit);
     Ok(())
   }
 }
 
+const RANGE_OP: &'static str = "..";
+
+/**
+* The instruction that does the work of a Core float operator inline, so it doesn't need a call.
+*/
+fn float_op(func: &FunctionRef) -> Option<Ir> {
+  if func.package != "Core" || func.module != "Core" {
+    return None;
+  }
+
+  match func.name.as_str() {
+    "+" => Some(Ir::AddFloat),
+    "-" => Some(Ir::SubFloat),
+    "*" => Some(Ir::MulFloat),
+    "/" => Some(Ir::DivFloat),
+    "<" => Some(Ir::LessFloat),
+    "<=" => Some(Ir::LessEqualFloat),
+    ">" => Some(Ir::GreaterFloat),
+    ">=" => Some(Ir::GreaterEqualFloat),
+    _ => None,
+  }
+}
+
+/**
+* Whether compiling this expression leaves a value on the stack. Declarations only store into locals, and nothing
+* runs after a return or panic to find one.
+*/
+fn pushes_value(ex: &Expression) -> bool {
+  match ex {
+    Expression::Return(_) | Expression::Panic(_) => false,
+    Expression::Import(_) => false,
+    Expression::Assignment(_) => false,
+    Expression::Reassignment(_) => false,
+    Expression::FunctionDeclaration(ex) => ex.context.is_lambda,
+    Expression::Block(ex) => ex.body.last().map(pushes_value).unwrap_or(false),
+    _ => true
+  }
+}
+
+/**
+* Every arm of an if with an else leaves one value, so an arm ending in something like a reassignment leaves ().
+*/
+fn push_unit_if_empty(block: &Expression, context: &mut IrModuleContext) {
+  if !pushes_value(block) && !diverges(block) {
+    context.append(Ir::LoadConstUnit);
+  }
+}
+
+/**
+* Whether this expression always leaves the function, by returning or panicking.
+*/
+fn diverges(ex: &Expression) -> bool {
+  match ex {
+    Expression::Return(_) | Expression::Panic(_) => true,
+    Expression::Block(ex) => ex.body.last().map(diverges).unwrap_or(false),
+    _ => false
+  }
+}
+
 impl IrCompilable for BinaryOpEx {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError> {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
     let BinaryOpEx { shape, loc, op, left, right } = self;
     compile_ir_expression(left, context)?;
     compile_ir_expression(right, context)?;
 
     if let ScopeLookup::Static(func) = context.lookup(&op, loc)? {
-      context.append(Ir::CallStatic { func });
+      // start..end is List.range(start, end, 1)
+      if op == RANGE_OP {
+        context.append(Ir::LoadConstFloat { value: 1.0 });
+      }
+
+      match float_op(&func) {
+        Some(ir) => context.append(ir),
+        None => context.append(Ir::CallStatic { func }),
+      }
+
       Ok(())
     } else {
-      loc.fail(&format!("Could not look up Core operator function {}", op))
+      Err(CompileError::MissingOperator { loc: loc.clone(), op: op.clone() })
     }
   }
 }
 
 impl IrCompilable for VariableEx {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError> {
-    Ok(context.append(Ir::LoadValue { local: self.id.clone() }))
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    match context.lookup(&self.id, &self.loc)? {
+      // A module function used as a value rather than called, it isn't in any local.
+      ScopeLookup::Static(value) => context.append(Ir::LoadConstFunction { value }),
+      ScopeLookup::Local(local) => context.append(Ir::LoadValue { local }),
+      ScopeLookup::Cell(local) => {
+        context.append(Ir::LoadValue { local });
+        context.append(Ir::LoadCell);
+      }
+    }
+
+    Ok(())
   }
 }
 
 impl IrCompilable for AssignmentEx {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError> {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
     compile_ir_expression(&self.body, context)?;
-    context.store(self.id.clone());
-    Ok(context.append(Ir::StoreValue { local: self.id.clone() }))
+    compile_ir_pattern(&self.pattern, context);
+    Ok(())
+  }
+}
+
+/**
+* A captured variable lives in a cell shared with the closures, so the new value goes into the cell rather than
+* the local that holds it.
+*/
+impl IrCompilable for ReassignmentEx {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    let local = context.local(&self.id);
+
+    if context.is_cell(&self.id) {
+      context.append(Ir::LoadValue { local });
+      compile_ir_expression(&self.body, context)?;
+      context.append(Ir::StoreCell);
+    } else {
+      compile_ir_expression(&self.body, context)?;
+      context.append(Ir::StoreValue { local });
+    }
+
+    Ok(())
+  }
+}
+
+/**
+* Stores the value on top of the stack into the pattern's locals, consuming it.
+* Tuples are kept on the stack while each element is pulled out of a copy.
+*/
+fn compile_ir_pattern(pattern: &Pattern, context: &mut IrModuleContext) {
+  match pattern {
+    Pattern::Id(id) => context.declare(id.clone()),
+    Pattern::Tuple(items) => {
+      for (index, item) in items.iter().enumerate() {
+        context.append(Ir::Duplicate);
+        context.append(Ir::LoadElement { index: index as LocalId });
+        compile_ir_pattern(item, context);
+      }
+
+      context.append(Ir::Pop);
+    }
+  }
+}
+
+impl IrCompilable for TupleEx {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
+    for item in &self.items {
+      compile_ir_expression(item, context)?;
+    }
+
+    Ok(context.append(Ir::BuildTuple { size: self.items.len() as LocalId }))
   }
 }
 
 impl IrCompilable for FunctionDeclarationEx {
-  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), SimpleError> {
+  fn compile_ir(&self, context: &mut IrModuleContext) -> Result<(), CompileError> {
     if self.context.closures.is_empty() {
-      let func_ref = compile_ir_function(self, context)?;
+      let func_ref = compile_ir_function(self, &HashSet::new(), context)?;
 
       context.append(Ir::LoadConstFunction { value: func_ref });
 
@@ -360,19 +888,26 @@ impl IrCompilable for FunctionDeclarationEx {
         context.append(Ir::BuildRecursiveFunction);
       }
 
-      if !self.context.is_lambda {
-        context.store(self.id.clone());
-        context.append((Ir::StoreValue { local: self.id.clone() }));
+      if self.context.is_forward {
+        context.define_forward(&self.id);
+      } else if !self.context.is_lambda {
+        context.declare(self.id.clone());
       }
 
       return Ok(());
     } else {
+      let mut cells = HashSet::new();
+
       for local in &self.context.closures {
         let lookup = context.lookup(&local.id, &self.loc)?;
 
         match lookup {
-          ScopeLookup::Local => {
-            context.append(Ir::LoadValue { local: local.id.clone() })
+          ScopeLookup::Local(name) => {
+            context.append(Ir::LoadValue { local: name })
+          }
+          ScopeLookup::Cell(name) => {
+            cells.insert(local.id.clone());
+            context.append(Ir::LoadValue { local: name })
           }
           ScopeLookup::Static(value) => {
             context.append(Ir::LoadConstFunction { value })
@@ -380,7 +915,7 @@ impl IrCompilable for FunctionDeclarationEx {
         }
       }
 
-      let func = compile_ir_function(self, context)?;
+      let func = compile_ir_function(self, &cells, context)?;
 
       context.append(Ir::BuildClosure { param_count: self.context.closures.len() as LocalId, func });
 
@@ -388,9 +923,10 @@ impl IrCompilable for FunctionDeclarationEx {
         context.append(Ir::BuildRecursiveFunction);
       }
 
-      if !self.context.is_lambda {
-        context.store(self.id.clone());
-        context.append(Ir::StoreValue { local: self.id.clone() });
+      if self.context.is_forward {
+        context.define_forward(&self.id);
+      } else if !self.context.is_lambda {
+        context.declare(self.id.clone());
       }
 
       return Ok(());
@@ -425,13 +961,28 @@ impl IrCoreContext {
       }));
     };
 
+    scope.insert(String::from(RANGE_OP), ScopeLookup::Static(FunctionRef {
+      package: String::from("Core"),
+      module: String::from("List"),
+      name: String::from("range"),
+      shape: Shape::SimpleFunctionShape {
+        args: vec![shape_float(), shape_float(), shape_float()],
+        result: Box::new(shape_list(shape_float())),
+      },
+    }));
+
     insert(&mut scope, "+", float_op.clone());
     insert(&mut scope, "-", float_op.clone());
     insert(&mut scope, "*", float_op.clone());
     insert(&mut scope, "/", float_op.clone());
 
-    insert(&mut scope, "==", float_compare_op.clone());
-    insert(&mut scope, "!=", float_compare_op.clone());
+    let equality_op = Shape::SimpleFunctionShape {
+      args: vec![Shape::UnknownShape, Shape::UnknownShape],
+      result: Box::new(shape_boolean()),
+    };
+
+    insert(&mut scope, "==", equality_op.clone());
+    insert(&mut scope, "!=", equality_op.clone());
     insert(&mut scope, ">", float_compare_op.clone());
     insert(&mut scope, "<", float_compare_op.clone());
     insert(&mut scope, ">=", float_compare_op.clone());
@@ -443,76 +994,13 @@ impl IrCoreContext {
   }
 }
 
-struct CoreLibContext {
-  scope: HashMap<String, Vec<ScopeLookup>>,
-}
-
-impl CoreLibContext {
-
-  fn new() -> CoreLibContext {
-    let mut me = CoreLibContext {
-      scope: HashMap::new()
-    };
-    me.list();
-    me
-  }
-
-  fn list(&mut self) {
-    let mut scope = Vec::new();
-
-    let float_list = shape_list(shape_float());
-
-    fn insert(scope: &mut Vec<ScopeLookup>, name: &'static str, shape: Shape) {
-      scope.push(ScopeLookup::Static(FunctionRef {
-        package: String::from("Core"),
-        module: String::from("List"),
-        name: String::from(name),
-        shape,
-      }));
-    };
-
-    insert(&mut scope, "new", Shape::SimpleFunctionShape {
-      args: vec![],
-      result: Box::new(float_list.clone())
-    });
-
-    insert(&mut scope, "append", Shape::SimpleFunctionShape {
-      args: vec![float_list.clone(), shape_float()],
-      result: Box::new(float_list.clone())
-    });
-
-    let mapper_shape = Shape::SimpleFunctionShape {
-      args: vec![shape_float()],
-      result: Box::new(shape_float())
-    };
-
-    insert(&mut scope, "map", Shape::SimpleFunctionShape {
-      args: vec![float_list.clone(), mapper_shape],
-      result: Box::new(float_list.clone())
-    });
-
-    let reducer_shape = Shape::SimpleFunctionShape {
-      args: vec![shape_float(), shape_float()],
-      result: Box::new(shape_float())
-    };
-
-    insert(&mut scope, "fold", Shape::SimpleFunctionShape {
-      args: vec![float_list.clone(), shape_float(), reducer_shape],
-      result: Box::new(float_list.clone())
-    });
-
-    self.scope.insert("List".to_string(), scope);
-  }
-
-}
-
 struct IrModuleContext {
   core: IrCoreContext,
   package: String,
   module: String,
 
   declared_functions: HashMap<String, ScopeLookup>,
-  functions: HashMap<String, IrFunction>,
+  functions: BTreeMap<String, IrFunction>,
 
   function_context: Vec<IrFuncContext>,
 }
@@ -525,7 +1013,7 @@ impl IrModuleContext {
       module,
 
       declared_functions: HashMap::new(),
-      functions: HashMap::new(),
+      functions: BTreeMap::new(),
       function_context: Vec::new(),
     }
   }
@@ -534,7 +1022,20 @@ impl IrModuleContext {
     self.function_context.last_mut().unwrap().append(ir)
   }
 
-  fn lookup(&self, name: &str, loc: &Location) -> Result<ScopeLookup, SimpleError> {
+  /**
+  * Throws away the value just pushed, or the instruction that pushed it if that was only a constant ().
+  */
+  fn discard(&mut self) {
+    let block = self.function_context.last_mut().unwrap().body.last_mut().unwrap();
+
+    if let Some(Ir::LoadConstUnit) = block.last() {
+      block.pop();
+    } else {
+      block.push(Ir::Pop);
+    }
+  }
+
+  fn lookup(&self, name: &str, loc: &Location) -> Result<ScopeLookup, CompileError> {
     for func in self.function_context.iter().rev() {
       if let Some(lookup) = func.lookup(name) {
         return Ok(lookup);
@@ -549,27 +1050,95 @@ impl IrModuleContext {
       return Ok(core.clone());
     }
 
-    loc.fail(&format!("Variable '{}' not found in IrCompiler scope", name))
+    Err(CompileError::UnknownLocal { loc: loc.clone(), name: String::from(name) })
   }
 
   fn store(&mut self, name: String) {
     self.function_context.last_mut().unwrap().store(name);
   }
 
-  fn push_function(&mut self) {
-    self.function_context.push(IrFuncContext::new())
+  fn store_cell(&mut self, name: String) {
+    self.function_context.last_mut().unwrap().store_cell(name);
   }
 
-  fn pop_function(&mut self, ex: &FunctionDeclarationEx) -> FunctionRef {
-    let mut context = self.function_context.pop().unwrap();
+  /**
+  * Stores the top of the stack as a new local, boxing it first if an inner function captures it. A name that shadows
+  * another gets a local of its own, so the one it hides is still intact once the block ends.
+  */
+  fn declare(&mut self, name: String) {
+    let func = self.function_context.last_mut().unwrap();
+    let local = func.fresh_local(&name);
+
+    if func.captured.contains(&name) {
+      func.append(Ir::NewCell);
+      func.bind(name, ScopeLookup::Cell(local.clone()));
+    } else {
+      func.bind(name, ScopeLookup::Local(local.clone()));
+    }
 
-    let func_ref = FunctionRef {
-      package: self.package.clone(),
-      module: self.module.clone(),
-      name: ex.id.clone(),
+    self.append(Ir::StoreValue { local });
+  }
 
-      shape: ex.shape().clone(),
-    };
+  /**
+  * Puts an empty cell in the local of a function declared further down its block, so the functions before it can
+  * capture it. define_forward fills it in once the function is built.
+  */
+  fn declare_forward(&mut self, name: String) {
+    let func = self.function_context.last_mut().unwrap();
+    let local = func.fresh_local(&name);
+
+    func.bind(name, ScopeLookup::Cell(local.clone()));
+    self.append(Ir::LoadConstUnit);
+    self.append(Ir::NewCell);
+    self.append(Ir::StoreValue { local });
+  }
+
+  /**
+  * Stores the function on top of the stack into the cell declare_forward made for it.
+  */
+  fn define_forward(&mut self, name: &str) {
+    let local = self.local(name);
+
+    self.append(Ir::LoadValue { local });
+    self.append(Ir::Swap);
+    self.append(Ir::StoreCell);
+  }
+
+  /**
+  * The local a name currently refers to.
+  */
+  fn local(&self, name: &str) -> String {
+    match self.function_context.last().unwrap().lookup(name) {
+      Some(ScopeLookup::Local(local)) | Some(ScopeLookup::Cell(local)) => local,
+      _ => String::from(name),
+    }
+  }
+
+  fn push_scope(&mut self) {
+    self.function_context.last_mut().unwrap().scope_stack.push(IrScope::new());
+  }
+
+  fn pop_scope(&mut self) {
+    self.function_context.last_mut().unwrap().scope_stack.pop();
+  }
+
+  fn is_captured(&self, name: &str) -> bool {
+    self.function_context.last().unwrap().captured.contains(name)
+  }
+
+  fn is_cell(&self, name: &str) -> bool {
+    match self.function_context.last().unwrap().lookup(name) {
+      Some(ScopeLookup::Cell(_)) => true,
+      _ => false,
+    }
+  }
+
+  fn push_function(&mut self, captured: HashSet<String>) {
+    self.function_context.push(IrFuncContext::new(captured))
+  }
+
+  fn pop_function(&mut self, ex: &FunctionDeclarationEx) -> FunctionRef {
+    let mut context = self.function_context.pop().unwrap();
 
     let mut args = ex.context.closures.clone();
 
@@ -579,6 +1148,18 @@ impl IrModuleContext {
 
     args.append(&mut ex.args.clone());
 
+    // Refs carry the shape the function is actually called with, captures and all, so they link against it.
+    let func_ref = FunctionRef {
+      package: self.package.clone(),
+      module: self.module.clone(),
+      name: ex.id.clone(),
+
+      shape: Shape::SimpleFunctionShape {
+        args: args.iter().map(|arg| arg.shape.clone()).collect(),
+        result: Box::new(ex.result.clone()),
+      },
+    };
+
     let func = IrFunction {
       func_ref: func_ref.clone(),
       args,
@@ -603,14 +1184,18 @@ struct IrFuncContext {
   pub body: Vec<Vec<Ir>>,
 
   scope_stack: Vec<IrScope>,
+  captured: HashSet<String>,
+  shadowed: usize,
 }
 
 impl IrFuncContext {
-  fn new() -> IrFuncContext {
+  fn new(captured: HashSet<String>) -> IrFuncContext {
     IrFuncContext {
       body: vec![Vec::new()],
 
       scope_stack: vec![IrScope::new()],
+      captured,
+      shadowed: 0,
     }
   }
 
@@ -629,7 +1214,28 @@ impl IrFuncContext {
   }
 
   fn store(&mut self, name: String) {
-    self.scope_stack.last_mut().unwrap().scope.insert(name, ScopeLookup::Local);
+    self.bind(name.clone(), ScopeLookup::Local(name));
+  }
+
+  fn store_cell(&mut self, name: String) {
+    self.bind(name.clone(), ScopeLookup::Cell(name));
+  }
+
+  fn bind(&mut self, name: String, lookup: ScopeLookup) {
+    self.scope_stack.last_mut().unwrap().scope.insert(name, lookup);
+  }
+
+  /**
+  * The name itself unless it's already bound in this function, then a numbered variant of it.
+  */
+  fn fresh_local(&mut self, name: &str) -> String {
+    match self.lookup(name) {
+      Some(ScopeLookup::Local(_)) | Some(ScopeLookup::Cell(_)) => {
+        self.shadowed += 1;
+        format!("{}#{}", name, self.shadowed)
+      }
+      _ => String::from(name),
+    }
   }
 
   fn push_block(&mut self) {
@@ -644,7 +1250,10 @@ impl IrFuncContext {
 #[derive(Clone)]
 enum ScopeLookup {
   Static(FunctionRef),
-  Local,
+  /** The name of the local holding it, which differs from the variable's own when it shadows another. */
+  Local(String),
+  /** A local holding a Value::Cell, read through LoadCell. */
+  Cell(String),
 }
 
 struct IrScope {
@@ -660,12 +1269,238 @@ impl IrScope {
 }
 
 
-pub fn serialize_ir_module<Writer: Write>(writer: &mut Writer, module: &IrModule) -> Result<(), SimpleError> {
+pub fn serialize_ir_module<Writer: Write>(writer: &mut Writer, module: &IrModule) -> bincode::Result<()> {
   serialize_into(writer, module)
-    .map_err(|err| SimpleError::from(err))
 }
 
-pub fn deserialize_ir_module<Reader: Read>(reader: &mut Reader) -> Result<IrModule, SimpleError> {
+pub fn deserialize_ir_module<Reader: Read>(reader: &mut Reader) -> bincode::Result<IrModule> {
   deserialize_from(reader)
-    .map_err(|err| SimpleError::from(err))
+}
+
+/**
+* Proves every LoadValue in func reads a local stored on every path that reaches it, the arguments counting as
+* stored on entry. The optimizers and the Machine both rely on this, a slot that was never stored holds Null.
+*/
+pub fn check_initialized(func: &IrFunction) -> Result<(), CompileError> {
+  let stored = func.args.iter().map(|arg| arg.id.clone()).collect();
+
+  initialized(&func.body, Some(stored), func).map(|_| ())
+}
+
+/**
+* The locals stored after running body, or None if it never finishes because it returns or fails on every path.
+*/
+fn initialized(body: &[Ir], stored: Option<HashSet<String>>, func: &IrFunction) -> Result<Option<HashSet<String>>, CompileError> {
+  let mut stored = match stored {
+    Some(stored) => stored,
+    None => return Ok(None),
+  };
+
+  for ir in body {
+    match ir {
+      Ir::LoadValue { local } if !stored.contains(local) => {
+        return Err(CompileError::Uninitialized { func: Box::new(func.func_ref.clone()), local: local.clone() });
+      }
+      Ir::StoreValue { local } => {
+        stored.insert(local.clone());
+      }
+      Ir::FreeLocal { local } => {
+        stored.remove(local);
+      }
+      Ir::Branch { then_block, else_block } => {
+        let then_stored = initialized(then_block, Some(stored.clone()), func)?;
+        let else_stored = initialized(else_block, Some(stored), func)?;
+
+        match meet(then_stored, else_stored) {
+          Some(both) => stored = both,
+          None => return Ok(None),
+        }
+      }
+      Ir::Cond { arms, else_block } => {
+        // Each arm runs after its own condition and every one before it, the else after all of them.
+        let mut conditions = Some(stored);
+        let mut after = None;
+
+        for (condition, then_block) in arms {
+          conditions = initialized(condition, conditions, func)?;
+          after = meet(after, initialized(then_block, conditions.clone(), func)?);
+        }
+
+        match meet(after, initialized(else_block, conditions, func)?) {
+          Some(all) => stored = all,
+          None => return Ok(None),
+        }
+      }
+      Ir::Loop { condition, body } => {
+        // The body might never run, only what the condition stores is certain afterwards.
+        match initialized(condition, Some(stored), func)? {
+          Some(checked) => {
+            initialized(body, Some(checked.clone()), func)?;
+            stored = checked;
+          }
+          None => return Ok(None),
+        }
+      }
+      Ir::Assert { condition, .. } => {
+        // Asserts can be stripped, so nothing they store counts.
+        initialized(condition, Some(stored.clone()), func)?;
+      }
+      Ir::Return | Ir::Error { .. } | Ir::Panic { .. } => return Ok(None),
+      _ => {}
+    }
+  }
+
+  Ok(Some(stored))
+}
+
+/**
+* What's stored after either of two paths, where None is a path that never gets there.
+*/
+fn meet(left: Option<HashSet<String>>, right: Option<HashSet<String>>) -> Option<HashSet<String>> {
+  match (left, right) {
+    (Some(left), Some(right)) => Some(left.intersection(&right).cloned().collect()),
+    (Some(one), None) | (None, Some(one)) => Some(one),
+    (None, None) => None,
+  }
+}
+
+/**
+* Checks the invariants the optimizers and the bytecode compiler rely on. Nothing pops more than is on the stack,
+* both sides of a Branch or every arm of a Cond leave the stack equally deep, conditions push exactly one value,
+* loop bodies leave the stack as they found it, locals are stored before they're read and every path returns, the
+* body's own Return with only its result on the stack.
+*/
+pub fn validate(func: &IrFunction) -> Result<(), String> {
+  check_initialized(func).map_err(|err| err.to_string())?;
+
+  match stack_depth(&func.body, 0, "")? {
+    Some(_) => Err(String::from("Body can finish without returning")),
+    None => Ok(()),
+  }
+}
+
+/**
+* How deep the stack is after running body from depth, or None if it returns or fails on every path. path is where
+* body is in the function, for the error message.
+*/
+fn stack_depth(body: &[Ir], mut depth: usize, path: &str) -> Result<Option<usize>, String> {
+  for (index, ir) in body.iter().enumerate() {
+    let at = format!("{}{}", path, index);
+
+    let (pops, pushes) = match ir {
+      Ir::Return => {
+        // An early return from inside an expression leaves what was under it for the machine to throw away, only
+        // the Return the body ends with has to find just its result.
+        return match depth {
+          0 => Err(format!("Return of empty stack at {}", at)),
+          1 => Ok(None),
+          _ if path.is_empty() && index + 1 == body.len() => Err(format!("Return leaves {} values behind on the stack at {}", depth - 1, at)),
+          _ => Ok(None),
+        };
+      }
+      Ir::Error { .. } | Ir::Panic { .. } => return Ok(None),
+      Ir::Branch { then_block, else_block } => {
+        depth = pop(depth, 1, &at)?;
+        let then_depth = stack_depth(then_block, depth, &format!("{}.then.", at))?;
+        let else_depth = stack_depth(else_block, depth, &format!("{}.else.", at))?;
+
+        match agree(vec![then_depth, else_depth], &at)? {
+          Some(after) => depth = after,
+          None => return Ok(None),
+        }
+        continue;
+      }
+      Ir::Cond { arms, else_block } => {
+        let mut ends = Vec::new();
+
+        for (arm, (condition, then_block)) in arms.iter().enumerate() {
+          condition_depth(condition, depth, &format!("{}.condition{}.", at, arm))?;
+          ends.push(stack_depth(then_block, depth, &format!("{}.then{}.", at, arm))?);
+        }
+
+        ends.push(stack_depth(else_block, depth, &format!("{}.else.", at))?);
+
+        match agree(ends, &at)? {
+          Some(after) => depth = after,
+          None => return Ok(None),
+        }
+        continue;
+      }
+      Ir::Loop { condition, body } => {
+        condition_depth(condition, depth, &format!("{}.condition.", at))?;
+
+        match stack_depth(body, depth, &format!("{}.body.", at))? {
+          Some(after) if after != depth => return Err(format!("Loop body changes the stack by {} at {}", after as i64 - depth as i64, at)),
+          _ => {}
+        }
+        continue;
+      }
+      Ir::Assert { condition, .. } => {
+        condition_depth(condition, depth, &format!("{}.condition.", at))?;
+        continue;
+      }
+      _ => stack_effect(ir).ok_or_else(|| format!("CallStatic without a function shape at {}", at))?,
+    };
+
+    depth = pop(depth, pops, &at)? + pushes;
+  }
+
+  Ok(Some(depth))
+}
+
+/**
+* How many values an instruction without blocks of its own pops and then pushes. None for control flow, and for a
+* CallStatic whose shape doesn't say how many arguments it takes.
+*/
+pub fn stack_effect(ir: &Ir) -> Option<(usize, usize)> {
+  let effect = match ir {
+    Ir::NoOp | Ir::Debug | Ir::FreeLocal { .. } => (0, 0),
+    Ir::Swap => (2, 2),
+    Ir::BuildRecursiveFunction | Ir::LoadElement { .. } | Ir::IsVariant { .. } | Ir::LoadVariantValue { .. }
+      | Ir::NewCell | Ir::LoadCell => (1, 1),
+    Ir::Duplicate => (1, 2),
+    Ir::Pop | Ir::StoreValue { .. } => (1, 0),
+    Ir::LoadConstUnit | Ir::LoadConstTrue | Ir::LoadConstFalse | Ir::LoadConstString { .. } | Ir::LoadConstFunction { .. }
+      | Ir::LoadConstFloat { .. } | Ir::LoadValue { .. } => (0, 1),
+    Ir::CallStatic { func } => match &func.shape {
+      Shape::SimpleFunctionShape { args, .. } => (args.len(), 1),
+      _ => return None,
+    },
+    Ir::CallDynamic { param_count } => (*param_count as usize + 1, 1),
+    Ir::BuildClosure { param_count, .. } => (*param_count as usize, 1),
+    Ir::BuildTuple { size } | Ir::BuildVariant { size, .. } => (*size as usize, 1),
+    Ir::BuildList { count } => (*count as usize, 1),
+    Ir::ListGet | Ir::AddFloat | Ir::SubFloat | Ir::MulFloat | Ir::DivFloat | Ir::LessFloat | Ir::LessEqualFloat
+      | Ir::GreaterFloat | Ir::GreaterEqualFloat => (2, 1),
+    Ir::StoreCell => (2, 0),
+    Ir::Return | Ir::Error { .. } | Ir::Panic { .. } | Ir::Branch { .. } | Ir::Cond { .. } | Ir::Loop { .. } | Ir::Assert { .. } => return None,
+  };
+
+  Some(effect)
+}
+
+/**
+* A condition has to leave exactly one more value than it started with, the one that's tested.
+*/
+fn condition_depth(condition: &[Ir], depth: usize, path: &str) -> Result<(), String> {
+  match stack_depth(condition, depth, path)? {
+    Some(after) if after != depth + 1 => Err(format!("Condition changes the stack by {} instead of 1 at {}", after as i64 - depth as i64, path)),
+    _ => Ok(()),
+  }
+}
+
+/**
+* The one depth every path that finishes ends at, None if none of them finish.
+*/
+fn agree(ends: Vec<Option<usize>>, path: &str) -> Result<Option<usize>, String> {
+  let finished: Vec<usize> = ends.into_iter().filter_map(|end| end).collect();
+
+  match finished.first() {
+    Some(first) if finished.iter().any(|end| end != first) => Err(format!("Branches leave the stack at different depths {:?} at {}", finished, path)),
+    first => Ok(first.cloned()),
+  }
+}
+
+fn pop(depth: usize, pops: usize, path: &str) -> Result<usize, String> {
+  depth.checked_sub(pops).ok_or_else(|| format!("Pops {} values with only {} on the stack at {}", pops, depth, path))
 }
@@ -0,0 +1,294 @@
+use std::fmt;
+
+/**
+* Just enough JSON for the language server's messages. Objects keep their keys in the order they were written.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+impl Json {
+
+  pub fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(key, value)| (String::from(key), value)).collect())
+  }
+
+  pub fn string(value: &str) -> Json {
+    Json::String(String::from(value))
+  }
+
+  /**
+  * The field key of an object, Null for anything else or a missing key.
+  */
+  pub fn get(&self, key: &str) -> &Json {
+    match self {
+      Json::Object(fields) => fields.iter().find(|(next, _)| next == key).map(|(_, value)| value).unwrap_or(&Json::Null),
+      _ => &Json::Null,
+    }
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Json::String(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_f64(&self) -> Option<f64> {
+    match self {
+      Json::Number(value) => Some(*value),
+      _ => None,
+    }
+  }
+
+  pub fn as_array(&self) -> Option<&Vec<Json>> {
+    match self {
+      Json::Array(items) => Some(items),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for Json {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Json::Null => write!(f, "null"),
+      Json::Bool(value) => write!(f, "{}", value),
+      Json::Number(value) if value.fract() == 0.0 && value.abs() < 1e15 => write!(f, "{}", *value as i64),
+      Json::Number(value) => write!(f, "{}", value),
+      Json::String(value) => write_string(f, value),
+      Json::Array(items) => {
+        write!(f, "[")?;
+
+        for (index, item) in items.iter().enumerate() {
+          if index > 0 {
+            write!(f, ",")?;
+          }
+
+          item.fmt(f)?;
+        }
+
+        write!(f, "]")
+      }
+      Json::Object(fields) => {
+        write!(f, "{{")?;
+
+        for (index, (key, value)) in fields.iter().enumerate() {
+          if index > 0 {
+            write!(f, ",")?;
+          }
+
+          write_string(f, key)?;
+          write!(f, ":")?;
+          value.fmt(f)?;
+        }
+
+        write!(f, "}}")
+      }
+    }
+  }
+}
+
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+  write!(f, "\"")?;
+
+  for ch in value.chars() {
+    match ch {
+      '"' => write!(f, "\\\"")?,
+      '\\' => write!(f, "\\\\")?,
+      '\n' => write!(f, "\\n")?,
+      '\r' => write!(f, "\\r")?,
+      '\t' => write!(f, "\\t")?,
+      ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+      ch => write!(f, "{}", ch)?,
+    }
+  }
+
+  write!(f, "\"")
+}
+
+pub fn parse_json(src: &str) -> Result<Json, String> {
+  let mut parser = JsonParser { chars: src.chars().collect(), index: 0 };
+  let value = parser.value()?;
+
+  parser.skip_space();
+
+  if parser.index < parser.chars.len() {
+    return Err(format!("Unexpected '{}' after the value at {}", parser.chars[parser.index], parser.index));
+  }
+
+  Ok(value)
+}
+
+struct JsonParser {
+  chars: Vec<char>,
+  index: usize,
+}
+
+impl JsonParser {
+
+  fn value(&mut self) -> Result<Json, String> {
+    self.skip_space();
+
+    match self.peek() {
+      Some('{') => self.object(),
+      Some('[') => self.array(),
+      Some('"') => Ok(Json::String(self.string()?)),
+      Some('t') => self.word("true", Json::Bool(true)),
+      Some('f') => self.word("false", Json::Bool(false)),
+      Some('n') => self.word("null", Json::Null),
+      Some(ch) if ch == '-' || ch.is_ascii_digit() => self.number(),
+      Some(ch) => Err(format!("Unexpected '{}' at {}", ch, self.index)),
+      None => Err(String::from("Unexpected end of input")),
+    }
+  }
+
+  fn object(&mut self) -> Result<Json, String> {
+    self.expect('{')?;
+    let mut fields = Vec::new();
+
+    self.skip_space();
+
+    if self.peek() == Some('}') {
+      self.index += 1;
+      return Ok(Json::Object(fields));
+    }
+
+    loop {
+      self.skip_space();
+      let key = self.string()?;
+
+      self.skip_space();
+      self.expect(':')?;
+      fields.push((key, self.value()?));
+
+      self.skip_space();
+
+      match self.next() {
+        Some(',') => continue,
+        Some('}') => return Ok(Json::Object(fields)),
+        _ => return Err(format!("Expected ',' or '}}' at {}", self.index)),
+      }
+    }
+  }
+
+  fn array(&mut self) -> Result<Json, String> {
+    self.expect('[')?;
+    let mut items = Vec::new();
+
+    self.skip_space();
+
+    if self.peek() == Some(']') {
+      self.index += 1;
+      return Ok(Json::Array(items));
+    }
+
+    loop {
+      items.push(self.value()?);
+
+      self.skip_space();
+
+      match self.next() {
+        Some(',') => continue,
+        Some(']') => return Ok(Json::Array(items)),
+        _ => return Err(format!("Expected ',' or ']' at {}", self.index)),
+      }
+    }
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    self.expect('"')?;
+    let mut value = String::new();
+
+    loop {
+      match self.next() {
+        Some('"') => return Ok(value),
+        Some('\\') => match self.next() {
+          Some('n') => value.push('\n'),
+          Some('r') => value.push('\r'),
+          Some('t') => value.push('\t'),
+          Some('b') => value.push('\u{8}'),
+          Some('f') => value.push('\u{c}'),
+          Some('u') => {
+            let code = self.hex()?;
+
+            // A surrogate pair, the second half follows as its own escape.
+            let code = if code >= 0xD800 && code < 0xDC00 && self.peek() == Some('\\') {
+              self.index += 1;
+              self.expect('u')?;
+              let low = self.hex()?;
+              0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00)
+            } else {
+              code
+            };
+
+            value.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+          }
+          Some(ch) => value.push(ch),
+          None => return Err(String::from("Unterminated string")),
+        },
+        Some(ch) => value.push(ch),
+        None => return Err(String::from("Unterminated string")),
+      }
+    }
+  }
+
+  fn hex(&mut self) -> Result<u32, String> {
+    let digits: String = (0..4).filter_map(|_| self.next()).collect();
+    u32::from_str_radix(&digits, 16).map_err(|_| format!("Invalid escape \\u{} at {}", digits, self.index))
+  }
+
+  fn number(&mut self) -> Result<Json, String> {
+    let start = self.index;
+
+    while let Some(ch) = self.peek() {
+      if ch.is_ascii_digit() || ch == '-' || ch == '+' || ch == '.' || ch == 'e' || ch == 'E' {
+        self.index += 1;
+      } else {
+        break;
+      }
+    }
+
+    let text: String = self.chars[start..self.index].iter().collect();
+    text.parse().map(Json::Number).map_err(|_| format!("Invalid number {} at {}", text, start))
+  }
+
+  fn word(&mut self, word: &str, value: Json) -> Result<Json, String> {
+    for expected in word.chars() {
+      if self.next() != Some(expected) {
+        return Err(format!("Expected {} at {}", word, self.index));
+      }
+    }
+
+    Ok(value)
+  }
+
+  fn expect(&mut self, expected: char) -> Result<(), String> {
+    match self.next() {
+      Some(ch) if ch == expected => Ok(()),
+      _ => Err(format!("Expected '{}' at {}", expected, self.index)),
+    }
+  }
+
+  fn skip_space(&mut self) {
+    while self.peek().map(|ch| ch.is_whitespace()).unwrap_or(false) {
+      self.index += 1;
+    }
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.index).cloned()
+  }
+
+  fn next(&mut self) -> Option<char> {
+    let next = self.peek();
+    self.index += 1;
+    next
+  }
+}
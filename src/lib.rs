@@ -0,0 +1,77 @@
+extern crate core;
+extern crate serde;
+extern crate bincode;
+
+use std::collections::HashMap;
+
+pub use bytecode::{BitApplication, BitModule, BitPackage, FunctionRef};
+pub use error::{CompileError, LetError, LexError, Limit, ParseError, RuntimeError, TypeError};
+pub use interpreter::{Backend, DEFAULT_MAX_CALL_DEPTH, Dispatch, ExecutionLimits, Machine, NativeModuleBuilder, STACK_SIZE_PER_CALL, SecurityConfig};
+pub use linker::Linker;
+pub use optimize::{Optimizer, OptLevel};
+pub use runtime::Value;
+pub use shapes::{BaseShapeKind, Shape};
+pub use testing::verify_roundtrip;
+pub use typechecker::{AppShapes, Shadowing};
+
+use compiler::compile_source_module;
+
+#[macro_use]
+pub mod shapes;
+pub mod ast;
+pub mod bench;
+pub mod bytecode;
+pub mod cache;
+pub mod cfg;
+pub mod compiler;
+pub mod debugger;
+pub mod error;
+pub mod format;
+pub mod interpreter;
+pub mod ir;
+pub mod json;
+pub mod linker;
+pub mod lsp;
+mod lib_core;
+pub mod optimize;
+pub mod parser;
+pub mod profiler;
+pub mod register;
+pub mod repl;
+pub mod runtime;
+pub mod testing;
+pub mod threaded;
+pub mod typechecker;
+pub mod watch;
+
+/**
+* Package name given to code compiled with compile_source.
+*/
+pub const SOURCE_PACKAGE: &'static str = "script";
+
+/**
+* Module name given to code compiled with compile_source.
+*/
+pub const SOURCE_MODULE: &'static str = "Main";
+
+/**
+* Compiles a single letLang source string into a package holding one module named SOURCE_MODULE.
+* Load it with `app.packages.insert(SOURCE_PACKAGE, package)` and call into it with Machine::call.
+*/
+pub fn compile_source(src: &str) -> Result<BitPackage, CompileError> {
+  compile_source_with(src, &AppShapes::new())
+}
+
+/**
+* Same as compile_source, but able to import host functions. Get the shapes from AppShapes::for_application.
+*/
+pub fn compile_source_with(src: &str, shapes: &AppShapes) -> Result<BitPackage, CompileError> {
+  let module = compile_source_module(SOURCE_PACKAGE, SOURCE_MODULE, src, &Optimizer::new(), shapes)?;
+  let mut modules = HashMap::new();
+
+  modules.insert(String::from(SOURCE_MODULE), module);
+
+  Ok(BitPackage {
+    modules
+  })
+}
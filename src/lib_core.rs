@@ -1,20 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
 
-use simple_error::SimpleError;
-
 use ast::Expression::BinaryOp;
+use ast::{VARIANT_ERR, VARIANT_NONE, VARIANT_OK, VARIANT_SOME};
 use bytecode::{BitModule, BitPackage, FunctionRef};
+use error::RuntimeError;
 use interpreter::{Machine, NativeFunction, RunFunction};
-use runtime::{Value, ListValue};
-use shapes::{Shape, BaseShapeKind, shape_list};
+use runtime::{Value, ListValue, MapValue, VariantValue};
+use shapes::{Shape, BaseShapeKind, shape_list, shape_map, shape_option, shape_result};
 use std::borrow::Borrow;
 
+/**
+* Most items List.range will build. The list is built eagerly, so a range like 0..1e12 would otherwise exhaust memory.
+*/
+const MAX_RANGE_SIZE: usize = 10_000_000;
+
 pub fn core_runtime() -> BitPackage {
   let mut modules = HashMap::new();
 
   modules.insert(String::from("Core"), core_module());
   modules.insert(String::from("List"), list_module());
+  modules.insert(String::from("Map"), map_module());
+  modules.insert(String::from("Console"), console_module());
+  modules.insert(String::from("Assert"), assert_module());
+  modules.insert(String::from("Env"), env_module());
+  modules.insert(String::from("File"), file_module());
 
   BitPackage {
     modules
@@ -22,19 +34,42 @@ pub fn core_runtime() -> BitPackage {
 }
 
 fn core_module() -> BitModule {
-  let mut functions = HashMap::new();
+  let mut functions = BTreeMap::new();
   float_op(&mut functions, "+", |l, r| l + r);
   float_op(&mut functions, "-", |l, r| l - r);
   float_op(&mut functions, "*", |l, r| l * r);
   float_op(&mut functions, "/", |l, r| l / r);
 
-  float_compare_op(&mut functions, "==", |l, r| l == r);
-  float_compare_op(&mut functions, "!=", |l, r| l != r);
+  equality_op(&mut functions, "==", |l, r| l == r);
+  equality_op(&mut functions, "!=", |l, r| l != r);
+  equality_op(&mut functions, "equals", |l, r| l == r);
   float_compare_op(&mut functions, ">", |l, r| l > r);
   float_compare_op(&mut functions, ">=", |l, r| l >= r);
   float_compare_op(&mut functions, "<", |l, r| l < r);
   float_compare_op(&mut functions, "<=", |l, r| l <= r);
 
+  exact(&mut functions, "Core", "show", 1, |_, args| {
+    Ok(Value::String(Rc::new(args[0].to_string())))
+  }, Shape::SimpleFunctionShape {
+    args: vec![Shape::UnknownShape],
+    result: Box::new(shape!(String)),
+  });
+
+  exact(&mut functions, "Core", "tryCall", 1, |machine, args| {
+    if let Value::Function(func) = &args[0] {
+      match machine.execute_handle(func.clone(), vec![]) {
+        Ok(value) => Ok(variant(VARIANT_OK, value)),
+        Err(RuntimeError::Panic { message, .. }) => Ok(variant(VARIANT_ERR, Value::String(Rc::new(message)))),
+        Err(err) => Err(err),
+      }
+    } else {
+      Err(RuntimeError::native("Core.tryCall argument must be a function"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![Shape::SimpleFunctionShape { args: vec![], result: Box::new(shape!(Float)) }],
+    result: Box::new(shape_result(shape!(Float), shape!(String))),
+  });
+
   BitModule {
     functions,
     string_constants: vec![],
@@ -44,7 +79,7 @@ fn core_module() -> BitModule {
 }
 
 fn list_module() -> BitModule {
-  let mut functions = HashMap::new();
+  let mut functions = BTreeMap::new();
   let float_list = shape!(List[Float]);
   let mapper_shape = Shape::SimpleFunctionShape {
     args: vec![shape!(Float)],
@@ -63,14 +98,12 @@ fn list_module() -> BitModule {
   exact(&mut functions, "List", "append", 2, |_, args| {
     if let Value::List(list) = &args[0] {
       if let Value::Float(num) = args[1] {
-        let mut copy = list.copy_contents();
-        copy.push(Value::Float(num));
-        Ok(Value::List(Rc::new(ListValue{ contents: copy, shape: list.shape.clone()})))
+        Ok(Value::List(Rc::new(ListValue{ contents: list.contents.push(Value::Float(num)), shape: list.shape.clone()})))
       } else {
-        Err(SimpleError::new("List.append second argument must be a float"))
+        Err(RuntimeError::native("List.append second argument must be a float"))
       }
     } else {
-      Err(SimpleError::new("List.append first argument must be a list"))
+      Err(RuntimeError::native("List.append first argument must be a list"))
     }
   }, Shape::SimpleFunctionShape {
     args: vec![float_list.clone(), shape!(Float)],
@@ -82,18 +115,21 @@ fn list_module() -> BitModule {
       if let Value::Function(mapper) = &args[1] {
         let mut result = Vec::with_capacity(list.contents.len());
 
-        for next in 0..list.contents.len() {
-          result.push(machine.execute_handle(mapper.clone(), vec![ list.contents[next].clone() ])?);
+        for next in list.contents.iter() {
+          result.push(machine.execute_handle(mapper.clone(), vec![ next.clone() ])?);
         }
 
-        Ok(Value::List(Rc::new(ListValue{ contents: result, shape: list.shape.clone()})))
+        Ok(Value::List(Rc::new(ListValue{ contents: result.into(), shape: list.shape.clone()})))
       } else {
-        Err(SimpleError::new("List.map second argument must be a function"))
+        Err(RuntimeError::native("List.map second argument must be a function"))
       }
     } else {
-      Err(SimpleError::new("List.map first argument must be a list"))
+      Err(RuntimeError::native("List.map first argument must be a list"))
     }
-  }, mapper_shape);
+  }, Shape::SimpleFunctionShape {
+    args: vec![float_list.clone(), mapper_shape],
+    result: Box::new(float_list.clone()),
+  });
 
   exact(&mut functions, "List", "fold", 3, |machine, args| {
     if let Value::List(list) = args[0].clone() {
@@ -101,27 +137,333 @@ fn list_module() -> BitModule {
         if let Value::Function(mapper) = &args[2] {
           let mut result = init;
 
-          for item in &list.contents {
+          for item in list.contents.iter() {
             if let Value::Float(next) = machine.execute_handle(mapper.clone(), vec![Value::Float(result), item.clone()])? {
               result = next
             } else {
-              return Err(SimpleError::new("List.fold callback must return a float"))
+              return Err(RuntimeError::native("List.fold callback must return a float"))
             }
           }
 
           Ok(Value::Float(result))
         } else {
-          Err(SimpleError::new("List.fold third argument must be a function"))
+          Err(RuntimeError::native("List.fold third argument must be a function"))
         }
       } else {
-        Err(SimpleError::new("List.fold second argument must be a float"))
+        Err(RuntimeError::native("List.fold second argument must be a float"))
       }
     } else {
-      Err(SimpleError::new("List.fold first argument must be a list"))
+      Err(RuntimeError::native("List.fold first argument must be a list"))
     }
   }, Shape::SimpleFunctionShape {
     args: vec![float_list.clone(), shape!(Float), reducer_shape],
-    result: Box::new(float_list.clone())
+    result: Box::new(shape!(Float))
+  });
+
+  exact(&mut functions, "List", "range", 3, |_, args| {
+    match (&args[0], &args[1], &args[2]) {
+      (Value::Float(start), Value::Float(end), Value::Float(step)) => {
+        if *step == 0.0 || step.is_nan() {
+          return Err(RuntimeError::native("List.range step must not be zero"));
+        }
+
+        // Worked out up front, so a huge or endless range fails before anything is built.
+        let size = ((end - start) / step).ceil();
+
+        if size > MAX_RANGE_SIZE as f64 {
+          return Err(RuntimeError::native(format!("List.range from {} to {} by {} would have more than {} items", start, end, step, MAX_RANGE_SIZE)));
+        }
+
+        // Negative when end is already behind start, and NaN if either is NaN. Both mean an empty list.
+        let size = if size > 0.0 { size as usize } else { 0 };
+        let contents: Vec<Value> = (0..size).map(|index| Value::Float(start + step * index as f64)).collect();
+
+        Ok(Value::List(Rc::new(ListValue{ contents: contents.into(), shape: shape!(List[Float]) })))
+      }
+      _ => Err(RuntimeError::native("List.range arguments must be floats")),
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![shape!(Float), shape!(Float), shape!(Float)],
+    result: Box::new(float_list.clone()),
+  });
+
+  BitModule {
+    functions,
+    string_constants: vec![],
+    function_refs: vec![],
+    shape_refs: vec![],
+  }
+}
+
+fn console_module() -> BitModule {
+  let mut functions = BTreeMap::new();
+
+  exact(&mut functions, "Console", "print", 1, |machine, args| {
+    if let Value::String(text) = &args[0] {
+      machine.write(text)?;
+      Ok(Value::Unit)
+    } else {
+      Err(RuntimeError::native("Console.print first argument must be a string"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![shape!(String)],
+    result: Box::new(shape!(Unit)),
+  });
+
+  exact(&mut functions, "Console", "println", 1, |machine, args| {
+    if let Value::String(text) = &args[0] {
+      machine.write(&format!("{}\n", text))?;
+      Ok(Value::Unit)
+    } else {
+      Err(RuntimeError::native("Console.println first argument must be a string"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![shape!(String)],
+    result: Box::new(shape!(Unit)),
+  });
+
+  exact(&mut functions, "Console", "readLine", 0, |machine, _| {
+    Ok(Value::String(Rc::new(machine.read_line()?)))
+  }, Shape::SimpleFunctionShape {
+    args: vec![],
+    result: Box::new(shape!(String)),
+  });
+
+  BitModule {
+    functions,
+    string_constants: vec![],
+    function_refs: vec![],
+    shape_refs: vec![],
+  }
+}
+
+fn assert_module() -> BitModule {
+  let mut functions = BTreeMap::new();
+
+  exact(&mut functions, "Assert", "assertEq", 2, |_, args| {
+    if let (Value::Float(expected), Value::Float(actual)) = (&args[0], &args[1]) {
+      if expected == actual {
+        Ok(Value::Unit)
+      } else {
+        Err(RuntimeError::native(format!("Assertion failed: expected {} but found {}", expected, actual)))
+      }
+    } else {
+      Err(RuntimeError::native("Assert.assertEq arguments must be floats"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![shape!(Float), shape!(Float)],
+    result: Box::new(shape!(Unit)),
+  });
+
+  exact(&mut functions, "Assert", "assertTrue", 1, |_, args| {
+    match args[0] {
+      Value::True => Ok(Value::Unit),
+      Value::False => Err(RuntimeError::native("Assertion failed: expected true")),
+      _ => Err(RuntimeError::native("Assert.assertTrue first argument must be a boolean")),
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![shape!(Boolean)],
+    result: Box::new(shape!(Unit)),
+  });
+
+  BitModule {
+    functions,
+    string_constants: vec![],
+    function_refs: vec![],
+    shape_refs: vec![],
+  }
+}
+
+fn env_module() -> BitModule {
+  let mut functions = BTreeMap::new();
+
+  exact(&mut functions, "Env", "args", 0, |machine, _| {
+    Ok(machine.args_list())
+  }, Shape::SimpleFunctionShape {
+    args: vec![],
+    result: Box::new(shape!(List[String])),
+  });
+
+  exact(&mut functions, "Env", "getVar", 1, |machine, args| {
+    if let Value::String(name) = &args[0] {
+      // An unset variable reads as empty, the same as Console.readLine at the end of input.
+      Ok(Value::String(Rc::new(machine.env_var(name).unwrap_or_default())))
+    } else {
+      Err(RuntimeError::native("Env.getVar first argument must be a string"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![shape!(String)],
+    result: Box::new(shape!(String)),
+  });
+
+  BitModule {
+    functions,
+    string_constants: vec![],
+    function_refs: vec![],
+    shape_refs: vec![],
+  }
+}
+
+fn file_module() -> BitModule {
+  let mut functions = BTreeMap::new();
+
+  exact(&mut functions, "File", "readText", 1, |_, args| {
+    if let Value::String(path) = &args[0] {
+      Ok(Value::String(Rc::new(fs::read_to_string(path.as_str())?)))
+    } else {
+      Err(RuntimeError::native("File.readText first argument must be a string"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![shape!(String)],
+    result: Box::new(shape!(String)),
+  });
+
+  exact(&mut functions, "File", "writeText", 2, |_, args| {
+    if let (Value::String(path), Value::String(text)) = (&args[0], &args[1]) {
+      fs::write(path.as_str(), text.as_bytes())?;
+      Ok(Value::Unit)
+    } else {
+      Err(RuntimeError::native("File.writeText arguments must be strings"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![shape!(String), shape!(String)],
+    result: Box::new(shape!(Unit)),
+  });
+
+  exact(&mut functions, "File", "exists", 1, |_, args| {
+    if let Value::String(path) = &args[0] {
+      Ok(if Path::new(path.as_str()).exists() { Value::True } else { Value::False })
+    } else {
+      Err(RuntimeError::native("File.exists first argument must be a string"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![shape!(String)],
+    result: Box::new(shape!(Boolean)),
+  });
+
+  BitModule {
+    functions,
+    string_constants: vec![],
+    function_refs: vec![],
+    shape_refs: vec![],
+  }
+}
+
+fn map_module() -> BitModule {
+  let mut functions = BTreeMap::new();
+  // Generic over the key, the value and fold's accumulator, the typechecker works out each from how it's called.
+  let key = Shape::VariableShape { id: 0 };
+  let value = Shape::VariableShape { id: 1 };
+  let acc = Shape::VariableShape { id: 2 };
+  let map = shape_map(key.clone(), value.clone());
+  let reducer_shape = Shape::SimpleFunctionShape {
+    args: vec![acc.clone(), key.clone(), value.clone()],
+    result: Box::new(acc.clone())
+  };
+
+  exact(&mut functions, "Map", "new", 0, |_, _| Ok(Value::Map(Rc::new(MapValue::new(shape_map(Shape::UnknownShape, Shape::UnknownShape))))), Shape::SimpleFunctionShape {
+    args: vec![],
+    result: Box::new(map.clone()),
+  });
+
+  exact(&mut functions, "Map", "put", 3, |_, args| {
+    if let Value::Map(map) = &args[0] {
+      args[1].check_key()?;
+      let mut copy = map.copy_contents();
+      copy.insert(args[1].clone(), args[2].clone());
+      Ok(Value::Map(Rc::new(MapValue{ contents: copy, shape: map.shape.clone()})))
+    } else {
+      Err(RuntimeError::native("Map.put first argument must be a map"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![map.clone(), key.clone(), value.clone()],
+    result: Box::new(map.clone()),
+  });
+
+  exact(&mut functions, "Map", "get", 2, |_, args| {
+    if let Value::Map(map) = &args[0] {
+      args[1].check_key()?;
+      Ok(match map.contents.get(&args[1]) {
+        Some(value) => variant(VARIANT_SOME, value.clone()),
+        None => Value::Variant(Rc::new(VariantValue { tag: String::from(VARIANT_NONE), values: vec![] })),
+      })
+    } else {
+      Err(RuntimeError::native("Map.get first argument must be a map"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![map.clone(), key.clone()],
+    result: Box::new(shape_option(value.clone())),
+  });
+
+  exact(&mut functions, "Map", "contains", 2, |_, args| {
+    if let Value::Map(map) = &args[0] {
+      args[1].check_key()?;
+      Ok(if map.contents.contains_key(&args[1]) { Value::True } else { Value::False })
+    } else {
+      Err(RuntimeError::native("Map.contains first argument must be a map"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![map.clone(), key.clone()],
+    result: Box::new(shape!(Boolean)),
+  });
+
+  exact(&mut functions, "Map", "remove", 2, |_, args| {
+    if let Value::Map(map) = &args[0] {
+      args[1].check_key()?;
+      let mut copy = map.copy_contents();
+      copy.remove(&args[1]);
+      Ok(Value::Map(Rc::new(MapValue{ contents: copy, shape: map.shape.clone()})))
+    } else {
+      Err(RuntimeError::native("Map.remove first argument must be a map"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![map.clone(), key.clone()],
+    result: Box::new(map.clone()),
+  });
+
+  exact(&mut functions, "Map", "keys", 1, |_, args| {
+    if let Value::Map(map) = &args[0] {
+      let contents = map.contents.keys().map(|key| key.clone()).collect();
+      Ok(Value::List(Rc::new(ListValue{ contents, shape: Shape::UnknownShape})))
+    } else {
+      Err(RuntimeError::native("Map.keys first argument must be a map"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![map.clone()],
+    result: Box::new(shape_list(key.clone())),
+  });
+
+  exact(&mut functions, "Map", "values", 1, |_, args| {
+    if let Value::Map(map) = &args[0] {
+      let contents = map.contents.values().map(|value| value.clone()).collect();
+      Ok(Value::List(Rc::new(ListValue{ contents, shape: Shape::UnknownShape})))
+    } else {
+      Err(RuntimeError::native("Map.values first argument must be a map"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![map.clone()],
+    result: Box::new(shape_list(value.clone())),
+  });
+
+  exact(&mut functions, "Map", "fold", 3, |machine, args| {
+    if let Value::Map(map) = args[0].clone() {
+      if let Value::Function(reducer) = &args[2] {
+        let mut result = args[1].clone();
+
+        for (key, value) in &map.contents {
+          result = machine.execute_handle(reducer.clone(), vec![result, key.clone(), value.clone()])?;
+        }
+
+        Ok(result)
+      } else {
+        Err(RuntimeError::native("Map.fold third argument must be a function"))
+      }
+    } else {
+      Err(RuntimeError::native("Map.fold first argument must be a map"))
+    }
+  }, Shape::SimpleFunctionShape {
+    args: vec![map.clone(), acc.clone(), reducer_shape],
+    result: Box::new(acc.clone())
   });
 
   BitModule {
@@ -133,17 +475,33 @@ fn list_module() -> BitModule {
 }
 
 #[inline]
-fn float_op<Op: Fn(f64, f64) -> f64 + 'static>(funcs: &mut HashMap<String, RunFunction>, name: &'static str, op_fun: Op) {
+fn float_op<Op: Fn(f64, f64) -> f64 + 'static>(funcs: &mut BTreeMap<String, RunFunction>, name: &'static str, op_fun: Op) {
   op(funcs, name, op_fun, |result| Value::Float(result), shape!(Float))
 }
 
+fn variant(tag: &str, value: Value) -> Value {
+  Value::Variant(Rc::new(VariantValue { tag: String::from(tag), values: vec![value] }))
+}
+
+/**
+* Compares any two values of the same shape, structurally. See Value's PartialEq.
+*/
+fn equality_op<Op: Fn(&Value, &Value) -> bool + 'static>(funcs: &mut BTreeMap<String, RunFunction>, name: &'static str, op: Op) {
+  exact(funcs, "Core", name, 2, move |_, args| {
+    Ok(if op(&args[0], &args[1]) { Value::True } else { Value::False })
+  }, Shape::SimpleFunctionShape {
+    args: vec![Shape::UnknownShape, Shape::UnknownShape],
+    result: Box::new(shape!(Boolean)),
+  });
+}
+
 #[inline]
-fn float_compare_op<Op: Fn(f64, f64) -> bool + 'static>(funcs: &mut HashMap<String, RunFunction>, name: &'static str, op_fun: Op) {
+fn float_compare_op<Op: Fn(f64, f64) -> bool + 'static>(funcs: &mut BTreeMap<String, RunFunction>, name: &'static str, op_fun: Op) {
   op(funcs, name, op_fun, |result| if result { Value::True } else { Value::False}, shape!(Boolean));
 }
 
 #[inline]
-fn op<Result, Op: Fn(f64, f64) -> Result + 'static, Map: Fn(Result) -> Value + 'static>(funcs: &mut HashMap<String, RunFunction>, name: &'static str, op: Op, map: Map, result_shape: Shape) {
+fn op<Result, Op: Fn(f64, f64) -> Result + 'static, Map: Fn(Result) -> Value + 'static>(funcs: &mut BTreeMap<String, RunFunction>, name: &'static str, op: Op, map: Map, result_shape: Shape) {
   let func = Box::new(move |machine: &Machine, args: Vec<Value>| {
     if args.len() == 2 {
       if let Value::Float(first) = args[0] {
@@ -154,7 +512,7 @@ fn op<Result, Op: Fn(f64, f64) -> Result + 'static, Map: Fn(Result) -> Value + '
       }
     }
 
-    return Err(SimpleError::new(format!("{} takes exactly two float arguments", name)));
+    return Err(RuntimeError::native(format!("{} takes exactly two float arguments", name)));
   });
 
   let result = NativeFunction {
@@ -175,13 +533,13 @@ fn op<Result, Op: Fn(f64, f64) -> Result + 'static, Map: Fn(Result) -> Value + '
 }
 
 #[inline]
-fn exact<Op: Fn(&Machine, Vec<Value>) -> Result<Value, SimpleError> + 'static>(funcs: &mut HashMap<String, RunFunction>, module: &'static str, name: &'static str, arg_count: usize, op: Op, shape: Shape) {
+fn exact<Op: Fn(&Machine, Vec<Value>) -> Result<Value, RuntimeError> + 'static>(funcs: &mut BTreeMap<String, RunFunction>, module: &'static str, name: &'static str, arg_count: usize, op: Op, shape: Shape) {
   let func = Box::new(move |machine: &Machine, args: Vec<Value>| {
     if args.len() == arg_count {
       return op(machine, args)
     }
 
-    return Err(SimpleError::new(format!("{}.{} takes exactly two float arguments", module, name)));
+    return Err(RuntimeError::native(format!("{}.{} takes exactly two float arguments", module, name)));
   });
 
   let result = NativeFunction {
@@ -0,0 +1,39 @@
+use bytecode::{BitApplication, BitPackage, FunctionRef};
+use error::RuntimeError;
+use lib_core::core_runtime;
+
+/**
+* Assembles packages into a BitApplication, checking with BitApplication::link that everything they reference
+* resolves before anything runs. Core is always linked in, the same as a Machine would load it.
+*/
+pub struct Linker {
+  app: BitApplication,
+}
+
+impl Linker {
+
+  pub fn new() -> Linker {
+    let mut app = BitApplication::library();
+    app.packages.insert(String::from("Core"), core_runtime());
+
+    Linker { app }
+  }
+
+  pub fn package(mut self, name: &str, package: BitPackage) -> Linker {
+    self.app.packages.insert(String::from(name), package);
+    self
+  }
+
+  /**
+  * The entry point, which has to exist with exactly main's shape.
+  */
+  pub fn main(mut self, main: FunctionRef) -> Linker {
+    self.app.main = Some(main);
+    self
+  }
+
+  pub fn link(self) -> Result<BitApplication, RuntimeError> {
+    self.app.link()?;
+    Ok(self.app)
+  }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, ErrorKind, Write};
+
+use ast::{AstModule, Expression, Location};
+use json::{Json, parse_json};
+use parser::{FullToken, Token, TokenKind, lex_lossless, parse_str_partial};
+use typechecker::{AppShapes, analyze_module};
+
+/**
+* Package every open document is checked as part of. Each document is checked on its own, so only Core imports resolve.
+*/
+pub const LSP_PACKAGE: &'static str = "lsp";
+
+/**
+* One checked source file. Lines and columns are counted from 1, the same as a Location.
+*/
+pub struct Document {
+  /** Every parse error, or every type error if it parsed, with where it is when it's known. */
+  pub errors: Vec<(Option<Location>, String)>,
+  /** Warnings from the typechecker, only there when it got as far as checking. */
+  pub warnings: Vec<(Option<Location>, String)>,
+  /** The typechecked module, only there if it checked without errors. */
+  pub module: Option<AstModule>,
+  pub definitions: Vec<(Location, Location)>,
+  /** The source lexed losslessly, to find what's under the cursor. There even if it didn't parse. */
+  pub tokens: Vec<FullToken>,
+}
+
+pub fn check_document(src: &str, name: &str) -> Document {
+  let tokens = lex_lossless(src, name);
+  let (parsed, parse_errors) = parse_str_partial(src, LSP_PACKAGE, name);
+
+  if !parse_errors.is_empty() {
+    return Document {
+      errors: parse_errors.iter().map(|err| (err.loc().cloned(), err.to_string())).collect(),
+      warnings: Vec::new(),
+      module: None,
+      definitions: Vec::new(),
+      tokens,
+    };
+  }
+
+  let analysis = analyze_module(parsed, &AppShapes::new());
+  let warnings = analysis.warnings.iter().map(|warning| (warning.loc().cloned(), warning.to_string())).collect();
+
+  match analysis.result {
+    Ok(module) => Document { errors: Vec::new(), warnings, module: Some(module), definitions: analysis.definitions, tokens },
+    Err(errors) => Document {
+      errors: errors.iter().map(|err| (err.loc().cloned(), err.to_string())).collect(),
+      warnings,
+      module: None,
+      definitions: analysis.definitions,
+      tokens,
+    },
+  }
+}
+
+impl Document {
+
+  /**
+  * The shape of the variable at line and column, or the signature of the function declared there.
+  */
+  pub fn hover(&self, line: usize, column: usize) -> Option<String> {
+    let module = self.module.as_ref()?;
+    let index = self.token_at(line, column)?;
+
+    // Both `fun` and the name after it count as the declaration.
+    let declared = match (index.checked_sub(1).map(|before| &self.tokens[before].token), &self.tokens[index].token) {
+      (_, fun) if is_fun(fun) => self.tokens.get(index + 1).map(|name| &name.token),
+      (Some(fun), name) if is_fun(fun) => Some(name),
+      _ => None,
+    };
+
+    if let Some(name) = declared {
+      if let Some(dec) = module.functions.iter().find(|dec| dec.ex.id == name.value) {
+        return Some(format!("{}: {}", dec.ex.id, dec.ex.shape().pretty()));
+      }
+    }
+
+    self.variable_at(index).map(|(id, ex)| format!("{}: {}", id, ex.shape().pretty()))
+  }
+
+  /**
+  * Where the variable at line and column was declared.
+  */
+  pub fn definition(&self, line: usize, column: usize) -> Option<Location> {
+    let (_, ex) = self.variable_at(self.token_at(line, column)?)?;
+
+    self.definitions.iter()
+      .find(|(used, _)| used == ex.loc())
+      .map(|(_, declared)| declared.clone())
+  }
+
+  /**
+  * Index of the token under line and column. None in whitespace, which no token covers.
+  */
+  fn token_at(&self, line: usize, column: usize) -> Option<usize> {
+    self.tokens.iter().position(|full| full.token.kind != TokenKind::EOF && full.token.location.contains(line, column))
+  }
+
+  fn variable_at(&self, index: usize) -> Option<(&String, &Expression)> {
+    let token = &self.tokens[index].token;
+
+    if token.kind != TokenKind::Id {
+      return None;
+    }
+
+    let mut all = Vec::new();
+
+    for dec in &self.module.as_ref()?.functions {
+      expressions(&dec.ex.body, &mut all);
+    }
+
+    all.into_iter().find_map(|ex| match ex {
+      Expression::Variable(var) if var.loc.contains(token.location.y, token.location.x) => Some((&var.id, ex)),
+      _ => None,
+    })
+  }
+}
+
+fn is_fun(token: &Token) -> bool {
+  token.kind == TokenKind::Keyword && token.value == "fun"
+}
+
+/**
+* ex and everything inside it.
+*/
+fn expressions<'a>(ex: &'a Expression, all: &mut Vec<&'a Expression>) {
+  all.push(ex);
+
+  let children: Vec<&Expression> = match ex {
+    Expression::FunctionDeclaration(ex) => vec![&ex.body],
+    Expression::Assignment(ex) => vec![&ex.body],
+    Expression::Reassignment(ex) => vec![&ex.body],
+    Expression::BinaryOp(ex) => vec![&ex.left, &ex.right],
+    Expression::Call(ex) => Some(&ex.func).into_iter().chain(ex.args.iter()).chain(ex.named.iter().map(|(_, arg)| arg)).collect(),
+    Expression::If(ex) => vec![&ex.condition, &ex.then_block, &ex.else_block],
+    Expression::While(ex) => vec![&ex.condition, &ex.body],
+    Expression::Block(ex) => ex.body.iter().collect(),
+    Expression::Tuple(ex) => ex.items.iter().collect(),
+    Expression::List(ex) => ex.items.iter().collect(),
+    Expression::Variant(ex) => ex.values.iter().collect(),
+    Expression::Try(ex) => vec![&ex.body],
+    Expression::Return(ex) => vec![&ex.body],
+    Expression::Index(ex) => vec![&ex.list, &ex.index],
+    Expression::Field(ex) => vec![&ex.value],
+    Expression::Assert(ex) => vec![&ex.condition],
+    _ => vec![],
+  };
+
+  for child in children {
+    expressions(child, all);
+  }
+}
+
+/**
+* Serves the Language Server Protocol over input and output until the client says exit. Supports diagnostics,
+* hover and go to definition, always syncing whole documents.
+*/
+pub fn run_server<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> io::Result<()> {
+  let mut documents: HashMap<String, Document> = HashMap::new();
+
+  while let Some(message) = read_message(input)? {
+    let method = message.get("method").as_str().unwrap_or("");
+    let params = message.get("params");
+    let id = message.get("id").clone();
+
+    let result = match method {
+      "initialize" => Json::object(vec![
+        ("capabilities", Json::object(vec![
+          ("textDocumentSync", Json::Number(1.0)),
+          ("hoverProvider", Json::Bool(true)),
+          ("definitionProvider", Json::Bool(true)),
+        ])),
+        ("serverInfo", Json::object(vec![("name", Json::string("letlang"))])),
+      ]),
+      "exit" => return Ok(()),
+      "textDocument/didOpen" | "textDocument/didChange" => {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+        let text = match method {
+          "textDocument/didOpen" => params.get("textDocument").get("text").as_str(),
+          _ => params.get("contentChanges").as_array().and_then(|changes| changes.last()).and_then(|change| change.get("text").as_str()),
+        };
+
+        let document = check_document(text.unwrap_or(""), &module_name(uri));
+        write_message(output, &publish_diagnostics(uri, &document))?;
+        documents.insert(String::from(uri), document);
+        continue;
+      }
+      "textDocument/didClose" => {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+        documents.remove(uri);
+        write_message(output, &publish_diagnostics(uri, &Document { errors: Vec::new(), warnings: Vec::new(), module: None, definitions: Vec::new(), tokens: Vec::new() }))?;
+        continue;
+      }
+      "textDocument/hover" => {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+
+        match documents.get(uri).and_then(|doc| position(params).and_then(|(line, column)| doc.hover(line, column))) {
+          Some(text) => Json::object(vec![("contents", Json::object(vec![("kind", Json::string("plaintext")), ("value", Json::String(text))]))]),
+          None => Json::Null,
+        }
+      }
+      "textDocument/definition" => {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+
+        match documents.get(uri).and_then(|doc| position(params).and_then(|(line, column)| doc.definition(line, column))) {
+          Some(loc) => Json::object(vec![("uri", Json::string(uri)), ("range", range(Some(&loc)))]),
+          None => Json::Null,
+        }
+      }
+      _ if id == Json::Null => continue, // Notifications we don't care about, like initialized.
+      "shutdown" => Json::Null,
+      _ => {
+        let error = Json::object(vec![("code", Json::Number(-32601.0)), ("message", Json::String(format!("Unknown method {}", method)))]);
+        write_message(output, &Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", id), ("error", error)]))?;
+        continue;
+      }
+    };
+
+    write_message(output, &Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", id), ("result", result)]))?;
+  }
+
+  Ok(())
+}
+
+/**
+* The module a document is checked as, its file name without .let.
+*/
+fn module_name(uri: &str) -> String {
+  let file = uri.rsplit('/').next().unwrap_or(uri);
+  String::from(file.trim_end_matches(".let"))
+}
+
+/**
+* The position of a request as a line and column counted from 1.
+*/
+fn position(params: &Json) -> Option<(usize, usize)> {
+  let line = params.get("position").get("line").as_f64()?;
+  let character = params.get("position").get("character").as_f64()?;
+
+  Some((line as usize + 1, character as usize + 1))
+}
+
+/**
+* The range loc spans, at least one character long, or the very start when there isn't one.
+*/
+fn range(loc: Option<&Location>) -> Json {
+  let point = |line: usize, column: usize| Json::object(vec![
+    ("line", Json::Number(line.saturating_sub(1) as f64)),
+    ("character", Json::Number(column.saturating_sub(1) as f64)),
+  ]);
+
+  match loc {
+    Some(loc) if (loc.end_y, loc.end_x) > (loc.y, loc.x) => Json::object(vec![("start", point(loc.y, loc.x)), ("end", point(loc.end_y, loc.end_x))]),
+    Some(loc) => Json::object(vec![("start", point(loc.y, loc.x)), ("end", point(loc.y, loc.x + 1))]),
+    None => Json::object(vec![("start", point(1, 1)), ("end", point(1, 2))]),
+  }
+}
+
+fn publish_diagnostics(uri: &str, document: &Document) -> Json {
+  let errors = document.errors.iter().map(|error| (error, 1.0));
+  let warnings = document.warnings.iter().map(|warning| (warning, 2.0));
+
+  let diagnostics = errors.chain(warnings)
+    .map(|((loc, message), severity)| Json::object(vec![
+      ("range", range(loc.as_ref())),
+      ("severity", Json::Number(severity)),
+      ("source", Json::string("letlang")),
+      ("message", Json::string(message)),
+    ]))
+    .collect();
+
+  Json::object(vec![
+    ("jsonrpc", Json::string("2.0")),
+    ("method", Json::string("textDocument/publishDiagnostics")),
+    ("params", Json::object(vec![("uri", Json::string(uri)), ("diagnostics", Json::Array(diagnostics))])),
+  ])
+}
+
+/**
+* Reads one Content-Length framed message, None once input is closed.
+*/
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Json>> {
+  let mut length = None;
+
+  loop {
+    let mut header = String::new();
+
+    if input.read_line(&mut header)? == 0 {
+      return Ok(None);
+    }
+
+    let header = header.trim();
+
+    if header.is_empty() {
+      break;
+    }
+
+    if header.to_lowercase().starts_with("content-length:") {
+      length = header["content-length:".len()..].trim().parse::<usize>().ok();
+    }
+  }
+
+  let length = length.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Message without a Content-Length"))?;
+  let mut body = vec![0; length];
+  input.read_exact(&mut body)?;
+
+  let text = String::from_utf8(body).map_err(|err| io::Error::new(ErrorKind::InvalidData, err.to_string()))?;
+
+  parse_json(&text).map(Some).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+  let body = message.to_string();
+
+  write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+  output.flush()
+}
@@ -1,68 +1,380 @@
-extern crate core;
-extern crate simple_error;
-extern crate serde;
-extern crate bincode;
-
-use std::collections::HashMap;
-use std::path::Path;
-
-use simple_error::SimpleError;
-
-use ast::AstModule;
-use bytecode::{BitApplication, BitModule, BitPackage};
-use bytecode::BitFunction;
-use bytecode::FunctionRef;
-use bytecode::Instruction;
-use compiler::compile_package;
-use interpreter::Machine;
-use interpreter::RunFunction;
-use ir::compile_ir_module;
-use parser::parse;
-use runtime::Value;
-use shapes::{BaseShapeKind, shape_unknown, shape_float};
-use shapes::Shape;
-use typechecker::check_module;
-
-#[macro_use]
-mod shapes;
-mod ast;
-mod bytecode;
-mod compiler;
-mod interpreter;
-mod ir;
-mod lib_core;
-mod optimize;
-mod parser;
-mod runtime;
-mod typechecker;
+extern crate rust_let_lang;
 
+use rust_let_lang::{AppShapes, Backend, BitApplication, Dispatch, BitPackage, DEFAULT_MAX_CALL_DEPTH, FunctionRef, Machine, Optimizer, OptLevel, LetError, Linker, STACK_SIZE_PER_CALL, SecurityConfig, Value, verify_roundtrip};
+use rust_let_lang::bench;
+use rust_let_lang::bytecode::{deserialize_bit_module, serialize_bit_module};
+use rust_let_lang::cache::{BuildCache, DEFAULT_CACHE_DIR};
+use rust_let_lang::compiler::{MANIFEST_FILE, SCRIPT_PACKAGE, compile_application, compile_package_cached, compile_script};
+use rust_let_lang::debugger::ConsoleDebugger;
+use rust_let_lang::format::format_module;
+use rust_let_lang::lsp::run_server;
+use rust_let_lang::optimize::Pass;
+use rust_let_lang::parser::parse;
+use rust_let_lang::repl::run_repl;
+use rust_let_lang::shapes::shape_float;
+use rust_let_lang::testing::test_directory;
+use rust_let_lang::watch::Watcher;
+use rust_let_lang::Shape;
+
+/**
+* Runs on the experimental register backend instead of the stack interpreter.
+*/
+const BACKEND_REGISTER_FLAG: &'static str = "--backend=register";
+
+/**
+* Runs the stack interpreter on closures decoded ahead of time instead of matching on each instruction.
+*/
+const DISPATCH_THREADED_FLAG: &'static str = "--dispatch=threaded";
+
+/**
+* Strips asserts from the program, which are otherwise checked at every optimization level.
+*/
+const RELEASE_FLAG: &'static str = "--release";
+
+/**
+* Names the file build writes its module to.
+*/
+const OUTPUT_FLAG: &'static str = "-o";
+
+/**
+* Timed runs of each benchmark per config when bench isn't given a count.
+*/
+const DEFAULT_BENCH_ITERATIONS: u32 = 10;
+
+/**
+* How often watch looks for changed sources.
+*/
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
 
 fn main() {
+  // The Machine nests a Rust call per letLang call, the main thread's stack is too small for the default depth limit.
+  let runner = std::thread::Builder::new()
+    .stack_size(DEFAULT_MAX_CALL_DEPTH * STACK_SIZE_PER_CALL)
+    .spawn(run);
+
+  match runner {
+    Ok(handle) => if handle.join().is_err() {
+      std::process::exit(101);
+    }
+    Err(error) => println!("Error: {}", error),
+  }
+}
+
+fn run() {
+  if std::env::args().nth(1).filter(|arg| arg == "repl").is_some() {
+    if let Err(error) = run_repl(&Optimizer::with_level(OptLevel::O2)) {
+      println!("Error: {}", error);
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "lsp").is_some() {
+    // Stdout belongs to the client, anything else goes to stderr.
+    let stdin = std::io::stdin();
+
+    if let Err(error) = run_server(&mut stdin.lock(), &mut std::io::stdout()) {
+      eprintln!("Error: {}", error);
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "fmt").is_some() {
+    match std::env::args().nth(2) {
+      Some(file) => format_file(&file),
+      None => println!("Usage: fmt <file>"),
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "build").is_some() {
+    let output = std::env::args().skip_while(|arg| arg != OUTPUT_FLAG).nth(1);
+
+    match (std::env::args().nth(2).filter(|arg| arg != OUTPUT_FLAG), output) {
+      (Some(script), Some(output)) => build_file(&script, &output),
+      _ => println!("Usage: build <script.let> {} <file.letb>", OUTPUT_FLAG),
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "disasm").is_some() {
+    match std::env::args().nth(2) {
+      Some(file) => disassemble_file(&file),
+      None => println!("Usage: disasm <file.letb>"),
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "bench").is_some() {
+    match std::env::args().nth(2).map(|count| count.parse::<u32>()) {
+      None => run_benchmarks(DEFAULT_BENCH_ITERATIONS),
+      Some(Ok(iterations)) => run_benchmarks(iterations),
+      Some(Err(_)) => println!("Usage: bench [iterations]"),
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "debug").is_some() {
+    match debug_test() {
+      Ok(result) => println!("Result: {:?}", result),
+      Err(error) => println!("Error: {}", error),
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "test").is_some() {
+    match std::env::args().nth(2) {
+      Some(dir) => run_tests(&dir),
+      None => println!("Usage: test <dir>"),
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "run").is_some() {
+    match std::env::args().nth(2) {
+      Some(target) => print_result(run_application(&target)),
+      None => println!("Usage: run <script.let or dir with {}>", MANIFEST_FILE),
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "watch").is_some() {
+    match std::env::args().nth(2) {
+      Some(target) => watch_application(&target),
+      None => println!("Usage: watch <script.let or dir with {}>", MANIFEST_FILE),
+    }
+    return;
+  }
+
+  if std::env::args().nth(1).filter(|arg| arg == "profile").is_some() {
+    if let Err(error) = profile_test() {
+      println!("Error: {}", error);
+    }
+    return;
+  }
+
   match compile_test() {
-    Ok(Value::Float(result)) => println!("Success: \n{:#?}", result),
-    Ok(_) => println!("Failure: "),
-    Err(simple_error) => println!("Error: {}", simple_error.as_str())
+    Ok(result) => println!("Success: \n{}", result),
+    Err(error) => println!("Error: {}", error)
+  }
+}
+
+fn format_file(file: &str) {
+  let path = std::path::Path::new(file);
+  let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("main");
+
+  match parse(path, "fmt", name) {
+    Ok(module) => print!("{}", format_module(&module)),
+    Err(errors) => {
+      for error in errors {
+        println!("Error: {}", error);
+      }
+    }
+  }
+}
+
+fn run_application(target: &str) -> Result<Value, LetError> {
+  let path = std::path::Path::new(target);
+  let optimizer = if std::env::args().any(|arg| arg == RELEASE_FLAG) {
+    Optimizer::builder(OptLevel::O2).enable(Pass::StripAssert).build()
+  } else {
+    Optimizer::with_level(OptLevel::O2)
+  };
+
+  let mut shapes = AppShapes::new();
+
+  let app = if path.extension().and_then(|ex| ex.to_str()) == Some("let") {
+    compile_script(path, &optimizer, &shapes)
+  } else {
+    compile_application(path, &optimizer, Some(&BuildCache::new(DEFAULT_CACHE_DIR)), &mut shapes)
+  };
+
+  print_warnings(&shapes);
+  let app = app?;
+
+  // Everything after run <target> is passed on to the program, except the flags meant for us.
+  let mut machine = Machine::new(app);
+  machine.set_args(std::env::args().skip(3).filter(|arg| arg != BACKEND_REGISTER_FLAG && arg != DISPATCH_THREADED_FLAG && arg != RELEASE_FLAG).collect());
+  machine.set_security(SecurityConfig::trusted());
+
+  if std::env::args().any(|arg| arg == BACKEND_REGISTER_FLAG) {
+    machine.set_backend(Backend::Register);
+  }
+
+  if std::env::args().any(|arg| arg == DISPATCH_THREADED_FLAG) {
+    machine.set_dispatch(Dispatch::Threaded);
+  }
+
+  Ok(machine.run_main()?)
+}
+
+fn print_warnings(shapes: &AppShapes) {
+  for warning in shapes.take_warnings() {
+    eprintln!("Warning: {}", warning);
   }
 }
 
-fn compile_test() -> Result<Value, SimpleError> {
-  let module_name = String::from("basic");
-  let package_name = String::from("test");
+fn print_result(result: Result<Value, LetError>) {
+  match result {
+    // A main returning Unit has nothing to say.
+    Ok(Value::Unit) => {}
+    Ok(result) => println!("{}", result),
+    Err(error) => println!("Error: {}", error),
+  }
+}
+
+/**
+* Runs the target, then again each time a source under it changes. Unchanged modules come from the build cache.
+*/
+fn watch_application(target: &str) {
+  let path = std::path::Path::new(target);
+
+  let dir = if path.extension().and_then(|ex| ex.to_str()) == Some("let") {
+    path.parent().unwrap_or(std::path::Path::new("."))
+  } else {
+    path
+  };
 
-  let package = compile_package("test", "/home/dillon/projects/rustLetLang/test")?;
-  let mut app = BitApplication::new(FunctionRef {
-    package: package_name.clone(),
-    module: module_name.clone(),
+  let mut watcher = match Watcher::new(dir) {
+    Ok(watcher) => watcher,
+    Err(error) => return println!("Error: {}", error),
+  };
+
+  loop {
+    print_result(run_application(target));
+    println!("Watching {} for changes", dir.display());
+
+    loop {
+      std::thread::sleep(WATCH_INTERVAL);
+
+      match watcher.changed() {
+        Ok(true) => break,
+        Ok(false) => {}
+        Err(error) => println!("Error: {}", error),
+      }
+    }
+  }
+}
+
+fn run_benchmarks(iterations: u32) {
+  match bench::run_benchmarks(iterations) {
+    Ok(measurements) => println!("{}", bench::report(&measurements)),
+    Err(error) => println!("Error: {}", error),
+  }
+}
+
+fn run_tests(dir: &str) {
+  let optimizer = Optimizer::with_level(OptLevel::O2);
+  let mut shapes = AppShapes::new();
+  let result = test_directory(dir, &optimizer, &mut shapes);
+
+  print_warnings(&shapes);
+
+  match result {
+    Ok(report) => {
+      println!("{}", report.report());
+
+      if report.failed() > 0 {
+        std::process::exit(1);
+      }
+    }
+    Err(error) => {
+      println!("Error: {}", error);
+      std::process::exit(1);
+    }
+  }
+}
+
+/**
+* Compiles a script and writes its module out as bytecode, for disasm to read back.
+*/
+fn build_file(script: &str, output: &str) {
+  let shapes = AppShapes::new();
+  let result = compile_script(std::path::Path::new(script), &Optimizer::with_level(OptLevel::O2), &shapes);
+
+  print_warnings(&shapes);
+
+  let result = result
+    .map_err(|err| err.to_string())
+    .and_then(|app| {
+      let module = app.packages.get(SCRIPT_PACKAGE)
+        .and_then(|package| package.modules.values().next())
+        .ok_or_else(|| String::from("Script compiled to nothing"))?;
+
+      let mut writer = std::fs::File::create(output).map_err(|err| err.to_string())?;
+      serialize_bit_module(&mut writer, module).map_err(|err| err.to_string())
+    });
+
+  if let Err(error) = result {
+    println!("Error: {}", error);
+  }
+}
+
+fn disassemble_file(file: &str) {
+  let module = std::fs::File::open(file)
+    .map_err(|err| err.to_string())
+    .and_then(|mut reader| deserialize_bit_module(&mut reader).map_err(|err| err.to_string()));
+
+  let result = module.and_then(|module| module.disassemble(&mut std::io::stdout()).map_err(|err| err.to_string()));
+
+  if let Err(error) = result {
+    println!("Error: {}", error);
+  }
+}
+
+fn compile_test() -> Result<Value, LetError> {
+  if std::env::args().any(|arg| arg == "--verify") {
+    return Ok(verify_roundtrip("test", test_package()?, &test_main(), vec![])?);
+  }
+
+  let machine = Machine::new(test_application()?);
+
+  Ok(machine.run_main()?)
+}
+
+fn debug_test() -> Result<Value, LetError> {
+  let mut machine = Machine::new(test_application()?);
+  machine.set_debugger(Box::new(ConsoleDebugger));
+
+  Ok(machine.run_main()?)
+}
+
+fn profile_test() -> Result<(), LetError> {
+  let mut machine = Machine::new(test_application()?);
+  machine.enable_profiling();
+
+  println!("Result: {:?}", machine.run_main()?);
+
+  if let Some(profile) = machine.profile() {
+    println!("{}", profile.report());
+  }
+
+  Ok(())
+}
+
+fn test_application() -> Result<BitApplication, LetError> {
+  Ok(Linker::new()
+    .package("test", test_package()?)
+    .main(test_main())
+    .link()?)
+}
+
+fn test_package() -> Result<BitPackage, LetError> {
+  let optimizer = Optimizer::with_level(OptLevel::O2);
+
+  let cache = BuildCache::new(DEFAULT_CACHE_DIR);
+
+  Ok(compile_package_cached("test", "/home/dillon/projects/rustLetLang/test", &optimizer, &mut AppShapes::new(), &cache)?)
+}
+
+fn test_main() -> FunctionRef {
+  FunctionRef {
+    package: String::from("test"),
+    module: String::from("basic"),
     name: String::from("main"),
 
     shape: Shape::SimpleFunctionShape {
       args: vec![],
       result: Box::new(shape_float()),
     }
-  });
-  app.packages.insert(package_name, package);
-
-  let machine = Machine::new(app);
-
-  machine.run_main()
+  }
 }
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use ir::{IrFunction, Ir};
+
+/**
+* Finds the pattern of
+* Load(a)
+* Load(b)
+* Op
+*
+* repeated later in the same block with neither a nor b stored in between, where Op is a pure float operation.
+* The first one gets a Duplicate and Store(temp) after it and each repeat becomes Load(temp).
+*/
+pub fn common_subexpression_opt(func: &mut IrFunction) {
+  let mut temps = 0usize;
+  common_subexpression(&mut func.body, &mut temps);
+}
+
+type Key = (String, String, &'static str);
+
+fn common_subexpression(body: &mut Vec<Ir>, temps: &mut usize) {
+  let input: Vec<Ir> = body.drain(..).collect();
+  let mut available: HashMap<Key, (usize, Option<String>)> = HashMap::new();
+  // Kept apart from available, a store can drop an expression after its temp was already used.
+  let mut stores: Vec<(usize, String)> = Vec::new();
+  let mut index = 0usize;
+
+  while index < input.len() {
+    if let Some(key) = expression_at(&input, index) {
+      if let Some((op_index, temp)) = available.get_mut(&key) {
+        let local = temp.get_or_insert_with(|| {
+          *temps += 1;
+          let local = format!("#cse{}", temps);
+          stores.push((*op_index, local.clone()));
+          local
+        });
+
+        body.push(Ir::LoadValue { local: local.clone() });
+      } else {
+        body.extend(input[index..index + 3].iter().cloned());
+        available.insert(key, (body.len() - 1, None));
+      }
+
+      index += 3;
+      continue;
+    }
+
+    let mut next = input[index].clone();
+    nested(&mut next, temps);
+
+    let mut stored = Vec::new();
+    stored_locals(&next, &mut stored);
+    available.retain(|(left, right, _), _| !stored.contains(left) && !stored.contains(right));
+
+    body.push(next);
+    index += 1;
+  }
+
+  stores.sort_by(|left, right| right.0.cmp(&left.0));
+
+  for (op_index, local) in stores {
+    body.insert(op_index + 1, Ir::StoreValue { local });
+    body.insert(op_index + 1, Ir::Duplicate);
+  }
+}
+
+/**
+* The expression starting at index, if there's one a temp could stand in for.
+*/
+fn expression_at(body: &[Ir], index: usize) -> Option<Key> {
+  match (body.get(index)?, body.get(index + 1)?, pure_op(body.get(index + 2)?)?) {
+    (Ir::LoadValue { local: left }, Ir::LoadValue { local: right }, op) => Some((left.clone(), right.clone(), op)),
+    _ => None,
+  }
+}
+
+fn pure_op(ir: &Ir) -> Option<&'static str> {
+  match ir {
+    Ir::AddFloat => Some("+"),
+    Ir::SubFloat => Some("-"),
+    Ir::MulFloat => Some("*"),
+    Ir::DivFloat => Some("/"),
+    Ir::LessFloat => Some("<"),
+    Ir::LessEqualFloat => Some("<="),
+    Ir::GreaterFloat => Some(">"),
+    Ir::GreaterEqualFloat => Some(">="),
+    _ => None,
+  }
+}
+
+fn nested(ir: &mut Ir, temps: &mut usize) {
+  match ir {
+    Ir::Branch { then_block, else_block } => {
+      common_subexpression(then_block, temps);
+      common_subexpression(else_block, temps);
+    }
+    Ir::Cond { arms, else_block } => {
+      for (condition, then_block) in arms.iter_mut() {
+        common_subexpression(condition, temps);
+        common_subexpression(then_block, temps);
+      }
+
+      common_subexpression(else_block, temps);
+    }
+    Ir::Loop { condition, body } => {
+      common_subexpression(condition, temps);
+      common_subexpression(body, temps);
+    }
+    _ => {}
+  }
+}
+
+/**
+* Every local ir stores, at any depth.
+*/
+fn stored_locals(ir: &Ir, stored: &mut Vec<String>) {
+  match ir {
+    Ir::StoreValue { local } => stored.push(local.clone()),
+    Ir::Branch { then_block, else_block } => {
+      then_block.iter().chain(else_block.iter()).for_each(|ir| stored_locals(ir, stored));
+    }
+    Ir::Cond { arms, else_block } => {
+      for (condition, then_block) in arms {
+        condition.iter().chain(then_block.iter()).for_each(|ir| stored_locals(ir, stored));
+      }
+
+      else_block.iter().for_each(|ir| stored_locals(ir, stored));
+    }
+    Ir::Loop { condition, body } => {
+      condition.iter().chain(body.iter()).for_each(|ir| stored_locals(ir, stored));
+    }
+    Ir::Assert { condition, .. } => condition.iter().for_each(|ir| stored_locals(ir, stored)),
+    _ => {}
+  }
+}
@@ -0,0 +1,29 @@
+use cfg::Cfg;
+use ir::{IrFunction, Ir};
+
+/**
+* Turns a Store(x) into a Pop when no path reads x before it's stored again, using liveness over the function's
+* basic blocks.
+*/
+pub fn dead_store_opt(func: &mut IrFunction) {
+  let mut cfg = Cfg::lower(func);
+  let live_out = cfg.live_out();
+
+  for (block, mut live) in cfg.blocks.iter_mut().zip(live_out) {
+    for ir in block.body.iter_mut().rev() {
+      match ir {
+        Ir::LoadValue { local } => {
+          live.insert(local.clone());
+        }
+        Ir::StoreValue { local } => {
+          if !live.remove(local) {
+            *ir = Ir::Pop;
+          }
+        }
+        _ => {}
+      }
+    }
+  }
+
+  cfg.raise(func);
+}
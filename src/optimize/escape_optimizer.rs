@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use bytecode::FunctionRef;
+use ir::{IrModule, IrFunction, Ir, stack_effect};
+
+/**
+* Finds closures that never escape the function that builds them, that is the pattern of
+* Load(capture) ...
+* BuildClosure
+* Store(f)
+*
+* where f is stored nowhere else and every Load(f) is only ever the function of a CallDynamic. The closure is never
+* built, each call instead loads the captures itself and calls the closure's function with CallStatic, the captures
+* going first just as BuildClosure would have put them.
+*
+* Captured locals are only ever cells or values stored once, so loading them again at the call gives the same
+* values as loading them when the closure would have been built.
+*/
+pub fn escape_opt(module: &mut IrModule) {
+  for func in module.functions.values_mut() {
+    escape(func);
+  }
+}
+
+struct Closure {
+  local: String,
+  captures: Vec<Ir>,
+  func: FunctionRef,
+}
+
+fn escape(func: &mut IrFunction) {
+  let mut closures = Vec::new();
+  find_closures(&func.body, &mut closures);
+
+  let mut stores = HashMap::new();
+  count_stores(&func.body, &mut stores);
+
+  for closure in closures {
+    let stored_once = |local: &String| stores.get(local).cloned().unwrap_or(0) <= 1;
+
+    let captures_fixed = closure.captures.iter().all(|capture| match capture {
+      Ir::LoadValue { local } => stored_once(local),
+      _ => true,
+    });
+
+    if stores.get(&closure.local) == Some(&1) && captures_fixed && only_called(&func.body, &closure.local) {
+      rewrite(&mut func.body, &closure);
+    }
+  }
+}
+
+fn find_closures(body: &[Ir], closures: &mut Vec<Closure>) {
+  for (index, ir) in body.iter().enumerate() {
+    if let (Ir::BuildClosure { param_count, func }, Some(Ir::StoreValue { local })) = (ir, body.get(index + 1)) {
+      let count = *param_count as usize;
+
+      let captures = match index.checked_sub(count).map(|first| &body[first..index]) {
+        Some(captures) if captures.iter().all(|capture| is_capture(capture)) => captures.to_vec(),
+        _ => continue,
+      };
+
+      closures.push(Closure { local: local.clone(), captures, func: func.clone() });
+    }
+
+    for block in blocks(ir) {
+      find_closures(block, closures);
+    }
+  }
+}
+
+fn is_capture(ir: &Ir) -> bool {
+  match ir {
+    Ir::LoadValue { .. } | Ir::LoadConstFunction { .. } => true,
+    _ => false,
+  }
+}
+
+/**
+* Whether every Load(local) is followed, with nothing but straight line code in between, by the CallDynamic it's
+* the function of.
+*/
+fn only_called(body: &[Ir], local: &str) -> bool {
+  body.iter().enumerate().all(|(index, ir)| match ir {
+    Ir::LoadValue { local: loaded } if loaded == local => call_of(body, index).is_some(),
+    _ => blocks(ir).iter().all(|block| only_called(block, local)),
+  })
+}
+
+/**
+* The index of the CallDynamic that calls the function loaded at index.
+*/
+fn call_of(body: &[Ir], index: usize) -> Option<usize> {
+  let mut above = 0usize;
+
+  for (next, ir) in body.iter().enumerate().skip(index + 1) {
+    let (pops, pushes) = stack_effect(ir)?;
+
+    if pops > above {
+      return match ir {
+        Ir::CallDynamic { param_count } if *param_count as usize == above => Some(next),
+        _ => None,
+      };
+    }
+
+    above = above - pops + pushes;
+  }
+
+  None
+}
+
+fn rewrite(body: &mut Vec<Ir>, closure: &Closure) {
+  let mut index = 0usize;
+
+  while index < body.len() {
+    match &body[index] {
+      Ir::BuildClosure { .. } if is_store_of(body.get(index + 1), &closure.local) => {
+        let first = index - closure.captures.len();
+        body.drain(first..index + 2);
+        index = first;
+        continue;
+      }
+      Ir::LoadValue { local } if *local == closure.local => {
+        if let Some(call) = call_of(body, index) {
+          body[call] = Ir::CallStatic { func: closure.func.clone() };
+          body.splice(index..index + 1, closure.captures.iter().cloned());
+          index += closure.captures.len();
+          continue;
+        }
+      }
+      _ => {}
+    }
+
+    for block in blocks_mut(&mut body[index]) {
+      rewrite(block, closure);
+    }
+
+    index += 1;
+  }
+}
+
+fn is_store_of(ir: Option<&Ir>, local: &str) -> bool {
+  match ir {
+    Some(Ir::StoreValue { local: stored }) => stored == local,
+    _ => false,
+  }
+}
+
+fn count_stores(body: &[Ir], stores: &mut HashMap<String, usize>) {
+  for ir in body {
+    if let Ir::StoreValue { local } = ir {
+      *stores.entry(local.clone()).or_insert(0) += 1;
+    }
+
+    for block in blocks(ir) {
+      count_stores(block, stores);
+    }
+  }
+}
+
+fn blocks(ir: &Ir) -> Vec<&Vec<Ir>> {
+  match ir {
+    Ir::Branch { then_block, else_block } => vec![then_block, else_block],
+    Ir::Cond { arms, else_block } => arms.iter().flat_map(|(condition, then_block)| vec![condition, then_block]).chain(vec![else_block]).collect(),
+    Ir::Loop { condition, body } => vec![condition, body],
+    Ir::Assert { condition, .. } => vec![condition],
+    _ => vec![],
+  }
+}
+
+fn blocks_mut(ir: &mut Ir) -> Vec<&mut Vec<Ir>> {
+  match ir {
+    Ir::Branch { then_block, else_block } => vec![then_block, else_block],
+    Ir::Cond { arms, else_block } => arms.iter_mut().flat_map(|(condition, then_block)| vec![condition, then_block]).chain(vec![else_block]).collect(),
+    Ir::Loop { condition, body } => vec![condition, body],
+    Ir::Assert { condition, .. } => vec![condition],
+    _ => vec![],
+  }
+}
@@ -1,37 +1,251 @@
+use std::collections::HashSet;
+
 use ir::{IrFunction, Ir};
 
+/**
+* Frees each local once it's dead, found by a backward liveness analysis over the Ir tree.
+*
+* The compiler hands out slots in the order code is laid out, not the order it runs, so a local is only freed once
+* it's also never mentioned again further down. Otherwise a local declared on the else side of a Branch could be
+* given the slot of one the then side freed, and a store after the last load would still write into a reused slot.
+*/
 pub fn free_local_opt(func: &mut IrFunction) {
-  free_local(&mut func.body, &Vec::new());
+  strip_frees(&mut func.body);
+  free_local(&mut func.body, &HashSet::new(), &HashSet::new());
 }
 
-fn free_local(body: &mut Vec<Ir>, prev_locals: &Vec<String>) {
-  let mut index = body.len();
-  let mut known_locals = prev_locals.clone();
-  let mut do_free = false;
+/**
+* Inserts FreeLocal into body, where live_after is what's live once body is done and later is every local mentioned
+* after body in layout order. Gives back the locals freed, at any depth.
+*/
+fn free_local(body: &mut Vec<Ir>, live_after: &HashSet<String>, later: &HashSet<String>) -> HashSet<String> {
+  let mut live_at = Vec::with_capacity(body.len());
+  let mut mentioned_at = Vec::with_capacity(body.len());
+  let mut live = live_after.clone();
+  let mut mentioned = later.clone();
 
-  while index > 0 {
-    match body[index - 1] {
-      Ir::LoadValue{local: ref next_load} => {
-        if !known_locals.contains(next_load) {
-          known_locals.push(next_load.clone());
-          do_free = true;
+  for ir in body.iter().rev() {
+    live_at.push(live.clone());
+    mentioned_at.push(mentioned.clone());
+    live = live_before(ir, live);
+    mentions(ir, &mut mentioned);
+  }
 
-        }
+  live_at.reverse();
+  mentioned_at.reverse();
+
+  let mut freed = HashSet::new();
+  let mut frees = Vec::new();
+
+  for (index, ir) in body.iter_mut().enumerate() {
+    let (live, later) = (&live_at[index], &mentioned_at[index]);
+    let inner = free_nested(ir, live, later);
+
+    let mut own = HashSet::new();
+    mentions(ir, &mut own);
+
+    let mut dead: Vec<String> = own.into_iter()
+      .filter(|local| !inner.contains(local) && !live.contains(local) && !later.contains(local))
+      .collect();
+
+    // HashSet order would make the slots each local gets change from one build to the next.
+    dead.sort();
+
+    freed.extend(inner);
+    freed.extend(dead.iter().cloned());
+    frees.push(dead);
+  }
+
+  for (index, dead) in frees.into_iter().enumerate().rev() {
+    for local in dead.into_iter().rev() {
+      body.insert(index + 1, Ir::FreeLocal { local });
+    }
+  }
+
+  freed
+}
+
+/**
+* Frees what dies inside the blocks of ir. An Assert's condition is left alone, so stripping it can't take a free
+* with it, anything it uses is freed after it instead.
+*/
+fn free_nested(ir: &mut Ir, live_after: &HashSet<String>, later: &HashSet<String>) -> HashSet<String> {
+  match ir {
+    Ir::Branch { then_block, else_block } => {
+      let mut then_later = later.clone();
+      mentions_block(else_block, &mut then_later);
+
+      let mut freed = free_local(then_block, live_after, &then_later);
+      freed.extend(free_local(else_block, live_after, later));
+      freed
+    }
+    Ir::Cond { arms, else_block } => {
+      // Laid out as each condition followed by its block, then the else. Only one block runs, but every
+      // condition before it does too.
+      let mut rest_live = live_block(else_block, live_after.clone());
+      let mut rest_mentioned = later.clone();
+      mentions_block(else_block, &mut rest_mentioned);
+
+      let mut freed = free_local(else_block, live_after, later);
+
+      for (condition, then_block) in arms.iter_mut().rev() {
+        let mut condition_live = live_block(then_block, live_after.clone());
+        condition_live.extend(rest_live);
+
+        let mut condition_later = rest_mentioned.clone();
+        mentions_block(then_block, &mut condition_later);
+
+        freed.extend(free_local(then_block, live_after, &rest_mentioned));
+        freed.extend(free_local(condition, &condition_live, &condition_later));
+
+        rest_live = live_block(condition, condition_live);
+        rest_mentioned = condition_later;
+        mentions_block(condition, &mut rest_mentioned);
       }
-      Ir::Branch {ref mut then_block, ref mut else_block} => {
 
-        free_local( then_block, &known_locals);
-        free_local( else_block, &known_locals);
+      freed
+    }
+    Ir::Loop { condition, body } => {
+      let head = loop_head(condition, body, live_after);
+
+      let mut condition_live = live_block(body, head.clone());
+      condition_live.extend(live_after.iter().cloned());
+
+      let mut condition_later = later.clone();
+      mentions_block(body, &mut condition_later);
+
+      let mut freed = free_local(condition, &condition_live, &condition_later);
+      freed.extend(free_local(body, &head, later));
+      freed
+    }
+    _ => HashSet::new(),
+  }
+}
+
+/**
+* What's live at the top of a loop, where the condition runs. Grows until going round once more adds nothing.
+*/
+fn loop_head(condition: &[Ir], body: &[Ir], live_after: &HashSet<String>) -> HashSet<String> {
+  let mut head = HashSet::new();
+
+  loop {
+    let mut condition_live = live_block(body, head.clone());
+    condition_live.extend(live_after.iter().cloned());
+
+    let next = live_block(condition, condition_live);
+
+    if next == head {
+      return head;
+    }
+
+    head = next;
+  }
+}
+
+fn live_block(body: &[Ir], live_after: HashSet<String>) -> HashSet<String> {
+  body.iter().rev().fold(live_after, |live, ir| live_before(ir, live))
+}
+
+fn live_before(ir: &Ir, mut live: HashSet<String>) -> HashSet<String> {
+  match ir {
+    Ir::LoadValue { local } => {
+      live.insert(local.clone());
+      live
+    }
+    Ir::StoreValue { local } => {
+      live.remove(local);
+      live
+    }
+    Ir::Branch { then_block, else_block } => {
+      let mut before = live_block(then_block, live.clone());
+      before.extend(live_block(else_block, live));
+      before
+    }
+    Ir::Cond { arms, else_block } => {
+      let mut rest = live_block(else_block, live.clone());
+
+      for (condition, then_block) in arms.iter().rev() {
+        let mut condition_live = live_block(then_block, live.clone());
+        condition_live.extend(rest);
+        rest = live_block(condition, condition_live);
       }
-      _ => {}
+
+      rest
+    }
+    Ir::Loop { condition, body } => loop_head(condition, body, &live),
+    Ir::Assert { condition, .. } => {
+      // Stripping the Assert skips whatever its condition stores.
+      let mut before = live_block(condition, live.clone());
+      before.extend(live);
+      before
     }
+    Ir::Return | Ir::Error { .. } | Ir::Panic { .. } => HashSet::new(),
+    _ => live,
+  }
+}
 
-    if do_free {
-      body.insert(index, Ir::FreeLocal {local: known_locals.last().unwrap().clone()});
-      do_free = false;
+/**
+* Every local ir loads or stores, at any depth.
+*/
+fn mentions(ir: &Ir, locals: &mut HashSet<String>) {
+  match ir {
+    Ir::LoadValue { local } | Ir::StoreValue { local } => {
+      locals.insert(local.clone());
     }
+    Ir::Branch { then_block, else_block } => {
+      mentions_block(then_block, locals);
+      mentions_block(else_block, locals);
+    }
+    Ir::Cond { arms, else_block } => {
+      for (condition, then_block) in arms {
+        mentions_block(condition, locals);
+        mentions_block(then_block, locals);
+      }
 
-    index -= 1;
+      mentions_block(else_block, locals);
+    }
+    Ir::Loop { condition, body } => {
+      mentions_block(condition, locals);
+      mentions_block(body, locals);
+    }
+    Ir::Assert { condition, .. } => mentions_block(condition, locals),
+    _ => {}
   }
+}
+
+fn mentions_block(body: &[Ir], locals: &mut HashSet<String>) {
+  body.iter().for_each(|ir| mentions(ir, locals));
+}
+
+/**
+* Frees from an earlier run would only get in the way of working out where the new ones go.
+*/
+fn strip_frees(body: &mut Vec<Ir>) {
+  body.retain(|ir| match ir {
+    Ir::FreeLocal { .. } => false,
+    _ => true,
+  });
 
+  for ir in body.iter_mut() {
+    match ir {
+      Ir::Branch { then_block, else_block } => {
+        strip_frees(then_block);
+        strip_frees(else_block);
+      }
+      Ir::Cond { arms, else_block } => {
+        for (condition, then_block) in arms.iter_mut() {
+          strip_frees(condition);
+          strip_frees(then_block);
+        }
+
+        strip_frees(else_block);
+      }
+      Ir::Loop { condition, body } => {
+        strip_frees(condition);
+        strip_frees(body);
+      }
+      Ir::Assert { condition, .. } => strip_frees(condition),
+      _ => {}
+    }
+  }
 }
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use ir::{IrModule, IrFunction, Ir};
+use shapes::Shape;
+
+/**
+* Replaces CallStatic instructions that target small functions in the same module with the body of that function.
+*
+* The arguments are already on the stack at the call site, so they are stored into freshly named locals
+* (in reverse order) and the inlined body has all of its locals renamed to match. The trailing Return is dropped
+* so the result is simply left on the stack.
+*
+* Only functions that take no closures, never call themselves and have a single Return at the very end are candidates.
+* Candidates are snapshot before any inlining happens so mutually recursive functions are only ever expanded once.
+*/
+pub fn inline_opt(module: &mut IrModule, threshold: usize) {
+  let candidates: HashMap<String, IrFunction> = module.functions.iter()
+    .filter(|(_, func)| is_candidate(func, threshold))
+    .map(|(name, func)| (name.clone(), func.clone()))
+    .collect();
+
+  if candidates.is_empty() {
+    return;
+  }
+
+  for func in module.functions.values_mut() {
+    let mut counter = 0usize;
+    let package = func.func_ref.package.clone();
+    let module_name = func.func_ref.module.clone();
+
+    inline(&mut func.body, &candidates, &package, &module_name, &func.func_ref.name, &mut counter);
+  }
+}
+
+fn inline(body: &mut Vec<Ir>, candidates: &HashMap<String, IrFunction>, package: &str, module: &str, caller: &str, counter: &mut usize) {
+  let mut index = 0usize;
+
+  while index < body.len() {
+    let target = match body[index] {
+      Ir::CallStatic {ref func} => {
+        if func.package == package && func.module == module && func.name != caller {
+          candidates.get(&func.name)
+        } else {
+          None
+        }
+      }
+      Ir::Branch {ref mut then_block, ref mut else_block} => {
+        inline(then_block, candidates, package, module, caller, counter);
+        inline(else_block, candidates, package, module, caller, counter);
+        None
+      }
+      Ir::Cond {ref mut arms, ref mut else_block} => {
+        for (condition, then_block) in arms.iter_mut() {
+          inline(condition, candidates, package, module, caller, counter);
+          inline(then_block, candidates, package, module, caller, counter);
+        }
+
+        inline(else_block, candidates, package, module, caller, counter);
+        None
+      }
+      Ir::Loop {ref mut condition, body: ref mut loop_body} => {
+        inline(condition, candidates, package, module, caller, counter);
+        inline(loop_body, candidates, package, module, caller, counter);
+        None
+      }
+      Ir::Assert {ref mut condition, ..} => {
+        inline(condition, candidates, package, module, caller, counter);
+        None
+      }
+      _ => None
+    };
+
+    if let Some(callee) = target {
+      let prefix = format!("$inline_{}_{}", *counter, callee.func_ref.name);
+      *counter += 1;
+
+      let mut replacement: Vec<Ir> = callee.args.iter().rev()
+        .map(|arg| Ir::StoreValue {local: rename(&prefix, &arg.id)})
+        .collect();
+
+      let mut inlined = callee.body.clone();
+      inlined.pop(); // Drop the trailing Return
+      remap_locals(&mut inlined, &prefix);
+      replacement.append(&mut inlined);
+
+      let size = replacement.len();
+      body.splice(index..index + 1, replacement);
+      index += size;
+    } else {
+      index += 1;
+    }
+  }
+}
+
+fn remap_locals(body: &mut Vec<Ir>, prefix: &str) {
+  for next in body.iter_mut() {
+    match next {
+      Ir::LoadValue {local} | Ir::StoreValue {local} | Ir::FreeLocal {local} => {
+        *local = rename(prefix, local);
+      }
+      Ir::Branch {then_block, else_block} => {
+        remap_locals(then_block, prefix);
+        remap_locals(else_block, prefix);
+      }
+      Ir::Cond {arms, else_block} => {
+        for (condition, then_block) in arms.iter_mut() {
+          remap_locals(condition, prefix);
+          remap_locals(then_block, prefix);
+        }
+
+        remap_locals(else_block, prefix);
+      }
+      Ir::Loop {condition, body} => {
+        remap_locals(condition, prefix);
+        remap_locals(body, prefix);
+      }
+      Ir::Assert {condition, ..} => remap_locals(condition, prefix),
+      _ => {}
+    }
+  }
+}
+
+fn rename(prefix: &str, local: &str) -> String {
+  format!("{}_{}", prefix, local)
+}
+
+fn is_candidate(func: &IrFunction, threshold: usize) -> bool {
+  let declared_args = if let Shape::SimpleFunctionShape {args, ..} = &func.shape {
+    args.len()
+  } else {
+    return false;
+  };
+
+  // Closures and recursive functions carry extra hidden args.
+  if func.args.len() != declared_args {
+    return false;
+  }
+
+  if let Some(Ir::Return) = func.body.last() {} else {
+    return false;
+  }
+
+  let body = &func.body[..func.body.len() - 1];
+
+  size(body) <= threshold && !contains_return(body) && !calls(body, &func.func_ref.name)
+}
+
+fn size(body: &[Ir]) -> usize {
+  body.iter().map(|next| match next {
+    Ir::Branch {then_block, else_block} => 1 + size(then_block) + size(else_block),
+    Ir::Cond {arms, else_block} => 1 + arms.iter().map(|(condition, then_block)| size(condition) + size(then_block)).sum::<usize>() + size(else_block),
+    Ir::Loop {condition, body} => 1 + size(condition) + size(body),
+    Ir::Assert {condition, ..} => 1 + size(condition),
+    _ => 1
+  }).sum()
+}
+
+fn contains_return(body: &[Ir]) -> bool {
+  body.iter().any(|next| match next {
+    Ir::Return => true,
+    Ir::Branch {then_block, else_block} => contains_return(then_block) || contains_return(else_block),
+    Ir::Cond {arms, else_block} => arms.iter().any(|(condition, then_block)| contains_return(condition) || contains_return(then_block)) || contains_return(else_block),
+    Ir::Loop {condition, body} => contains_return(condition) || contains_return(body),
+    Ir::Assert {condition, ..} => contains_return(condition),
+    _ => false
+  })
+}
+
+fn calls(body: &[Ir], name: &str) -> bool {
+  body.iter().any(|next| match next {
+    Ir::CallStatic {func} => func.name == name,
+    Ir::LoadConstFunction {value} => value.name == name,
+    Ir::BuildClosure {func, ..} => func.name == name,
+    Ir::Branch {then_block, else_block} => calls(then_block, name) || calls(else_block, name),
+    Ir::Cond {arms, else_block} => arms.iter().any(|(condition, then_block)| calls(condition, name) || calls(then_block, name)) || calls(else_block, name),
+    Ir::Loop {condition, body} => calls(condition, name) || calls(body, name),
+    Ir::Assert {condition, ..} => calls(condition, name),
+    _ => false
+  })
+}
@@ -12,10 +12,17 @@ fn lift_return(body: &mut Vec<Ir>) {
     match body[index] {
       Ir::Return => {
         if let Ir::Branch {ref mut then_block, ref mut else_block} = body[index - 1] {
-          then_block.push(Ir::Return);
-          lift_return(then_block);
-          else_block.push(Ir::Return);
-          lift_return(else_block);
+          push_return(then_block);
+          push_return(else_block);
+          do_remove = true;
+        }
+
+        if let Ir::Cond {ref mut arms, ref mut else_block} = body[index - 1] {
+          for (_, then_block) in arms.iter_mut() {
+            push_return(then_block);
+          }
+
+          push_return(else_block);
           do_remove = true;
         }
       }
@@ -30,3 +37,15 @@ fn lift_return(body: &mut Vec<Ir>) {
     index -= 1;
   }
 }
+
+/**
+* Moves the Return into block, unless it already returns early or fails on its own.
+*/
+fn push_return(block: &mut Vec<Ir>) {
+  match block.last() {
+    Some(Ir::Return) | Some(Ir::Error { .. }) | Some(Ir::Panic { .. }) => {}
+    _ => block.push(Ir::Return),
+  }
+
+  lift_return(block);
+}
@@ -19,7 +19,7 @@ fn load_store(body: &mut Vec<Ir>) {
   let mut do_remove = false;
   let mut do_dup = false;
 
-  while index < body.len() - 2 {
+  while index + 2 < body.len() {
     if let Ir::StoreValue {local: ref store} = body[index] {
       if let Ir::LoadValue{local: ref load} = body[index + 1] {
         if store == load {
@@ -39,6 +39,24 @@ fn load_store(body: &mut Vec<Ir>) {
       load_store( else_block);
     }
 
+    if let Ir::Cond {ref mut arms, ref mut else_block} = body[index] {
+      for (condition, then_block) in arms.iter_mut() {
+        load_store(condition);
+        load_store(then_block);
+      }
+
+      load_store(else_block);
+    }
+
+    if let Ir::Loop {ref mut condition, body: ref mut loop_body} = body[index] {
+      load_store( condition);
+      load_store( loop_body);
+    }
+
+    if let Ir::Assert {ref mut condition, ..} = body[index] {
+      load_store(condition);
+    }
+
     if do_remove {
       body.drain(index..index + 3);
       do_remove = false;
@@ -0,0 +1,214 @@
+use ir::{IrFunction, Ir};
+
+/**
+* Finds the pattern of
+* Load(a) or LoadConstFloat
+* Load(b) or LoadConstFloat
+* Op
+*
+* anywhere in a loop where neither a nor b is stored, and Op is a pure float operation. It's computed once into a
+* temp before the loop and each use in the loop becomes Load(temp). Float operations can't fail, so running one
+* for a loop that never iterates is harmless.
+*/
+pub fn loop_invariant_opt(func: &mut IrFunction) {
+  let mut temps = 0usize;
+  loop_invariant(&mut func.body, &mut temps);
+}
+
+#[derive(Clone, PartialEq)]
+enum Operand {
+  Local(String),
+  Const(u64),
+}
+
+struct Hoisted {
+  left: Operand,
+  right: Operand,
+  op: &'static str,
+  temp: String,
+}
+
+fn loop_invariant(body: &mut Vec<Ir>, temps: &mut usize) {
+  let mut index = 0usize;
+
+  while index < body.len() {
+    nested(&mut body[index], temps);
+
+    let mut hoisted = Vec::new();
+
+    if let Ir::Loop { condition, body: loop_body } = &mut body[index] {
+      let mut stored = Vec::new();
+      stored_locals(condition, &mut stored);
+      stored_locals(loop_body, &mut stored);
+
+      hoist(condition, &stored, &mut hoisted, temps);
+      hoist(loop_body, &stored, &mut hoisted, temps);
+    }
+
+    let before: Vec<Ir> = hoisted.into_iter()
+      .flat_map(|Hoisted { left, right, op, temp }| vec![load(left), load(right), op_of(op), Ir::StoreValue { local: temp }])
+      .collect();
+
+    let count = before.len();
+    body.splice(index..index, before);
+    index += count + 1;
+  }
+}
+
+/**
+* Inner loops go first, so what they hoist into this block can be hoisted again by the loop around it.
+*/
+fn nested(ir: &mut Ir, temps: &mut usize) {
+  match ir {
+    Ir::Branch { then_block, else_block } => {
+      loop_invariant(then_block, temps);
+      loop_invariant(else_block, temps);
+    }
+    Ir::Cond { arms, else_block } => {
+      for (condition, then_block) in arms.iter_mut() {
+        loop_invariant(condition, temps);
+        loop_invariant(then_block, temps);
+      }
+
+      loop_invariant(else_block, temps);
+    }
+    Ir::Loop { condition, body } => {
+      loop_invariant(condition, temps);
+      loop_invariant(body, temps);
+    }
+    _ => {}
+  }
+}
+
+/**
+* Replaces every invariant expression in body, at any depth, with a load of its temp.
+*/
+fn hoist(body: &mut Vec<Ir>, stored: &[String], hoisted: &mut Vec<Hoisted>, temps: &mut usize) {
+  let mut index = 0usize;
+
+  while index < body.len() {
+    if let Some((left, right, op)) = expression_at(body, index) {
+      let invariant = [&left, &right].iter().all(|operand| match operand {
+        Operand::Local(local) => !stored.contains(local),
+        Operand::Const(_) => true,
+      });
+
+      if invariant {
+        let temp = match hoisted.iter().find(|next| next.left == left && next.right == right && next.op == op) {
+          Some(found) => found.temp.clone(),
+          None => {
+            *temps += 1;
+            let temp = format!("#hoist{}", temps);
+            hoisted.push(Hoisted { left, right, op, temp: temp.clone() });
+            temp
+          }
+        };
+
+        body.splice(index..index + 3, vec![Ir::LoadValue { local: temp }]);
+        index += 1;
+        continue;
+      }
+    }
+
+    match &mut body[index] {
+      Ir::Branch { then_block, else_block } => {
+        hoist(then_block, stored, hoisted, temps);
+        hoist(else_block, stored, hoisted, temps);
+      }
+      Ir::Cond { arms, else_block } => {
+        for (condition, then_block) in arms.iter_mut() {
+          hoist(condition, stored, hoisted, temps);
+          hoist(then_block, stored, hoisted, temps);
+        }
+
+        hoist(else_block, stored, hoisted, temps);
+      }
+      Ir::Loop { condition, body } => {
+        hoist(condition, stored, hoisted, temps);
+        hoist(body, stored, hoisted, temps);
+      }
+      _ => {}
+    }
+
+    index += 1;
+  }
+}
+
+fn expression_at(body: &[Ir], index: usize) -> Option<(Operand, Operand, &'static str)> {
+  let left = operand(body.get(index)?)?;
+  let right = operand(body.get(index + 1)?)?;
+  let op = pure_op(body.get(index + 2)?)?;
+
+  Some((left, right, op))
+}
+
+fn operand(ir: &Ir) -> Option<Operand> {
+  match ir {
+    Ir::LoadValue { local } => Some(Operand::Local(local.clone())),
+    Ir::LoadConstFloat { value } => Some(Operand::Const(value.to_bits())),
+    _ => None,
+  }
+}
+
+fn load(operand: Operand) -> Ir {
+  match operand {
+    Operand::Local(local) => Ir::LoadValue { local },
+    Operand::Const(bits) => Ir::LoadConstFloat { value: f64::from_bits(bits) },
+  }
+}
+
+fn pure_op(ir: &Ir) -> Option<&'static str> {
+  match ir {
+    Ir::AddFloat => Some("+"),
+    Ir::SubFloat => Some("-"),
+    Ir::MulFloat => Some("*"),
+    Ir::DivFloat => Some("/"),
+    Ir::LessFloat => Some("<"),
+    Ir::LessEqualFloat => Some("<="),
+    Ir::GreaterFloat => Some(">"),
+    Ir::GreaterEqualFloat => Some(">="),
+    _ => None,
+  }
+}
+
+fn op_of(op: &'static str) -> Ir {
+  match op {
+    "+" => Ir::AddFloat,
+    "-" => Ir::SubFloat,
+    "*" => Ir::MulFloat,
+    "/" => Ir::DivFloat,
+    "<" => Ir::LessFloat,
+    "<=" => Ir::LessEqualFloat,
+    ">" => Ir::GreaterFloat,
+    _ => Ir::GreaterEqualFloat,
+  }
+}
+
+/**
+* Every local body stores, at any depth.
+*/
+fn stored_locals(body: &[Ir], stored: &mut Vec<String>) {
+  for ir in body {
+    match ir {
+      Ir::StoreValue { local } => stored.push(local.clone()),
+      Ir::Branch { then_block, else_block } => {
+        stored_locals(then_block, stored);
+        stored_locals(else_block, stored);
+      }
+      Ir::Cond { arms, else_block } => {
+        for (condition, then_block) in arms {
+          stored_locals(condition, stored);
+          stored_locals(then_block, stored);
+        }
+
+        stored_locals(else_block, stored);
+      }
+      Ir::Loop { condition, body } => {
+        stored_locals(condition, stored);
+        stored_locals(body, stored);
+      }
+      Ir::Assert { condition, .. } => stored_locals(condition, stored),
+      _ => {}
+    }
+  }
+}
@@ -1,36 +1,225 @@
+
 use bytecode::{BitModule, BitFunction};
 use optimize::load_store_optimizer::load_store_opt;
-use ir::IrFunction;
+use ir::{IrFunction, IrModule, validate};
 use optimize::free_local_optimizer::free_local_opt;
 use optimize::lift_return_optimizer::lift_return_opt;
+use optimize::inline_optimizer::inline_opt;
+use optimize::strip_assert_optimizer::strip_assert_opt;
+use optimize::common_subexpression_optimizer::common_subexpression_opt;
+use optimize::loop_invariant_optimizer::loop_invariant_opt;
+use optimize::dead_store_optimizer::dead_store_opt;
+use optimize::escape_optimizer::escape_opt;
 
 mod load_store_optimizer;
 mod free_local_optimizer;
 mod lift_return_optimizer;
+mod inline_optimizer;
+mod strip_assert_optimizer;
+mod common_subexpression_optimizer;
+mod loop_invariant_optimizer;
+mod dead_store_optimizer;
+mod escape_optimizer;
+
+const DEFAULT_INLINE_THRESHOLD: usize = 8;
+
+/** Every pass in the order they run. */
+const PIPELINE: [Pass; 10] = [Pass::Escape, Pass::Inline, Pass::StripAssert, Pass::LiftReturn, Pass::CommonSubexpression,
+  Pass::LoopInvariant, Pass::DeadStore, Pass::FreeLocal, Pass::LoadStore, Pass::Peephole];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OptLevel {
+  O0, // No optimizations at all, IR is compiled exactly as generated.
+  O1, // Per function passes only.
+  O2, // Per function passes plus cross function inlining and unboxing closures. Asserts are kept, enable StripAssert to drop them.
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Pass {
+  Escape,
+  Inline,
+  LiftReturn,
+  FreeLocal,
+  LoadStore,
+  StripAssert,
+  CommonSubexpression,
+  LoopInvariant,
+  DeadStore,
+  /** Runs on the final bytecode rather than the IR, see compiler::peephole_opt. */
+  Peephole,
+}
+
+impl OptLevel {
+
+  pub fn parse(raw: &str) -> Option<OptLevel> {
+    match raw {
+      "0" | "O0" => Some(OptLevel::O0),
+      "1" | "O1" => Some(OptLevel::O1),
+      "2" | "O2" => Some(OptLevel::O2),
+      _ => None
+    }
+  }
+
+  pub fn passes(&self) -> Vec<Pass> {
+    match self {
+      OptLevel::O0 => vec![],
+      OptLevel::O1 => vec![Pass::LiftReturn, Pass::CommonSubexpression, Pass::LoopInvariant, Pass::DeadStore, Pass::FreeLocal, Pass::LoadStore, Pass::Peephole],
+      OptLevel::O2 => vec![Pass::Escape, Pass::Inline, Pass::LiftReturn, Pass::CommonSubexpression, Pass::LoopInvariant, Pass::DeadStore, Pass::FreeLocal, Pass::LoadStore, Pass::Peephole],
+    }
+  }
+}
 
 pub struct Optimizer {
-  ops: Vec<Box<Fn(&mut IrFunction) -> ()>>
+  level: OptLevel,
+  passes: Vec<Pass>,
+  ops: Vec<(String, Box<Fn(&mut IrFunction) -> ()>)>,
+  escape: bool,
+  inline_threshold: Option<usize>,
+  peephole: bool,
 }
 
 impl Optimizer {
 
   pub fn new() -> Optimizer {
-    Optimizer {
-      ops: vec![
-        Box::new(lift_return_opt),
-        Box::new(free_local_opt),
-        Box::new(load_store_opt),
-      ]
+    Optimizer::builder(OptLevel::O2).build()
+  }
+
+  pub fn with_level(level: OptLevel) -> Optimizer {
+    Optimizer::builder(level).build()
+  }
+
+  pub fn builder(level: OptLevel) -> OptimizerBuilder {
+    OptimizerBuilder {
+      level,
+      passes: level.passes(),
+      inline_threshold: DEFAULT_INLINE_THRESHOLD,
     }
   }
 
+  pub fn level(&self) -> OptLevel {
+    self.level
+  }
+
+  /**
+  * The passes this optimizer will run, in the order it runs them.
+  */
+  pub fn passes(&self) -> &[Pass] {
+    &self.passes
+  }
+
+  pub fn optimize_module(&self, module: &mut IrModule) {
+    module.functions.values().for_each(|func| check(func, "IR generation"));
+
+    if self.escape {
+      escape_opt(module);
+      module.functions.values().for_each(|func| check(func, "Escape"));
+    }
+
+    if let Some(threshold) = self.inline_threshold {
+      inline_opt(module, threshold);
+      module.functions.values().for_each(|func| check(func, "Inline"));
+    }
+
+    module.functions.values_mut().for_each(|func| self.optimize(func));
+  }
+
+  /**
+  * Whether the compiler should fuse instructions in the bytecode it generates.
+  */
+  pub fn peephole(&self) -> bool {
+    self.peephole
+  }
+
   pub fn optimize(&self, func: &mut IrFunction) {
-    self.ops.iter().for_each(|op| op(func));
+    for (name, op) in &self.ops {
+      op(func);
+      check(func, name);
+    }
   }
 
   pub fn register(&mut self, func: Box<Fn(&mut IrFunction) -> ()>) {
-    self.ops.push(func)
+    self.ops.push((String::from("registered pass"), func))
+  }
+
+  /**
+  * Maximum number of instructions a function may have to be inlined into its callers. None disables inlining.
+  */
+  pub fn set_inline_threshold(&mut self, threshold: Option<usize>) {
+    self.inline_threshold = threshold;
+
+    let enabled = self.passes.clone();
+    self.passes = PIPELINE.iter().cloned()
+      .filter(|pass| if *pass == Pass::Inline { threshold.is_some() } else { enabled.contains(pass) })
+      .collect();
+  }
+
+}
+
+/**
+* Starts from the passes of an OptLevel and lets individual passes be switched on or off.
+* Passes always run in the fixed pipeline order regardless of the order they are enabled in.
+*/
+pub struct OptimizerBuilder {
+  level: OptLevel,
+  passes: Vec<Pass>,
+  inline_threshold: usize,
+}
+
+impl OptimizerBuilder {
+
+  pub fn enable(mut self, pass: Pass) -> OptimizerBuilder {
+    if !self.passes.contains(&pass) {
+      self.passes.push(pass);
+    }
+    self
+  }
+
+  pub fn disable(mut self, pass: Pass) -> OptimizerBuilder {
+    self.passes.retain(|next| *next != pass);
+    self
   }
 
+  pub fn inline_threshold(mut self, threshold: usize) -> OptimizerBuilder {
+    self.inline_threshold = threshold;
+    self
+  }
+
+  pub fn build(self) -> Optimizer {
+    let OptimizerBuilder{level, passes, inline_threshold} = self;
+    let mut ops: Vec<(String, Box<Fn(&mut IrFunction) -> ()>)> = Vec::new();
+    let mut add = |pass: Pass, op: Box<Fn(&mut IrFunction) -> ()>| if passes.contains(&pass) {
+      ops.push((format!("{:?}", pass), op));
+    };
+
+    // First, so later passes never see the locals only an assert used.
+    add(Pass::StripAssert, Box::new(strip_assert_opt));
+    add(Pass::LiftReturn, Box::new(lift_return_opt));
+    // Before FreeLocal, which has to know about the temps these add.
+    add(Pass::CommonSubexpression, Box::new(common_subexpression_opt));
+    add(Pass::LoopInvariant, Box::new(loop_invariant_opt));
+    add(Pass::DeadStore, Box::new(dead_store_opt));
+    add(Pass::FreeLocal, Box::new(free_local_opt));
+    add(Pass::LoadStore, Box::new(load_store_opt));
+
+    Optimizer {
+      level,
+      passes: PIPELINE.iter().cloned().filter(|pass| passes.contains(pass)).collect(),
+      ops,
+      escape: passes.contains(&Pass::Escape),
+      inline_threshold: if passes.contains(&Pass::Inline) { Some(inline_threshold) } else { None },
+      peephole: passes.contains(&Pass::Peephole),
+    }
+  }
 }
 
+/**
+* Debug builds validate the IR after every pass, so a broken invariant is blamed on the pass that broke it rather
+* than turning up later as bad bytecode.
+*/
+fn check(func: &IrFunction, after: &str) {
+  if cfg!(debug_assertions) {
+    if let Err(message) = validate(func) {
+      panic!("Invalid IR in {} after {}: {}", func.func_ref.pretty(), after, message);
+    }
+  }
+}
@@ -0,0 +1,35 @@
+use ir::{IrFunction, Ir};
+
+/**
+* Removes every Assert along with its condition. The Unit each assert statement leaves is pushed after the Assert, so
+* it stays in place.
+*/
+pub fn strip_assert_opt(func: &mut IrFunction) {
+  strip_assert(&mut func.body);
+}
+
+fn strip_assert(body: &mut Vec<Ir>) {
+  body.retain(|next| if let Ir::Assert {..} = next { false } else { true });
+
+  for next in body.iter_mut() {
+    match next {
+      Ir::Branch {then_block, else_block} => {
+        strip_assert(then_block);
+        strip_assert(else_block);
+      }
+      Ir::Cond {arms, else_block} => {
+        for (condition, then_block) in arms.iter_mut() {
+          strip_assert(condition);
+          strip_assert(then_block);
+        }
+
+        strip_assert(else_block);
+      }
+      Ir::Loop {condition, body} => {
+        strip_assert(condition);
+        strip_assert(body);
+      }
+      _ => {}
+    }
+  }
+}
@@ -1,16 +1,46 @@
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::io::Cursor;
 
-use simple_error::*;
+use error::{LexError, ParseError};
 
 use ast::*;
 use shapes::*;
 use std::path::Path;
 use std::collections::HashMap;
 
-pub fn lex(src: &Path) -> Result<Vec<Token>, SimpleError> {
-  let mut source = Lexer::new(src)?;
+pub fn lex(src: &Path) -> Result<Vec<Token>, LexError> {
+  Ok(lex_all(Lexer::new(src)?))
+}
+
+pub fn lex_str(src: &str, name: &str) -> Vec<Token> {
+  lex_all(Lexer::from_str(src, name))
+}
+
+/**
+* Lexes without losing anything, for tools like the formatter and language server. Joining each token's trivia and
+* text gives back src exactly. A character no token can start with becomes an Unknown token instead of ending the
+* stream, so the last token is always the EOF, holding any whitespace at the very end as its trivia.
+*/
+pub fn lex_lossless(src: &str, name: &str) -> Vec<FullToken> {
+  let mut source = Lexer::from_str(src, name);
+  let mut tokens: Vec<FullToken> = Vec::new();
+
+  loop {
+    let next = source.lex_full();
+    let is_done = next.token.kind == TokenKind::EOF;
+
+    tokens.push(next);
+
+    if is_done {
+      break;
+    }
+  }
+  tokens
+}
+
+fn lex_all<R: BufRead>(mut source: Lexer<R>) -> Vec<Token> {
   let mut tokens: Vec<Token> = Vec::new();
 
   loop {
@@ -23,107 +53,320 @@ pub fn lex(src: &Path) -> Result<Vec<Token>, SimpleError> {
       break;
     }
   }
-  Ok(tokens)
+  tokens
 }
 
-pub fn parse(src: &Path, package: &str, name: &str) -> Result<AstModule, SimpleError> {
+pub fn parse(src: &Path, package: &str, name: &str) -> Result<AstModule, Vec<ParseError>> {
+  let (module, errors) = parse_partial(src, package, name).map_err(|err| vec![ParseError::from(err)])?;
+  complete(module, errors)
+}
+
+pub fn parse_str(src: &str, package: &str, name: &str) -> Result<AstModule, Vec<ParseError>> {
+  let (module, errors) = parse_str_partial(src, package, name);
+  complete(module, errors)
+}
+
+/**
+* Parses as much of the module as possible, skipping past anything that doesn't parse.
+* The module only holds the declarations and statements that parsed cleanly, every error is returned beside it.
+*/
+pub fn parse_partial(src: &Path, package: &str, name: &str) -> Result<(AstModule, Vec<ParseError>), LexError> {
   let tokens = lex(src)?;
-  let mut parser = Parser { tokens, index: 0, closure_id: 0 };
-  parser.parse_module(package, name)
+  Ok(Parser::new(tokens).parse_module(package, name, false))
+}
+
+pub fn parse_str_partial(src: &str, package: &str, name: &str) -> (AstModule, Vec<ParseError>) {
+  let tokens = lex_str(src, name);
+  Parser::new(tokens).parse_module(package, name, false)
+}
+
+/**
+* Parses a script, a module that may also have statements at the top level. The statements are gathered in order
+* into a public function named main that returns the value of the last one.
+*/
+pub fn parse_script(src: &Path, package: &str, name: &str) -> Result<AstModule, Vec<ParseError>> {
+  let tokens = lex(src).map_err(|err| vec![ParseError::from(err)])?;
+  let (module, errors) = Parser::new(tokens).parse_module(package, name, true);
+  complete(module, errors)
+}
+
+fn complete(module: AstModule, errors: Vec<ParseError>) -> Result<AstModule, Vec<ParseError>> {
+  if errors.is_empty() {
+    Ok(module)
+  } else {
+    Err(errors)
+  }
+}
+
+/**
+* Parses a single import or statement, used by the repl. The whole source must be consumed.
+**/
+pub fn parse_repl(src: &str, name: &str) -> Result<Expression, ParseError> {
+  let tokens = lex_str(src, name);
+  let mut parser = Parser::new(tokens);
+
+  let result = if parser.check_literal("import") {
+    parser.parse_import()?.wrap()
+  } else {
+    parser.parse_statement()?
+  };
+
+  // Only a single statement is parsed, so report the first problem recovered from inside it.
+  if !parser.errors.is_empty() {
+    return Err(parser.errors.remove(0));
+  }
+
+  let end = parser.next();
+
+  if end.kind != TokenKind::EOF {
+    return end.expected("<EOF>");
+  }
+
+  Ok(result)
 }
 
+/**
+* Reads a shape written the way Shape::pretty prints it. Builtin types are resolved, any other name is left for the
+* typechecker to look up.
+*/
+pub fn parse_shape(src: &str) -> Result<Shape, ParseError> {
+  let tokens = lex_str(src, "<shape>");
+  let mut parser = Parser::new(tokens);
+
+  let shape = parser.parse_type()?;
+  let end = parser.next();
+
+  if end.kind != TokenKind::EOF {
+    return end.expected("<EOF>");
+  }
+
+  Ok(shape.with_builtins())
+}
+
+const DECLARATION_STARTS: &'static [&'static str] = &["import", "type", "data", "fun", "public", "internal", "protected", "private"];
+const STATEMENT_STARTS: &'static [&'static str] = &["let", "fun"];
+const SCRIPT_STARTS: &'static [&'static str] = &["import", "type", "data", "fun", "public", "internal", "protected", "private", "let"];
+pub const SCRIPT_MAIN: &'static str = "main";
+/** Starts the name of the local a method call's receiver is stored in, see parse_method. */
+pub const RECEIVER_PREFIX: &'static str = "$receiver_";
 const SUM_OPS: &'static [&'static str] = &["+", "-"];
 const PROD_OPS: &'static [&'static str] = &["*", "/"];
 const EQUAL_OPS: &'static [&'static str] = &["==", "!="];
 const COMPARE_OPS: &'static [&'static str] = &["<", ">", "<=", ">="];
+const RANGE_OPS: &'static [&'static str] = &[".."];
+
+fn is_overloadable(op: &str) -> bool {
+  [SUM_OPS, PROD_OPS, EQUAL_OPS, COMPARE_OPS].iter().any(|ops| ops.contains(&op))
+}
+const PIPE_OP: &'static str = "|>";
 
 struct Parser {
   tokens: Vec<Token>,
   index: usize,
+  errors: Vec<ParseError>,
 
   closure_id: usize,
+  receiver_id: usize,
+}
+
+enum Declaration {
+  Import(ImportEx),
+  Alias(TypeAliasEx),
+  Data(DataEx),
+  Function(AstFunctionDeclaration),
 }
 
 impl Parser {
-  fn parse_module(&mut self, package: &str, name: &str) -> Result<AstModule, SimpleError> {
+  fn new(tokens: Vec<Token>) -> Parser {
+    Parser { tokens, index: 0, errors: Vec::new(), closure_id: 0, receiver_id: 0 }
+  }
+
+  fn parse_module(mut self, package: &str, name: &str, is_script: bool) -> (AstModule, Vec<ParseError>) {
     let mut functions = Vec::new();
     let mut imports = Vec::new();
+    let mut aliases = Vec::new();
+    let mut datas = Vec::new();
+    let mut statements = Vec::new();
+
+    while self.peek().kind != TokenKind::EOF {
+      let start = self.index;
+
+      // In a script anything that doesn't start a declaration is a statement of main.
+      if is_script && !DECLARATION_STARTS.contains(&self.peek().value.as_str()) {
+        match self.parse_statement() {
+          Ok(statement) => statements.push(statement),
+          Err(err) => {
+            self.errors.push(err);
+            self.synchronize(start, SCRIPT_STARTS);
+          }
+        }
 
-    loop {
-      let token = self.next();
-
-      let visibility = match token.value.as_ref() {
-        "import" => {
-          imports.push(self.parse_import()?);
-          continue;
-        },
-        "public" => Visibility::Public,
-        "internal" => Visibility::Internal,
-        "protected" => Visibility::Protected,
-        "private" => Visibility::Private,
-        "fun" => {
-          self.prev();
-          Visibility::Private
-        },
-        "<EOF>" => {
-          return Ok(AstModule {
-            package: String::from(package),
-            name: String::from(name),
-            functions,
-            imports
-          });
+        continue;
+      }
+
+      match self.parse_declaration() {
+        Ok(Declaration::Import(imp)) => imports.push(imp),
+        Ok(Declaration::Alias(alias)) => aliases.push(alias),
+        Ok(Declaration::Data(data)) => datas.push(data),
+        Ok(Declaration::Function(func)) => functions.push(func),
+        Err(err) => {
+          self.errors.push(err);
+          self.synchronize(start, if is_script { SCRIPT_STARTS } else { DECLARATION_STARTS });
         }
-        _ => {
-          return Err(SimpleError::new(format!("Unexpected token: '{}' {}", token.value, token.location.pretty())));
+      }
+    }
+
+    if !statements.is_empty() {
+      let loc = statements[0].loc().to(statements[statements.len() - 1].loc());
+
+      functions.push(AstFunctionDeclaration {
+        visibility: Visibility::Public,
+        ex: FunctionDeclarationEx {
+          result: shape_unknown(),
+          loc: loc.clone(),
+          id: String::from(SCRIPT_MAIN),
+          args: Vec::new(),
+          defaults: Vec::new(),
+          body: BlockEx { shape: shape_unknown(), loc, body: statements }.wrap(),
+          context: FunctionContext::new(false, false),
         }
-      };
+      });
+    }
+
+    let module = AstModule {
+      package: String::from(package),
+      name: String::from(name),
+      functions,
+      imports,
+      aliases,
+      datas,
+    };
+
+    (module, self.errors)
+  }
+
+  fn parse_declaration(&mut self) -> Result<Declaration, ParseError> {
+    let token = self.next();
+
+    let visibility = match token.value.as_ref() {
+      "import" => return Ok(Declaration::Import(self.parse_import()?)),
+      "public" => Visibility::Public,
+      "internal" => Visibility::Internal,
+      "protected" => Visibility::Protected,
+      "private" => Visibility::Private,
+      "fun" | "type" | "data" => {
+        self.prev();
+        Visibility::Private
+      },
+      _ => {
+        return token.expected("import, type, data or function declaration");
+      }
+    };
+
+    if self.check_literal("type") {
+      return Ok(Declaration::Alias(self.parse_type_alias(visibility)?));
+    }
 
-      let ex = self.parse_function(false)?;
-      functions.push(AstFunctionDeclaration {visibility, ex});
+    if self.check_literal("data") {
+      return Ok(Declaration::Data(self.parse_data(visibility)?));
     }
+
+    let ex = self.parse_function(false)?;
+    Ok(Declaration::Function(AstFunctionDeclaration {visibility, ex}))
   }
 
-  fn parse_function(&mut self, is_local: bool) -> Result<FunctionDeclarationEx, SimpleError> {
+  /**
+  * Skips ahead after an error to the next token in starts that isn't nested inside brackets opened while skipping,
+  * or to a '}' that closes the enclosing block. Always moves past at least one token so parsing can't get stuck.
+  */
+  fn synchronize(&mut self, start: usize, starts: &[&str]) {
+    // A failed parse may have consumed the EOF token itself.
+    self.index = self.index.min(self.tokens.len() - 1);
+
+    if self.index == start && self.peek().kind != TokenKind::EOF {
+      self.skip();
+    }
+
+    let mut depth = 0;
+
+    loop {
+      let token = self.peek();
+
+      if token.kind == TokenKind::EOF {
+        return;
+      }
+
+      match token.value.as_ref() {
+        "{" | "(" => depth = depth + 1,
+        "}" | ")" if depth > 0 => depth = depth - 1,
+        "}" => return,
+        value if depth == 0 && starts.contains(&value) => return,
+        _ => {}
+      }
+
+      self.skip();
+    }
+  }
+
+  fn parse_function(&mut self, is_local: bool) -> Result<FunctionDeclarationEx, ParseError> {
     // Assume fun is already parsed
 
     let fun = self.next();
     assert!(&fun.value == "fun");
     let loc = fun.location.clone();
 
-    let id = self.expect_kind(TokenKind::Id)?.value;
+    // Module functions may be named by an operator to overload it for their operand types.
+    let id = if !is_local && self.peek().kind == TokenKind::Symbol && is_overloadable(&self.peek().value) {
+      self.next().value
+    } else {
+      self.expect_kind(TokenKind::Id)?.value
+    };
 
     self.expect_literal("(")?;
 
     let mut args = Vec::new();
+    let mut defaults = Vec::new();
 
     if !self.check_literal(")") {
-      let arg_id = self.expect_kind(TokenKind::Id)?.value;
-      self.expect_literal(":")?;
-      let arg_shape = self.parse_type()?;
-      args.push(Parameter{id: arg_id, shape: arg_shape});
-
-      while self.check_literal(",") {
+      loop {
+        // Shapes left out are inferred from the body.
         let arg_id = self.expect_kind(TokenKind::Id)?.value;
-        self.expect_literal(":")?;
-        let arg_shape = self.parse_type()?;
+        let arg_shape = if self.check_literal(":") { self.parse_type()? } else { shape_unknown() };
         args.push(Parameter{id: arg_id, shape: arg_shape});
+
+        defaults.push(if self.check_literal("=") { Some(self.parse_default()?) } else { None });
+
+        if !self.check_literal(",") {
+          break;
+        }
       }
 
       self.expect_literal(")")?;
     }
 
-    self.expect_literal(":")?;
-
-    let result = self.parse_type()?;
+    let result = if self.check_literal(":") { self.parse_type()? } else { shape_unknown() };
 
     self.expect_literal("=")?;
 
     let body = self.parse_expression()?;
+    let loc = self.span(&loc);
 
-    Ok(FunctionDeclarationEx{ result, loc, id, args, body, context: FunctionContext::new(is_local, false) })
+    Ok(FunctionDeclarationEx{ result, loc, id, args, defaults, body, context: FunctionContext::new(is_local, false) })
   }
 
-  fn parse_lambda(&mut self) -> Result<Expression, SimpleError> {
+  fn parse_default(&mut self) -> Result<Literal, ParseError> {
+    let start = self.peek();
+
+    match self.parse_expression()? {
+      Expression::NumberLiteral(number) => Ok(Literal::Number(number.value)),
+      Expression::StringLiteral(string) => Ok(Literal::String(string.value)),
+      Expression::BooleanLiteral(_, value) => Ok(Literal::Boolean(value)),
+      Expression::UnitLiteral(_) => Ok(Literal::Unit),
+      _ => start.expected("a literal default value"),
+    }
+  }
+
+  fn parse_lambda(&mut self) -> Result<Expression, ParseError> {
     // assume we've already checked and confirmed this is a lambda.
 
     let loc = self.peek_back().location;
@@ -165,8 +408,9 @@ impl Parser {
       body.push(self.parse_statement()?)
     }
 
+    let loc = self.span(&loc);
     let block = Expression::Block (Box::new(BlockEx{
-      loc: block_loc,
+      loc: self.span(&block_loc),
       shape: shape_unknown(),
       body
     }));
@@ -174,15 +418,66 @@ impl Parser {
     let id = format!("$closure_{}", self.closure_id);
     self.closure_id += 1;
 
-    Ok(FunctionDeclarationEx { result, loc, id, args, body: block, context: FunctionContext::new(true, true) }.wrap())
+    let defaults = args.iter().map(|_| None).collect();
+
+    Ok(FunctionDeclarationEx { result, loc, id, args, defaults, body: block, context: FunctionContext::new(true, true) }.wrap())
   }
 
-  fn parse_statement(&mut self) -> Result<Expression, SimpleError> {
+  /**
+  * `x -> body` or `(x, y: Float) -> body`, the short form of `{ x, y: Float => body }`. The body is the whole
+  * expression after the arrow.
+  */
+  fn parse_arrow_lambda(&mut self) -> Result<Expression, ParseError> {
+    // assume check_is_arrow has confirmed this is a lambda.
+
+    let loc = self.peek().location;
+    let mut args = Vec::new();
+
+    if self.check_literal("(") {
+      if !self.check_literal(")") {
+        loop {
+          let arg_id = self.expect_kind(TokenKind::Id)?.value;
+          let arg_shape = if self.check_literal(":") { self.parse_type()? } else { shape_unknown() };
+          args.push(Parameter{id: arg_id, shape: arg_shape});
+
+          if !self.check_literal(",") {
+            break;
+          }
+        }
+
+        self.expect_literal(")")?;
+      }
+    } else {
+      let arg_id = self.expect_kind(TokenKind::Id)?.value;
+      args.push(Parameter{id: arg_id, shape: shape_unknown()});
+    }
+
+    self.expect_literal("->")?;
+
+    let body = self.parse_expression()?;
+    let block = BlockEx { loc: body.loc().clone(), shape: shape_unknown(), body: vec![body] }.wrap();
+
+    let id = format!("$closure_{}", self.closure_id);
+    self.closure_id += 1;
+
+    let defaults = args.iter().map(|_| None).collect();
+
+    Ok(FunctionDeclarationEx { result: shape_unknown(), loc: self.span(&loc), id, args, defaults, body: block, context: FunctionContext::new(true, true) }.wrap())
+  }
+
+  fn parse_statement(&mut self) -> Result<Expression, ParseError> {
     let maybe_key = self.peek();
 
     let result = match maybe_key.value.as_ref() {
       "let" => self.parse_assignment()?,
       "fun" => self.parse_function(true)?.wrap(),
+      "assert" => {
+        let loc = self.next().location;
+        let condition = self.parse_expression()?;
+
+        AssertEx { shape: shape_unit(), loc: self.span(&loc), condition }.wrap()
+      }
+      _ if maybe_key.kind == TokenKind::Id && self.tokens[self.index + 1].value == "=" => self.parse_reassignment()?,
 //      "import" => {
 //        self.skip();
 //        self.parse_import()?.wrap()
@@ -190,11 +485,48 @@ impl Parser {
       _ => self.parse_expression()?
     };
 
-    self.check_literal(";");
+    // A statement ends at a ;, at the end of its line, or right before the } closing its block. Anything else after it
+    // is reported, but the statement is still kept.
+    if !self.check_literal(";") && !self.at_statement_end() {
+      let next = self.peek();
+
+      if let Err(err) = next.expected::<()>("; or a new line after the statement") {
+        self.errors.push(err);
+      }
+    }
+
     Ok(result)
   }
 
-  fn parse_import(&mut self) -> Result<ImportEx, SimpleError> {
+  fn at_statement_end(&self) -> bool {
+    let next = self.peek();
+    next.kind == TokenKind::EOF || next.value == "}" || !self.same_line()
+  }
+
+  /**
+  * Whether the next token is on the line the last one ended on. A ( or [ starting a line begins a new statement
+  * instead of calling or indexing the one before, while an operator or . starting a line carries it on.
+  */
+  fn same_line(&self) -> bool {
+    self.index == 0 || self.peek().location.y == self.tokens[self.index - 1].location.end_y
+  }
+
+  /**
+  * Whether a line starts with a negative literal like -1, which begins a new statement rather than subtracting from
+  * the one before. With a space, as in - 1, it's a subtraction that carries on.
+  */
+  fn at_negative_literal(&self) -> bool {
+    let minus = self.peek();
+
+    if minus.value != "-" || self.same_line() {
+      return false;
+    }
+
+    let number = &self.tokens[self.index + 1];
+    number.kind == TokenKind::Number && number.location.y == minus.location.y && number.location.x == minus.location.x + 1
+  }
+
+  fn parse_import(&mut self) -> Result<ImportEx, ParseError> {
     // assume 'import' is already parsed.
 
     let loc = self.peek().location;
@@ -204,6 +536,7 @@ impl Parser {
     self.expect_literal("::")?;
 
     let module = self.expect_kind(TokenKind::Id)?.value;
+    let loc = self.span(&loc);
 
     self.check_literal(";");
 
@@ -215,16 +548,17 @@ impl Parser {
     })
   }
 
-  fn parse_expression(&mut self) -> Result<Expression, SimpleError> {
+  fn parse_expression(&mut self) -> Result<Expression, ParseError> {
     self.parse_ops()
   }
 
-  fn parse_assignment(&mut self) -> Result<Expression, SimpleError> {
+  fn parse_assignment(&mut self) -> Result<Expression, ParseError> {
     let maybe_let = self.next();
     assert_eq!(&maybe_let.value, "let");
 
     let loc = maybe_let.location.clone();
-    let id = self.expect_kind(TokenKind::Id)?.value;
+    let mutable = self.check_literal("mut");
+    let pattern = self.parse_pattern()?;
 
     let shape = if self.check_literal(":") {
       self.parse_type()?
@@ -235,30 +569,83 @@ impl Parser {
     self.expect_literal("=")?;
     let body = self.parse_expression()?;
 
-    Ok(AssignmentEx { shape, loc, id, body }.wrap())
+    Ok(AssignmentEx { shape, loc: self.span(&loc), pattern, body, mutable }.wrap())
   }
 
-  fn parse_ops(&mut self) -> Result<Expression, SimpleError> {
-    let start = |me: &mut Parser| me.parse_call();
+  fn parse_reassignment(&mut self) -> Result<Expression, ParseError> {
+    let id = self.expect_kind(TokenKind::Id)?;
+    self.expect_literal("=")?;
+    let body = self.parse_expression()?;
+
+    Ok(ReassignmentEx { loc: self.span(&id.location), id: id.value, body }.wrap())
+  }
+
+  fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
+    if self.check_literal("(") {
+      let mut items = vec![self.parse_pattern()?];
+
+      while self.check_literal(",") {
+        items.push(self.parse_pattern()?);
+      }
+
+      self.expect_literal(")")?;
+
+      if items.len() == 1 {
+        Ok(items.pop().unwrap())
+      } else {
+        Ok(Pattern::Tuple(items))
+      }
+    } else {
+      Ok(Pattern::Id(self.expect_kind(TokenKind::Id)?.value))
+    }
+  }
+
+  fn parse_ops(&mut self) -> Result<Expression, ParseError> {
+    let start = |me: &mut Parser| me.parse_try();
     let prod = |me: &mut Parser| me.parse_binary_op(PROD_OPS, start);
     let sum = |me: &mut Parser| me.parse_binary_op(SUM_OPS, prod);
-    let compare = |me: &mut Parser| me.parse_binary_op(COMPARE_OPS, sum);
+    let range = |me: &mut Parser| me.parse_binary_op(RANGE_OPS, sum);
+    let compare = |me: &mut Parser| me.parse_binary_op(COMPARE_OPS, range);
     let equal = |me: &mut Parser| me.parse_binary_op(EQUAL_OPS, compare);
 
-    equal(self)
+    self.parse_pipe(equal)
   }
 
-  fn parse_binary_op<Next: Fn(&mut Parser) -> Result<Expression, SimpleError>>(&mut self, ops: &[&str], next: Next) -> Result<Expression, SimpleError> {
+  /**
+  * `x |> f` is f(x) and `x |> f(y)` is f(x, y), so the left side always becomes the first argument.
+  */
+  fn parse_pipe<Next: Fn(&mut Parser) -> Result<Expression, ParseError>>(&mut self, next: Next) -> Result<Expression, ParseError> {
+    let mut value = next(self)?;
+
+    while self.peek().value == PIPE_OP {
+      self.skip();
+
+      value = match next(self)? {
+        Expression::Call(call) => {
+          let CallEx { shape, func, mut args, named, .. } = *call;
+          let loc = self.span(value.loc());
+          args.insert(0, value);
+
+          CallEx { shape, loc, func, args, named, piped: true }.wrap()
+        }
+        func => CallEx { shape: shape_unknown(), loc: self.span(value.loc()), func, args: vec![value], named: Vec::new(), piped: true }.wrap(),
+      };
+    }
+
+    Ok(value)
+  }
+
+  fn parse_binary_op<Next: Fn(&mut Parser) -> Result<Expression, ParseError>>(&mut self, ops: &[&str], next: Next) -> Result<Expression, ParseError> {
     let mut left = next(self)?;
 
     let mut maybe_op = self.peek();
 
-    while ops.contains(&maybe_op.value.as_ref()) {
+    while ops.contains(&maybe_op.value.as_ref()) && !self.at_negative_literal() {
       self.skip();
       let op = maybe_op.value;
-      let loc = maybe_op.location.clone();
       let shape = shape_unknown();
       let right = next(self)?;
+      let loc = self.span(left.loc());
 
       left = BinaryOpEx { shape, loc, left, right, op }.wrap();
       maybe_op = self.peek();
@@ -267,40 +654,125 @@ impl Parser {
     Ok(left)
   }
 
-  fn parse_call(&mut self) -> Result<Expression, SimpleError> {
-    let func = self.parse_block()?;
+  fn parse_try(&mut self) -> Result<Expression, ParseError> {
+    let mut body = self.parse_postfix()?;
 
-    if self.check_literal("(") {
-      if let Expression::Variable(var) = &func {
-        if "if" == &var.id {
-          return self.parse_if(func.loc().clone());
+    while self.peek().value == "?" {
+      self.skip();
+      body = TryEx { shape: shape_unknown(), loc: self.span(body.loc()), body }.wrap();
+    }
+
+    Ok(body)
+  }
+
+  /**
+  * A term followed by any number of calls, indexes and method calls, applied left to right, so f(x)(y) calls what
+  * f(x) returns.
+  */
+  fn parse_postfix(&mut self) -> Result<Expression, ParseError> {
+    let mut value = self.parse_block()?;
+
+    // An if ends at its last branch, a ( or [ after it starts whatever comes next.
+    if let Expression::If(_) = value {
+      return Ok(value);
+    }
+
+    loop {
+      value = match self.peek().value.as_str() {
+        "(" | "[" if !self.same_line() => return Ok(value),
+        "(" => {
+          self.skip();
+          self.parse_call(value)?
         }
-      }
+        "[" => {
+          self.skip();
+          let index = self.parse_expression()?;
+          self.expect_literal("]")?;
 
-      let mut args = Vec::new();
+          IndexEx { shape: shape_unknown(), loc: self.span(value.loc()), list: value, index }.wrap()
+        }
+        "." => self.parse_method(value)?,
+        _ => return Ok(value),
+      };
+    }
+  }
 
-      if !self.check_literal(")") {
-        args.push(self.parse_expression()?);
+  fn parse_call(&mut self, func: Expression) -> Result<Expression, ParseError> {
+    // assume '(' is already parsed
 
-        while self.check_literal(",") {
+    let mut args = Vec::new();
+    let mut named = Vec::new();
+
+    if !self.check_literal(")") {
+      loop {
+        if self.is_named_argument() {
+          let id = self.next().value;
+          self.skip();
+          named.push((id, self.parse_expression()?));
+        } else if named.is_empty() {
           args.push(self.parse_expression()?);
+        } else {
+          return self.peek().expected("a named argument, positional arguments must come first");
         }
 
-        self.expect_literal(")")?;
+        if !self.check_literal(",") {
+          break;
+        }
       }
 
-      return Ok(CallEx {
-        shape: shape_unknown(),
-        loc: func.loc().clone(),
-        func,
-        args
-      }.wrap())
-    } else {
-      return Ok(func);
+      self.expect_literal(")")?;
+    }
+
+    Ok(CallEx {
+      shape: shape_unknown(),
+      loc: self.span(func.loc()),
+      func,
+      args,
+      named,
+      piped: false,
+    }.wrap())
+  }
+
+  /**
+  * `value.name(args)` on anything but a variable, whose dotted name the lexer already reads as a single Id. value
+  * is stored in a local first, so the typechecker resolves the method just as it would for a variable. Without the
+  * arguments it's `value.name`, a field.
+  */
+  fn parse_method(&mut self, receiver: Expression) -> Result<Expression, ParseError> {
+    // assume '.' is next
+
+    self.skip();
+    let name = self.expect_kind(TokenKind::Id)?.value;
+    let start = receiver.loc().clone();
+
+    // The lexer reads a.b as one Id, so each part is a field of the one before.
+    if !self.check_literal("(") {
+      let loc = self.span(&start);
+      return Ok(name.split('.').fold(receiver, |value, field| {
+        FieldEx { shape: shape_unknown(), loc: loc.clone(), value, field: String::from(field), index: 0 }.wrap()
+      }));
     }
+
+    let local = format!("{}{}", RECEIVER_PREFIX, self.receiver_id);
+    self.receiver_id += 1;
+
+    let func = VariableEx { id: format!("{}.{}", local, name), shape: shape_unknown(), loc: self.span(&start) }.wrap();
+    let call = self.parse_call(func)?;
+
+    let store = AssignmentEx { shape: shape_unknown(), loc: start.clone(), pattern: Pattern::Id(local), body: receiver, mutable: false }.wrap();
+
+    Ok(BlockEx { shape: shape_unknown(), loc: self.span(&start), body: vec![store, call] }.wrap())
+  }
+
+
+  /**
+  * An argument written as `name = value`. Checked ahead of parsing, since `name` alone would be a whole argument.
+  */
+  fn is_named_argument(&self) -> bool {
+    self.peek().kind == TokenKind::Id && self.tokens.get(self.index + 1).map_or(false, |next| next.value == "=")
   }
 
-  fn parse_if(&mut self, loc: Location) -> Result<Expression, SimpleError> {
+  fn parse_if(&mut self, loc: Location) -> Result<Expression, ParseError> {
     // assume 'if' and '(' are already parsed
 
     let condition = self.parse_expression()?;
@@ -317,7 +789,37 @@ impl Parser {
 
     Ok(IfEx {
       shape: shape_unknown(),
-      loc,
+      loc: self.span(&loc),
+
+      condition,
+      then_block,
+      else_block
+    }.wrap())
+  }
+
+  /**
+  * `if cond { ... } else { ... }`, where the branches have to be blocks so the condition needs no parentheses.
+  * The else may instead be another if, in either form.
+  */
+  fn parse_if_block(&mut self, loc: Location) -> Result<Expression, ParseError> {
+    // assume 'if' is already parsed
+
+    let condition = self.parse_expression()?;
+    let then_block = self.expect_block()?;
+
+    let else_block = if self.check_literal("else") {
+      if self.peek().value == "if" {
+        self.parse_block()?
+      } else {
+        self.expect_block()?
+      }
+    } else {
+      Expression::NoOp(self.peek().location.clone())
+    };
+
+    Ok(IfEx {
+      shape: shape_unknown(),
+      loc: self.span(&loc),
 
       condition,
       then_block,
@@ -325,7 +827,44 @@ impl Parser {
     }.wrap())
   }
 
-  fn parse_block(&mut self) -> Result<Expression, SimpleError> {
+  fn expect_block(&mut self) -> Result<Expression, ParseError> {
+    if self.peek().value != "{" {
+      return self.peek().expected("{");
+    }
+
+    self.parse_block()
+  }
+
+  fn parse_while(&mut self, loc: Location) -> Result<Expression, ParseError> {
+    // assume 'while' is already parsed
+
+    let condition = self.parse_expression()?;
+
+    let block_loc = self.expect_literal("{")?.location;
+    let mut body = Vec::new();
+
+    while !self.check_literal("}") {
+      body.push(self.parse_statement()?)
+    }
+
+    let block_loc = self.span(&block_loc);
+
+    let body = if body.is_empty() {
+      Expression::NoOp(block_loc)
+    } else {
+      BlockEx { loc: block_loc, shape: shape_unknown(), body }.wrap()
+    };
+
+    Ok(WhileEx {
+      shape: shape_unit(),
+      loc: self.span(&loc),
+
+      condition,
+      body
+    }.wrap())
+  }
+
+  fn parse_block(&mut self) -> Result<Expression, ParseError> {
     if self.check_literal("{") {
       if self.check_is_lambda() {
         return self.parse_lambda();
@@ -336,10 +875,24 @@ impl Parser {
       let mut body= Vec::new();
 
       while "}" != self.peek().value {
-        body.push(self.parse_statement()?)
+        let start = self.index;
+
+        match self.parse_statement() {
+          Ok(statement) => body.push(statement),
+          Err(err) => {
+            // There is nothing left to recover with once the tokens run out.
+            if let ParseError::UnexpectedEof {..} = err {
+              return Err(err);
+            }
+
+            self.errors.push(err);
+            self.synchronize(start, STATEMENT_STARTS);
+          }
+        }
       }
       // Skip '}'
       self.skip();
+      let loc = self.span(&loc);
 
       if body.is_empty() {
         return Ok(Expression::NoOp(loc));
@@ -351,17 +904,56 @@ impl Parser {
     }
   }
 
-  fn parse_term(&mut self) -> Result<Expression, SimpleError> {
+  fn parse_term(&mut self) -> Result<Expression, ParseError> {
+    if self.check_is_arrow() {
+      return self.parse_arrow_lambda();
+    }
+
     let term = self.next();
     let loc = term.location.clone();
 
     let raw = match term {
-      Token { kind: TokenKind::Id, .. } => {
+      Token { kind: TokenKind::Id, .. } | Token { kind: TokenKind::Keyword, .. } => {
+        let is_keyword = term.kind == TokenKind::Keyword;
         let id = term.value;
 
         match id.as_str() {
           "true" => Expression::BooleanLiteral(loc, true),
           "false" => Expression::BooleanLiteral(loc, false),
+          "Unit" => Expression::UnitLiteral(loc),
+          VARIANT_NONE => VariantEx { shape: shape_unknown(), loc, tag: id, values: Vec::new() }.wrap(),
+          VARIANT_SOME | VARIANT_OK | VARIANT_ERR => {
+            self.expect_literal("(")?;
+            let value = self.parse_expression()?;
+            self.expect_literal(")")?;
+
+            VariantEx { shape: shape_unknown(), loc: self.span(&loc), tag: id, values: vec![value] }.wrap()
+          }
+          "while" => self.parse_while(loc)?,
+          "if" if self.check_literal("(") => self.parse_if(loc)?,
+          "if" => self.parse_if_block(loc)?,
+          "panic" => {
+            self.expect_literal("(")?;
+            let message = self.next();
+
+            if message.kind != TokenKind::String {
+              return message.expected("panic message string");
+            }
+
+            self.expect_literal(")")?;
+
+            PanicEx { shape: shape_unknown(), loc: self.span(&loc), message: message.value }.wrap()
+          }
+          "return" => {
+            let body = if self.at_statement_end() || self.peek().value == ";" {
+              Expression::UnitLiteral(self.span(&loc))
+            } else {
+              self.parse_expression()?
+            };
+
+            ReturnEx { shape: shape_unknown(), loc: self.span(&loc), body }.wrap()
+          }
+          _ if is_keyword => return Err(ParseError::UnexpectedToken { loc, found: id, expected: String::from("expression") }),
           _ => {
             let shape = shape_unknown();
             VariableEx { id, shape, loc }.wrap()
@@ -373,23 +965,138 @@ impl Parser {
         let shape = shape_string();
         StringLiteralEx { shape, loc, value }.wrap()
       }
+      Token { kind: TokenKind::Symbol, .. } if term.value == "(" && self.check_literal(")") => Expression::UnitLiteral(self.span(&loc)),
+      Token { kind: TokenKind::Symbol, .. } if term.value == "(" => {
+        let mut items = vec![self.parse_expression()?];
+
+        while self.check_literal(",") {
+          items.push(self.parse_expression()?);
+        }
+
+        self.expect_literal(")")?;
+
+        if items.len() == 1 {
+          items.pop().unwrap()
+        } else {
+          TupleEx { shape: shape_unknown(), loc: self.span(&loc), items }.wrap()
+        }
+      }
+      Token { kind: TokenKind::Symbol, .. } if term.value == "[" => {
+        let mut items = Vec::new();
+
+        if !self.check_literal("]") {
+          items.push(self.parse_expression()?);
+
+          while self.check_literal(",") {
+            items.push(self.parse_expression()?);
+          }
+
+          self.expect_literal("]")?;
+        }
+
+        ListEx { shape: shape_unknown(), loc: self.span(&loc), items }.wrap()
+      }
+      Token { kind: TokenKind::Symbol, .. } if term.value == HOLE => HoleEx { shape: shape_unknown(), loc }.wrap(),
       Token { kind: TokenKind::Number, .. } => {
-        let value = term.value.parse().or_else(|_| Err(SimpleError::new("Invalid float literal")))?;
+        let value = parse_number(&term.value, &loc)?;
         let shape = shape_float();
         NumberLiteralEx { shape, loc, value }.wrap()
       }
-      Token { kind: TokenKind::EOF, .. } => return Err(SimpleError::new("Unexpected <EOF>")),
-      _ => return Err(SimpleError::new(format!("Unexpected Token: {:?}", term)))
+      // Only a literal can be negated, there's no unary minus.
+      Token { kind: TokenKind::Symbol, .. } if term.value == "-" && self.peek().kind == TokenKind::Number => {
+        let number = self.next();
+        let loc = self.span(&loc);
+        let value = parse_number(&number.value, &loc)?;
+        NumberLiteralEx { shape: shape_float(), loc, value: -value }.wrap()
+      }
+      _ => return term.expected("expression")
     };
 
     Ok(raw)
   }
 
-  fn parse_type(&mut self) -> Result<Shape, SimpleError> {
+  fn parse_type_alias(&mut self, visibility: Visibility) -> Result<TypeAliasEx, ParseError> {
+    // assume 'type' is already parsed.
+
+    let loc = self.peek().location;
+
+    let id = self.expect_kind(TokenKind::Id)?.value;
+
+    self.expect_literal("=")?;
+
+    let shape = self.parse_type()?;
+    let loc = self.span(&loc);
+
+    self.check_literal(";");
+
+    Ok(TypeAliasEx{
+      loc,
+      visibility,
+
+      id,
+      shape,
+    })
+  }
+
+  fn parse_data(&mut self, visibility: Visibility) -> Result<DataEx, ParseError> {
+    // assume 'data' is already parsed.
+
+    let loc = self.peek().location;
+
+    let id = self.expect_kind(TokenKind::Id)?.value;
+    let mut params = Vec::new();
+
+    if self.check_literal("[") {
+      loop {
+        params.push(self.expect_kind(TokenKind::Id)?.value);
+
+        if !self.check_literal(",") {
+          break;
+        }
+      }
+
+      self.expect_literal("]")?;
+    }
+
+    self.expect_literal("(")?;
+
+    let mut fields = Vec::new();
+
+    if !self.check_literal(")") {
+      loop {
+        // Unlike a function's parameters, a field always says its shape.
+        let field_id = self.expect_kind(TokenKind::Id)?.value;
+        self.expect_literal(":")?;
+        fields.push(Parameter { id: field_id, shape: self.parse_type()? });
+
+        if !self.check_literal(",") {
+          break;
+        }
+      }
+
+      self.expect_literal(")")?;
+    }
+
+    let loc = self.span(&loc);
+
+    self.check_literal(";");
+
+    Ok(DataEx {
+      loc,
+      visibility,
+
+      id,
+      params,
+      fields,
+      shape: shape_unknown(),
+    })
+  }
+
+  fn parse_type(&mut self) -> Result<Shape, ParseError> {
     self.parse_type_function()
   }
 
-  fn parse_type_function(&mut self) -> Result<Shape, SimpleError> {
+  fn parse_type_function(&mut self) -> Result<Shape, ParseError> {
     if self.check_literal("{") {
       let mut args = Vec::new();
 
@@ -416,7 +1123,7 @@ impl Parser {
     }
   }
 
-  fn parse_type_generic(&mut self) -> Result<Shape, SimpleError> {
+  fn parse_type_generic(&mut self) -> Result<Shape, ParseError> {
     let base = self.parse_type_term()?;
 
     if self.check_literal("[") {
@@ -439,7 +1146,27 @@ impl Parser {
     }
   }
 
-  fn parse_type_term(&mut self) -> Result<Shape, SimpleError> {
+  fn parse_type_term(&mut self) -> Result<Shape, ParseError> {
+    if self.check_literal("_") {
+      return Ok(shape_unknown());
+    }
+
+    if self.check_literal("(") {
+      let mut args = vec![self.parse_type()?];
+
+      while self.check_literal(",") {
+        args.push(self.parse_type()?);
+      }
+
+      self.expect_literal(")")?;
+
+      return if args.len() == 1 {
+        Ok(args.pop().unwrap())
+      } else {
+        Ok(shape_tuple(args))
+      };
+    }
+
     let token = self.expect_kind(TokenKind::Id)?;
     Ok(shape_named(token.value))
   }
@@ -477,22 +1204,67 @@ impl Parser {
     false
   }
 
-  fn expect_literal(&mut self, value: &str) -> Result<Token, SimpleError> {
-    let token = self.next();
+  /**
+  * Whether the next tokens are an Id or parenthesised list and then ->, the start of an arrow lambda.
+  */
+  fn check_is_arrow(&self) -> bool {
+    let is_arrow = |index: usize| self.tokens.get(index).map_or(false, |token| token.value == "->");
+
+    match self.tokens.get(self.index) {
+      Some(token) if token.kind == TokenKind::Id => is_arrow(self.index + 1),
+      Some(token) if token.value == "(" => {
+        let mut index = self.index + 1;
+        let mut opens = 1;
+
+        while let Some(token) = self.tokens.get(index) {
+          index = index + 1;
+
+          match token.value.as_ref() {
+            "(" => opens = opens + 1,
+            ")" => {
+              opens = opens - 1;
+              if opens == 0 {
+                return is_arrow(index);
+              }
+            }
+            _ => {}
+          }
+        }
+
+        false
+      }
+      _ => false,
+    }
+  }
+
+  /**
+  * A mismatched token is left in place so error recovery can still see it, it may well be a closing brace.
+  */
+  fn expect_literal(&mut self, value: &str) -> Result<Token, ParseError> {
+    let token = self.peek();
 
     if &token.value != value {
       return token.expected(value);
     } else {
+      self.skip();
       Ok(token)
     }
   }
 
-  fn expect_kind(&mut self, kind: TokenKind) -> Result<Token, SimpleError> {
-    let token = self.next();
+  /**
+  * Expecting an Id and finding a keyword is reported as the keyword being reserved.
+  */
+  fn expect_kind(&mut self, kind: TokenKind) -> Result<Token, ParseError> {
+    let token = self.peek();
+
+    if kind == TokenKind::Id && token.kind == TokenKind::Keyword {
+      return Err(ParseError::ReservedWord { loc: token.location, word: token.value });
+    }
 
     if token.kind != kind {
       return token.expected(format!("{:?}", kind).as_ref());
     } else {
+      self.skip();
       Ok(token)
     }
   }
@@ -533,6 +1305,13 @@ impl Parser {
     self.tokens[self.index - 1].clone()
   }
 
+  /**
+  * From the start of start to the end of the last token parsed.
+  */
+  fn span(&self, start: &Location) -> Location {
+    start.to(&self.tokens[self.index - 1].location)
+  }
+
   fn skip(&mut self) {
     self.index = self.index + 1;
   }
@@ -543,42 +1322,237 @@ impl Parser {
 }
 
 
-const SINGLE_OPS: &'static str = "(){}[];,";
-const MERGE_OPS: &'static str = "=+-*/:<>";
+/**
+* Integers written in hex or binary have to fit a Float exactly, there being no Int to hold anything bigger.
+*/
+const MAX_EXACT_INTEGER: u64 = 1 << 53;
+
+/**
+* The value of a number token. Underscores may only sit between two digits, and 0x and 0b start hex and binary
+* integers.
+*/
+fn parse_number(text: &str, loc: &Location) -> Result<f64, ParseError> {
+  let invalid = || ParseError::InvalidNumber { loc: loc.clone(), value: String::from(text) };
+
+  let (radix, body) = match text.get(..2) {
+    Some("0x") | Some("0X") => (16, &text[2..]),
+    Some("0b") | Some("0B") => (2, &text[2..]),
+    _ => (10, text),
+  };
+
+  let is_digit = |ch: &char| if radix == 16 { ch.is_ascii_hexdigit() } else { ch.is_ascii_digit() };
+  let chars: Vec<char> = body.chars().collect();
+
+  for (index, ch) in chars.iter().enumerate() {
+    if *ch == '_' {
+      let before = index.checked_sub(1).and_then(|before| chars.get(before));
+      let after = chars.get(index + 1);
+
+      if !before.map_or(false, &is_digit) || !after.map_or(false, &is_digit) {
+        return Err(invalid());
+      }
+    }
+  }
+
+  let digits: String = chars.into_iter().filter(|ch| *ch != '_').collect();
+
+  if radix == 10 {
+    // Rust would also take words like inf and NaN, but a number token always starts with a digit or a dot.
+    return digits.parse().map_err(|_| invalid());
+  }
 
-struct Lexer {
+  // from_str_radix would take a sign, but a sign here is always a mistake.
+  if digits.is_empty() || !digits.chars().all(|ch| ch.is_digit(radix)) {
+    return Err(invalid());
+  }
+
+  match u64::from_str_radix(&digits, radix) {
+    Ok(value) if value <= MAX_EXACT_INTEGER => Ok(value as f64),
+    _ => Err(ParseError::NumberTooBig { loc: loc.clone(), value: String::from(text) }),
+  }
+}
+
+/**
+* Words that are never names. Some mean nothing yet and are only kept back for later.
+*/
+pub const KEYWORDS: &'static [&'static str] = &[
+  "fun", "let", "mut", "if", "else", "while", "assert", "panic", "return", "true", "false",
+  "import", "export", "type", "data", "public", "internal", "protected", "private", "match",
+];
+const SINGLE_OPS: &'static str = "(){}[];,_";
+const MERGE_OPS: &'static str = "=+-*/:<>!.|";
+const HOLE: &'static str = "???";
+
+struct Lexer<R: BufRead> {
   src: String,
-  reader: CharReader<BufReader<File>>,
+  reader: CharReader<R>,
 }
 
-impl Lexer {
-  fn new(src: &Path) -> Result<Lexer, SimpleError> {
-    let file = File::open(src).map_err(SimpleError::from)?;
+impl Lexer<BufReader<File>> {
+  fn new(src: &Path) -> Result<Lexer<BufReader<File>>, LexError> {
+    let name = src.display().to_string();
+    let file = File::open(src).map_err(|err| LexError::Io { src: name.clone(), message: err.to_string() })?;
     let buff = BufReader::new(file);
     let reader = CharReader::new(buff);
 
-    Ok(Lexer { reader, src: String::from(src.to_str().ok_or_else(|| SimpleError::new("File has no name"))?) })
+    Ok(Lexer { reader, src: name })
+  }
+}
+
+impl Lexer<Cursor<String>> {
+  fn from_str(src: &str, name: &str) -> Lexer<Cursor<String>> {
+    let reader = CharReader::new(Cursor::new(String::from(src)));
+
+    Lexer { reader, src: String::from(name) }
   }
+}
+
+impl<R: BufRead> Lexer<R> {
 
   fn point(&self) -> Location {
     let (x, y) = self.reader.point();
-    Location { x, y, src: self.src.clone() }
+    Location::point(&self.src, x, y)
   }
 
   fn lex(&mut self) -> Token {
+    self.skip_trivia();
+
+    let token = self.lex_token();
+    self.spanned(token)
+  }
+
+  /**
+  * Lexes the next token keeping the whitespace before it and exactly how it was written.
+  */
+  fn lex_full(&mut self) -> FullToken {
+    let trivia = self.skip_trivia();
+
+    let mut token = self.lex_token();
+
+    if token.kind == TokenKind::EOF {
+      if let Some(unknown) = self.reader.current {
+        self.reader.next();
+        token = Token { kind: TokenKind::Unknown, value: unknown.to_string(), location: token.location };
+      }
+    }
+
+    let text = self.reader.take();
+    let location = token.location.spanning(&text);
+
+    FullToken { token: Token { location, ..token }, trivia, text }
+  }
+
+  /**
+  * Skips whitespace by parsing it as a word that's never saved, giving back what was skipped.
+  */
+  fn skip_trivia(&mut self) -> String {
     let is_space = |ch: char| ch.is_whitespace();
-    let is_merge_op = |ch: char| MERGE_OPS.contains(ch);
 
-    // Effectively skips whitespace by parsing and never saving it.
+    self.reader.take();
     self.lex_word(TokenKind::EOF, is_space, is_space);
-    self.lex_word(TokenKind::Id, |ch| ch.is_alphabetic(), |ch| ch.is_alphanumeric() || ch == '.')
+    self.reader.take()
+  }
+
+  /**
+  * token spanning everything read since the trivia before it.
+  */
+  fn spanned(&mut self, token: Token) -> Token {
+    let text = self.reader.take();
+    Token { location: token.location.spanning(&text), ..token }
+  }
+
+  fn lex_token(&mut self) -> Token {
+    let is_merge_op = |ch: char| MERGE_OPS.contains(ch);
+
+    self.lex_word(TokenKind::Id, |ch| ch.is_alphabetic(), |ch| ch.is_alphanumeric() || ch == '.' || ch == '_')
+      .map(|id| if KEYWORDS.contains(&id.value.as_str()) { Token { kind: TokenKind::Keyword, ..id } } else { id })
+      .or_else(|| self.lex_string())
+      .or_else(|| self.lex_number())
       .or_else(|| self.lex_word(TokenKind::Symbol, |ch| SINGLE_OPS.contains(ch), |_ch| { false }))
       .or_else(|| self.lex_word(TokenKind::Symbol, is_merge_op, is_merge_op))
-      .or_else(|| self.lex_word(TokenKind::Number, |ch| ch.is_numeric(), |ch| ch.is_numeric() || ch == '.'))
+      .or_else(|| self.lex_word(TokenKind::Symbol, |ch| ch == '?', |ch| ch == '?'))
       .unwrap_or_else(|| Token { kind: TokenKind::EOF, value: String::from("<EOF>"), location: self.point() })
   }
 
-  fn lex_word<L: Fn(char) -> bool, R: Fn(char) -> bool>(&mut self, kind: TokenKind, test_first: L, test: R) -> Option<Token> {
+  /**
+  * Double quoted string with \n, \t, \" and \\ escapes. The value excludes the quotes.
+  */
+  fn lex_string(&mut self) -> Option<Token> {
+    if self.reader.current != Some('"') {
+      return None;
+    }
+
+    let location = self.point();
+    let mut value = String::new();
+    let mut escaped = false;
+
+    loop {
+      match self.reader.next() {
+        Some(next) => {
+          if escaped {
+            value.push(match next {
+              'n' => '\n',
+              't' => '\t',
+              other => other,
+            });
+            escaped = false;
+          } else if next == '\\' {
+            escaped = true;
+          } else if next == '"' {
+            // Skip the closing quote
+            self.reader.next();
+            break;
+          } else {
+            value.push(next);
+          }
+        }
+        None => break
+      }
+    }
+
+    Some(Token { kind: TokenKind::String, value, location })
+  }
+
+  /**
+  * A number starts with a digit, or a dot right before one, and runs on through letters, digits, dots and
+  * underscores, so 1e9, .5, 1_000 and 0xFF are each one token. A sign right after an exponent's e is kept too. Whether
+  * it's a valid number is left to the parser, which can then point at the whole literal.
+  */
+  fn lex_number(&mut self) -> Option<Token> {
+    let starts = match self.reader.current {
+      Some(first) if first.is_ascii_digit() => true,
+      Some('.') => self.reader.peek().map_or(false, |next| next.is_ascii_digit()),
+      _ => false,
+    };
+
+    if !starts {
+      return None;
+    }
+
+    let location = self.point();
+    let mut value = String::new();
+
+    while let Some(next) = self.reader.current {
+      let is_hex = value.starts_with("0x") || value.starts_with("0X");
+      let is_exponent_sign = (next == '+' || next == '-') && (value.ends_with('e') || value.ends_with('E')) && !is_hex;
+
+      // A .. always starts a new token, so a range like 0..n needs no spaces.
+      if next == '.' && self.reader.peek() == Some('.') {
+        break;
+      }
+
+      if next.is_alphanumeric() || next == '.' || next == '_' || is_exponent_sign {
+        value.push(next);
+        self.reader.next();
+      } else {
+        break;
+      }
+    }
+
+    Some(Token { kind: TokenKind::Number, value, location })
+  }
+
+  fn lex_word<L: Fn(char) -> bool, T: Fn(char) -> bool>(&mut self, kind: TokenKind, test_first: L, test: T) -> Option<Token> {
     match self.reader.current {
       Some(first) => if test_first(first) {
         let location = self.point();
@@ -587,6 +1561,8 @@ impl Lexer {
 
         loop {
           match self.reader.next() {
+            // A .. always starts a new token, so a range like 0..n needs no spaces.
+            Some('.') if self.reader.peek() == Some('.') && !value.ends_with('.') => break,
             Some(next) => if test(next) {
               value.push(next)
             } else {
@@ -615,15 +1591,35 @@ pub struct Token {
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum TokenKind {
   Id,
+  /** One of KEYWORDS, which could otherwise have been an Id. */
+  Keyword,
   Symbol,
   Number,
   String,
+  /** A character nothing starts with, only ever from lex_lossless. */
+  Unknown,
   EOF,
 }
 
+/**
+* A token from lex_lossless, with the whitespace before it and its exact source.
+*/
+#[derive(Debug, Clone)]
+pub struct FullToken {
+  pub token: Token,
+  /** Everything skipped since the last token, which is only ever whitespace. */
+  pub trivia: String,
+  /** The token as written, so a string keeps its quotes and escapes. */
+  pub text: String,
+}
+
 impl Token {
-  pub fn expected<T>(&self, expected: &str) -> Result<T, SimpleError> {
-    Err(SimpleError::new(format!("Unexpected '{}' found {}. Expected: {}", self.value, self.location.pretty(), expected)))
+  pub fn expected<T>(&self, expected: &str) -> Result<T, ParseError> {
+    if self.kind == TokenKind::EOF {
+      return Err(ParseError::UnexpectedEof { loc: self.location.clone() });
+    }
+
+    Err(ParseError::UnexpectedToken { loc: self.location.clone(), found: self.value.clone(), expected: String::from(expected) })
   }
 }
 
@@ -632,12 +1628,14 @@ struct CharReader<R: BufRead> {
   y: usize,
   current: Option<char>,
   line: String,
+  /** Every character moved past since the last take. */
+  taken: String,
   reader: R,
 }
 
 impl<R: BufRead> CharReader<R> {
   fn new(reader: R) -> CharReader<R> {
-    let mut result = CharReader { x: 0, y: 0, current: None, line: String::new(), reader };
+    let mut result = CharReader { x: 0, y: 0, current: None, line: String::new(), taken: String::new(), reader };
     result.next();
     result
   }
@@ -648,6 +1646,10 @@ impl<R: BufRead> CharReader<R> {
   }
 
   fn advance(&mut self) {
+    if let Some(current) = self.current {
+      self.taken.push(current);
+    }
+
     if self.x >= self.line.len() {
       self.line.clear();
       let char_count = self.reader.read_line(&mut self.line)
@@ -668,4 +1670,15 @@ impl<R: BufRead> CharReader<R> {
   fn point(&self) -> (usize, usize) {
     return (self.x, self.y);
   }
+
+  fn take(&mut self) -> String {
+    std::mem::replace(&mut self.taken, String::new())
+  }
+
+  /**
+  * The character after current, if it's on the same line.
+  */
+  fn peek(&self) -> Option<char> {
+    self.line.chars().nth(self.x)
+  }
 }
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bytecode::FunctionRef;
+
+/**
+* Totals for one function over a whole run.
+*
+* Time is inclusive of everything the function called, and a recursive function counts its inner calls again.
+* A tail call is counted as a call of its own, but its time goes to the function that made it.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct FunctionStats {
+  pub calls: u64,
+  pub instructions: u64,
+  pub time: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+  functions: HashMap<FunctionRef, FunctionStats>,
+}
+
+impl Profile {
+
+  pub fn new() -> Profile {
+    Profile { functions: HashMap::new() }
+  }
+
+  pub fn record_call(&mut self, func: &FunctionRef) {
+    self.stats(func).calls += 1;
+  }
+
+  pub fn record_instruction(&mut self, func: &FunctionRef) {
+    self.stats(func).instructions += 1;
+  }
+
+  pub fn record_time(&mut self, func: &FunctionRef, time: Duration) {
+    self.stats(func).time += time;
+  }
+
+  pub fn get(&self, func: &FunctionRef) -> Option<&FunctionStats> {
+    self.functions.get(func)
+  }
+
+  /**
+  * Every function that was called, slowest first.
+  */
+  pub fn sorted(&self) -> Vec<(&FunctionRef, &FunctionStats)> {
+    let mut all: Vec<(&FunctionRef, &FunctionStats)> = self.functions.iter().collect();
+
+    all.sort_by(|left, right| right.1.time.cmp(&left.1.time).then_with(|| left.0.pretty().cmp(&right.0.pretty())));
+    all
+  }
+
+  pub fn report(&self) -> String {
+    let mut lines = vec![format!("{:>10} {:>12} {:>12}  {}", "calls", "instructions", "time (us)", "function")];
+
+    for (func, stats) in self.sorted() {
+      let micros = stats.time.as_secs() * 1_000_000 + u64::from(stats.time.subsec_micros());
+
+      lines.push(format!("{:>10} {:>12} {:>12}  {}", stats.calls, stats.instructions, micros, func.pretty()));
+    }
+
+    lines.join("\n")
+  }
+
+  fn stats(&mut self, func: &FunctionRef) -> &mut FunctionStats {
+    if !self.functions.contains_key(func) {
+      self.functions.insert(func.clone(), FunctionStats::default());
+    }
+
+    self.functions.get_mut(func).unwrap()
+  }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use bytecode::{BitFunction, BitModule, FunctionRef, Instruction, LocalId};
+use runtime::Value;
+use shapes::Shape;
+
+/**
+* Locals come first, followed by one register for each slot of the operand stack.
+*/
+pub type Register = LocalId;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatOp {
+  Add,
+  Sub,
+  Mul,
+  Div,
+  Less,
+  LessEqual,
+  Greater,
+  GreaterEqual,
+}
+
+/**
+* Three address code for the register backend. Jump targets are absolute indexes into the body.
+*/
+#[derive(Debug)]
+pub enum RegInstruction {
+  Move { dst: Register, src: Register },
+  LoadConst { dst: Register, value: Value },
+  Float { op: FloatOp, dst: Register, left: Register, right: Register },
+  Call { dst: Register, func: FunctionRef, args: Vec<Register> },
+  BranchFalse { condition: Register, target: usize },
+  Jump { target: usize },
+  Return { src: Register },
+}
+
+pub struct RegFunction {
+  pub func_ref: FunctionRef,
+  pub registers: LocalId,
+  pub body: Vec<RegInstruction>,
+}
+
+/**
+* Where a value on the simulated operand stack really is. Loads are only copied into a register when they have to
+* be, so most operations read straight from locals.
+*/
+#[derive(Clone)]
+enum Operand {
+  Reg(Register),
+  Local(LocalId),
+  Const(Value),
+}
+
+/**
+* Converts a stack function to registers by simulating its operand stack, where each depth has a fixed register.
+*
+* Only loads, stores, float operators, static calls and control flow are supported, anything else gives None so the
+* function stays on the stack interpreter.
+*/
+pub fn to_registers(func: &BitFunction, module: &BitModule) -> Option<RegFunction> {
+  let base = func.max_locals;
+  let slot = |depth: usize| base + depth as Register;
+
+  let targets: HashMap<usize, usize> = func.body.iter().enumerate()
+    .filter_map(|(index, instruction)| jump_target(instruction, index).map(|target| (target, index)))
+    .collect();
+
+  let mut body: Vec<RegInstruction> = Vec::new();
+  let mut stack: Vec<Operand> = Vec::new();
+  let mut labels: HashMap<usize, usize> = HashMap::new();
+  let mut depth_at: HashMap<usize, usize> = HashMap::new();
+  let mut block_start = 0;
+  let mut reachable = true;
+
+  for (index, instruction) in func.body.iter().enumerate() {
+    if targets.contains_key(&index) {
+      if reachable {
+        flush(&mut stack, &mut body, slot);
+      } else {
+        stack = (0..*depth_at.get(&index)?).map(|depth| Operand::Reg(slot(depth))).collect();
+      }
+
+      labels.insert(index, body.len());
+      block_start = body.len();
+      reachable = true;
+    }
+
+    if !reachable {
+      continue;
+    }
+
+    match instruction {
+      Instruction::NoOp => {}
+      Instruction::Pop => {
+        stack.pop()?;
+      }
+      Instruction::Duplicate => {
+        // A copy may only point down the stack, never up, so a slot is always dead before it's overwritten.
+        let depth = stack.len().checked_sub(1)?;
+        let top = materialize(&mut stack, &mut body, depth, slot);
+        stack.push(Operand::Reg(top));
+      }
+      Instruction::LoadConstUnit => stack.push(Operand::Const(Value::Unit)),
+      Instruction::LoadConstTrue => stack.push(Operand::Const(Value::True)),
+      Instruction::LoadConstFalse => stack.push(Operand::Const(Value::False)),
+      Instruction::LoadConstFloat { value } => stack.push(Operand::Const(Value::Float(*value))),
+      Instruction::LoadConstString { const_id } => stack.push(Operand::Const(Value::String(module.lookup_string(*const_id).ok()?.clone()))),
+      Instruction::LoadValue { local } => stack.push(Operand::Local(*local)),
+      Instruction::StoreValue { local } => {
+        let value = stack.pop()?;
+        preserve_local(&mut stack, &mut body, *local, slot);
+
+        match value {
+          // Have whatever made the value write it to the local instead.
+          Operand::Reg(reg) if reg == slot(stack.len()) && body.len() > block_start && writes(body.last()?) == Some(reg) => retarget(body.last_mut()?, *local),
+          Operand::Reg(src) | Operand::Local(src) => body.push(RegInstruction::Move { dst: *local, src }),
+          Operand::Const(value) => body.push(RegInstruction::LoadConst { dst: *local, value }),
+        }
+      }
+      Instruction::StoreConstFloat { value, local } => {
+        preserve_local(&mut stack, &mut body, *local, slot);
+        body.push(RegInstruction::LoadConst { dst: *local, value: Value::Float(*value) });
+      }
+      Instruction::AddLocals { left, right } => {
+        let dst = slot(stack.len());
+        body.push(RegInstruction::Float { op: FloatOp::Add, dst, left: *left, right: *right });
+        stack.push(Operand::Reg(dst));
+      }
+      Instruction::AddFloat => float_op(&mut stack, &mut body, FloatOp::Add, slot)?,
+      Instruction::SubFloat => float_op(&mut stack, &mut body, FloatOp::Sub, slot)?,
+      Instruction::MulFloat => float_op(&mut stack, &mut body, FloatOp::Mul, slot)?,
+      Instruction::DivFloat => float_op(&mut stack, &mut body, FloatOp::Div, slot)?,
+      Instruction::LessFloat => float_op(&mut stack, &mut body, FloatOp::Less, slot)?,
+      Instruction::LessEqualFloat => float_op(&mut stack, &mut body, FloatOp::LessEqual, slot)?,
+      Instruction::GreaterFloat => float_op(&mut stack, &mut body, FloatOp::Greater, slot)?,
+      Instruction::GreaterEqualFloat => float_op(&mut stack, &mut body, FloatOp::GreaterEqual, slot)?,
+      Instruction::CallStatic { func_id } => {
+        let func = module.function_refs.get(*func_id as usize)?.clone();
+
+        let count = match &func.shape {
+          Shape::SimpleFunctionShape { args, .. } => args.len(),
+          _ => return None,
+        };
+
+        let first = stack.len().checked_sub(count)?;
+        let args = (first..stack.len()).map(|depth| register_of(&mut stack, &mut body, depth, slot)).collect();
+
+        stack.truncate(first);
+        body.push(RegInstruction::Call { dst: slot(first), func, args });
+        stack.push(Operand::Reg(slot(first)));
+      }
+      Instruction::Branch { .. } => {
+        let depth = stack.len().checked_sub(1)?;
+        let condition = register_of(&mut stack, &mut body, depth, slot);
+        stack.pop();
+        flush(&mut stack, &mut body, slot);
+
+        depth_at.insert(jump_target(instruction, index)?, stack.len());
+        body.push(RegInstruction::BranchFalse { condition, target: jump_target(instruction, index)? });
+      }
+      Instruction::Jump { .. } => {
+        flush(&mut stack, &mut body, slot);
+
+        depth_at.insert(jump_target(instruction, index)?, stack.len());
+        body.push(RegInstruction::Jump { target: jump_target(instruction, index)? });
+        reachable = false;
+      }
+      Instruction::Return => {
+        let depth = stack.len().checked_sub(1)?;
+        let src = register_of(&mut stack, &mut body, depth, slot);
+        body.push(RegInstruction::Return { src });
+        reachable = false;
+      }
+      _ => return None,
+    }
+  }
+
+  // Targets were recorded as stack indexes, point them at where those ended up.
+  labels.insert(func.body.len(), body.len());
+
+  for instruction in body.iter_mut() {
+    match instruction {
+      RegInstruction::BranchFalse { target, .. } | RegInstruction::Jump { target } => *target = *labels.get(target)?,
+      _ => {}
+    }
+  }
+
+  Some(RegFunction {
+    func_ref: func.func_ref.clone(),
+    registers: base + func.max_stack,
+    body,
+  })
+}
+
+fn float_op<Slot: Fn(usize) -> Register>(stack: &mut Vec<Operand>, body: &mut Vec<RegInstruction>, op: FloatOp, slot: Slot) -> Option<()> {
+  let left_depth = stack.len().checked_sub(2)?;
+  let left = register_of(stack, body, left_depth, &slot);
+  let right = register_of(stack, body, left_depth + 1, &slot);
+
+  stack.truncate(left_depth);
+  body.push(RegInstruction::Float { op, dst: slot(left_depth), left, right });
+  stack.push(Operand::Reg(slot(left_depth)));
+  Some(())
+}
+
+/**
+* A register holding the operand at depth, only loading constants since locals and registers can be read in place.
+*/
+fn register_of<Slot: Fn(usize) -> Register>(stack: &mut Vec<Operand>, body: &mut Vec<RegInstruction>, depth: usize, slot: Slot) -> Register {
+  match &stack[depth] {
+    Operand::Reg(reg) | Operand::Local(reg) => *reg,
+    Operand::Const(_) => materialize(stack, body, depth, slot),
+  }
+}
+
+/**
+* Copies the operand at depth into that depth's own register.
+*/
+fn materialize<Slot: Fn(usize) -> Register>(stack: &mut Vec<Operand>, body: &mut Vec<RegInstruction>, depth: usize, slot: Slot) -> Register {
+  let dst = slot(depth);
+
+  match stack[depth].clone() {
+    Operand::Reg(reg) if reg == dst => {}
+    Operand::Reg(src) | Operand::Local(src) => body.push(RegInstruction::Move { dst, src }),
+    Operand::Const(value) => body.push(RegInstruction::LoadConst { dst, value }),
+  }
+
+  stack[depth] = Operand::Reg(dst);
+  dst
+}
+
+/**
+* Jumps can only meet if the stack is laid out the same on both sides, so before one everything goes to its own register.
+*/
+fn flush<Slot: Fn(usize) -> Register>(stack: &mut Vec<Operand>, body: &mut Vec<RegInstruction>, slot: Slot) {
+  for depth in 0..stack.len() {
+    materialize(stack, body, depth, &slot);
+  }
+}
+
+/**
+* Anything still reading a local that's about to be overwritten needs its own copy of the old value first.
+*/
+fn preserve_local<Slot: Fn(usize) -> Register>(stack: &mut Vec<Operand>, body: &mut Vec<RegInstruction>, local: LocalId, slot: Slot) {
+  for depth in 0..stack.len() {
+    if let Operand::Local(other) = stack[depth] {
+      if other == local {
+        materialize(stack, body, depth, &slot);
+      }
+    }
+  }
+}
+
+fn writes(instruction: &RegInstruction) -> Option<Register> {
+  match instruction {
+    RegInstruction::Move { dst, .. } | RegInstruction::LoadConst { dst, .. } | RegInstruction::Float { dst, .. } | RegInstruction::Call { dst, .. } => Some(*dst),
+    _ => None,
+  }
+}
+
+fn retarget(instruction: &mut RegInstruction, local: LocalId) {
+  match instruction {
+    RegInstruction::Move { dst, .. } | RegInstruction::LoadConst { dst, .. } | RegInstruction::Float { dst, .. } | RegInstruction::Call { dst, .. } => *dst = local,
+    _ => {}
+  }
+}
+
+fn jump_target(instruction: &Instruction, index: usize) -> Option<usize> {
+  match instruction {
+    Instruction::Branch { jump } | Instruction::Jump { jump } => Some((index as i64 + 1 + *jump as i64) as usize),
+    _ => None,
+  }
+}
+
+/**
+* Runs one operation of the register backend.
+*/
+pub fn float(op: FloatOp, left: f64, right: f64) -> Value {
+  let test = |result: bool| if result { Value::True } else { Value::False };
+
+  match op {
+    FloatOp::Add => Value::Float(left + right),
+    FloatOp::Sub => Value::Float(left - right),
+    FloatOp::Mul => Value::Float(left * right),
+    FloatOp::Div => Value::Float(left / right),
+    FloatOp::Less => test(left < right),
+    FloatOp::LessEqual => test(left <= right),
+    FloatOp::Greater => test(left > right),
+    FloatOp::GreaterEqual => test(left >= right),
+  }
+}
+
+/**
+* Cache of converted functions, None for those that have to stay on the stack interpreter.
+*/
+pub type RegCache = HashMap<FunctionRef, Option<Rc<RegFunction>>>;
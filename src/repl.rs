@@ -0,0 +1,187 @@
+use std::io::{self, Write};
+
+use ast::{AstFunctionDeclaration, AstModule, BlockEx, Expression, Parameter, TupleEx, VariableEx, Visibility};
+use bytecode::{BitApplication, FunctionRef};
+use compiler::compile;
+use error::{CompileError, LetError, ParseError, TypeError};
+use interpreter::Machine;
+use ir::compile_ir_module;
+use optimize::Optimizer;
+use parser::parse_repl;
+use runtime::Value;
+use shapes::{shape_unit, shape_unknown};
+use typechecker::ReplScope;
+
+const REPL_PACKAGE: &'static str = "repl";
+
+/**
+* Reads statements from stdin until EOF, evaluating each one and printing the result.
+* Input that ends mid expression keeps reading lines until the statement is complete.
+*/
+pub fn run_repl(optimizer: &Optimizer) -> Result<(), LetError> {
+  let mut repl = Repl::new(optimizer);
+  let stdin = io::stdin();
+  let mut pending = String::new();
+
+  prompt("> ")?;
+
+  loop {
+    // Don't hold the stdin lock, Console.readLine needs it while the line runs.
+    let mut line = String::new();
+
+    if stdin.read_line(&mut line)? == 0 {
+      break;
+    }
+
+    let line = line.trim_end_matches(|ch| ch == '\n' || ch == '\r');
+
+    if line.trim().is_empty() && pending.is_empty() {
+      prompt("> ")?;
+      continue;
+    }
+
+    pending.push_str(line);
+    pending.push('\n');
+
+    match repl.eval(&pending) {
+      Ok(result) => {
+        println!("{}", result);
+        pending.clear();
+        prompt("> ")?;
+      }
+      Err(LetError::Compile(CompileError::Parse(ParseError::UnexpectedEof { .. }))) => {
+        prompt("| ")?;
+      }
+      Err(err) => {
+        println!("Error: {}", err);
+        pending.clear();
+        prompt("> ")?;
+      }
+    }
+  }
+
+  Ok(())
+}
+
+fn prompt(text: &str) -> io::Result<()> {
+  let mut out = io::stdout();
+  out.write_all(text.as_bytes())?;
+  out.flush()
+}
+
+pub struct Repl<'a> {
+  optimizer: &'a Optimizer,
+  scope: ReplScope,
+  machine: Machine,
+  values: Vec<Value>,
+  line: usize,
+}
+
+impl <'a> Repl<'a> {
+
+  pub fn new(optimizer: &'a Optimizer) -> Repl<'a> {
+    Repl {
+      optimizer,
+      scope: ReplScope::new(),
+      machine: Machine::new(BitApplication::library()),
+      values: Vec::new(),
+      line: 0,
+    }
+  }
+
+  /**
+  * Evaluates a single line and returns a printable description of the result.
+  * Bindings made by let and fun are only kept if the whole line succeeds.
+  */
+  pub fn eval(&mut self, src: &str) -> Result<String, LetError> {
+    self.line += 1;
+    let module_name = format!("line{}", self.line);
+
+    let (binding, body) = match parse_repl(src, &format!("<repl:{}>", self.line))? {
+      Expression::Import(imp) => {
+        let description = format!("imported {}::{}", imp.package, imp.module);
+        self.scope.import(*imp)?;
+        return Ok(description);
+      }
+      Expression::Assignment(ex) => {
+        let ids = ex.pattern.ids();
+        (ids.clone(), bind_result(ids, Expression::Assignment(ex)))
+      }
+      Expression::FunctionDeclaration(ex) => {
+        let ids = vec![ex.id.clone()];
+        (ids.clone(), bind_result(ids, Expression::FunctionDeclaration(ex)))
+      }
+      ex => (vec![], ex)
+    };
+
+    let checked = self.scope.check_statement(String::from("$repl"), body)?;
+    let shape = checked.result.clone();
+
+    let ast = AstModule {
+      package: String::from(REPL_PACKAGE),
+      name: module_name.clone(),
+      functions: vec![AstFunctionDeclaration { visibility: Visibility::Public, ex: checked }],
+      imports: self.scope.imports().clone(),
+      aliases: Vec::new(),
+      datas: Vec::new(),
+    };
+
+    let ir = compile_ir_module(&ast, self.scope.shapes())?;
+    let bytecode = compile(ir, self.optimizer)?;
+    let func_ref = FunctionRef {
+      package: String::from(REPL_PACKAGE),
+      module: module_name.clone(),
+      name: String::from("$repl"),
+
+      shape: ast.functions[0].ex.shape(),
+    };
+
+    self.machine.load_module(REPL_PACKAGE, &module_name, bytecode);
+    let result = self.machine.execute(func_ref, self.values.clone())?;
+    if binding.is_empty() {
+      return Ok(format!("{}: {}", result, shape.pretty()));
+    }
+
+    // A single binding comes back as itself, several come back as a tuple in declaration order.
+    let (values, shapes) = if binding.len() == 1 {
+      (vec![result], vec![shape])
+    } else {
+      match (result, shape.tuple_args()) {
+        (Value::Tuple(items), Some(shapes)) => (items.to_vec(), shapes.clone()),
+        _ => return Err(TypeError::Internal { message: String::from("Repl bindings did not return a tuple") }.into()),
+      }
+    };
+
+    let mut descriptions = Vec::new();
+
+    for ((id, value), shape) in binding.into_iter().zip(values).zip(shapes) {
+      if let Some(index) = self.scope.bindings().iter().position(|prev| prev.id == id) {
+        self.values.remove(index);
+      }
+
+      descriptions.push(format!("{} = {}: {}", id, value, shape.pretty()));
+      self.scope.bind(Parameter { id, shape });
+      self.values.push(value);
+    }
+
+    Ok(descriptions.join("\n"))
+  }
+}
+
+/**
+* Wraps a declaration in a block that also returns the declared values so they can be kept as bindings.
+*/
+fn bind_result(ids: Vec<String>, ex: Expression) -> Expression {
+  let loc = ex.loc().clone();
+  let mut vars: Vec<Expression> = ids.into_iter()
+    .map(|id| VariableEx { shape: shape_unit(), loc: loc.clone(), id }.wrap())
+    .collect();
+
+  let result = if vars.len() == 1 {
+    vars.pop().unwrap()
+  } else {
+    TupleEx { shape: shape_unknown(), loc: loc.clone(), items: vars }.wrap()
+  };
+
+  BlockEx { shape: shape_unit(), loc, body: vec![ex, result] }.wrap()
+}
@@ -1,23 +1,142 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::iter::FromIterator;
 use std::rc::Rc;
 
+use error::RuntimeError;
 use interpreter::FunctionHandle;
 use shapes::BaseShapeKind;
 use shapes::Shape;
 
 #[derive(Clone, Debug)]
 pub enum Value {
-  Null,
+  Unit,
   True,
   False,
   String(Rc<String>),
   Float(f64),
   Function(Rc<FunctionHandle>),
-  List(Rc<ListValue>)
+  List(Rc<ListValue>),
+  Map(Rc<MapValue>),
+  Tuple(Rc<Vec<Value>>),
+  Variant(Rc<VariantValue>),
+  /** A local shared with the closures that captured it. Never seen by letLang code, only by the bytecode. */
+  Cell(Rc<RefCell<Value>>),
+}
+
+/**
+* How the repl and Core.show write a value. Floats drop a trailing .0, strings are quoted and functions are named by
+* the function they call.
+*/
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Value::Unit => write!(f, "()"),
+      Value::True => write!(f, "true"),
+      Value::False => write!(f, "false"),
+      Value::String(value) => write!(f, "'{}'", value),
+      Value::Float(value) => write!(f, "{}", value),
+      Value::Function(func) => write!(f, "<{}>", func.func_ref().pretty()),
+      Value::List(list) => write!(f, "[{}]", join(list.contents.iter())),
+      Value::Tuple(items) => write!(f, "({})", join(items.iter())),
+      Value::Map(map) => {
+        let contents: Vec<String> = map.contents.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+        write!(f, "{{{}}}", contents.join(", "))
+      }
+      Value::Variant(variant) if variant.values.is_empty() => write!(f, "{}", variant.tag),
+      Value::Variant(variant) => write!(f, "{}({})", variant.tag, join(variant.values.iter())),
+      Value::Cell(cell) => cell.borrow().fmt(f),
+    }
+  }
+}
+
+/**
+* Deep equality for everything but functions, which are only equal to themselves. A Cell compares by what it holds.
+*/
+impl PartialEq for Value {
+  fn eq(&self, other: &Value) -> bool {
+    match (self, other) {
+      (Value::Cell(cell), other) => *cell.borrow() == *other,
+      (value, Value::Cell(cell)) => *value == *cell.borrow(),
+      (Value::Unit, Value::Unit) => true,
+      (Value::True, Value::True) => true,
+      (Value::False, Value::False) => true,
+      (Value::String(left), Value::String(right)) => left == right,
+      (Value::Float(left), Value::Float(right)) => left == right,
+      (Value::Function(left), Value::Function(right)) => Rc::ptr_eq(left, right),
+      (Value::List(left), Value::List(right)) => left.contents == right.contents,
+      (Value::Map(left), Value::Map(right)) => left.contents == right.contents,
+      (Value::Tuple(left), Value::Tuple(right)) => left == right,
+      (Value::Variant(left), Value::Variant(right)) => left.tag == right.tag && left.values == right.values,
+      _ => false,
+    }
+  }
+}
+
+/**
+* Only sound because NaN, the one value not equal to itself, is never let in as a map key. See check_key.
+*/
+impl Eq for Value {}
+
+/**
+* Agrees with PartialEq, so -0 and 0 hash the same. Functions and maps can't be keys, they only hash something cheap.
+*/
+impl Hash for Value {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    match self {
+      Value::Cell(cell) => return cell.borrow().hash(state),
+      _ => {}
+    }
+
+    ::std::mem::discriminant(self).hash(state);
+
+    match self {
+      Value::String(value) => value.hash(state),
+      Value::Float(value) => (if *value == 0.0 { 0.0f64 } else { *value }).to_bits().hash(state),
+      Value::Function(func) => func.func_ref().hash(state),
+      Value::List(list) => list.contents.hash(state),
+      Value::Map(map) => map.contents.len().hash(state),
+      Value::Tuple(items) => items.hash(state),
+      Value::Variant(variant) => {
+        variant.tag.hash(state);
+        variant.values.hash(state);
+      }
+      Value::Unit | Value::True | Value::False | Value::Cell(_) => {}
+    }
+  }
+}
+
+impl Value {
+
+  /**
+  * Fails unless the value can be used as a map key. The typechecker already rules out functions and maps, but NaN
+  * can only be caught here.
+  */
+  pub fn check_key(&self) -> Result<(), RuntimeError> {
+    match self {
+      Value::Float(value) if value.is_nan() => Err(RuntimeError::native("NaN can't be used as a map key")),
+      Value::Function(_) => Err(RuntimeError::native("Functions can't be used as map keys")),
+      Value::Map(_) => Err(RuntimeError::native("Maps can't be used as map keys")),
+      Value::List(list) => list.contents.iter().map(Value::check_key).collect(),
+      Value::Tuple(items) => items.iter().map(Value::check_key).collect(),
+      Value::Variant(variant) => variant.values.iter().map(Value::check_key).collect(),
+      Value::Cell(cell) => cell.borrow().check_key(),
+      _ => Ok(()),
+    }
+  }
+}
+
+fn join<'a, I: Iterator<Item = &'a Value>>(values: I) -> String {
+  let contents: Vec<String> = values.map(|value| value.to_string()).collect();
+  contents.join(", ")
 }
 
 #[derive(Clone, Debug)]
 pub struct ListValue {
-  pub contents: Vec<Value>,
+  pub contents: PersistentList,
   pub shape: Shape,
 }
 
@@ -25,12 +144,237 @@ impl ListValue {
 
   pub fn new(shape: Shape) -> ListValue {
     ListValue {
-      contents: Vec::new(),
+      contents: PersistentList::new(),
+      shape
+    }
+  }
+
+}
+
+const LIST_BITS: usize = 5;
+const LIST_WIDTH: usize = 1 << LIST_BITS;
+const LIST_MASK: usize = LIST_WIDTH - 1;
+
+#[derive(Debug)]
+enum ListNode {
+  Branch(Vec<Rc<ListNode>>),
+  Leaf(Vec<Value>),
+}
+
+/**
+* An immutable vector that shares structure between versions, so push and clone don't copy the whole list.
+*
+* Elements live in a tree of 32 wide nodes, with the last few kept in a separate tail. A push only copies the tail,
+* and once that fills up, the one path from the root down to where the tail is moved into the tree.
+*/
+#[derive(Clone)]
+pub struct PersistentList {
+  len: usize,
+  /** How far to shift an index to find its child in the root. Grows by LIST_BITS each time the tree gets deeper. */
+  shift: usize,
+  root: Rc<ListNode>,
+  tail: Rc<Vec<Value>>,
+}
+
+impl PersistentList {
+
+  pub fn new() -> PersistentList {
+    PersistentList {
+      len: 0,
+      shift: LIST_BITS,
+      root: Rc::new(ListNode::Branch(Vec::new())),
+      tail: Rc::new(Vec::new()),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn get(&self, index: usize) -> Option<&Value> {
+    if index >= self.len {
+      return None;
+    }
+
+    if index >= self.tail_offset() {
+      return self.tail.get(index - self.tail_offset());
+    }
+
+    let mut node = &self.root;
+    let mut level = self.shift;
+
+    loop {
+      match **node {
+        ListNode::Branch(ref children) => {
+          node = &children[(index >> level) & LIST_MASK];
+          level -= LIST_BITS;
+        }
+        ListNode::Leaf(ref values) => return values.get(index & LIST_MASK),
+      }
+    }
+  }
+
+  /**
+  * A new list with the value added to the end. This list is left as it was.
+  */
+  pub fn push(&self, value: Value) -> PersistentList {
+    let mut copy = self.clone();
+    copy.push_mut(value);
+    copy
+  }
+
+  pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a Value> + 'a {
+    let mut chunks: Vec<&'a [Value]> = Vec::new();
+    collect_leaves(&self.root, &mut chunks);
+    chunks.push(&self.tail);
+
+    chunks.into_iter().flat_map(|chunk| chunk.iter())
+  }
+
+  /**
+  * Index of the first element in the tail, every element before it is in the tree.
+  */
+  fn tail_offset(&self) -> usize {
+    if self.len < LIST_WIDTH {
+      0
+    } else {
+      ((self.len - 1) >> LIST_BITS) << LIST_BITS
+    }
+  }
+
+  fn push_mut(&mut self, value: Value) {
+    if self.len - self.tail_offset() == LIST_WIDTH {
+      let full_tail = ::std::mem::replace(&mut self.tail, Rc::new(Vec::with_capacity(LIST_WIDTH)));
+      let leaf = Rc::new(ListNode::Leaf(Rc::try_unwrap(full_tail).unwrap_or_else(|shared| (*shared).clone())));
+
+      if (self.len >> LIST_BITS) > (1 << self.shift) {
+        // The tree is full, so the old root becomes the first child of a new one.
+        let path = new_path(self.shift, leaf);
+        self.root = Rc::new(ListNode::Branch(vec![self.root.clone(), path]));
+        self.shift += LIST_BITS;
+      } else {
+        self.root = Rc::new(push_tail(self.len, self.shift, &self.root, leaf));
+      }
+    }
+
+    Rc::make_mut(&mut self.tail).push(value);
+    self.len += 1;
+  }
+}
+
+/**
+* Copies the path down to where a full tail of a list of len elements belongs, creating nodes along the way as needed.
+*/
+fn push_tail(len: usize, level: usize, parent: &ListNode, leaf: Rc<ListNode>) -> ListNode {
+  let mut children = match parent {
+    ListNode::Branch(children) => children.clone(),
+    ListNode::Leaf(_) => unreachable!("List leaves are never above the bottom level"),
+  };
+
+  let child_index = ((len - 1) >> level) & LIST_MASK;
+
+  if level == LIST_BITS {
+    children.push(leaf);
+  } else if child_index < children.len() {
+    children[child_index] = Rc::new(push_tail(len, level - LIST_BITS, &children[child_index], leaf));
+  } else {
+    children.push(new_path(level - LIST_BITS, leaf));
+  }
+
+  ListNode::Branch(children)
+}
+
+fn new_path(level: usize, leaf: Rc<ListNode>) -> Rc<ListNode> {
+  if level == 0 {
+    leaf
+  } else {
+    Rc::new(ListNode::Branch(vec![new_path(level - LIST_BITS, leaf)]))
+  }
+}
+
+fn collect_leaves<'a>(node: &'a ListNode, chunks: &mut Vec<&'a [Value]>) {
+  match node {
+    ListNode::Branch(children) => children.iter().for_each(|child| collect_leaves(child, chunks)),
+    ListNode::Leaf(values) => chunks.push(values),
+  }
+}
+
+impl Default for PersistentList {
+  fn default() -> PersistentList {
+    PersistentList::new()
+  }
+}
+
+impl FromIterator<Value> for PersistentList {
+  fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> PersistentList {
+    let mut list = PersistentList::new();
+    iter.into_iter().for_each(|value| list.push_mut(value));
+    list
+  }
+}
+
+impl From<Vec<Value>> for PersistentList {
+  fn from(values: Vec<Value>) -> PersistentList {
+    values.into_iter().collect()
+  }
+}
+
+impl PartialEq for PersistentList {
+  fn eq(&self, other: &PersistentList) -> bool {
+    self.len == other.len && self.iter().eq(other.iter())
+  }
+}
+
+/**
+* Hashes the same as a Vec of the same values would.
+*/
+impl Hash for PersistentList {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.len.hash(state);
+    self.iter().for_each(|value| value.hash(state));
+  }
+}
+
+impl fmt::Debug for PersistentList {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_list().entries(self.iter()).finish()
+  }
+}
+
+/**
+* One case of a sum type, such as Some or Err, named by its tag and holding the values it was built with.
+*/
+#[derive(Clone, Debug)]
+pub struct VariantValue {
+  pub tag: String,
+  pub values: Vec<Value>,
+}
+
+/**
+* Hashed with fixed keys, so the same puts always iterate in the same order, from one run to the next.
+*/
+pub type MapContents = HashMap<Value, Value, BuildHasherDefault<DefaultHasher>>;
+
+#[derive(Clone, Debug)]
+pub struct MapValue {
+  pub contents: MapContents,
+  pub shape: Shape,
+}
+
+impl MapValue {
+
+  pub fn new(shape: Shape) -> MapValue {
+    MapValue {
+      contents: MapContents::default(),
       shape
     }
   }
 
-  pub fn copy_contents(&self) -> Vec<Value> {
+  pub fn copy_contents(&self) -> MapContents {
     self.contents.clone()
   }
 
@@ -2,7 +2,7 @@ use serde::{Serialize, Deserialize};
 use ast::Location;
 use typechecker::fill_shape;
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Shape {
   GenericShapeConstructor {
     base: Box<Shape>,
@@ -22,10 +22,97 @@ pub enum Shape {
   NamedShape {
     name: String
   },
-  UnknownShape
+  UnknownShape,
+  /** A shape the typechecker is still working out from how it's used. None are left once a module is checked. */
+  VariableShape {
+    id: usize
+  },
 }
 
 impl Shape {
+
+  /**
+  * The module that owns functions on this shape, where `value.name(args)` looks for name. List[Float] is owned by List.
+  */
+  pub fn owner_module(&self) -> Option<&'static str> {
+    let kind = match self {
+      Shape::BaseShape { kind } => kind,
+      Shape::GenericShape { base, .. } => match **base {
+        Shape::BaseShape { ref kind } => kind,
+        _ => return None,
+      },
+      _ => return None,
+    };
+
+    Some(match kind {
+      BaseShapeKind::Boolean => "Boolean",
+      BaseShapeKind::Float => "Float",
+      BaseShapeKind::String => "String",
+      BaseShapeKind::Unit => "Unit",
+      BaseShapeKind::List => "List",
+      BaseShapeKind::Map => "Map",
+      BaseShapeKind::Tuple => "Tuple",
+      BaseShapeKind::Option => "Option",
+      BaseShapeKind::Result => "Result",
+    })
+  }
+
+  /**
+  * Element shapes if this is a tuple.
+  */
+  pub fn tuple_args(&self) -> Option<&Vec<Shape>> {
+    match self {
+      Shape::GenericShape{base, args} if **base == Shape::BaseShape { kind: BaseShapeKind::Tuple } => Some(args),
+      _ => None
+    }
+  }
+
+  /**
+  * The element shape if this is a List.
+  */
+  pub fn list_arg(&self) -> Option<&Shape> {
+    match self {
+      Shape::GenericShape{base, args} if **base == Shape::BaseShape { kind: BaseShapeKind::List } && args.len() == 1 => Some(&args[0]),
+      _ => None
+    }
+  }
+
+  /**
+  * The key and value shapes if this is a Map.
+  */
+  pub fn map_args(&self) -> Option<(&Shape, &Shape)> {
+    match self {
+      Shape::GenericShape{base, args} if **base == Shape::BaseShape { kind: BaseShapeKind::Map } && args.len() == 2 => Some((&args[0], &args[1])),
+      _ => None
+    }
+  }
+
+  /**
+  * The value shape if this is an Option.
+  */
+  pub fn option_arg(&self) -> Option<&Shape> {
+    match self {
+      Shape::GenericShape{base, args} if **base == Shape::BaseShape { kind: BaseShapeKind::Option } && args.len() == 1 => Some(&args[0]),
+      _ => None
+    }
+  }
+
+  /**
+  * The value and error shapes if this is a Result.
+  */
+  pub fn result_args(&self) -> Option<(&Shape, &Shape)> {
+    match self {
+      Shape::GenericShape{base, args} if **base == Shape::BaseShape { kind: BaseShapeKind::Result } && args.len() == 2 => Some((&args[0], &args[1])),
+      _ => None
+    }
+  }
+
+  /**
+  * The shape as it would be written in source, so parser::parse_shape reads it back to an equal shape. Unknown is
+  * written as _, the placeholder a type annotation can use to ask for inference. A GenericShapeConstructor, a data
+  * type not yet given its type arguments, is shown with a _ per parameter. A data type without parameters is just its
+  * name.
+  */
   pub fn pretty(&self) -> String {
 
     match self {
@@ -35,12 +122,19 @@ impl Shape {
 
         format!("{}[{}]", base_name, arg_names)
       }
+      Shape::GenericShape{base, args} if **base == Shape::BaseShape { kind: BaseShapeKind::Tuple } => {
+        let arg_names = args.iter().map(|a| a.pretty()).collect::<Vec<String>>().join(", ");
+
+        format!("({})", arg_names)
+      }
+      Shape::GenericShape{base, args} if args.is_empty() => base.pretty(),
       Shape::GenericShape{base, args} => {
         let arg_names = args.iter().map(|a| a.pretty()).collect::<Vec<String>>().join(", ");
         let base_name = base.pretty();
 
         format!("{}[{}]", base_name, arg_names)
       },
+      Shape::SimpleFunctionShape{args, result} if args.is_empty() => format!("{{ -> {} }}", result.pretty()),
       Shape::SimpleFunctionShape{args, result} => {
         let arg_names = args.iter().map(|a| a.pretty()).collect::<Vec<String>>().join(", ");
         let result_name = result.pretty();
@@ -52,23 +146,72 @@ impl Shape {
       Shape::BaseShape{kind: BaseShapeKind::String} => String::from("String"),
       Shape::BaseShape{kind: BaseShapeKind::Unit} => String::from("Unit"),
       Shape::BaseShape { kind: BaseShapeKind::List } => String::from("List"),
+      Shape::BaseShape { kind: BaseShapeKind::Map } => String::from("Map"),
+      Shape::BaseShape { kind: BaseShapeKind::Tuple } => String::from("Tuple"),
+      Shape::BaseShape { kind: BaseShapeKind::Option } => String::from("Option"),
+      Shape::BaseShape { kind: BaseShapeKind::Result } => String::from("Result"),
       Shape::NamedShape{name} => name.clone(),
-      Shape::UnknownShape => String::from("Unknown"),
+      Shape::UnknownShape => String::from("_"),
+      Shape::VariableShape { id } if *id < 26 => format!("'{}", (b'a' + *id as u8) as char),
+      Shape::VariableShape { id } => format!("'t{}", id),
+    }
+  }
+
+  /**
+  * Replaces the names of builtin types with their shapes, leaving every other name as it is.
+  */
+  pub fn with_builtins(self) -> Shape {
+    match self {
+      Shape::GenericShapeConstructor { base, args } => Shape::GenericShapeConstructor { base: Box::new(base.with_builtins()), args },
+      Shape::GenericShape { base, args } => Shape::GenericShape {
+        base: Box::new(base.with_builtins()),
+        args: args.into_iter().map(Shape::with_builtins).collect(),
+      },
+      Shape::SimpleFunctionShape { args, result } => Shape::SimpleFunctionShape {
+        args: args.into_iter().map(Shape::with_builtins).collect(),
+        result: Box::new(result.with_builtins()),
+      },
+      Shape::NamedShape { name } => builtin_shape(&name).unwrap_or(Shape::NamedShape { name }),
+      other => other,
     }
   }
 
   pub fn fill_shape_native(self) -> Shape {
-    fill_shape(self, &Location { src: String::from("<native>"), x: 0, y: 0, }).unwrap()
+    fill_shape(self, &Location::point("<native>", 0, 0)).unwrap()
   }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum BaseShapeKind {
   Boolean,
   Float,
   String,
   Unit,
-  List
+  List,
+  Map,
+  Tuple,
+  Option,
+  Result,
+}
+
+/**
+* Type names every module can use without declaring or importing them.
+*/
+pub const BUILTIN_TYPES: &'static [&'static str] = &["String", "Float", "Boolean", "Unit", "List", "Map", "Tuple", "Option", "Result"];
+
+pub fn builtin_shape(name: &str) -> Option<Shape> {
+  match name {
+    "String" => Some(shape_string()),
+    "Float" => Some(shape_float()),
+    "Boolean" => Some(shape_boolean()),
+    "Unit" => Some(shape_unit()),
+    "List" => Some(Shape::BaseShape { kind: BaseShapeKind::List }),
+    "Map" => Some(Shape::BaseShape { kind: BaseShapeKind::Map }),
+    "Tuple" => Some(Shape::BaseShape { kind: BaseShapeKind::Tuple }),
+    "Option" => Some(Shape::BaseShape { kind: BaseShapeKind::Option }),
+    "Result" => Some(Shape::BaseShape { kind: BaseShapeKind::Result }),
+    _ => None,
+  }
 }
 
 pub fn shape_named(name: String) -> Shape {
@@ -95,6 +238,22 @@ pub fn shape_list(arg: Shape) -> Shape {
   Shape::GenericShape {base: Box::new(Shape::BaseShape {kind: BaseShapeKind::List}), args: vec![arg]}
 }
 
+pub fn shape_map(key: Shape, value: Shape) -> Shape {
+  Shape::GenericShape {base: Box::new(Shape::BaseShape {kind: BaseShapeKind::Map}), args: vec![key, value]}
+}
+
+pub fn shape_tuple(args: Vec<Shape>) -> Shape {
+  Shape::GenericShape {base: Box::new(Shape::BaseShape {kind: BaseShapeKind::Tuple}), args}
+}
+
+pub fn shape_option(value: Shape) -> Shape {
+  Shape::GenericShape {base: Box::new(Shape::BaseShape {kind: BaseShapeKind::Option}), args: vec![value]}
+}
+
+pub fn shape_result(value: Shape, error: Shape) -> Shape {
+  Shape::GenericShape {base: Box::new(Shape::BaseShape {kind: BaseShapeKind::Result}), args: vec![value, error]}
+}
+
 pub fn shape_unknown() -> Shape {
   Shape::UnknownShape
 }
@@ -128,4 +287,8 @@ macro_rules! shape {
   (String) => (Shape::BaseShape { kind: BaseShapeKind::String });
   (Unit) => (Shape::BaseShape { kind: BaseShapeKind::Unit });
   (List) => (Shape::BaseShape { kind: BaseShapeKind::List });
+  (Map) => (Shape::BaseShape { kind: BaseShapeKind::Map });
+  (Tuple) => (Shape::BaseShape { kind: BaseShapeKind::Tuple });
+  (Option) => (Shape::BaseShape { kind: BaseShapeKind::Option });
+  (Result) => (Shape::BaseShape { kind: BaseShapeKind::Result });
 }
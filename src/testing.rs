@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use bytecode::{BitApplication, BitPackage, FunctionRef, deserialize_bit_module, serialize_bit_module};
+use compiler::compile_package;
+use error::{LetError, RuntimeError};
+use interpreter::{Machine, RunFunction};
+use optimize::Optimizer;
+use runtime::Value;
+use shapes::Shape;
+use typechecker::AppShapes;
+
+/**
+* Functions with this prefix and no arguments are run as tests.
+*/
+pub const TEST_PREFIX: &'static str = "test_";
+
+pub struct TestOutcome {
+  pub func: FunctionRef,
+  pub result: Result<(), RuntimeError>,
+}
+
+impl TestOutcome {
+
+  pub fn passed(&self) -> bool {
+    self.result.is_ok()
+  }
+}
+
+pub struct TestReport {
+  pub outcomes: Vec<TestOutcome>,
+}
+
+impl TestReport {
+
+  pub fn passed(&self) -> usize {
+    self.outcomes.iter().filter(|outcome| outcome.passed()).count()
+  }
+
+  pub fn failed(&self) -> usize {
+    self.outcomes.len() - self.passed()
+  }
+
+  /**
+  * One line per test followed by the totals.
+  */
+  pub fn report(&self) -> String {
+    let mut lines: Vec<String> = self.outcomes.iter()
+      .map(|outcome| match &outcome.result {
+        Ok(()) => format!("test {} ... ok", outcome.func.pretty()),
+        Err(err) => format!("test {} ... FAILED\n  {}", outcome.func.pretty(), err.to_string().replace("\n", "\n  ")),
+      })
+      .collect();
+
+    lines.push(format!("\n{} passed, {} failed", self.passed(), self.failed()));
+    lines.join("\n")
+  }
+}
+
+/**
+* Every test in the package, in name order so runs are repeatable.
+*/
+pub fn find_tests(package: &BitPackage) -> Vec<FunctionRef> {
+  let mut tests: Vec<FunctionRef> = package.modules.values()
+    .flat_map(|module| module.functions.values())
+    .filter_map(|func| match func {
+      RunFunction::BitFunction(func) => Some(&func.func_ref),
+      RunFunction::NativeFunction(_) => None,
+    })
+    .filter(|func_ref| func_ref.name.starts_with(TEST_PREFIX))
+    .filter(|func_ref| match &func_ref.shape {
+      Shape::SimpleFunctionShape { args, .. } => args.is_empty(),
+      _ => false,
+    })
+    .cloned()
+    .collect();
+
+  tests.sort_by(|left, right| left.pretty().cmp(&right.pretty()));
+  tests
+}
+
+/**
+* Runs each test in the package on a fresh Machine, so state can't leak from one test into the next. A test passes
+* unless it fails with an error, such as from Core::Assert, or returns false.
+*/
+pub fn run_tests(name: &str, package: &BitPackage) -> Result<TestReport, RuntimeError> {
+  // Compiled modules can't be cloned, so each Machine gets its own copy read back from bytes.
+  let saved = save_package(package)?;
+  let mut outcomes = Vec::new();
+
+  for func in find_tests(package) {
+    let machine = Machine::new(application(name, load_package(&saved)?));
+    let result = match machine.execute(func.clone(), vec![]) {
+      Ok(Value::False) => Err(RuntimeError::native("Test returned false")),
+      Ok(_) => Ok(()),
+      Err(err) => Err(err),
+    };
+
+    outcomes.push(TestOutcome { func, result });
+  }
+
+  Ok(TestReport { outcomes })
+}
+
+/**
+* Compiles every module under dir as a package named after the directory, then runs its tests.
+*/
+pub fn test_directory(dir: &str, optimizer: &Optimizer, shapes: &mut AppShapes) -> Result<TestReport, LetError> {
+  let name = Path::new(dir).file_name()
+    .and_then(|name| name.to_str())
+    .unwrap_or("test");
+
+  let package = compile_package(name, dir, optimizer, shapes)?;
+
+  Ok(run_tests(name, &package)?)
+}
+
+/**
+* Runs func as compiled, then again after writing every module out as bytecode and reading it back, and fails unless
+* both runs agree. Values don't have equality yet, so they're compared by their debug output.
+*/
+pub fn verify_roundtrip(name: &str, package: BitPackage, func: &FunctionRef, args: Vec<Value>) -> Result<Value, RuntimeError> {
+  let restored = load_package(&save_package(&package)?)?;
+
+  let original = Machine::new(application(name, package)).execute(func.clone(), args.clone());
+  let copied = Machine::new(application(name, restored));
+  copied.link()?;
+
+  let copied = copied.execute(func.clone(), args);
+
+  let (left, right) = match (&original, &copied) {
+    (Ok(left), Ok(right)) => (format!("{:?}", left), format!("{:?}", right)),
+    (Err(left), Err(right)) => (left.to_string(), right.to_string()),
+    (Ok(left), Err(right)) => (format!("{:?}", left), format!("Error: {}", right)),
+    (Err(left), Ok(right)) => (format!("Error: {}", left), format!("{:?}", right)),
+  };
+
+  if left != right {
+    return Err(RuntimeError::RoundTripMismatch { func: Box::new(func.clone()), original: left, restored: right });
+  }
+
+  original
+}
+
+fn application(name: &str, package: BitPackage) -> BitApplication {
+  let mut app = BitApplication::library();
+  app.packages.insert(String::from(name), package);
+  app
+}
+
+fn save_package(package: &BitPackage) -> Result<Vec<(String, Vec<u8>)>, RuntimeError> {
+  let mut saved = Vec::new();
+
+  for (module_name, module) in &package.modules {
+    let mut bytes = Vec::new();
+    serialize_bit_module(&mut bytes, module).map_err(|err| RuntimeError::Io { message: err.to_string() })?;
+    saved.push((module_name.clone(), bytes));
+  }
+
+  Ok(saved)
+}
+
+fn load_package(saved: &Vec<(String, Vec<u8>)>) -> Result<BitPackage, RuntimeError> {
+  let mut package = BitPackage::new();
+
+  for (module_name, bytes) in saved {
+    let module = deserialize_bit_module(&mut bytes.as_slice()).map_err(|err| RuntimeError::Io { message: err.to_string() })?;
+    package.modules.insert(module_name.clone(), module);
+  }
+
+  Ok(package)
+}
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use bytecode::{BitFunction, BitModule, FunctionRef, Instruction, LocalId};
+use error::RuntimeError;
+use interpreter::Machine;
+use runtime::Value;
+use shapes::Shape;
+
+/**
+* The state one op of threaded code works on.
+*/
+pub struct ThreadFrame<'a> {
+  pub machine: &'a Machine,
+  pub func: &'a FunctionRef,
+  pub stack: Vec<Value>,
+  pub locals: Vec<Value>,
+}
+
+/**
+* What to run after an op. Jump targets are absolute indexes into the ops.
+*/
+pub enum Control {
+  Next,
+  Jump(usize),
+  Return(Value),
+  TailCall(FunctionRef, Vec<Value>),
+}
+
+pub type Op = Box<Fn(&mut ThreadFrame) -> Result<Control, RuntimeError>>;
+
+/**
+* A BitFunction decoded ahead of time into one closure per instruction, with operands and constants already looked up.
+*/
+pub struct ThreadedFunction {
+  pub max_locals: LocalId,
+  pub max_stack: LocalId,
+  pub ops: Vec<Op>,
+}
+
+/**
+* Decoded functions, None for those with an instruction threaded code doesn't cover, which the match loop runs instead.
+*/
+pub type ThreadedCache = HashMap<FunctionRef, Option<Rc<ThreadedFunction>>>;
+
+/**
+* Decodes every instruction of func, or gives None if it uses one without an op here yet.
+*/
+pub fn decode(func: &BitFunction, module: &BitModule) -> Option<ThreadedFunction> {
+  let mut ops: Vec<Op> = Vec::with_capacity(func.body.len());
+
+  for (index, instruction) in func.body.iter().enumerate() {
+    let is_tail = match func.body.get(index + 1) {
+      Some(Instruction::Return) => true,
+      _ => false,
+    };
+
+    let op: Op = match *instruction {
+      Instruction::NoOp => Box::new(|_| Ok(Control::Next)),
+      Instruction::Duplicate => Box::new(|frame| {
+        let top = frame.stack.last().cloned().ok_or_else(|| empty(frame, "Duplicate"))?;
+        frame.stack.push(top);
+        Ok(Control::Next)
+      }),
+      Instruction::Pop => Box::new(|frame| {
+        pop(frame, "Pop")?;
+        Ok(Control::Next)
+      }),
+      Instruction::Swap => Box::new(|frame| {
+        let first = pop(frame, "Swap")?;
+        let second = pop(frame, "Swap")?;
+        frame.stack.push(first);
+        frame.stack.push(second);
+        Ok(Control::Next)
+      }),
+      Instruction::LoadConstUnit => push_const(Value::Unit),
+      Instruction::LoadConstTrue => push_const(Value::True),
+      Instruction::LoadConstFalse => push_const(Value::False),
+      Instruction::LoadConstFloat { value } => push_const(Value::Float(value)),
+      Instruction::LoadConstString { const_id } => push_const(Value::String(module.lookup_string(const_id).ok()?.clone())),
+      Instruction::LoadConstFunction { const_id } => push_const(Value::Function(Rc::new(module.lookup_function(const_id).ok()?))),
+      Instruction::LoadValue { local } => Box::new(move |frame| {
+        let value = frame.locals.get(local as usize).cloned()
+          .ok_or_else(|| RuntimeError::invalid_bytecode(frame.func, "LoadValue of local that doesn't exist"))?;
+        frame.stack.push(value);
+        Ok(Control::Next)
+      }),
+      Instruction::StoreValue { local } => Box::new(move |frame| {
+        let value = pop(frame, "StoreValue")?;
+        frame.locals[local as usize] = value;
+        Ok(Control::Next)
+      }),
+      Instruction::StoreConstFloat { value, local } => Box::new(move |frame| {
+        frame.locals[local as usize] = Value::Float(value);
+        Ok(Control::Next)
+      }),
+      Instruction::AddLocals { left, right } => Box::new(move |frame| {
+        match (&frame.locals[left as usize], &frame.locals[right as usize]) {
+          (Value::Float(left), Value::Float(right)) => {
+            let sum = left + right;
+            frame.stack.push(Value::Float(sum));
+            Ok(Control::Next)
+          }
+          _ => Err(RuntimeError::invalid_bytecode(frame.func, "AddLocals needs two float locals")),
+        }
+      }),
+      Instruction::AddFloat => float_op(|left, right| Value::Float(left + right)),
+      Instruction::SubFloat => float_op(|left, right| Value::Float(left - right)),
+      Instruction::MulFloat => float_op(|left, right| Value::Float(left * right)),
+      Instruction::DivFloat => float_op(|left, right| Value::Float(left / right)),
+      Instruction::LessFloat => float_op(|left, right| boolean(left < right)),
+      Instruction::LessEqualFloat => float_op(|left, right| boolean(left <= right)),
+      Instruction::GreaterFloat => float_op(|left, right| boolean(left > right)),
+      Instruction::GreaterEqualFloat => float_op(|left, right| boolean(left >= right)),
+      Instruction::CallStatic { func_id } => {
+        let callee = module.lookup_function(func_id).ok()?;
+
+        let count = match &callee.shape {
+          Shape::SimpleFunctionShape { args, .. } => args.len(),
+          _ => return None,
+        };
+
+        Box::new(move |frame| {
+          let first = frame.stack.len().checked_sub(count)
+            .ok_or_else(|| RuntimeError::invalid_bytecode(frame.func, "Not enough args for function"))?;
+          let params = frame.stack.split_off(first);
+
+          if is_tail {
+            return Ok(Control::TailCall(callee.clone(), params));
+          }
+
+          let result = frame.machine.execute(callee.clone(), params)?;
+          frame.stack.push(result);
+          Ok(Control::Next)
+        })
+      }
+      Instruction::Branch { jump } => {
+        let target = target(index, jump);
+
+        Box::new(move |frame| match pop(frame, "Branch")? {
+          Value::True => Ok(Control::Next),
+          Value::False => Ok(Control::Jump(target)),
+          _ => Err(RuntimeError::invalid_bytecode(frame.func, "Attempt to Branch on non boolean")),
+        })
+      }
+      Instruction::Jump { jump } => {
+        let target = target(index, jump);
+
+        Box::new(move |_| Ok(Control::Jump(target)))
+      }
+      Instruction::Return => Box::new(|frame| Ok(Control::Return(pop(frame, "Return")?))),
+      _ => return None,
+    };
+
+    ops.push(op);
+  }
+
+  Some(ThreadedFunction {
+    max_locals: func.max_locals,
+    max_stack: func.max_stack,
+    ops,
+  })
+}
+
+fn push_const(value: Value) -> Op {
+  Box::new(move |frame| {
+    frame.stack.push(value.clone());
+    Ok(Control::Next)
+  })
+}
+
+fn float_op<F: Fn(f64, f64) -> Value + 'static>(op: F) -> Op {
+  Box::new(move |frame| {
+    let right = pop(frame, "float operation")?;
+    let left = pop(frame, "float operation")?;
+
+    match (left, right) {
+      (Value::Float(left), Value::Float(right)) => {
+        frame.stack.push(op(left, right));
+        Ok(Control::Next)
+      }
+      _ => Err(RuntimeError::invalid_bytecode(frame.func, "Float operation on non floats")),
+    }
+  })
+}
+
+fn boolean(value: bool) -> Value {
+  if value { Value::True } else { Value::False }
+}
+
+fn target(index: usize, jump: i32) -> usize {
+  (index as i64 + 1 + jump as i64) as usize
+}
+
+fn pop(frame: &mut ThreadFrame, name: &str) -> Result<Value, RuntimeError> {
+  frame.stack.pop().ok_or_else(|| empty(frame, name))
+}
+
+fn empty(frame: &ThreadFrame, name: &str) -> RuntimeError {
+  RuntimeError::invalid_bytecode(frame.func, &format!("Attempt to {} empty stack", name))
+}
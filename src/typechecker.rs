@@ -1,75 +1,357 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 
-use simple_error::*;
+use error::TypeError;
 
 use ast::*;
 use shapes::*;
+use bytecode::BitApplication;
 use ir::IrModule;
 
-pub fn check_module(module: AstModule) -> Result<AstModule, SimpleError> {
-  let mut app = AppShapes::new();
+/**
+* Checks every function in the module, returning all of the type errors found rather than stopping at the first.
+* Warnings from a module that checks are kept in app for the caller to take, see AppShapes::take_warnings.
+*/
+pub fn check_module(module: AstModule, app: &AppShapes) -> Result<AstModule, Vec<TypeError>> {
+  let analysis = analyze_module(module, app);
+
+  if analysis.result.is_ok() {
+    app.warnings.borrow_mut().extend(analysis.warnings);
+  }
+
+  analysis.result
+}
+
+/**
+* What check_module finds, along with where each variable it resolved was declared, for tools like the language
+* server.
+*/
+pub struct Analysis {
+  pub result: Result<AstModule, Vec<TypeError>>,
+  /** Each use of a local or a function in this module, and the declaration it refers to. */
+  pub definitions: Vec<(Location, Location)>,
+  /** Problems that don't stop the module compiling, like shadowing when it's allowed or discarded values. */
+  pub warnings: Vec<TypeError>,
+}
+
+pub fn analyze_module(module: AstModule, app: &AppShapes) -> Analysis {
   let mut imports = module.imports.clone();
   let mut functions = Vec::new();
 
   let mut scope = Scope::new();
-  scope.create_function_scope();
+  scope.shadowing = app.shadowing;
+  scope.create_function_scope(shape_unknown());
 
   for imp in &imports {
-    let module_name = &imp.module.clone();
-    let module = app.lookup_module(&imp.package, &imp.module)
-      .ok_or_else(|| SimpleError::new("No such module"))?;
+    if let Err(err) = scope.import_module(app, imp, &module.package, &module.name) {
+      scope.report(err);
+    }
+  }
 
-    for func in module.list_values() {
-      let shape = module.lookup(&func).expect("Invalid impl");
-      scope.pre_fill_module_function( format!("{}.{}", module_name, func), shape, &imp.loc);
+  for alias in &module.aliases {
+    if let Err(err) = scope.declare_type(&module.name, alias) {
+      scope.report(err);
     }
   }
 
-  for dec in &module.functions {
-    scope.pre_fill_module_function(dec.ex.id.clone(), dec.ex.shape(), &dec.ex.loc)?;
+  for data in &module.datas {
+    if let Err(err) = scope.declare_data(&module.name, data) {
+      scope.report(err);
+    }
   }
 
-  for dec in module.functions {
-    if let Expression::FunctionDeclaration(content) = dec.ex.check(&mut scope, shape_unknown())? {
-      functions.push(AstFunctionDeclaration {visibility: dec.visibility, ex: *content});
-    } else {
-      return Err(SimpleError::new("FunctionDeclaration didn't return itself!"))
+  // Expand every alias once up front so cycles and unknown types are reported at the declaration.
+  let mut aliases = Vec::with_capacity(module.aliases.len());
+
+  for alias in module.aliases {
+    let shape = match scope.fill_shape(shape_named(alias.id.clone()), &alias.loc) {
+      Ok(shape) => shape,
+      Err(err) => {
+        scope.report(err);
+        shape_unknown()
+      }
+    };
+
+    aliases.push(TypeAliasEx { shape, ..alias });
+  }
+
+  // Each data type brings the function that builds it, checked along with the rest.
+  let mut datas = Vec::with_capacity(module.datas.len());
+  let mut constructors = Vec::with_capacity(module.datas.len());
+
+  for data in module.datas {
+    match scope.data_constructor(&module.name, &data) {
+      Ok(constructor) => constructors.push(constructor),
+      Err(err) => scope.report(err),
+    }
+
+    let fields = match scope.declare_fields(&module.name, &data) {
+      Ok(fields) => fields,
+      Err(err) => {
+        scope.report(err);
+        data.fields.clone()
+      }
+    };
+
+    let shape = data_shape(&module.name, &data);
+    datas.push(DataEx { shape, fields, ..data });
+  }
+
+  let constructor_ids: Vec<String> = constructors.iter().map(|dec| dec.ex.id.clone()).collect();
+
+  // Operators are stored under a name that includes their operand shapes, so one module can overload each for many types.
+  let declarations: Vec<AstFunctionDeclaration> = constructors.into_iter()
+    .chain(module.functions)
+    .map(|dec| scope.declare_operator(dec))
+    .collect();
+
+  for dec in &declarations {
+    // A bad signature is reported when the function itself is checked, callers just see an unknown shape.
+    let shape = scope.declared_shape(&dec.ex);
+
+    if scope.pre_fill_module_function(dec.ex.id.clone(), shape, &dec.ex.loc).is_err() {
+      scope.static_scope.insert(dec.ex.id.clone(), shape_unknown());
+    }
+
+    scope.declared_at.insert(dec.ex.id.clone(), dec.ex.loc.clone());
+
+    let params = dec.ex.args.iter().map(|arg| arg.id.clone()).collect();
+    scope.signatures.insert(dec.ex.id.clone(), Signature { params, defaults: dec.ex.defaults.clone() });
+  }
+
+  // Functions that leave shapes out go first, so everything after sees them generalized.
+  let mut order: Vec<usize> = (0..declarations.len()).collect();
+  order.sort_by_key(|index| free_variables(&scope.static_scope[&declarations[*index].ex.id]).is_empty());
+
+  let mut declarations: Vec<Option<AstFunctionDeclaration>> = declarations.into_iter().map(Some).collect();
+  let mut checked = Vec::with_capacity(order.len());
+
+  for (position, index) in order.iter().enumerate() {
+    let dec = declarations[*index].take().expect("Each function is checked once");
+    let id = dec.ex.id.clone();
+    let declared = scope.static_scope[&id].clone();
+
+    match dec.ex.check(&mut scope, declared) {
+      Ok(Expression::FunctionDeclaration(content)) => checked.push((*index, AstFunctionDeclaration {visibility: dec.visibility, ex: *content})),
+      Ok(_) => scope.report(TypeError::Internal { message: String::from("FunctionDeclaration didn't return itself!") }),
+      Err(err) => scope.report(err),
+    }
+
+    // Variables still used by a function not checked yet could be pinned down by it, so only the rest generalize.
+    let pending: Vec<usize> = order[position + 1..].iter()
+      .flat_map(|later| free_variables(&scope.resolve(&scope.static_scope[&declarations[*later].as_ref().expect("Not checked yet").ex.id])))
+      .collect();
+
+    let shape = scope.resolve(&scope.static_scope[&id]);
+    let quantified = free_variables(&shape).into_iter().filter(|var| !pending.contains(var)).collect();
+
+    scope.quantified.insert(id, quantified);
+  }
+
+  checked.sort_by_key(|(index, _)| *index);
+
+  // A data type's parameter that no field uses is only ever decided by an annotation, which is fine.
+  for (_, dec) in checked.iter().filter(|(_, dec)| !constructor_ids.contains(&dec.ex.id)) {
+    if let Shape::SimpleFunctionShape { args, result } = scope.resolve(&scope.static_scope[&dec.ex.id]) {
+      if is_ambiguous(&args, &result) {
+        scope.report(TypeError::AmbiguousResult { loc: Box::new(dec.ex.loc.clone()), id: dec.ex.id.clone() });
+      }
+    }
+  }
+
+  for (_, mut dec) in checked {
+    settle_function(&scope, &mut dec.ex);
+    functions.push(dec);
+  }
+
+  if !scope.errors.is_empty() {
+    return Analysis { result: Err(scope.errors), definitions: scope.definitions, warnings: scope.warnings };
+  }
+
+  Analysis {
+    result: Ok(AstModule { package: module.package, name: module.name, functions, imports, aliases, datas }),
+    definitions: scope.definitions,
+    warnings: scope.warnings,
+  }
+}
+
+/**
+* Typechecks repl input one statement at a time.
+*
+* Each statement becomes the body of a throwaway function whose parameters are every binding made so far, so
+* earlier results are passed back in as arguments rather than living in some global state.
+**/
+pub struct ReplScope {
+  app: AppShapes,
+  imports: Vec<ImportEx>,
+  bindings: Vec<Parameter>,
+}
+
+impl ReplScope {
+
+  pub fn new() -> ReplScope {
+    ReplScope::with_shapes(AppShapes::new())
+  }
+
+  pub fn with_shapes(app: AppShapes) -> ReplScope {
+    ReplScope {
+      app,
+      imports: Vec::new(),
+      bindings: Vec::new(),
     }
   }
 
-  Ok(AstModule { package: module.package, name: module.name, functions, imports })
+  pub fn shapes(&self) -> &AppShapes {
+    &self.app
+  }
+
+  pub fn imports(&self) -> &Vec<ImportEx> {
+    &self.imports
+  }
+
+  pub fn bindings(&self) -> &Vec<Parameter> {
+    &self.bindings
+  }
+
+  pub fn import(&mut self, imp: ImportEx) -> Result<(), TypeError> {
+    self.app.lookup_module(&imp.package, &imp.module)
+      .ok_or_else(|| TypeError::NoSuchModule { loc: Box::new(imp.loc.clone()), package: imp.package.clone(), module: imp.module.clone() })?;
+
+    self.imports.push(imp);
+    Ok(())
+  }
+
+  /**
+  * Redefining a name replaces the old binding, so a repl user can freely redo a let.
+  **/
+  pub fn bind(&mut self, binding: Parameter) {
+    self.bindings.retain(|prev| prev.id != binding.id);
+    self.bindings.push(binding);
+  }
+
+  pub fn check_statement(&self, id: String, ex: Expression) -> Result<FunctionDeclarationEx, Vec<TypeError>> {
+    let loc = ex.loc().clone();
+
+    // Every earlier binding is a parameter here, a let redoing one would always shadow it.
+    let mut scope = Scope::new();
+    scope.shadowing = Shadowing::Allow;
+    scope.create_function_scope(shape_unknown());
+
+    for imp in &self.imports {
+      // The repl is never part of a package it imports from, so it only sees public functions.
+      scope.import_module(&self.app, imp, "", "").map_err(|err| vec![err])?;
+    }
+
+    let func = FunctionDeclarationEx {
+      result: shape_unknown(),
+      loc,
+      id,
+      args: self.bindings.clone(),
+      defaults: self.bindings.iter().map(|_| None).collect(),
+      body: ex,
+      context: FunctionContext::new(false, false),
+    };
+
+    match func.check(&mut scope, shape_unknown()) {
+      Err(err) => {
+        scope.report(err);
+        Err(scope.errors)
+      }
+      Ok(_) if !scope.errors.is_empty() => Err(scope.errors),
+      Ok(Expression::FunctionDeclaration(mut content)) => {
+        settle_function(&scope, &mut content);
+        Ok(*content)
+      }
+      Ok(_) => Err(vec![TypeError::Internal { message: String::from("FunctionDeclaration didn't return itself!") }]),
+    }
+  }
 }
 
+/**
+* An Err from check only abandons the expression being checked. The check function below records it on the scope and
+* carries on with an UnknownShape in its place, so one mistake doesn't hide the rest of the errors in a module.
+*/
 trait Typed {
 
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError>;
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError>;
 
 }
 
 impl Typed for FunctionDeclarationEx {
 
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError> {
-    let args = verify_function_declaration(self.args.clone(), expected, &self.loc)?;
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let first = scope.variables;
+
+    // A lambda takes any parameter or result shape it leaves out from where it's passed or assigned.
+    let expected = scope.fill_shape(expected, &self.loc)?;
+    let args = verify_function_declaration(scope, self.args.clone(), expected.clone(), &self.loc)?;
+
+    let declared = match (&self.result, expected) {
+      (Shape::UnknownShape, Shape::SimpleFunctionShape { result, .. }) => *result,
+      (declared, _) => declared.clone(),
+    };
+
+    // Recursive calls need something to agree on for the result before the body says what it is.
+    let result_shape = if declared == Shape::UnknownShape { scope.fresh() } else { scope.fill_shape(declared.clone(), &self.loc)? };
+    let is_named_local = self.context.is_local && !self.context.is_lambda;
 
-    if !self.context.is_lambda {
-      scope.set_scope(&self.id, &fill_shape(self.shape(), &self.loc)?, &self.loc)?;
+    // Module functions are already in static scope. Binding them locally too would make later functions capture them.
+    let is_forward = is_named_local && scope.take_forward(&self.id);
+
+    if is_named_local {
+      let shape = Shape::SimpleFunctionShape { args: args.iter().map(|arg| arg.shape.clone()).collect(), result: Box::new(result_shape.clone()) };
+
+      if is_forward {
+        let declared = scope.check_scope(&self.id, &self.loc)?;
+        verify(scope, declared, shape, &self.loc)?;
+      } else {
+        scope.set_scope(&self.id, &shape, &self.loc)?;
+      }
     }
 
-    scope.create_function_scope();
+    scope.create_function_scope(result_shape.clone());
 
     for Parameter{id, shape} in &args {
-      scope.set_scope(id, shape, &self.loc)?;
+      if let Err(err) = scope.set_scope(id, shape, &self.loc) {
+        scope.report(err);
+      }
     }
 
-    let id = self.id.clone();
-    let body = check(scope, self.body, self.result.clone())?;
+    for (arg, default) in args.iter().zip(&self.defaults) {
+      if let Some(default) = default {
+        if let Err(err) = verify(scope, arg.shape.clone(), default.shape(), &self.loc) {
+          scope.report(err);
+        }
+      }
+    }
 
-    let returned_shape = body.shape();
+    let id = self.id.clone();
+    let body = check(scope, self.body, declared.clone())?;
 
-    let result = verify(self.result, returned_shape, &self.loc)?;
+    // A Unit function is a procedure, whatever its body ends with is thrown away.
+    let returned_shape = if scope.resolve(&result_shape) == shape_unit() {
+      warn_if_discarded(scope, final_statement(&body));
+      shape_unit()
+    } else {
+      body.shape()
+    };
 
     let closures = scope.destroy_function_scope();
 
+    let result = verify(scope, result_shape, returned_shape, &self.loc)?;
+    let result = scope.resolve(&result);
+    let args: Vec<Parameter> = args.into_iter().map(|Parameter{id, shape}| Parameter{id, shape: scope.resolve(&shape)}).collect();
+
+    // Module functions are only known once every one of them has been checked, check_module looks at those.
+    if is_named_local && self.result == Shape::UnknownShape && is_ambiguous(&args.iter().map(|arg| arg.shape.clone()).collect::<Vec<Shape>>(), &result) {
+      return Err(TypeError::AmbiguousResult { loc: Box::new(self.loc), id });
+    }
+
+    // A group is generalized as a whole or not at all, and there's no whole group to look at here.
+    if is_named_local && !is_forward {
+      scope.generalize(&id, first);
+    }
+
     let before_size = closures.len();
     let maybe_me: Vec<Parameter> = closures.into_iter().filter(|param| param.id != id).collect();
 
@@ -78,16 +360,16 @@ impl Typed for FunctionDeclarationEx {
         .set_closures(maybe_me)
     } else {
       self.context.set_closures(maybe_me)
-    };
+    }.set_is_forward(is_forward);
 
-    Ok(FunctionDeclarationEx{result, body, id, args, loc: self.loc, context}.wrap())
+    Ok(FunctionDeclarationEx{result, body, id, args, defaults: self.defaults, loc: self.loc, context}.wrap())
   }
 
 }
 
 impl Typed for BlockEx {
 
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError> {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
     let BlockEx{shape: raw_shape, loc, body: raw_body} = self;
     let mut body = Vec::with_capacity(raw_body.len());
 
@@ -96,6 +378,15 @@ impl Typed for BlockEx {
     } else {
       scope.create_block_scope();
 
+      // Functions that call each other are all declared up front, or the first of them couldn't see the rest.
+      for group in recursive_groups(&raw_body) {
+        for func in &group {
+          let shape = scope.declared_shape(func);
+          let shape = scope.fill_shape(shape, &func.loc)?;
+          scope.declare(&func.id, Binding { shape, mutable: false, loc: func.loc.clone(), quantified: Vec::new(), forward: true }, &func.loc)?;
+        }
+      }
+
       let mut index = 0usize;
       let max = raw_body.len();
       for next in raw_body {
@@ -106,7 +397,13 @@ impl Typed for BlockEx {
           shape_unknown()
         };
 
-        body.push(check(scope, next, expect)?);
+        let checked = check(scope, next, expect)?;
+
+        if index < max {
+          warn_if_discarded(scope, &checked);
+        }
+
+        body.push(checked);
       }
       let shape = body.last().expect("This shouldn't be possible!").shape();
 
@@ -117,91 +414,374 @@ impl Typed for BlockEx {
   }
 }
 
+/**
+* Warns about a statement that isn't the last in its block but still has a value, like a call made for nothing.
+* Declarations never count, and neither does anything whose shape isn't known yet.
+*/
+fn warn_if_discarded(scope: &mut Scope, statement: &Expression) {
+  match statement {
+    Expression::Assignment(_) | Expression::Reassignment(_) | Expression::FunctionDeclaration(_) | Expression::Import(_) | Expression::NoOp(_) => return,
+    _ => {}
+  }
+
+  match scope.resolve(&statement.shape()) {
+    Shape::UnknownShape | Shape::VariableShape { .. } => {}
+    shape if shape == shape_unit() => {}
+    shape => scope.warnings.push(TypeError::DiscardedValue { loc: Box::new(statement.loc().clone()), shape }),
+  }
+}
+
+/**
+* The statement whose value a block ends up with, looking through blocks nested at the end of blocks.
+*/
+fn final_statement(ex: &Expression) -> &Expression {
+  match ex {
+    Expression::Block(block) => block.body.last().map(final_statement).unwrap_or(ex),
+    _ => ex,
+  }
+}
+
 impl Typed for AssignmentEx {
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError> {
-    let AssignmentEx{shape: raw_shape, id, loc, body: raw_body} = self;
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let AssignmentEx{shape: raw_shape, pattern, loc, body: raw_body, mutable} = self;
+    let first = scope.variables;
     let body = check(scope, raw_body, raw_shape.clone())?;
-    let shape = verify(raw_shape, body.shape(), &loc)?;
 
-    scope.set_scope(&id, &shape, &loc)?;
+    // Still bind the names on a mismatch so later uses of them don't report errors of their own.
+    let shape = match verify(scope, raw_shape, body.shape(), &loc) {
+      Ok(shape) => shape,
+      Err(err) => {
+        scope.report(err);
+        shape_unknown()
+      }
+    };
+
+    bind_pattern(scope, &pattern, &shape, mutable, &loc)?;
+
+    // Only a lambda is generalized, any other value is one thing with one shape.
+    if let (Pattern::Id(id), Expression::FunctionDeclaration(_), false) = (&pattern, &body, mutable) {
+      scope.generalize(id, first);
+    }
+
+    Ok(AssignmentEx{shape, pattern, loc, body, mutable}.wrap())
+  }
+}
+
+impl Typed for ReassignmentEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let ReassignmentEx{loc, id, body: raw_body} = self;
+    let declared = scope.check_mutable(&id, &loc)?;
+    let body = check(scope, raw_body, declared.clone())?;
+
+    verify(scope, declared, body.shape(), &loc)?;
+
+    Ok(ReassignmentEx{loc, id, body}.wrap())
+  }
+}
+
+fn bind_pattern(scope: &mut Scope, pattern: &Pattern, shape: &Shape, mutable: bool, loc: &Location) -> Result<(), TypeError> {
+  match pattern {
+    Pattern::Id(id) => scope.declare_let(id, Binding { shape: shape.clone(), mutable, loc: loc.clone(), quantified: Vec::new(), forward: false }, loc),
+    Pattern::Tuple(items) if *shape == Shape::UnknownShape => {
+      for item in items {
+        bind_pattern(scope, item, shape, mutable, loc)?;
+      }
+
+      Ok(())
+    }
+    Pattern::Tuple(items) => {
+      let shape = &scope.fill_shape(shape.clone(), loc)?;
+      let args = shape.tuple_args()
+        .ok_or_else(|| TypeError::NotATuple { loc: Box::new(loc.clone()), shape: shape.clone() })?;
+
+      if args.len() != items.len() {
+        return Err(TypeError::PatternArity { loc: Box::new(loc.clone()), shape: shape.clone(), expected: items.len(), found: args.len() });
+      }
+
+      for (item, arg) in items.iter().zip(args) {
+        bind_pattern(scope, item, arg, mutable, loc)?;
+      }
+
+      Ok(())
+    }
+  }
+}
+
+impl Typed for TupleEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let TupleEx{shape: raw_shape, loc, items: raw_items} = self;
+    let expected_args = scope.fill_shape(expected, &loc)?.tuple_args().cloned()
+      .filter(|args| args.len() == raw_items.len())
+      .unwrap_or_else(|| vec![shape_unknown(); raw_items.len()]);
+
+    let mut items = Vec::with_capacity(raw_items.len());
+
+    for (raw_item, expect) in raw_items.into_iter().zip(expected_args) {
+      items.push(check(scope, raw_item, expect)?);
+    }
 
-    Ok(AssignmentEx{shape, id, loc, body}.wrap())
+    let shape = shape_tuple(items.iter().map(|item| item.shape()).collect());
+
+    Ok(TupleEx{shape, loc, items}.wrap())
   }
 }
 
 const FLOAT_OPS: &'static [&'static str] = &["+", "-", "*", "/"];
 const COMPARE_OPS: &'static [&'static str] = &["==", "!=", "<", ">", "<=", ">="];
+const EQUAL_OPS: &'static [&'static str] = &["==", "!="];
+const RANGE_OP: &'static str = "..";
 
 impl Typed for BinaryOpEx {
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError> {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
     let BinaryOpEx{shape: raw_shape, left: raw_left, right: raw_right, op, loc} = self;
 
     let result_shape = if FLOAT_OPS.contains(&op.as_str()) {
       shape_float()
+    } else if op == RANGE_OP {
+      shape_list(shape_float())
     } else {
       shape_boolean()
     };
 
-    let left = check(scope, raw_left, shape_float())?;
-    let right = check(scope, raw_right, shape_float())?;
+    // Equality works on values of any shape, everything else is only for Floats.
+    let operand_shape = if EQUAL_OPS.contains(&op.as_str()) {
+      shape_unknown()
+    } else {
+      shape_float()
+    };
+
+    // With an overload in scope the operands can't be assumed to be Floats until their shapes are known.
+    let overloaded = scope.is_overloaded(&op);
+    let operand_expected = if overloaded { shape_unknown() } else { operand_shape.clone() };
+
+    let left = check(scope, raw_left, operand_expected.clone())?;
+    let right = check(scope, raw_right, operand_expected)?;
+
+    if overloaded {
+      if let Some(id) = scope.operators.get(&operator_id(&op, &scope.resolve(&left.shape()), &scope.resolve(&right.shape()))).cloned() {
+        return call_operator(scope, id, left, right, loc);
+      }
+    }
 
-    if left.shape() == right.shape() {
+    if scope.unify(&left.shape(), &operand_shape).is_some() && scope.unify(&left.shape(), &right.shape()).is_some() {
       Ok(BinaryOpEx{shape: result_shape, left, right, op, loc}.wrap())
     } else {
-      Err(SimpleError::new(format!("Incompatible types! Cannot perform operation '{}' on distinct types '{}' and '{}' {}", op, left.shape().pretty(), right.shape().pretty(), loc.pretty())))
+      Err(TypeError::InvalidOperands { loc: Box::new(loc), op, left: scope.resolve(&left.shape()), right: scope.resolve(&right.shape()) })
     }
   }
 }
 
+/**
+* The name an operator function is stored under, so each pair of operand shapes gets its own.
+*/
+fn operator_id(op: &str, left: &Shape, right: &Shape) -> String {
+  format!("{}({}, {})", op, left.pretty(), right.pretty())
+}
+
+fn is_operator(id: &str) -> bool {
+  id.starts_with(|ch: char| "+-*/<>=!".contains(ch))
+}
+
+fn is_primitive(shape: &Shape) -> bool {
+  match shape {
+    Shape::BaseShape { .. } => true,
+    _ => false,
+  }
+}
+
+/**
+* Replaces a binary operation with a call to the function overloading it.
+*/
+fn call_operator(scope: &mut Scope, id: String, left: Expression, right: Expression, loc: Location) -> Result<Expression, TypeError> {
+  let func = check(scope, Expression::Variable(Box::new(VariableEx { shape: shape_unknown(), loc: loc.clone(), id })), shape_unknown())?;
+
+  let shape = match func.shape() {
+    Shape::SimpleFunctionShape { result, .. } => *result,
+    other => return Err(TypeError::NotAFunction { loc: Box::new(loc), func: callee(&func), shape: other }),
+  };
+
+  Ok(CallEx { shape, loc, func, args: vec![left, right], named: Vec::new(), piped: false }.wrap())
+}
+
 impl Typed for CallEx {
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError> {
-    let CallEx{shape: raw_shape, loc, func: raw_func, args: raw_args} = self;
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let CallEx{shape: raw_shape, loc, func: raw_func, args: raw_args, named, piped} = self;
+    let (raw_func, raw_args) = resolve_method(scope, raw_func, raw_args)?;
+    let raw_args = resolve_arguments(scope, &raw_func, raw_args, named, &loc)?;
     let func = check(scope, raw_func, shape_unknown())?;
 
-    if let Shape::SimpleFunctionShape {args: expected_args, result} = func.shape() {
+    // Calling something whose shape isn't known yet says it's a function taking this many arguments.
+    let func_shape = match scope.resolve(&func.shape()) {
+      variable @ Shape::VariableShape { .. } => {
+        let args = raw_args.iter().map(|_| scope.fresh()).collect();
+        let called = Shape::SimpleFunctionShape { args, result: Box::new(scope.fresh()) };
+        scope.unify(&variable, &called);
+        called
+      }
+      other => other,
+    };
+
+    if let Shape::SimpleFunctionShape {args: expected_args, result} = func_shape {
       if raw_args.len() != expected_args.len() {
-        return loc.fail("Incorrect number of arguments")?;
+        return Err(TypeError::ArgumentCount { loc: Box::new(loc), func: callee(&func), shape: scope.resolve(&func.shape()), expected: expected_args.len(), found: raw_args.len() });
       }
 
       let mut args = Vec::new();
 
-      for (expect, raw_arg) in expected_args.iter().zip(raw_args) {
+      for (index, (expect, raw_arg)) in expected_args.iter().zip(raw_args).enumerate() {
         let arg = check(scope, raw_arg, expect.clone())?;
 
-        if arg.shape() != *expect {
-          return loc.fail("Invalid argument types for call")?;
+        if scope.assign(expect, &arg.shape()).is_none() {
+          return Err(TypeError::ArgumentMismatch { loc: Box::new(loc), func: callee(&func), index, expected: scope.resolve(expect), found: scope.resolve(&arg.shape()) });
         }
 
         args.push(arg);
       }
 
+      // A map's key is often only decided by the arguments, so this is the first point it can be checked.
+      if let Some(key) = unhashable_key(&scope.resolve(&Shape::SimpleFunctionShape { args: expected_args, result: result.clone() })) {
+        return Err(TypeError::NotHashable { loc: Box::new(loc), shape: key });
+      }
+
       Ok(CallEx {
         shape: *result,
         loc,
         func,
-        args
+        args,
+        named: Vec::new(),
+        piped,
       }.wrap())
+    } else if func.shape() == Shape::UnknownShape {
+      let mut args = Vec::new();
+
+      for raw_arg in raw_args {
+        args.push(check(scope, raw_arg, shape_unknown())?);
+      }
+
+      Ok(CallEx { shape: shape_unknown(), loc, func, args, named: Vec::new(), piped }.wrap())
     } else {
-      return loc.fail("Attempt to call non-function");
+      return Err(TypeError::NotAFunction { loc: Box::new(loc), func: callee(&func), shape: func.shape() });
+    }
+  }
+}
+
+/**
+* What a call site's errors call the function it calls, the name it's called by or just a function when it has none.
+*/
+fn callee(func: &Expression) -> String {
+  match func {
+    Expression::Variable(var) => var.id.clone(),
+    _ => String::from("a function"),
+  }
+}
+
+/**
+* Turns `value.name(args)` into `Module.name(value, args)`, where Module owns value's shape, so List functions can be
+* called on any list. Only applies when value is a variable and the dotted name isn't already something in scope.
+*/
+fn resolve_method(scope: &mut Scope, func: Expression, mut args: Vec<Expression>) -> Result<(Expression, Vec<Expression>), TypeError> {
+  let (receiver, name, loc) = match &func {
+    Expression::Variable(var) if !scope.is_local(&var.id) && !scope.static_scope.contains_key(&var.id) => match var.id.find('.') {
+      Some(dot) => (var.id[..dot].to_string(), var.id[dot + 1..].to_string(), var.loc.clone()),
+      None => return Ok((func, args)),
+    },
+    _ => return Ok((func, args)),
+  };
+
+  if !scope.is_local(&receiver) && !scope.static_scope.contains_key(&receiver) {
+    return Ok((func, args));
+  }
+
+  let found = scope.check_scope(&receiver, &loc)?;
+  let shape = scope.fill_shape(found, &loc)?;
+
+  let id = shape.owner_module()
+    .map(|module| format!("{}.{}", module, name))
+    .filter(|id| scope.static_scope.contains_key(id))
+    .ok_or_else(|| TypeError::NoSuchMethod { loc: Box::new(loc.clone()), shape: shape.clone(), name })?;
+
+  args.insert(0, VariableEx { shape: shape_unknown(), loc: loc.clone(), id: receiver }.wrap());
+
+  Ok((VariableEx { shape: shape_unknown(), loc, id }.wrap(), args))
+}
+
+/**
+* Puts named arguments in their parameter's place and fills in defaults for any left out, so the call that gets
+* checked is positional. Named arguments are evaluated in parameter order, not the order they were written in.
+*
+* Only module functions called by name in their own module have a Signature, anything else must be called positionally.
+*/
+fn resolve_arguments(scope: &Scope, func: &Expression, mut args: Vec<Expression>, named: Vec<(String, Expression)>, loc: &Location) -> Result<Vec<Expression>, TypeError> {
+  let signature = match func {
+    Expression::Variable(var) if !scope.is_local(&var.id) => scope.signatures.get(&var.id),
+    _ => None,
+  };
+
+  let signature = match signature {
+    Some(signature) if args.len() < signature.params.len() || !named.is_empty() => signature,
+    _ if named.is_empty() => return Ok(args),
+    _ => return Err(TypeError::NamedArguments { loc: Box::new(loc.clone()), func: callee(func) }),
+  };
+
+  if args.len() > signature.params.len() {
+    let shape = scope.static_scope.get(&callee(func)).map(|shape| scope.resolve(shape)).unwrap_or(Shape::UnknownShape);
+    return Err(TypeError::ArgumentCount { loc: Box::new(loc.clone()), func: callee(func), shape, expected: signature.params.len(), found: args.len() + named.len() });
+  }
+
+  let mut slots: Vec<Option<Expression>> = args.drain(..).map(Some).collect();
+  slots.resize_with(signature.params.len(), || None);
+
+  for (id, value) in named {
+    let index = signature.params.iter().position(|param| param == &id)
+      .ok_or_else(|| TypeError::UnknownArgument { loc: Box::new(loc.clone()), func: callee(func), id: id.clone() })?;
+
+    if slots[index].is_some() {
+      return Err(TypeError::DuplicateArgument { loc: Box::new(loc.clone()), func: callee(func), id });
     }
+
+    slots[index] = Some(value);
   }
+
+  slots.into_iter().enumerate()
+    .map(|(index, slot)| match (slot, &signature.defaults[index]) {
+      (Some(value), _) => Ok(value),
+      (None, Some(default)) => Ok(default.to_expression(loc)),
+      (None, None) => Err(TypeError::MissingArgument { loc: Box::new(loc.clone()), func: callee(func), id: signature.params[index].clone() }),
+    })
+    .collect()
 }
 
 impl Typed for IfEx {
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError> {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
     let IfEx{shape: raw_shape, loc, condition: raw_condition, then_block: raw_then_block, else_block: raw_else_block} = self;
 
     let condition = check(scope, raw_condition, shape_boolean())?;
 
-    verify(shape_boolean(), condition.shape(), &loc)?;
+    verify(scope, shape_boolean(), condition.shape(), &loc)?;
 
     let then_block = check(scope, raw_then_block, shape_unknown())?;
+
+    // Without an else there's no value when the condition is false, so the then block can't have one either.
+    if let Expression::NoOp(_) = raw_else_block {
+      verify(scope, shape_unit(), then_block.shape(), &loc)?;
+
+      return Ok(IfEx{
+        shape: shape_unit(),
+        loc,
+
+        condition,
+        then_block,
+        else_block: raw_else_block
+      }.wrap());
+    }
+
     let else_block = check(scope, raw_else_block, shape_unknown())?;
 
-    verify(then_block.shape(), else_block.shape(), &loc)?;
+    // A branch that returns or panics takes its shape from the other one.
+    let shape = verify(scope, then_block.shape(), else_block.shape(), &loc)?;
 
     Ok(IfEx{
-      shape: then_block.shape(),
+      shape,
       loc,
 
       condition,
@@ -211,9 +791,54 @@ impl Typed for IfEx {
   }
 }
 
+impl Typed for WhileEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let WhileEx{shape: raw_shape, loc, condition: raw_condition, body: raw_body} = self;
+
+    let condition = check(scope, raw_condition, shape_boolean())?;
+
+    verify(scope, shape_boolean(), condition.shape(), &loc)?;
+
+    let body = check(scope, raw_body, shape_unknown())?;
+
+    Ok(WhileEx{
+      shape: shape_unit(),
+      loc,
+
+      condition,
+      body
+    }.wrap())
+  }
+}
+
+impl Typed for AssertEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let AssertEx{shape: raw_shape, loc, condition: raw_condition} = self;
+
+    let condition = check(scope, raw_condition, shape_boolean())?;
+
+    verify(scope, shape_boolean(), condition.shape(), &loc)?;
+
+    Ok(AssertEx{shape: shape_unit(), loc, condition}.wrap())
+  }
+}
+
 impl Typed for VariableEx {
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError> {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
     let VariableEx{shape: raw_shape, loc, id} = self;
+
+    // The lexer reads box.value as one Id. When box is a local that's its field, otherwise a name from another module.
+    if let Some(dot) = id.find('.') {
+      if scope.is_local(&id[..dot].to_string()) {
+        let value = VariableEx { shape: shape_unknown(), loc: loc.clone(), id: id[..dot].to_string() }.wrap();
+        let field = id[dot + 1..].split('.').fold(value, |value, field| {
+          FieldEx { shape: shape_unknown(), loc: loc.clone(), value, field: String::from(field), index: 0 }.wrap()
+        });
+
+        return check(scope, field, expected);
+      }
+    }
+
     let shape = scope.check_scope(&id, &loc)?;
 
     Ok(VariableEx {shape, loc, id}.wrap())
@@ -221,184 +846,1280 @@ impl Typed for VariableEx {
 }
 
 impl Typed for StringLiteralEx {
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError> {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
     Ok(self.wrap())
   }
 }
 
 impl Typed for NumberLiteralEx {
-  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, SimpleError> {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    // Every number is a Float, there's nothing for the expected shape to choose between yet.
     Ok(self.wrap())
   }
 }
 
-fn check(scope: &mut Scope, ex: Expression, expected: Shape) -> Result<Expression, SimpleError> {
-  match ex {
-    Expression::NoOp(_) => Ok(ex),
-    Expression::Import(_) => Ok(ex),
-    Expression::FunctionDeclaration(ex) => ex.check(scope, expected),
-    Expression::Block(ex) => ex.check(scope, expected),
-    Expression::Assignment(ex) => ex.check(scope, expected),
-    Expression::BinaryOp(ex) => ex.check(scope, expected),
-    Expression::Call(ex) => ex.check(scope, expected),
-    Expression::If(ex) => ex.check(scope, expected),
-    Expression::Variable(ex) => ex.check(scope, expected),
-    Expression::StringLiteral(ex) => ex.check(scope, expected),
-    Expression::NumberLiteral(ex) => ex.check(scope, expected),
-    Expression::BooleanLiteral(..) => Ok(ex),
+impl Typed for VariantEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let VariantEx{shape: raw_shape, loc, tag, values: raw_values} = self;
+
+    // Whatever the expected shape says about the side this variant doesn't hold.
+    let filled = scope.fill_shape(expected, &loc)?;
+    let expected_value = filled.option_arg().cloned().unwrap_or(Shape::UnknownShape);
+    let (expected_ok, expected_err) = filled.result_args()
+      .map(|(ok, err)| (ok.clone(), err.clone()))
+      .unwrap_or((Shape::UnknownShape, Shape::UnknownShape));
+
+    let mut values = Vec::with_capacity(raw_values.len());
+
+    for raw_value in raw_values {
+      let value_expected = match tag.as_str() {
+        VARIANT_SOME => expected_value.clone(),
+        VARIANT_OK => expected_ok.clone(),
+        VARIANT_ERR => expected_err.clone(),
+        // A data type's fields, which are the constructor's parameters and already have their shapes.
+        _ => Shape::UnknownShape,
+      };
+
+      values.push(check(scope, raw_value, value_expected)?);
+    }
+
+    let shape = match tag.as_str() {
+      VARIANT_SOME => shape_option(values[0].shape()),
+      VARIANT_NONE => shape_option(expected_value),
+      VARIANT_OK => shape_result(values[0].shape(), expected_err),
+      VARIANT_ERR => shape_result(expected_ok, values[0].shape()),
+      // Only a data type's constructor builds any other variant, already knowing the shape it builds.
+      _ => raw_shape,
+    };
+
+    Ok(VariantEx{shape, loc, tag, values}.wrap())
+  }
+}
+
+impl Typed for TryEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let TryEx{shape: raw_shape, loc, body: raw_body} = self;
+
+    let body = check(scope, raw_body, shape_unknown())?;
+    let found = scope.fill_shape(body.shape(), &loc)?;
+    let result = scope.resolve(&scope.function_result());
+
+    // A None or Err is returned as it is, so the function has to return the same kind, with the same error for Result.
+    let shape = if let Some(value) = found.option_arg() {
+      if result.option_arg().is_none() {
+        return Err(TypeError::InvalidTry { loc: Box::new(loc), found, result });
+      }
+
+      value.clone()
+    } else if let Some((value, error)) = found.result_args() {
+      match result.result_args() {
+        Some((_, result_error)) if scope.unify(error, result_error).is_some() => value.clone(),
+        _ => return Err(TypeError::InvalidTry { loc: Box::new(loc), found, result }),
+      }
+    } else if found == Shape::UnknownShape {
+      shape_unknown()
+    } else {
+      return Err(TypeError::InvalidTry { loc: Box::new(loc), found, result });
+    };
+
+    Ok(TryEx{shape, loc, body}.wrap())
+  }
+}
+
+impl Typed for ListEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let ListEx{shape: raw_shape, loc, items: raw_items} = self;
+
+    // An empty list only knows its shape from where it's used.
+    let mut element = scope.fill_shape(expected, &loc)?.list_arg().cloned().unwrap_or(Shape::UnknownShape);
+    let mut items = Vec::with_capacity(raw_items.len());
+
+    for raw_item in raw_items {
+      let item = check(scope, raw_item, element.clone())?;
+
+      element = scope.unify(&element, &item.shape())
+        .ok_or_else(|| TypeError::Mismatch { loc: Box::new(item.loc().clone()), declared: element.clone(), found: item.shape() })?;
+
+      items.push(item);
+    }
+
+    if element == Shape::UnknownShape {
+      return Err(TypeError::UnknownShape { loc: Box::new(loc) });
+    }
+
+    Ok(ListEx{shape: shape_list(element), loc, items}.wrap())
+  }
+}
+
+impl Typed for IndexEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let IndexEx{shape: raw_shape, loc, list: raw_list, index: raw_index} = self;
+
+    let list = check(scope, raw_list, shape_unknown())?;
+    let found = scope.fill_shape(list.shape(), &loc)?;
+
+    let shape = match found.list_arg() {
+      Some(element) => element.clone(),
+      None => return Err(TypeError::NotAList { loc: Box::new(loc), shape: found }),
+    };
+
+    let index = check(scope, raw_index, shape_float())?;
+
+    Ok(IndexEx{shape, loc, list, index}.wrap())
+  }
+}
+
+impl Typed for FieldEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let FieldEx{shape: raw_shape, loc, value: raw_value, field, index: raw_index} = self;
+
+    let value = check(scope, raw_value, shape_unknown())?;
+    let found = scope.resolve(&value.shape());
+
+    // A data type's fields have its parameters as variables 0 to n, which the value's type arguments stand in for.
+    let (index, shape) = match &found {
+      Shape::GenericShape { base, args } => match (&**base, scope.fields.get(&base.pretty())) {
+        (Shape::NamedShape { .. }, Some(fields)) => match fields.iter().position(|(id, _)| *id == field) {
+          Some(index) => (index, replace_variables(&fields[index].1, &|id| args[id].clone())),
+          None => return Err(TypeError::NoSuchField { loc: Box::new(loc), shape: found, field }),
+        },
+        _ => return Err(TypeError::NoSuchField { loc: Box::new(loc), shape: found, field }),
+      },
+      _ => return Err(TypeError::NoSuchField { loc: Box::new(loc), shape: found, field }),
+    };
+
+    Ok(FieldEx{shape, loc, value, field, index}.wrap())
+  }
+}
+
+impl Typed for PanicEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    Ok(PanicEx { shape: expected, ..self }.wrap())
+  }
+}
+
+impl Typed for ReturnEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    let ReturnEx{shape: raw_shape, loc, body: raw_body} = self;
+
+    let result = scope.function_result();
+    let body = check(scope, raw_body, result.clone())?;
+
+    verify(scope, result, body.shape(), &loc)?;
+
+    Ok(ReturnEx{shape: expected, loc, body}.wrap())
+  }
+}
+
+impl Typed for HoleEx {
+  fn check(self, scope: &mut Scope, expected: Shape) -> Result<Expression, TypeError> {
+    Ok(HoleEx { shape: expected, loc: self.loc }.wrap())
+  }
+}
+
+fn check(scope: &mut Scope, ex: Expression, expected: Shape) -> Result<Expression, TypeError> {
+  let loc = ex.loc().clone();
+
+  let result = match ex {
+    Expression::NoOp(_) => Ok(ex),
+    Expression::Import(_) => Ok(ex),
+    Expression::FunctionDeclaration(ex) => ex.check(scope, expected),
+    Expression::Block(ex) => ex.check(scope, expected),
+    Expression::Assignment(ex) => ex.check(scope, expected),
+    Expression::Reassignment(ex) => ex.check(scope, expected),
+    Expression::BinaryOp(ex) => ex.check(scope, expected),
+    Expression::Call(ex) => ex.check(scope, expected),
+    Expression::If(ex) => ex.check(scope, expected),
+    Expression::While(ex) => ex.check(scope, expected),
+    Expression::Variable(ex) => ex.check(scope, expected),
+    Expression::Tuple(ex) => ex.check(scope, expected),
+    Expression::List(ex) => ex.check(scope, expected),
+    Expression::StringLiteral(ex) => ex.check(scope, expected),
+    Expression::NumberLiteral(ex) => ex.check(scope, expected),
+    Expression::BooleanLiteral(..) => Ok(ex),
+    Expression::UnitLiteral(_) => Ok(ex),
+    Expression::Hole(ex) => ex.check(scope, expected),
+    Expression::Variant(ex) => ex.check(scope, expected),
+    Expression::Try(ex) => ex.check(scope, expected),
+    Expression::Index(ex) => ex.check(scope, expected),
+    Expression::Field(ex) => ex.check(scope, expected),
+    Expression::Panic(ex) => ex.check(scope, expected),
+    Expression::Return(ex) => ex.check(scope, expected),
+    Expression::Assert(ex) => ex.check(scope, expected),
+  };
+
+  match result {
+    Ok(ex) => Ok(ex),
+    Err(err) => {
+      scope.report(err);
+      Ok(failed(loc))
+    }
+  }
+}
+
+/**
+* Stands in for an expression that failed to check. Its UnknownShape is accepted everywhere so the error isn't repeated.
+*/
+fn failed(loc: Location) -> Expression {
+  BlockEx { shape: shape_unknown(), loc, body: Vec::new() }.wrap()
+}
+
+/**
+* The GenericShapeConstructor a data type is declared as. Its base is the type's qualified name, which is what tells
+* one data type from another, so it's never expanded.
+*/
+fn data_shape(module: &str, data: &DataEx) -> Shape {
+  Shape::GenericShapeConstructor {
+    base: Box::new(shape_named(format!("{}.{}", module, data.id))),
+    args: data.params.len() as u8,
+  }
+}
+
+/**
+* The shape with each name in params replaced by the shape it's mapped to.
+*/
+fn replace_params(shape: &Shape, params: &HashMap<String, Shape>) -> Shape {
+  match shape {
+    Shape::NamedShape { name } => params.get(name).cloned().unwrap_or_else(|| shape.clone()),
+    Shape::GenericShape { base, args } => Shape::GenericShape {
+      base: Box::new(replace_params(base, params)),
+      args: args.iter().map(|arg| replace_params(arg, params)).collect(),
+    },
+    Shape::SimpleFunctionShape { args, result } => Shape::SimpleFunctionShape {
+      args: args.iter().map(|arg| replace_params(arg, params)).collect(),
+      result: Box::new(replace_params(result, params)),
+    },
+    other => other.clone(),
+  }
+}
+
+pub fn fill_shape(shape: Shape, loc: &Location) -> Result<Shape, TypeError> {
+  TypeRegistry::new().resolve(shape, loc)
+}
+
+/**
+* Every type name a module can see: the builtin types, the module's own declarations and the types its imports export.
+* Declarations are kept as written and only expanded on resolve, so they may refer to each other in any order.
+*
+* A declaration may refer to itself from inside a List, Option, tuple or function, like a tree whose children are
+* trees. Inside those, a recursive type is always left as a NamedShape, by its qualified name so it means the same in
+* any module that imports it, and only expanded when something looks inside it.
+*/
+struct TypeRegistry {
+  types: HashMap<String, Shape>,
+  /** The module's own declarations by the name importers know them by, Tree as Main.Tree. */
+  qualified: HashMap<String, String>,
+}
+
+impl TypeRegistry {
+
+  fn new() -> TypeRegistry {
+    let mut types = HashMap::new();
+
+    for name in BUILTIN_TYPES {
+      types.insert(String::from(*name), builtin_shape(name).expect("Every builtin type has a shape"));
+    }
+
+    TypeRegistry {
+      types,
+      qualified: HashMap::new(),
+    }
+  }
+
+  fn declare(&mut self, name: String, shape: Shape, loc: &Location) -> Result<(), TypeError> {
+    if self.types.contains_key(&name) {
+      return Err(TypeError::TypeRedeclaration { loc: Box::new(loc.clone()), name });
+    }
+
+    self.types.insert(name, shape);
+    Ok(())
+  }
+
+  /**
+  * Declares one of the module's own types, under its qualified name as well so a reference to it left by resolve
+  * still resolves.
+  */
+  fn declare_own(&mut self, module: &str, name: String, shape: Shape, loc: &Location) -> Result<(), TypeError> {
+    let qualified = format!("{}.{}", module, name);
+
+    self.declare(name.clone(), shape.clone(), loc)?;
+    self.declare(qualified.clone(), shape, loc)?;
+    self.qualified.insert(name, qualified);
+    Ok(())
+  }
+
+  fn resolve(&self, shape: Shape, loc: &Location) -> Result<Shape, TypeError> {
+    self.resolve_visiting(shape, &mut Vec::new(), None, loc)
+  }
+
+  /**
+  * Whether the type name refers to, somewhere inside it, itself.
+  */
+  fn is_recursive(&self, name: &str) -> bool {
+    let canonical = |name: &str| self.qualified.get(name).cloned().unwrap_or_else(|| String::from(name));
+    let target = canonical(name);
+
+    let mut seen = Vec::new();
+    let mut next = Vec::new();
+    self.types.get(name).into_iter().for_each(|shape| named_shapes(shape, &mut next));
+
+    while let Some(other) = next.pop() {
+      if canonical(&other) == target {
+        return true;
+      }
+
+      if !seen.contains(&other) {
+        self.types.get(&other).into_iter().for_each(|shape| named_shapes(shape, &mut next));
+        seen.push(other);
+      }
+    }
+
+    false
+  }
+
+  /**
+  * The qualified name and parameter count of the data type shape names, if it names one.
+  */
+  fn data_type(&self, shape: &Shape) -> Option<(Shape, usize)> {
+    match shape {
+      Shape::NamedShape { name } => match self.types.get(name) {
+        Some(Shape::GenericShapeConstructor { base, args }) => Some(((**base).clone(), *args as usize)),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+
+  /**
+  * A recursive type's reference expanded one level so what's inside it can be seen, anything else as it is. A data
+  * type has nothing to expand to.
+  */
+  fn unfold(&self, shape: Shape) -> Shape {
+    match shape {
+      Shape::NamedShape { .. } if self.data_type(&shape).is_some() => shape,
+      Shape::NamedShape { .. } => self.resolve(shape.clone(), &Location::point("<unfold>", 0, 0)).unwrap_or(shape),
+      other => other,
+    }
+  }
+
+  /**
+  * The names currently being expanded are kept in visiting so a cycle is reported instead of overflowing the stack.
+  * guarded is None outside any List, Option, tuple or function, otherwise how many of visiting were entered outside
+  * the innermost one. Meeting one of those again is a recursive type rather than a cycle.
+  */
+  fn resolve_visiting(&self, shape: Shape, visiting: &mut Vec<String>, guarded: Option<usize>, loc: &Location) -> Result<Shape, TypeError> {
+    match shape {
+      // The base is a data type's name, which stands for itself.
+      Shape::GenericShapeConstructor{..} => Ok(shape),
+      Shape::GenericShape{base, args} => {
+        let mut filled_args = Vec::new();
+
+        for arg in args {
+          filled_args.push(self.resolve_visiting(arg, visiting, Some(visiting.len()), loc)?)
+        }
+
+        if let Some((data, count)) = self.data_type(&base) {
+          if count != filled_args.len() {
+            return Err(TypeError::TypeArgumentCount { loc: Box::new(loc.clone()), name: data.pretty(), expected: count, found: filled_args.len() });
+          }
+
+          return Ok(Shape::GenericShape { base: Box::new(data), args: filled_args });
+        }
+
+        Ok(Shape::GenericShape {
+          base: Box::new(self.resolve_visiting(*base, visiting, guarded, loc)?),
+          args: filled_args
+        })
+      },
+      Shape::SimpleFunctionShape { args: raw_args, result: raw_result } => {
+        let mut args: Vec<Shape> = Vec::new();
+
+        for next_arg in raw_args {
+          args.push(self.resolve_visiting(next_arg, visiting, Some(visiting.len()), loc)?);
+        }
+
+        let result = Box::new(self.resolve_visiting(*raw_result, visiting, Some(visiting.len()), loc)?);
+
+        Ok(Shape::SimpleFunctionShape{args, result})
+      }
+      Shape::NamedShape { name } => {
+        let declared = self.types.get(&name)
+          .ok_or_else(|| TypeError::UnknownType { loc: Box::new(loc.clone()), name: name.clone() })?;
+
+        if let Shape::GenericShapeConstructor { base, args } = declared {
+          return match *args {
+            0 => Ok(Shape::GenericShape { base: base.clone(), args: Vec::new() }),
+            expected => Err(TypeError::TypeArgumentCount { loc: Box::new(loc.clone()), name: base.pretty(), expected: expected as usize, found: 0 }),
+          };
+        }
+
+        match (visiting.iter().position(|next| *next == name), guarded) {
+          (Some(index), Some(first)) if index < first => {
+            let name = self.qualified.get(&name).cloned().unwrap_or(name);
+            return Ok(Shape::NamedShape { name });
+          }
+          (Some(_), _) => {
+            let mut cycle = visiting.clone();
+            cycle.push(name);
+            return Err(TypeError::CyclicAlias { loc: Box::new(loc.clone()), cycle });
+          }
+          (None, Some(_)) if self.is_recursive(&name) => {
+            let name = self.qualified.get(&name).cloned().unwrap_or(name);
+            return Ok(Shape::NamedShape { name });
+          }
+          (None, _) => {}
+        }
+
+        visiting.push(name);
+        let resolved = self.resolve_visiting(declared.clone(), visiting, guarded, loc)?;
+        visiting.pop();
+        Ok(resolved)
+      },
+      Shape::BaseShape{..} | Shape::VariableShape{..} => Ok(shape.clone()),
+      Shape::UnknownShape => Ok(shape_unknown()),
+    }
+  }
+}
+
+/**
+* Every type name mentioned anywhere in shape.
+*/
+fn named_shapes(shape: &Shape, names: &mut Vec<String>) {
+  match shape {
+    Shape::NamedShape { name } => names.push(name.clone()),
+    Shape::GenericShapeConstructor { .. } => {}
+    Shape::GenericShape { base, args } => {
+      named_shapes(base, names);
+      args.iter().for_each(|arg| named_shapes(arg, names));
+    }
+    Shape::SimpleFunctionShape { args, result } => {
+      args.iter().for_each(|arg| named_shapes(arg, names));
+      named_shapes(result, names);
+    }
+    Shape::BaseShape { .. } | Shape::VariableShape { .. } | Shape::UnknownShape => {}
+  }
+}
+
+/**
+* Replaces the shape variables left in a checked function with what they were bound to, once the whole module has had
+* its say. Nothing after the typechecker ever sees a variable.
+*/
+fn settle_function(scope: &Scope, ex: &mut FunctionDeclarationEx) {
+  for arg in ex.args.iter_mut().chain(ex.context.closures.iter_mut()) {
+    arg.shape = scope.settle_shape(&arg.shape);
+  }
+
+  ex.result = scope.settle_shape(&ex.result);
+  settle(scope, &mut ex.body);
+}
+
+fn settle(scope: &Scope, ex: &mut Expression) {
+  match ex {
+    Expression::NoOp(_) | Expression::Import(_) | Expression::BooleanLiteral(..) | Expression::UnitLiteral(_) => {}
+    Expression::FunctionDeclaration(ex) => settle_function(scope, ex),
+    Expression::Assignment(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.body);
+    }
+    Expression::Reassignment(ex) => settle(scope, &mut ex.body),
+    Expression::Variable(ex) => ex.shape = scope.settle_shape(&ex.shape),
+    Expression::BinaryOp(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.left);
+      settle(scope, &mut ex.right);
+    }
+    Expression::Call(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.func);
+      ex.args.iter_mut().for_each(|arg| settle(scope, arg));
+    }
+    Expression::If(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.condition);
+      settle(scope, &mut ex.then_block);
+      settle(scope, &mut ex.else_block);
+    }
+    Expression::While(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.condition);
+      settle(scope, &mut ex.body);
+    }
+    Expression::Block(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      ex.body.iter_mut().for_each(|item| settle(scope, item));
+    }
+    Expression::Tuple(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      ex.items.iter_mut().for_each(|item| settle(scope, item));
+    }
+    Expression::List(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      ex.items.iter_mut().for_each(|item| settle(scope, item));
+    }
+    Expression::StringLiteral(ex) => ex.shape = scope.settle_shape(&ex.shape),
+    Expression::NumberLiteral(ex) => ex.shape = scope.settle_shape(&ex.shape),
+    Expression::Hole(ex) => ex.shape = scope.settle_shape(&ex.shape),
+    Expression::Variant(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      ex.values.iter_mut().for_each(|value| settle(scope, value));
+    }
+    Expression::Try(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.body);
+    }
+    Expression::Index(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.list);
+      settle(scope, &mut ex.index);
+    }
+    Expression::Field(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.value);
+    }
+    Expression::Panic(ex) => ex.shape = scope.settle_shape(&ex.shape),
+    Expression::Return(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.body);
+    }
+    Expression::Assert(ex) => {
+      ex.shape = scope.settle_shape(&ex.shape);
+      settle(scope, &mut ex.condition);
+    }
+  }
+}
+
+/**
+* An UnknownShape found comes from an expression that already failed, so it is accepted without another error.
+*/
+fn verify(scope: &mut Scope, defined: Shape, found: Shape, loc: &Location) -> Result<Shape, TypeError> {
+  if let Shape::UnknownShape = defined {
+    if let Shape::UnknownShape = found {
+      Ok(shape_unknown())
+    } else {
+      Ok(scope.fill_shape(found, loc)?)
+    }
+  } else {
+    if let Shape::UnknownShape = found {
+      Ok(scope.fill_shape(defined, loc)?)
+    } else {
+      let filled_defined = scope.fill_shape(defined.clone(), loc)?;
+      let filled_found = scope.fill_shape(found.clone(), loc)?;
+
+      if let Some(unified) = scope.assign(&filled_defined, &filled_found) {
+        Ok(unified)
+      } else {
+        // Report the shapes as written so aliases keep their names
+        Err(TypeError::Mismatch { loc: Box::new(loc.clone()), declared: scope.resolve(&defined), found: scope.resolve(&found) })
+      }
+    }
+  }
+}
+
+/**
+* Whether values of this shape can be map keys. Functions only compare by identity and maps have no stable order to
+* hash in, so neither can, nor anything holding one. NaN can't either, but that's only known at runtime.
+*/
+fn is_hashable(shape: &Shape) -> bool {
+  match shape {
+    Shape::SimpleFunctionShape { .. } => false,
+    Shape::GenericShape { .. } if shape.map_args().is_some() => false,
+    Shape::GenericShape { args, .. } => args.iter().all(is_hashable),
+    _ => true,
+  }
+}
+
+/**
+* The key of the first map anywhere in shape whose key can't be hashed, if there is one.
+*/
+fn unhashable_key(shape: &Shape) -> Option<Shape> {
+  match shape {
+    Shape::GenericShape { args, .. } => match shape.map_args() {
+      Some((key, _)) if !is_hashable(key) => Some(key.clone()),
+      _ => args.iter().find_map(unhashable_key),
+    },
+    Shape::SimpleFunctionShape { args, result } => args.iter().find_map(unhashable_key).or_else(|| unhashable_key(result)),
+    _ => None,
+  }
+}
+
+fn verify_function_declaration(scope: &mut Scope, parameters: Vec<Parameter>, expected: Shape, loc: &Location) -> Result<Vec<Parameter>, TypeError> {
+  let expected_args = if let Shape::SimpleFunctionShape{args: expected_args, ..} = expected {
+    expected_args.clone()
+  } else {
+    vec![shape_unknown(); parameters.len()]
+  };
+
+  let mut filled_args = Vec::new();
+
+  for (arg, expected_arg) in parameters.iter().zip(expected_args) {
+    if arg.shape == Shape::UnknownShape && expected_arg == Shape::UnknownShape {
+      filled_args.push(Parameter{id: arg.id.clone(), shape: scope.fresh()});
+      continue;
+    }
+
+    let verified = verify(scope, expected_arg, arg.shape.clone(), &loc)?;
+    filled_args.push( Parameter{id: arg.id.clone(), shape: verified});
+  }
+
+  Ok(filled_args)
+}
+
+
+/**
+* Whether a function's result has a variable none of its arguments can decide. Nothing but calling itself ever said
+* anything about it then, like fun spin(n) = spin(n).
+*/
+fn is_ambiguous(args: &[Shape], result: &Shape) -> bool {
+  let decided: Vec<usize> = args.iter().flat_map(free_variables).collect();
+
+  free_variables(result).iter().any(|var| !decided.contains(var))
+}
+
+/**
+* Local functions declared in a block that call each other, as the strongly connected components of which function
+* mentions which. A function that only calls itself is left out, BuildRecursiveFunction covers it already.
+*/
+fn recursive_groups(body: &[Expression]) -> Vec<Vec<&FunctionDeclarationEx>> {
+  let funcs: Vec<&FunctionDeclarationEx> = body.iter()
+    .filter_map(|ex| match ex {
+      Expression::FunctionDeclaration(func) if !func.context.is_lambda => Some(&**func),
+      _ => None,
+    })
+    .collect();
+
+  let edges: Vec<Vec<usize>> = funcs.iter()
+    .map(|func| {
+      let mut ids = HashSet::new();
+      mentions(&func.body, &mut ids);
+      (0..funcs.len()).filter(|other| ids.contains(&funcs[*other].id)).collect()
+    })
+    .collect();
+
+  let mut tarjan = Tarjan { edges: &edges, index: vec![None; funcs.len()], low: vec![0; funcs.len()], stack: Vec::new(), next: 0, groups: Vec::new() };
+
+  for node in 0..funcs.len() {
+    if tarjan.index[node].is_none() {
+      tarjan.visit(node);
+    }
+  }
+
+  tarjan.groups.into_iter()
+    .filter(|group| group.len() > 1)
+    .map(|mut group| {
+      group.sort();
+      group.into_iter().map(|node| funcs[node]).collect()
+    })
+    .collect()
+}
+
+struct Tarjan<'a> {
+  edges: &'a [Vec<usize>],
+  index: Vec<Option<usize>>,
+  low: Vec<usize>,
+  stack: Vec<usize>,
+  next: usize,
+  groups: Vec<Vec<usize>>,
+}
+
+impl <'a> Tarjan<'a> {
+  fn visit(&mut self, node: usize) {
+    self.index[node] = Some(self.next);
+    self.low[node] = self.next;
+    self.next += 1;
+    self.stack.push(node);
+
+    for &other in &self.edges[node] {
+      match self.index[other] {
+        None => {
+          self.visit(other);
+          self.low[node] = self.low[node].min(self.low[other]);
+        }
+        Some(index) if self.stack.contains(&other) => self.low[node] = self.low[node].min(index),
+        Some(_) => {}
+      }
+    }
+
+    if Some(self.low[node]) == self.index[node] {
+      let mut group = Vec::new();
+
+      while let Some(member) = self.stack.pop() {
+        group.push(member);
+
+        if member == node {
+          break;
+        }
+      }
+
+      self.groups.push(group);
+    }
+  }
+}
+
+/**
+* Every name an unchecked expression refers to, shadowed or not. Only used to find which functions call which, where
+* a false match just costs declaring a function a little early.
+*/
+fn mentions(ex: &Expression, ids: &mut HashSet<String>) {
+  match ex {
+    Expression::Variable(var) => {
+      ids.insert(var.id.clone());
+    }
+    Expression::FunctionDeclaration(func) => mentions(&func.body, ids),
+    Expression::Assignment(ex) => mentions(&ex.body, ids),
+    Expression::Reassignment(ex) => mentions(&ex.body, ids),
+    Expression::BinaryOp(ex) => {
+      mentions(&ex.left, ids);
+      mentions(&ex.right, ids);
+    }
+    Expression::Call(ex) => {
+      mentions(&ex.func, ids);
+      ex.args.iter().chain(ex.named.iter().map(|(_, arg)| arg)).for_each(|arg| mentions(arg, ids));
+    }
+    Expression::If(ex) => {
+      mentions(&ex.condition, ids);
+      mentions(&ex.then_block, ids);
+      mentions(&ex.else_block, ids);
+    }
+    Expression::While(ex) => {
+      mentions(&ex.condition, ids);
+      mentions(&ex.body, ids);
+    }
+    Expression::Block(ex) => ex.body.iter().for_each(|item| mentions(item, ids)),
+    Expression::Tuple(ex) => ex.items.iter().for_each(|item| mentions(item, ids)),
+    Expression::List(ex) => ex.items.iter().for_each(|item| mentions(item, ids)),
+    Expression::Variant(ex) => ex.values.iter().for_each(|value| mentions(value, ids)),
+    Expression::Try(ex) => mentions(&ex.body, ids),
+    Expression::Index(ex) => {
+      mentions(&ex.list, ids);
+      mentions(&ex.index, ids);
+    }
+    Expression::Field(ex) => mentions(&ex.value, ids),
+    Expression::Return(ex) => mentions(&ex.body, ids),
+    Expression::Assert(ex) => mentions(&ex.condition, ids),
+    Expression::NoOp(_) | Expression::Import(_) | Expression::StringLiteral(_) | Expression::NumberLiteral(_)
+      | Expression::BooleanLiteral(..) | Expression::UnitLiteral(_) | Expression::Hole(_) | Expression::Panic(_) => {}
+  }
+}
+
+fn free_variables(shape: &Shape) -> Vec<usize> {
+  match shape {
+    Shape::VariableShape { id } => vec![*id],
+    Shape::GenericShapeConstructor { base, .. } => free_variables(base),
+    Shape::GenericShape { base, args } => free_variables(base).into_iter().chain(args.iter().flat_map(free_variables)).collect(),
+    Shape::SimpleFunctionShape { args, result } => args.iter().flat_map(free_variables).chain(free_variables(result)).collect(),
+    _ => Vec::new(),
+  }
+}
+
+fn replace_variables<F: Fn(usize) -> Shape>(shape: &Shape, replace: &F) -> Shape {
+  match shape {
+    Shape::VariableShape { id } => replace(*id),
+    Shape::GenericShapeConstructor { base, args } => Shape::GenericShapeConstructor { base: Box::new(replace_variables(base, replace)), args: *args },
+    Shape::GenericShape { base, args } => Shape::GenericShape {
+      base: Box::new(replace_variables(base, replace)),
+      args: args.iter().map(|arg| replace_variables(arg, replace)).collect(),
+    },
+    Shape::SimpleFunctionShape { args, result } => Shape::SimpleFunctionShape {
+      args: args.iter().map(|arg| replace_variables(arg, replace)).collect(),
+      result: Box::new(replace_variables(result, replace)),
+    },
+    other => other.clone(),
+  }
+}
+
+/**
+* What a call needs to know about a module function beyond its shape, to take named and default arguments.
+*/
+struct Signature {
+  params: Vec<String>,
+  defaults: Vec<Option<Literal>>,
+}
+
+struct Scope {
+  errors: Vec<TypeError>,
+  warnings: Vec<TypeError>,
+  shadowing: Shadowing,
+  types: TypeRegistry,
+  static_scope: HashMap<String, Shape>,
+  signatures: HashMap<String, Signature>,
+  operators: HashMap<String, String>, // operator ids mapped to the name to call them by
+  fields: HashMap<String, Vec<(String, Shape)>>, // each data type's fields by its qualified name, see declare_fields
+  hidden: HashSet<String>, // imported names that exist but aren't exported, to give a better error than undeclared
+  block_stack: Vec<Vec<HashMap<String, Binding>>>,
+  closures: Vec<Vec<Parameter>>,
+  results: Vec<Shape>, // the declared result of each function being checked, innermost last
+  substitution: HashMap<usize, Shape>, // what each shape variable has been bound to so far
+  assumed: Vec<(Shape, Shape)>, // recursive types being unified, taken to agree while their insides are compared
+  variables: usize,
+  quantified: HashMap<String, Vec<usize>>, // variables of generalized module functions, fresh for each use
+  declared_at: HashMap<String, Location>, // where each function of the module being checked is declared
+  definitions: Vec<(Location, Location)>, // each variable resolved so far and where it was declared
+}
+
+#[derive(Clone)]
+struct Binding {
+  shape: Shape,
+  mutable: bool,
+  loc: Location,
+  quantified: Vec<usize>,
+  forward: bool, // declared ahead of its definition, which hasn't been checked yet
+}
+
+impl Scope {
+
+  fn new() -> Scope {
+    Scope{
+      errors: Vec::new(),
+      warnings: Vec::new(),
+      shadowing: Shadowing::Warn,
+      types: TypeRegistry::new(),
+      static_scope: HashMap::new(),
+      signatures: HashMap::new(),
+      operators: HashMap::new(),
+      fields: HashMap::new(),
+      hidden: HashSet::new(),
+      block_stack: Vec::new(),
+      closures: Vec::new(),
+      results: Vec::new(),
+      substitution: HashMap::new(),
+      assumed: Vec::new(),
+      variables: 0,
+      quantified: HashMap::new(),
+      declared_at: HashMap::new(),
+      definitions: Vec::new(),
+    }
+  }
+
+  fn report(&mut self, err: TypeError) {
+    self.errors.push(err);
+  }
+
+  fn declare_type(&mut self, module: &str, alias: &TypeAliasEx) -> Result<(), TypeError> {
+    self.types.declare_own(module, alias.id.clone(), alias.shape.clone(), &alias.loc)
+  }
+
+  fn declare_data(&mut self, module: &str, data: &DataEx) -> Result<(), TypeError> {
+    self.types.declare_own(module, data.id.clone(), data_shape(module, data), &data.loc)
+  }
+
+  /**
+  * The fields of a data type with their shapes filled in and its parameters as shape variables 0 to n, in order, so a
+  * field of Box[Float] is read by replacing each variable with the type argument in its place.
+  */
+  fn declare_fields(&mut self, module: &str, data: &DataEx) -> Result<Vec<Parameter>, TypeError> {
+    let params: HashMap<String, Shape> = data.params.iter().enumerate()
+      .map(|(id, param)| (param.clone(), Shape::VariableShape { id }))
+      .collect();
+
+    let mut fields = Vec::with_capacity(data.fields.len());
+
+    for field in &data.fields {
+      fields.push(Parameter { id: field.id.clone(), shape: self.fill_shape(replace_params(&field.shape, &params), &data.loc)? });
+    }
+
+    self.fields.insert(format!("{}.{}", module, data.id), fields.iter().map(|field| (field.id.clone(), field.shape.clone())).collect());
+    Ok(fields)
+  }
+
+  /**
+  * The function that builds a data type from its fields, generic over the type's parameters. Its body is the variant
+  * tagged with the type's name, holding the fields in order.
+  */
+  fn data_constructor(&mut self, module: &str, data: &DataEx) -> Result<AstFunctionDeclaration, TypeError> {
+    let params: HashMap<String, Shape> = data.params.iter().map(|param| (param.clone(), self.fresh())).collect();
+
+    let mut args = Vec::with_capacity(data.fields.len());
+
+    for field in &data.fields {
+      args.push(Parameter { id: field.id.clone(), shape: self.fill_shape(replace_params(&field.shape, &params), &data.loc)? });
+    }
+
+    let result = Shape::GenericShape {
+      base: Box::new(shape_named(format!("{}.{}", module, data.id))),
+      args: data.params.iter().map(|param| params[param].clone()).collect(),
+    };
+
+    let values = args.iter().map(|arg| VariableEx { shape: shape_unknown(), loc: data.loc.clone(), id: arg.id.clone() }.wrap()).collect();
+    let body = VariantEx { shape: result.clone(), loc: data.loc.clone(), tag: data.id.clone(), values }.wrap();
+
+    Ok(AstFunctionDeclaration {
+      visibility: data.visibility.clone(),
+      ex: FunctionDeclarationEx {
+        result,
+        loc: data.loc.clone(),
+        id: data.id.clone(),
+        defaults: vec![None; args.len()],
+        args,
+        body,
+        context: FunctionContext::new(false, false),
+      },
+    })
+  }
+
+  fn fill_shape(&self, shape: Shape, loc: &Location) -> Result<Shape, TypeError> {
+    self.types.resolve(shape, loc)
+  }
+
+  /**
+  * A new shape variable, for a shape left out that will be worked out from how it's used.
+  */
+  fn fresh(&mut self) -> Shape {
+    self.variables += 1;
+    Shape::VariableShape { id: self.variables - 1 }
+  }
+
+  /**
+  * The shape with every variable bound so far replaced by what it's bound to.
+  */
+  fn resolve(&self, shape: &Shape) -> Shape {
+    match shape {
+      Shape::VariableShape { id } => match self.substitution.get(id) {
+        Some(bound) => self.resolve(bound),
+        None => shape.clone(),
+      },
+      Shape::GenericShapeConstructor { base, args } => Shape::GenericShapeConstructor { base: Box::new(self.resolve(base)), args: *args },
+      Shape::GenericShape { base, args } => Shape::GenericShape {
+        base: Box::new(self.resolve(base)),
+        args: args.iter().map(|arg| self.resolve(arg)).collect(),
+      },
+      Shape::SimpleFunctionShape { args, result } => Shape::SimpleFunctionShape {
+        args: args.iter().map(|arg| self.resolve(arg)).collect(),
+        result: Box::new(self.resolve(result)),
+      },
+      other => other.clone(),
+    }
+  }
+
+  /**
+  * Resolves the shape for good once checking is done. Variables nothing ever pinned down accept anything, like _.
+  */
+  fn settle_shape(&self, shape: &Shape) -> Shape {
+    self.types.unfold(replace_variables(&self.resolve(shape), &|_| Shape::UnknownShape))
+  }
+
+  /**
+  * The shape both describe if they agree, binding variables on either side to whatever they meet and filling in an
+  * UnknownShape from the other side. None, for example, is an Option[_] until it meets the Option it's used as.
+  */
+  fn unify(&mut self, left: &Shape, right: &Shape) -> Option<Shape> {
+    match (self.resolve(left), self.resolve(right)) {
+      (Shape::VariableShape { id: left }, Shape::VariableShape { id: right }) if left == right => Some(Shape::VariableShape { id: left }),
+      (Shape::VariableShape { id }, Shape::UnknownShape) | (Shape::UnknownShape, Shape::VariableShape { id }) => Some(Shape::VariableShape { id }),
+      (Shape::VariableShape { id }, other) | (other, Shape::VariableShape { id }) => {
+        if free_variables(&other).contains(&id) {
+          return None;
+        }
+
+        self.substitution.insert(id, other.clone());
+        Some(other)
+      }
+      (Shape::UnknownShape, other) | (other, Shape::UnknownShape) => Some(other),
+      (left @ Shape::NamedShape { .. }, right) | (left, right @ Shape::NamedShape { .. }) => {
+        let pair = (left.clone(), right.clone());
+
+        if left == right || self.assumed.contains(&pair) {
+          return Some(left);
+        }
+
+        let (left_inside, right_inside) = (self.types.unfold(left.clone()), self.types.unfold(right.clone()));
+
+        // Two different data types, neither can be expanded to look for agreement.
+        if left_inside == left && right_inside == right {
+          return None;
+        }
+
+        self.assumed.push(pair);
+        let unified = self.unify(&left_inside, &right_inside);
+        self.assumed.pop();
+
+        unified.map(|_| left)
+      }
+      (Shape::GenericShape { base: left_base, args: left_args }, Shape::GenericShape { base: right_base, args: right_args }) => {
+        if left_args.len() != right_args.len() {
+          return None;
+        }
+
+        let base = self.unify(&left_base, &right_base)?;
+        let args = left_args.iter().zip(&right_args).map(|(left, right)| self.unify(left, right)).collect::<Option<Vec<Shape>>>()?;
+
+        Some(Shape::GenericShape { base: Box::new(base), args })
+      }
+      (Shape::SimpleFunctionShape { args: left_args, result: left_result }, Shape::SimpleFunctionShape { args: right_args, result: right_result }) => {
+        if left_args.len() != right_args.len() {
+          return None;
+        }
+
+        let args = left_args.iter().zip(&right_args).map(|(left, right)| self.unify(left, right)).collect::<Option<Vec<Shape>>>()?;
+        let result = self.unify(&left_result, &right_result)?;
+
+        Some(Shape::SimpleFunctionShape { args, result: Box::new(result) })
+      }
+      (left, right) if left == right => Some(left),
+      _ => None,
+    }
+  }
+
+  /**
+  * The shape a value of shape found takes where expected is wanted, if it can be used there. Where unify needs the two
+  * to agree exactly, a function only has to take whatever expected's callers pass it, so its parameters are assigned
+  * the other way around. Its result is thrown away if expected's is Unit, so then any result will do. Everything else
+  * has to unify, with UnknownShape still agreeing with anything.
+  */
+  fn assign(&mut self, expected: &Shape, found: &Shape) -> Option<Shape> {
+    match (self.resolve(expected), self.resolve(found)) {
+      (Shape::SimpleFunctionShape { args: expected_args, result: expected_result }, Shape::SimpleFunctionShape { args: found_args, result: found_result }) => {
+        if expected_args.len() != found_args.len() {
+          return None;
+        }
+
+        let args = expected_args.iter().zip(&found_args).map(|(expected, found)| self.assign(found, expected)).collect::<Option<Vec<Shape>>>()?;
+
+        let result = if *expected_result == shape_unit() {
+          shape_unit()
+        } else {
+          self.assign(&expected_result, &found_result)?
+        };
+
+        Some(Shape::SimpleFunctionShape { args, result: Box::new(result) })
+      }
+      (expected, found) => self.unify(&expected, &found),
+    }
+  }
+
+  /**
+  * A copy of a generalized shape with fresh variables in place of its quantified ones, so each use can settle on
+  * different shapes.
+  */
+  fn instantiate(&mut self, shape: &Shape, quantified: &[usize]) -> Shape {
+    let fresh: HashMap<usize, Shape> = quantified.iter().map(|id| (*id, self.fresh())).collect();
+
+    replace_variables(&self.resolve(shape), &|id| fresh.get(&id).cloned().unwrap_or(Shape::VariableShape { id }))
   }
-}
 
-pub fn fill_shape(shape: Shape, loc: &Location) -> Result<Shape, SimpleError> {
-  match shape {
-    Shape::GenericShapeConstructor{base, args} => {
-      Ok(Shape::GenericShapeConstructor {
-        base: Box::new(fill_shape(*base, loc)?),
-        args
-      })
+  /**
+  * Generalizes the local binding id over the variables made since first that are still unbound. Anything made
+  * before first might still be pinned down by code around it, so it's left alone.
+  */
+  fn generalize(&mut self, id: &String, first: usize) {
+    let resolved = match self.visible(id) {
+      Some(binding) => self.resolve(&binding.shape),
+      None => return,
+    };
+
+    let quantified: Vec<usize> = free_variables(&resolved).into_iter().filter(|var| *var >= first).collect();
+    let block_scope = self.block_stack.last_mut().expect("Scope should never be empty!");
+
+    if let Some(binding) = block_scope.last_mut().and_then(|scope| scope.get_mut(id)) {
+      binding.shape = resolved;
+      binding.quantified = quantified;
     }
-    Shape::GenericShape{base, args} => {
-      let mut filled_args = Vec::new();
+  }
 
-      for arg in args {
-        filled_args.push(fill_shape(arg, loc)?)
-      }
+  /**
+  * A module function's shape as declared, with a variable for each parameter or result shape left out.
+  */
+  fn declared_shape(&mut self, ex: &FunctionDeclarationEx) -> Shape {
+    let mut or_fresh = |shape: &Shape| if *shape == Shape::UnknownShape { self.fresh() } else { shape.clone() };
 
-      Ok(Shape::GenericShape {
-        base: Box::new(fill_shape(*base, loc)?),
-        args: filled_args
-      })
-    },
-    Shape::SimpleFunctionShape { args: raw_args, result: raw_result } => {
-      let mut args: Vec<Shape> = Vec::new();
+    let args = ex.args.iter().map(|arg| or_fresh(&arg.shape)).collect();
+    let result = Box::new(or_fresh(&ex.result));
 
-      for next_arg in raw_args {
-        args.push(fill_shape(next_arg, loc)?);
-      }
+    Shape::SimpleFunctionShape { args, result }
+  }
 
-      let result = Box::new(fill_shape(*raw_result, loc)?);
+  fn pre_fill_module_function(&mut self, id: String, shape: Shape, loc: &Location) -> Result<(), TypeError> {
+    let shape = self.fill_shape(shape, &loc)?;
 
-      Ok(Shape::SimpleFunctionShape{args, result})
-    }
-    Shape::NamedShape { name } => {
-      // TODO: check against custom declared types.
-      match name.as_ref() {
-        "String" => Ok(shape_string()),
-        "Float" => Ok(shape_float()),
-        "Boolean" => Ok(shape_boolean()),
-        "Unit" => Ok(shape_unit()),
-        _ => Err(SimpleError::new(format!("Could not find type: {}, {}", name, loc.pretty())))
-      }
-    },
-    Shape::BaseShape{..} => Ok(shape.clone()),
-    Shape::UnknownShape => Ok(shape_unknown()),
+    self.static_scope.insert(id, shape);
+    Ok(())
   }
-}
 
-fn verify(defined: Shape, found: Shape, loc: &Location) -> Result<Shape, SimpleError> {
-  if let Shape::UnknownShape = defined {
-    if let Shape::UnknownShape = found {
-      loc.fail("Unknown shape")
-    } else {
-      Ok(fill_shape(found, loc)?)
+  fn import_module(&mut self, app: &AppShapes, imp: &ImportEx, package: &str, module_name: &str) -> Result<(), TypeError> {
+    let module = app.lookup_module(&imp.package, &imp.module)
+      .ok_or_else(|| TypeError::NoSuchModule { loc: Box::new(imp.loc.clone()), package: imp.package.clone(), module: imp.module.clone() })?;
+
+    // Types first, the shapes of the functions can refer to them.
+    for name in module.list_types() {
+      let shape = module.lookup_type(&name).expect("Invalid impl");
+      self.types.declare(format!("{}.{}", imp.module, name), shape, &imp.loc)?;
+
+      if let Some(fields) = module.lookup_fields(&name) {
+        self.fields.insert(format!("{}.{}", imp.module, name), fields);
+      }
     }
-  } else {
-    if let Shape::UnknownShape = found {
-      Ok(fill_shape(defined, loc)?)
-    } else {
-      let filled_defined = fill_shape(defined, loc)?;
-      let filled_found = fill_shape(found, loc)?;
 
-      if filled_defined == filled_found {
-        Ok(filled_found)
-      } else {
-        loc.fail(&format!("Incompatible types! Declared: {}, but found: {}", filled_defined.pretty(), filled_found.pretty()))
+    for func in module.list_values() {
+      if !module.visibility(&func).is_exported(&imp.package, &imp.module, package, module_name) {
+        self.hidden.insert(format!("{}.{}", imp.module, func));
+        continue;
       }
+
+      let id = format!("{}.{}", imp.module, func);
+      let shape = self.fill_shape(module.lookup(&func).expect("Invalid impl"), &imp.loc)?;
+
+      // Generic functions number their variables from 0, which would clash with this module's own. Fresh ones are
+      // never bound to anything, so each use instantiates its own copy like a local generic function.
+      let fresh: HashMap<usize, Shape> = free_variables(&shape).into_iter().map(|var| (var, self.fresh())).collect();
+      let shape = replace_variables(&shape, &|var| fresh[&var].clone());
+
+      self.quantified.insert(id.clone(), free_variables(&shape));
+      self.static_scope.insert(id, shape);
     }
+
+    Ok(())
   }
-}
 
-fn verify_function_declaration(parameters: Vec<Parameter>, expected: Shape, loc: &Location) -> Result<Vec<Parameter>, SimpleError> {
-  let expected_args = if let Shape::SimpleFunctionShape{args: expected_args, ..} = expected {
-    expected_args.clone()
-  } else {
-    vec![shape_unknown(); parameters.len()]
-  };
+  /**
+  * Renames a function declared by an operator to the id for its operand shapes. Shapes that don't resolve are left for
+  * checking the function to report. Overloads only apply inside the module declaring them, importing it doesn't change
+  * what an operator does in the importer.
+  */
+  fn declare_operator(&mut self, mut dec: AstFunctionDeclaration) -> AstFunctionDeclaration {
+    if !is_operator(&dec.ex.id) {
+      return dec;
+    }
 
-  let mut filled_args = Vec::new();
+    if dec.ex.args.len() != 2 {
+      self.report(TypeError::OperatorArity { loc: Box::new(dec.ex.loc.clone()), op: dec.ex.id.clone(), found: dec.ex.args.len() });
+      return dec;
+    }
 
-  for (arg, expected_arg) in parameters.iter().zip(expected_args) {
-    let verified = verify(expected_arg, arg.shape.clone(), &loc)?;
-    filled_args.push( Parameter{id: arg.id.clone(), shape: verified});
-  }
+    let left = self.fill_shape(dec.ex.args[0].shape.clone(), &dec.ex.loc);
+    let right = self.fill_shape(dec.ex.args[1].shape.clone(), &dec.ex.loc);
 
-  Ok(filled_args)
-}
+    if let (Ok(left), Ok(right)) = (left, right) {
+      if is_primitive(&left) && is_primitive(&right) {
+        self.report(TypeError::PrimitiveOperator { loc: Box::new(dec.ex.loc.clone()), op: dec.ex.id.clone(), left, right });
+        return dec;
+      }
 
+      dec.ex.id = operator_id(&dec.ex.id, &left, &right);
+      self.operators.insert(dec.ex.id.clone(), dec.ex.id.clone());
+    }
 
-struct Scope {
-  static_scope: HashMap<String, Shape>,
-  block_stack: Vec<Vec<HashMap<String, Shape>>>,
-  closures: Vec<Vec<Parameter>>,
-}
+    dec
+  }
 
-impl Scope {
+  fn is_overloaded(&self, op: &str) -> bool {
+    self.operators.keys().any(|id| id.starts_with(op) && id[op.len()..].starts_with('('))
+  }
 
-  fn new() -> Scope {
-    Scope{
-      static_scope: HashMap::new(),
-      block_stack: Vec::new(),
-      closures: Vec::new(),
+  /**
+  * Whether id was declared ahead in this block and is now being defined, clearing the mark so it's defined only once.
+  */
+  fn take_forward(&mut self, id: &String) -> bool {
+    let block_scope = self.block_stack.last_mut().expect("Scope should never be empty!");
+
+    match block_scope.last_mut().and_then(|scope| scope.get_mut(id)) {
+      Some(binding) if binding.forward => {
+        binding.forward = false;
+        true
+      }
+      _ => false,
     }
   }
 
-  fn pre_fill_module_function(&mut self, id: String, shape: Shape, loc: &Location) -> Result<(), SimpleError> {
-    let shape = fill_shape(shape, &loc)?;
+  /**
+  * Declares a name bound by let, which may shadow any binding already visible, in this block or out of it.
+  */
+  fn declare_let(&mut self, id: &String, binding: Binding, loc: &Location) -> Result<(), TypeError> {
+    if let Some(original) = self.visible(id) {
+      let shadowed = TypeError::Shadowed { loc: Box::new(loc.clone()), id: id.clone(), original: original.loc };
+
+      match self.shadowing {
+        Shadowing::Allow => {}
+        Shadowing::Warn => self.warnings.push(shadowed),
+        Shadowing::Deny => return Err(shadowed),
+      }
+    }
 
-    self.static_scope.insert(id, shape);
+    let block_scope = self.block_stack.last_mut().expect("Scope should never be empty!");
+    let scope = block_scope.last_mut().expect("Block Scope should never be empty!");
+
+    scope.insert(id.clone(), binding);
     Ok(())
   }
 
-  fn set_scope(&mut self, id: &String, shape: &Shape, loc: &Location) -> Result<(), SimpleError> {
+  /**
+  * The binding id currently refers to, without capturing it like find_local does.
+  */
+  fn visible(&self, id: &String) -> Option<Binding> {
+    self.block_stack.iter().rev()
+      .flat_map(|block_scope| block_scope.iter().rev())
+      .find_map(|scope| scope.get(id))
+      .cloned()
+  }
+
+  fn set_scope(&mut self, id: &String, shape: &Shape, loc: &Location) -> Result<(), TypeError> {
+    self.declare(id, Binding { shape: shape.clone(), mutable: false, loc: loc.clone(), quantified: Vec::new(), forward: false }, loc)
+  }
+
+  fn declare(&mut self, id: &String, binding: Binding, loc: &Location) -> Result<(), TypeError> {
     let block_scope = self.block_stack.last_mut().expect("Scope should never be empty!");
     let scope = block_scope.last_mut().expect("Block Scope should never be empty!");
 
     if scope.contains_key(id) {
-      Err(SimpleError::new(format!("Redeclaration of variable: {} {}", id, loc.pretty())))
+      Err(TypeError::Redeclaration { loc: Box::new(loc.clone()), id: id.clone() })
     } else {
-      scope.insert(id.clone(), shape.clone());
+      scope.insert(id.clone(), binding);
       Ok(())
     }
   }
 
-  fn check_scope(&mut self, id: &String, loc: &Location) -> Result<Shape, SimpleError> {
-    let mut first = true;
+  fn check_scope(&mut self, id: &String, loc: &Location) -> Result<Shape, TypeError> {
+    if let Some(binding) = self.find_local(id) {
+      self.definitions.push((loc.clone(), binding.loc.clone()));
+      return Ok(self.instantiate(&binding.shape, &binding.quantified));
+    }
 
-    for block_scope in self.block_stack.iter().rev() {
-      for scope in block_scope {
-        if scope.contains_key(id) {
-          if !first {
-            let shape = scope.get(id).unwrap();
-            let param = Parameter {
-              id: id.clone(),
-              shape: shape.clone(),
-            };
+    if let Some(shape) = self.static_scope.get(id).cloned() {
+      if let Some(declared) = self.declared_at.get(id) {
+        self.definitions.push((loc.clone(), declared.clone()));
+      }
 
-            self.closures.last_mut().expect("closures should never be empty!").push(param);
-          }
+      let quantified = self.quantified.get(id).cloned().unwrap_or_default();
+      return Ok(self.instantiate(&shape, &quantified));
+    }
 
-          return Ok(scope[id].clone());
-        }
-      }
+    if self.hidden.contains(id) {
+      return Err(TypeError::PrivateFunction { loc: Box::new(loc.clone()), id: id.clone() });
+    }
+
+    Err(TypeError::UndeclaredVariable { loc: Box::new(loc.clone()), id: id.clone() })
+  }
 
-      first = false;
+  /**
+  * Same as check_scope, but for a variable about to be assigned to. Only locals declared with let mut qualify.
+  */
+  fn check_mutable(&mut self, id: &String, loc: &Location) -> Result<Shape, TypeError> {
+    match self.find_local(id) {
+      Some(Binding { shape, mutable: true, .. }) => Ok(shape),
+      Some(_) => Err(TypeError::Immutable { loc: Box::new(loc.clone()), id: id.clone() }),
+      None if self.static_scope.contains_key(id) => Err(TypeError::Immutable { loc: Box::new(loc.clone()), id: id.clone() }),
+      None => Err(TypeError::UndeclaredVariable { loc: Box::new(loc.clone()), id: id.clone() }),
     }
+  }
+
+  /**
+  * Whether id is a local, without marking it captured the way find_local does.
+  */
+  fn is_local(&self, id: &String) -> bool {
+    self.block_stack.iter().any(|block_scope| block_scope.iter().any(|scope| scope.contains_key(id)))
+  }
+
+  fn find_local(&mut self, id: &String) -> Option<Binding> {
+    let (depth, binding) = self.block_stack.iter().enumerate().rev()
+      .filter_map(|(depth, block_scope)| block_scope.iter().rev().find_map(|scope| scope.get(id)).map(|binding| (depth, binding.clone())))
+      .next()?;
 
-    if self.static_scope.contains_key(id) {
-      return Ok(self.static_scope[id].clone())
+    // Every function between here and the declaration captures it, so each one can pass it on to the next.
+    for closures in self.closures.iter_mut().skip(depth + 1) {
+      if !closures.iter().any(|param| &param.id == id) {
+        closures.push(Parameter { id: id.clone(), shape: binding.shape.clone() });
+      }
     }
 
-    Err(SimpleError::new(format!("Undeclared variable: {} {}", id, loc.pretty())))
+    Some(binding)
   }
 
   fn create_block_scope(&mut self) {
@@ -409,20 +2130,41 @@ impl Scope {
     self.block_stack.last_mut().expect("Block Scope should never be empty!").pop();
   }
 
-  fn create_function_scope(&mut self) {
+  fn create_function_scope(&mut self, result: Shape) {
     self.block_stack.push(vec![HashMap::new()]);
     self.closures.push(Vec::new());
+    self.results.push(result);
+  }
+
+  /**
+  * The declared result of the function being checked, UnknownShape if it's left to be inferred.
+  */
+  fn function_result(&self) -> Shape {
+    self.results.last().cloned().unwrap_or(Shape::UnknownShape)
   }
 
   fn destroy_function_scope(&mut self) -> Vec<Parameter> {
     self.block_stack.pop();
+    self.results.pop();
     self.closures.pop()
       .expect("closures should never be empty!")
   }
 }
 
+/**
+* What to do when a let hides a binding that's already visible.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Shadowing {
+  Allow,
+  Warn,
+  Deny,
+}
+
 pub struct AppShapes {
   packages: HashMap<String, Box<PackageShapes>>,
+  shadowing: Shadowing,
+  warnings: RefCell<Vec<TypeError>>,
 }
 
 impl AppShapes {
@@ -433,26 +2175,103 @@ impl AppShapes {
     packages.insert(String::from("Core"), core_package());
 
     AppShapes {
-      packages
+      packages,
+      shadowing: Shadowing::Warn,
+      warnings: RefCell::new(Vec::new()),
+    }
+  }
+
+  pub fn set_shadowing(&mut self, shadowing: Shadowing) {
+    self.shadowing = shadowing;
+  }
+
+  /**
+  * Every warning from the modules checked against these shapes since the last call, oldest first.
+  */
+  pub fn take_warnings(&self) -> Vec<TypeError> {
+    self.warnings.replace(Vec::new())
+  }
+
+  /**
+  * Shapes for Core plus every function already loaded into the application, including host functions.
+  */
+  pub fn for_application(app: &BitApplication) -> Result<AppShapes, TypeError> {
+    let mut shapes = AppShapes::new();
+
+    for (package_name, package) in &app.packages {
+      if package_name == "Core" {
+        continue;
+      }
+
+      for (module_name, module) in &package.modules {
+        for (name, func) in &module.functions {
+          shapes.register(package_name, module_name, name, func.func_ref().shape.clone())?;
+        }
+      }
     }
+
+    Ok(shapes)
   }
 
-  fn lookup_module(&self, package: &str, module: &str) -> Option<&Box<ModuleShapes>> {
+  pub fn lookup_module(&self, package: &str, module: &str) -> Option<&Box<ModuleShapes>> {
     self.packages.get(package).and_then(|pack| pack.lookup_module(module))
   }
 
-  fn lookup(&self, package: &str, module: &str, name: &str) -> Option<Shape> {
+  pub fn lookup(&self, package: &str, module: &str, name: &str) -> Option<Shape> {
     self.packages.get(package).and_then(|pack| pack.lookup(module, name))
   }
 
+  /**
+  * Declares the shape of a function that will be provided at runtime, usually by BitApplication::register_native.
+  */
+  pub fn register(&mut self, package: &str, module: &str, name: &str, shape: Shape) -> Result<(), TypeError> {
+    self.register_with_visibility(package, module, name, shape, Visibility::Public)
+  }
+
+  /**
+  * Declares a compiled function, which importing modules only see if its visibility allows. Names other than the
+  * builtins are left for importers to resolve, a recursive type stays a name even once it's checked.
+  */
+  pub fn register_with_visibility(&mut self, package: &str, module: &str, name: &str, shape: Shape, visibility: Visibility) -> Result<(), TypeError> {
+    let shape = shape.with_builtins();
+
+    self.packages.entry(String::from(package))
+      .or_insert_with(|| Box::new(PackageShapesBundle { modules: HashMap::new() }))
+      .register(module, name, shape, visibility)
+  }
+
+  /**
+  * Declares a type that importing modules can refer to as Module.name. The shape must already be fully resolved.
+  */
+  pub fn register_type(&mut self, package: &str, module: &str, name: &str, shape: Shape) -> Result<(), TypeError> {
+    self.packages.entry(String::from(package))
+      .or_insert_with(|| Box::new(PackageShapesBundle { modules: HashMap::new() }))
+      .register_type(module, name, shape)
+  }
+
+  /**
+  * Declares the fields of a data type already registered with register_type, as the typechecker left them in its DataEx.
+  */
+  pub fn register_fields(&mut self, package: &str, module: &str, name: &str, fields: Vec<(String, Shape)>) -> Result<(), TypeError> {
+    self.packages.entry(String::from(package))
+      .or_insert_with(|| Box::new(PackageShapesBundle { modules: HashMap::new() }))
+      .register_fields(module, name, fields)
+  }
+
 }
 
-trait PackageShapes {
+pub trait PackageShapes {
 
   fn lookup_module(&self, module: &str) -> Option<&Box<ModuleShapes>>;
 
   fn lookup(&self, module: &str, name: &str) -> Option<Shape>;
 
+  fn register(&mut self, module: &str, name: &str, shape: Shape, visibility: Visibility) -> Result<(), TypeError>;
+
+  fn register_type(&mut self, module: &str, name: &str, shape: Shape) -> Result<(), TypeError>;
+
+  fn register_fields(&mut self, module: &str, name: &str, fields: Vec<(String, Shape)>) -> Result<(), TypeError>;
+
 }
 
 struct PackageShapesBundle {
@@ -467,18 +2286,61 @@ impl PackageShapes for PackageShapesBundle {
   fn lookup(&self, module: &str, name: &str) -> Option<Shape> {
     self.modules.get(module).and_then(|module| module.lookup(name))
   }
+
+  fn register(&mut self, module: &str, name: &str, shape: Shape, visibility: Visibility) -> Result<(), TypeError> {
+    self.modules.entry(String::from(module))
+      .or_insert_with(|| Box::new(CoreModuleShapes::new()))
+      .register(name, shape, visibility)
+  }
+
+  fn register_type(&mut self, module: &str, name: &str, shape: Shape) -> Result<(), TypeError> {
+    self.modules.entry(String::from(module))
+      .or_insert_with(|| Box::new(CoreModuleShapes::new()))
+      .register_type(name, shape)
+  }
+
+  fn register_fields(&mut self, module: &str, name: &str, fields: Vec<(String, Shape)>) -> Result<(), TypeError> {
+    self.modules.entry(String::from(module))
+      .or_insert_with(|| Box::new(CoreModuleShapes::new()))
+      .register_fields(name, fields)
+  }
 }
 
-trait ModuleShapes {
+pub trait ModuleShapes {
 
   fn lookup(&self, name: &str) -> Option<Shape>;
 
   fn list_values(&self) -> Vec<String>;
 
+  fn visibility(&self, name: &str) -> Visibility;
+
+  fn register(&mut self, name: &str, shape: Shape, visibility: Visibility) -> Result<(), TypeError>;
+
+  fn lookup_type(&self, name: &str) -> Option<Shape>;
+
+  fn list_types(&self) -> Vec<String>;
+
+  fn register_type(&mut self, name: &str, shape: Shape) -> Result<(), TypeError>;
+
+  /** The fields of a data type, see Scope::declare_fields. */
+  fn lookup_fields(&self, name: &str) -> Option<Vec<(String, Shape)>>;
+
+  fn register_fields(&mut self, name: &str, fields: Vec<(String, Shape)>) -> Result<(), TypeError>;
+
 }
 
 struct CoreModuleShapes {
-  functions: HashMap<String, Shape>
+  functions: HashMap<String, Shape>,
+  visibility: HashMap<String, Visibility>,
+  types: HashMap<String, Shape>,
+  fields: HashMap<String, Vec<(String, Shape)>>,
+}
+
+impl CoreModuleShapes {
+
+  fn new() -> CoreModuleShapes {
+    CoreModuleShapes { functions: HashMap::new(), visibility: HashMap::new(), types: HashMap::new(), fields: HashMap::new() }
+  }
 }
 
 impl ModuleShapes for IrModule {
@@ -489,6 +2351,34 @@ impl ModuleShapes for IrModule {
   fn list_values(&self) -> Vec<String> {
     self.functions.keys().into_iter().map(|i| i.clone()).collect()
   }
+
+  fn visibility(&self, _name: &str) -> Visibility {
+    Visibility::Public
+  }
+
+  fn register(&mut self, name: &str, _shape: Shape, _visibility: Visibility) -> Result<(), TypeError> {
+    Err(TypeError::ReadOnlyModule { package: self.package.clone(), module: self.name.clone(), name: String::from(name) })
+  }
+
+  fn lookup_type(&self, _name: &str) -> Option<Shape> {
+    None
+  }
+
+  fn list_types(&self) -> Vec<String> {
+    Vec::new()
+  }
+
+  fn register_type(&mut self, name: &str, _shape: Shape) -> Result<(), TypeError> {
+    Err(TypeError::ReadOnlyModule { package: self.package.clone(), module: self.name.clone(), name: String::from(name) })
+  }
+
+  fn lookup_fields(&self, _name: &str) -> Option<Vec<(String, Shape)>> {
+    None
+  }
+
+  fn register_fields(&mut self, name: &str, _fields: Vec<(String, Shape)>) -> Result<(), TypeError> {
+    Err(TypeError::ReadOnlyModule { package: self.package.clone(), module: self.name.clone(), name: String::from(name) })
+  }
 }
 
 impl ModuleShapes for CoreModuleShapes {
@@ -498,6 +2388,46 @@ impl ModuleShapes for CoreModuleShapes {
   fn list_values(&self) -> Vec<String> {
     self.functions.keys().into_iter().map(|i| i.clone()).collect()
   }
+
+  fn visibility(&self, name: &str) -> Visibility {
+    self.visibility.get(name).cloned().unwrap_or(Visibility::Public)
+  }
+
+  fn register(&mut self, name: &str, shape: Shape, visibility: Visibility) -> Result<(), TypeError> {
+    if self.functions.contains_key(name) {
+      return Err(TypeError::AlreadyDeclared { name: String::from(name) });
+    }
+
+    self.functions.insert(String::from(name), shape);
+    self.visibility.insert(String::from(name), visibility);
+    Ok(())
+  }
+
+  fn lookup_type(&self, name: &str) -> Option<Shape> {
+    self.types.get(name).map(|shape| shape.clone())
+  }
+
+  fn list_types(&self) -> Vec<String> {
+    self.types.keys().into_iter().map(|i| i.clone()).collect()
+  }
+
+  fn register_type(&mut self, name: &str, shape: Shape) -> Result<(), TypeError> {
+    if self.types.contains_key(name) {
+      return Err(TypeError::AlreadyDeclared { name: String::from(name) });
+    }
+
+    self.types.insert(String::from(name), shape);
+    Ok(())
+  }
+
+  fn lookup_fields(&self, name: &str) -> Option<Vec<(String, Shape)>> {
+    self.fields.get(name).cloned()
+  }
+
+  fn register_fields(&mut self, name: &str, fields: Vec<(String, Shape)>) -> Result<(), TypeError> {
+    self.fields.insert(String::from(name), fields);
+    Ok(())
+  }
 }
 
 fn core_package() -> Box<PackageShapes> {
@@ -505,6 +2435,11 @@ fn core_package() -> Box<PackageShapes> {
 
   modules.insert(String::from("Core"), core_module());
   modules.insert(String::from("List"), list_module());
+  modules.insert(String::from("Map"), map_module());
+  modules.insert(String::from("Console"), console_module());
+  modules.insert(String::from("Assert"), assert_module());
+  modules.insert(String::from("Env"), env_module());
+  modules.insert(String::from("File"), file_module());
 
   Box::new(PackageShapesBundle {
     modules
@@ -546,8 +2481,170 @@ fn list_module() -> Box<ModuleShapes> {
     result: Box::new(shape_float())
   });
 
+  functions.insert(String::from("range"), Shape::SimpleFunctionShape {
+    args: vec![shape_float(), shape_float(), shape_float()],
+    result: Box::new(float_list.clone())
+  });
+
+  Box::new(CoreModuleShapes {
+    functions,
+    visibility: HashMap::new(),
+    types: HashMap::new(),
+    fields: HashMap::new(),
+  })
+}
+
+fn console_module() -> Box<ModuleShapes> {
+  let mut functions = HashMap::new();
+
+  let printer = Shape::SimpleFunctionShape {
+    args: vec![shape_string()],
+    result: Box::new(shape_unit())
+  };
+
+  functions.insert(String::from("print"), printer.clone());
+  functions.insert(String::from("println"), printer);
+
+  functions.insert(String::from("readLine"), Shape::SimpleFunctionShape {
+    args: vec![],
+    result: Box::new(shape_string())
+  });
+
+  Box::new(CoreModuleShapes {
+    functions,
+    visibility: HashMap::new(),
+    types: HashMap::new(),
+    fields: HashMap::new(),
+  })
+}
+
+fn assert_module() -> Box<ModuleShapes> {
+  let mut functions = HashMap::new();
+
+  functions.insert(String::from("assertEq"), Shape::SimpleFunctionShape {
+    args: vec![shape_float(), shape_float()],
+    result: Box::new(shape_unit())
+  });
+
+  functions.insert(String::from("assertTrue"), Shape::SimpleFunctionShape {
+    args: vec![shape_boolean()],
+    result: Box::new(shape_unit())
+  });
+
+  Box::new(CoreModuleShapes {
+    functions,
+    visibility: HashMap::new(),
+    types: HashMap::new(),
+    fields: HashMap::new(),
+  })
+}
+
+fn env_module() -> Box<ModuleShapes> {
+  let mut functions = HashMap::new();
+
+  functions.insert(String::from("args"), Shape::SimpleFunctionShape {
+    args: vec![],
+    result: Box::new(shape_list(shape_string()))
+  });
+
+  functions.insert(String::from("getVar"), Shape::SimpleFunctionShape {
+    args: vec![shape_string()],
+    result: Box::new(shape_string())
+  });
+
+  Box::new(CoreModuleShapes {
+    functions,
+    visibility: HashMap::new(),
+    types: HashMap::new(),
+    fields: HashMap::new(),
+  })
+}
+
+fn file_module() -> Box<ModuleShapes> {
+  let mut functions = HashMap::new();
+
+  functions.insert(String::from("readText"), Shape::SimpleFunctionShape {
+    args: vec![shape_string()],
+    result: Box::new(shape_string())
+  });
+
+  functions.insert(String::from("writeText"), Shape::SimpleFunctionShape {
+    args: vec![shape_string(), shape_string()],
+    result: Box::new(shape_unit())
+  });
+
+  functions.insert(String::from("exists"), Shape::SimpleFunctionShape {
+    args: vec![shape_string()],
+    result: Box::new(shape_boolean())
+  });
+
+  Box::new(CoreModuleShapes {
+    functions,
+    visibility: HashMap::new(),
+    types: HashMap::new(),
+    fields: HashMap::new(),
+  })
+}
+
+fn map_module() -> Box<ModuleShapes> {
+  let mut functions = HashMap::new();
+
+  // Generic over the key, the value and fold's accumulator, each use gets its own. See Scope::import_module.
+  let key = Shape::VariableShape { id: 0 };
+  let value = Shape::VariableShape { id: 1 };
+  let acc = Shape::VariableShape { id: 2 };
+  let map = shape_map(key.clone(), value.clone());
+
+  functions.insert(String::from("new"), Shape::SimpleFunctionShape {
+    args: vec![],
+    result: Box::new(map.clone())
+  });
+
+  functions.insert(String::from("put"), Shape::SimpleFunctionShape {
+    args: vec![map.clone(), key.clone(), value.clone()],
+    result: Box::new(map.clone())
+  });
+
+  functions.insert(String::from("get"), Shape::SimpleFunctionShape {
+    args: vec![map.clone(), key.clone()],
+    result: Box::new(shape_option(value.clone()))
+  });
+
+  functions.insert(String::from("contains"), Shape::SimpleFunctionShape {
+    args: vec![map.clone(), key.clone()],
+    result: Box::new(shape_boolean())
+  });
+
+  functions.insert(String::from("remove"), Shape::SimpleFunctionShape {
+    args: vec![map.clone(), key.clone()],
+    result: Box::new(map.clone())
+  });
+
+  functions.insert(String::from("keys"), Shape::SimpleFunctionShape {
+    args: vec![map.clone()],
+    result: Box::new(shape_list(key.clone()))
+  });
+
+  functions.insert(String::from("values"), Shape::SimpleFunctionShape {
+    args: vec![map.clone()],
+    result: Box::new(shape_list(value.clone()))
+  });
+
+  let reducer_shape = Shape::SimpleFunctionShape {
+    args: vec![acc.clone(), key.clone(), value.clone()],
+    result: Box::new(acc.clone())
+  };
+
+  functions.insert(String::from("fold"), Shape::SimpleFunctionShape {
+    args: vec![map.clone(), acc.clone(), reducer_shape],
+    result: Box::new(acc.clone())
+  });
+
   Box::new(CoreModuleShapes {
-    functions
+    functions,
+    visibility: HashMap::new(),
+    types: HashMap::new(),
+    fields: HashMap::new(),
   })
 }
 
@@ -561,20 +2658,41 @@ fn core_module() -> Box<ModuleShapes> {
     args: vec![shape_float(), shape_float()],
     result: Box::new(shape_boolean())
   };
+  // Any two values of the same shape, which the typechecker checks for == and != itself.
+  let equality = Shape::SimpleFunctionShape {
+    args: vec![shape_unknown(), shape_unknown()],
+    result: Box::new(shape_boolean())
+  };
 
   functions.insert(String::from("+"), float_math.clone());
   functions.insert(String::from("-"), float_math.clone());
   functions.insert(String::from("*"), float_math.clone());
   functions.insert(String::from("/"), float_math.clone());
 
-  functions.insert(String::from("=="), float_compare.clone());
-  functions.insert(String::from("!="), float_compare.clone());
+  functions.insert(String::from("=="), equality.clone());
+  functions.insert(String::from("!="), equality.clone());
+  functions.insert(String::from("equals"), equality.clone());
   functions.insert(String::from(">"), float_compare.clone());
   functions.insert(String::from(">="), float_compare.clone());
   functions.insert(String::from("<"), float_compare.clone());
   functions.insert(String::from("<="), float_compare.clone());
 
+  // Takes a value of any shape.
+  functions.insert(String::from("show"), Shape::SimpleFunctionShape {
+    args: vec![shape_unknown()],
+    result: Box::new(shape_string()),
+  });
+
+  // Only for functions that return a Float for now, the error is the panic's message.
+  functions.insert(String::from("tryCall"), Shape::SimpleFunctionShape {
+    args: vec![Shape::SimpleFunctionShape { args: vec![], result: Box::new(shape_float()) }],
+    result: Box::new(shape_result(shape_float(), shape_string())),
+  });
+
   Box::new(CoreModuleShapes {
-    functions
+    functions,
+    visibility: HashMap::new(),
+    types: HashMap::new(),
+    fields: HashMap::new(),
   })
 }
@@ -0,0 +1,70 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use compiler::MANIFEST_FILE;
+use error::CompileError;
+
+/**
+* Notices changes to the sources under a directory by polling, so watching needs nothing beyond std. A file counts
+* as changed when its modified time or size is different, or when it appears or disappears.
+*/
+pub struct Watcher {
+  dir: PathBuf,
+  stamps: Vec<(PathBuf, SystemTime, u64)>,
+}
+
+impl Watcher {
+
+  pub fn new<P: Into<PathBuf>>(dir: P) -> Result<Watcher, CompileError> {
+    let dir = dir.into();
+    let stamps = stamps(&dir)?;
+
+    Ok(Watcher { dir, stamps })
+  }
+
+  /**
+  * Whether anything changed since the watcher was made or last asked.
+  */
+  pub fn changed(&mut self) -> Result<bool, CompileError> {
+    let next = stamps(&self.dir)?;
+    let changed = next != self.stamps;
+
+    self.stamps = next;
+    Ok(changed)
+  }
+}
+
+/**
+* Every .let file and manifest under dir, sorted by path so two walks of the same tree compare equal.
+*/
+fn stamps(dir: &Path) -> Result<Vec<(PathBuf, SystemTime, u64)>, CompileError> {
+  let mut stamps = Vec::new();
+  let mut dirs = vec![dir.to_path_buf()];
+
+  while let Some(next_dir) = dirs.pop() {
+    for entry in fs::read_dir(&next_dir).map_err(|err| io_error(&next_dir, err))? {
+      let path = entry.map_err(|err| io_error(&next_dir, err))?.path();
+
+      if path.is_dir() {
+        dirs.push(path);
+      } else if is_source(&path) {
+        let meta = fs::metadata(&path).map_err(|err| io_error(&path, err))?;
+        let modified = meta.modified().map_err(|err| io_error(&path, err))?;
+
+        stamps.push((path, modified, meta.len()));
+      }
+    }
+  }
+
+  stamps.sort_by(|left, right| left.0.cmp(&right.0));
+  Ok(stamps)
+}
+
+fn is_source(path: &Path) -> bool {
+  path.extension().and_then(|ex| ex.to_str()) == Some("let") || path.file_name().and_then(|name| name.to_str()) == Some(MANIFEST_FILE)
+}
+
+fn io_error<E: ToString>(path: &Path, err: E) -> CompileError {
+  CompileError::Io { path: path.display().to_string(), message: err.to_string() }
+}
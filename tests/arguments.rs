@@ -0,0 +1,53 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+use rust_let_lang::format::format_module;
+use rust_let_lang::parser::parse_str;
+
+use common::call;
+
+const SRC: &'static str = "
+fun line(x: Float, slope: Float = 1, offset: Float = 0): Float = x * slope + offset
+
+fun positional(): Float = line(2, 3, 4)
+
+fun defaulted(): Float = line(2)
+
+fun named(): Float = line(2, offset = 10)
+
+fun reordered(): Float = line(offset = 1, slope = 5, x = 2)
+
+fun piped(): Float = 2 |> line(slope = 4)
+";
+
+#[test]
+fn named_and_default_arguments() {
+  assert_eq!(call(SRC, "positional").unwrap(), Value::Float(10.0));
+  assert_eq!(call(SRC, "defaulted").unwrap(), Value::Float(2.0));
+  assert_eq!(call(SRC, "named").unwrap(), Value::Float(12.0));
+  assert_eq!(call(SRC, "reordered").unwrap(), Value::Float(11.0));
+  assert_eq!(call(SRC, "piped").unwrap(), Value::Float(8.0));
+}
+
+#[test]
+fn bad_arguments_are_type_errors() {
+  let base = "fun line(x: Float, slope: Float = 1): Float = x * slope\n";
+
+  let error = |body: &str| call(&format!("{}fun bad(): Float = {}\n", base, body), "bad").unwrap_err();
+
+  assert!(error("line(slope = 2)").contains("No argument passed for parameter x"));
+  assert!(error("line(1, size = 2)").contains("No parameter named size"));
+  assert!(error("line(1, x = 2)").contains("Argument x is passed more than once"));
+  assert!(error("{ let f = line\n f(1, slope = 2) }").contains("Named arguments can only be passed to functions declared in this module"));
+  assert!(call("fun bad(x: Float = \"one\"): Float = x\n", "bad").is_err());
+}
+
+#[test]
+fn arguments_format_as_written() {
+  let src = "fun line(x: Float, slope: Float = 1, label: String = \"line\"): Float = line(x, label = \"other\")\n";
+  let module = parse_str(src, "test", "arguments").unwrap();
+
+  assert_eq!(format_module(&module), src);
+}
@@ -0,0 +1,37 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+
+use common::call_with;
+
+#[test]
+fn operators_follow_float_rules() {
+  let src = "
+fun calc(a: Float, b: Float): Float = (a - b) * 2 / b + a
+";
+
+  assert_eq!(call_with(src, "calc", vec![Value::Float(7.0), Value::Float(2.0)]).unwrap(), Value::Float(12.0));
+  assert_eq!(call_with(src, "calc", vec![Value::Float(1.0), Value::Float(0.0)]).unwrap(), Value::Float(::std::f64::INFINITY));
+}
+
+#[test]
+fn comparisons_are_inclusive_only_with_equals() {
+  let src = "
+fun below(a: Float, b: Float): Boolean = a < b
+
+fun atMost(a: Float, b: Float): Boolean = a <= b
+
+fun above(a: Float, b: Float): Boolean = a > b
+
+fun atLeast(a: Float, b: Float): Boolean = a >= b
+";
+
+  let same = || vec![Value::Float(3.0), Value::Float(3.0)];
+
+  assert_eq!(call_with(src, "below", same()).unwrap(), Value::False);
+  assert_eq!(call_with(src, "atMost", same()).unwrap(), Value::True);
+  assert_eq!(call_with(src, "above", same()).unwrap(), Value::False);
+  assert_eq!(call_with(src, "atLeast", same()).unwrap(), Value::True);
+}
@@ -0,0 +1,47 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::format::format_module;
+use rust_let_lang::parser::parse_str;
+
+use common::call;
+
+#[test]
+fn arrows_are_lambdas() {
+  let src = "
+import Core::List;
+
+fun doubled(): List[Float] = [1, 2, 3].map(x -> x * 2)
+
+fun total(): Float = (1..5).fold(0, (acc, x) -> acc + x)
+
+fun typed(): Float = {
+  let add = (a: Float, b: Float) -> a + b
+  add(1, 2)
+}
+
+fun constant(): Float = {
+  let five = () -> 5
+  five()
+}
+
+fun curried(): Float = {
+  let add = a -> b -> a + b
+  add(3)(4)
+}
+";
+
+  assert_eq!(call(src, "doubled").unwrap().to_string(), "[2, 4, 6]");
+  assert_eq!(call(src, "total").unwrap().to_string(), "10");
+  assert_eq!(call(src, "typed").unwrap().to_string(), "3");
+  assert_eq!(call(src, "constant").unwrap().to_string(), "5");
+  assert_eq!(call(src, "curried").unwrap().to_string(), "7");
+}
+
+#[test]
+fn arrows_format_as_braced_lambdas() {
+  let module = parse_str("fun doubled(xs: List[Float]): List[Float] = xs.map(x -> x * 2)\n", "test", "arrows").unwrap();
+
+  assert_eq!(format_module(&module), "fun doubled(xs: List[Float]): List[Float] = xs.map({x => x * 2})\n");
+}
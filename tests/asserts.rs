@@ -0,0 +1,59 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::{AppShapes, BitApplication, Machine, Optimizer, OptLevel, Value};
+use rust_let_lang::compiler::compile_source_module;
+use rust_let_lang::optimize::Pass;
+
+const SOURCE: &'static str = "
+fun checked(x: Float): Float = {
+  assert x > 0
+  x * 2
+}
+";
+
+fn call(level: OptLevel, arg: f64) -> String {
+  call_with(&Optimizer::with_level(level), arg)
+}
+
+fn call_with(optimizer: &Optimizer, arg: f64) -> String {
+  let module = compile_source_module("test", "asserts", SOURCE, optimizer, &AppShapes::new()).unwrap();
+
+  let mut machine = Machine::new(BitApplication::library());
+  machine.load_module("test", "asserts", module);
+
+  match machine.call("test", "asserts", "checked", vec![Value::Float(arg)]) {
+    Ok(value) => format!("{:?}", value),
+    Err(err) => err.to_string(),
+  }
+}
+
+#[test]
+fn passing_assert_continues() {
+  assert_eq!(call(OptLevel::O1, 2.0), "Float(4.0)");
+}
+
+#[test]
+fn failing_assert_reports_condition_and_point() {
+  assert_eq!(call(OptLevel::O1, -2.0), "Assertion failed: x > 0 in test::asserts.checked at line: 3, column: 3");
+}
+
+#[test]
+fn asserts_are_kept_at_o2() {
+  assert_eq!(call(OptLevel::O2, -2.0), "Assertion failed: x > 0 in test::asserts.checked at line: 3, column: 3");
+}
+
+#[test]
+fn asserts_are_stripped_when_asked() {
+  let optimizer = Optimizer::builder(OptLevel::O2).enable(Pass::StripAssert).build();
+
+  assert_eq!(call_with(&optimizer, -2.0), "Float(-4.0)");
+}
+
+#[test]
+fn compile_source_keeps_asserts() {
+  let result = common::call_with(SOURCE, "checked", vec![Value::Float(-2.0)]);
+
+  assert_eq!(result.unwrap_err(), "Assertion failed: x > 0 in script::Main.checked at line: 3, column: 3");
+}
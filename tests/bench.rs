@@ -0,0 +1,20 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::bench::{PROGRAMS, configs, report, run_benchmarks};
+
+#[test]
+fn every_config_agrees() {
+  let measurements = run_benchmarks(1).unwrap();
+
+  assert_eq!(measurements.len(), PROGRAMS.len() * configs().len());
+
+  for program in PROGRAMS.iter() {
+    let results: Vec<_> = measurements.iter().filter(|measurement| measurement.program == program.name).collect();
+
+    for measurement in &results {
+      assert_eq!(measurement.result, results[0].result, "{} on {}", program.name, measurement.config.name());
+    }
+  }
+
+  assert_eq!(report(&measurements).lines().count(), measurements.len() + 1);
+}
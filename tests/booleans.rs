@@ -0,0 +1,34 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+
+use common::call_with;
+
+#[test]
+fn booleans_can_be_written() {
+  let src = "
+fun yes(): Boolean = true
+
+fun no(): Boolean = false
+
+fun either(flag: Boolean = true): Boolean = if (flag) false else true
+
+fun defaulted(): Boolean = either()
+";
+
+  assert_eq!(call_with(src, "yes", vec![]).unwrap(), Value::True);
+  assert_eq!(call_with(src, "no", vec![]).unwrap(), Value::False);
+  assert_eq!(call_with(src, "either", vec![Value::False]).unwrap(), Value::True);
+  assert_eq!(call_with(src, "defaulted", vec![]).unwrap(), Value::False);
+}
+
+#[test]
+fn booleans_compare() {
+  let src = "
+fun same(): Boolean = true == (1 < 2)
+";
+
+  assert_eq!(call_with(src, "same", vec![]).unwrap(), Value::True);
+}
@@ -0,0 +1,84 @@
+extern crate rust_let_lang;
+
+use std::fs;
+use std::path::PathBuf;
+
+use rust_let_lang::{AppShapes, OptLevel, Optimizer};
+use rust_let_lang::cache::BuildCache;
+use rust_let_lang::compiler::compile_package_cached;
+
+const UTIL: &'static str = "public fun two(): Float = 2\n";
+const MAIN: &'static str = "import scratch::util\n\npublic fun four(): Float = util.two() * 2\n";
+
+fn scratch_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("letlang-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(dir.join("src")).unwrap();
+
+  fs::write(dir.join("src").join("util.let"), UTIL).unwrap();
+  fs::write(dir.join("src").join("main.let"), MAIN).unwrap();
+  dir
+}
+
+fn build(dir: &PathBuf, cache: &BuildCache) -> Result<AppShapes, String> {
+  let mut shapes = AppShapes::new();
+  let src = dir.join("src");
+
+  compile_package_cached("scratch", src.to_str().unwrap(), &Optimizer::with_level(OptLevel::O2), &mut shapes, cache)
+    .map_err(|err| err.to_string())?;
+
+  Ok(shapes)
+}
+
+#[test]
+fn unchanged_modules_are_served_from_the_cache() {
+  let dir = scratch_dir("cache-hit");
+  let cache = BuildCache::new(dir.join("cache"));
+
+  let shapes = build(&dir, &cache).unwrap();
+
+  assert!(cache.load("scratch", "util", UTIL.as_bytes(), &shapes).is_some());
+  assert!(cache.load("scratch", "main", MAIN.as_bytes(), &shapes).is_some());
+
+  // A second build reads everything back and still registers the same shapes.
+  let shapes = build(&dir, &cache).unwrap();
+  assert!(cache.load("scratch", "main", MAIN.as_bytes(), &shapes).is_some());
+
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn edited_sources_miss() {
+  let dir = scratch_dir("cache-edit");
+  let cache = BuildCache::new(dir.join("cache"));
+
+  let shapes = build(&dir, &cache).unwrap();
+  let edited = MAIN.replace("* 2", "* 3");
+
+  assert!(cache.load("scratch", "main", edited.as_bytes(), &shapes).is_none());
+  assert!(cache.imports("scratch", "main", edited.as_bytes()).is_none());
+
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn changed_imports_invalidate_their_importers() {
+  let dir = scratch_dir("cache-deps");
+  let cache = BuildCache::new(dir.join("cache"));
+
+  build(&dir, &cache).unwrap();
+
+  // Rebuild util alone with a different signature for two, main's source is unchanged.
+  fs::remove_file(dir.join("src").join("main.let")).unwrap();
+  fs::write(dir.join("src").join("util.let"), "public fun two(): String = \"2\"\n").unwrap();
+  let shapes = build(&dir, &cache).unwrap();
+
+  assert!(cache.imports("scratch", "main", MAIN.as_bytes()).is_some());
+  assert!(cache.load("scratch", "main", MAIN.as_bytes(), &shapes).is_none());
+
+  // So putting main back checks it again against the new util, instead of reusing the stale entry.
+  fs::write(dir.join("src").join("main.let"), MAIN).unwrap();
+  assert!(build(&dir, &cache).is_err());
+
+  fs::remove_dir_all(&dir).unwrap();
+}
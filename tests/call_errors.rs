@@ -0,0 +1,43 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::compile_source;
+
+fn error(body: &str) -> String {
+  let src = format!("fun line(x: Float, slope: Float = 1): Float = x * slope\n\nfun bad(): Float = {}\n", body);
+  compile_source(&src).err().unwrap().to_string()
+}
+
+#[test]
+fn mismatches_name_the_function_and_argument() {
+  let err = error("line(1, \"two\")");
+  assert!(err.contains("Invalid argument types for call to line, argument 2 expected Float but found String"), "{}", err);
+}
+
+#[test]
+fn counts_show_the_shape_called() {
+  let err = error("line(1, 2, 3)");
+  assert!(err.contains("Incorrect number of arguments for call to line: { Float, Float -> Float }, expected 2 but found 3"), "{}", err);
+
+  let err = error("{ let f = line\n f(1) }");
+  assert!(err.contains("Incorrect number of arguments for call to f: { Float, Float -> Float }, expected 2 but found 1"), "{}", err);
+}
+
+#[test]
+fn calls_without_a_name_say_so() {
+  let err = error("{x: Float => x}(\"one\")");
+  assert!(err.contains("Invalid argument types for call to a function, argument 1 expected Float but found String"), "{}", err);
+}
+
+#[test]
+fn non_functions_are_named() {
+  let err = error("{ let two = 2\n two(1) }");
+  assert!(err.contains("Attempt to call two, a non-function of type Float"), "{}", err);
+}
+
+#[test]
+fn named_argument_errors_name_the_function() {
+  assert!(error("line(slope = 2)").contains("No argument passed for parameter x in call to line"));
+  assert!(error("line(1, size = 2)").contains("No parameter named size in call to line"));
+  assert!(error("line(1, x = 2)").contains("Argument x is passed more than once in call to line"));
+  assert!(error("{ let f = line\n f(1, slope = 2) }").contains("not to f"));
+}
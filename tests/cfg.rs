@@ -0,0 +1,99 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::{Optimizer, OptLevel};
+use rust_let_lang::cfg::{Cfg, Exit};
+use rust_let_lang::ir::Ir;
+use rust_let_lang::optimize::Pass;
+
+use common::{float, ir_function, load, store};
+
+fn render(body: &Vec<Ir>) -> String {
+  let mut out = Vec::new();
+  Ir::pretty_print(body, "", &mut out).unwrap();
+  String::from_utf8(out).unwrap()
+}
+
+/**
+* x = 0, while (x < n) { x = x + 1 }, if (x > 10) 1 else x
+*/
+fn branchy() -> Vec<Ir> {
+  vec![
+    float(0.0), store("x"),
+    Ir::Loop {
+      condition: vec![load("x"), load("n"), Ir::LessFloat],
+      body: vec![load("x"), float(1.0), Ir::AddFloat, store("x")],
+    },
+    load("x"), float(10.0), Ir::GreaterFloat,
+    Ir::Branch {
+      then_block: vec![float(1.0), Ir::Return],
+      else_block: vec![load("x"), Ir::Return],
+    },
+  ]
+}
+
+#[test]
+fn loops_and_branches_become_edges() {
+  let cfg = Cfg::lower(&ir_function(&["n"], branchy()));
+  let exits: Vec<Exit> = cfg.blocks.iter().map(|block| block.exit.clone()).collect();
+
+  assert_eq!(exits, vec![
+    Exit::Jump(1),
+    Exit::Branch { then_block: 2, else_block: 3 },
+    Exit::Jump(1),
+    Exit::Branch { then_block: 4, else_block: 5 },
+    Exit::Return,
+    Exit::Return,
+    Exit::Jump(8),
+    Exit::Jump(8),
+    Exit::Stop,
+  ]);
+
+  let live_out = cfg.live_out();
+  assert!(live_out[0].contains("x") && live_out[0].contains("n"));
+  assert!(!live_out[3].contains("n"));
+}
+
+#[test]
+fn raising_puts_every_block_back() {
+  let body = branchy();
+  let mut func = ir_function(&["n"], body.clone());
+
+  Cfg::lower(&func).raise(&mut func);
+
+  assert_eq!(render(&func.body), render(&body));
+}
+
+#[test]
+fn stores_nothing_reads_are_popped() {
+  let mut func = ir_function(&["n"], vec![
+    load("n"), store("unused"),
+    load("n"), store("x"),
+    Ir::LoadConstTrue,
+    Ir::Branch {
+      then_block: vec![float(1.0), store("x")],
+      else_block: vec![],
+    },
+    load("x"),
+    Ir::Return,
+  ]);
+
+  Optimizer::builder(OptLevel::O0).enable(Pass::DeadStore).build().optimize(&mut func);
+
+  assert_eq!(render(&func.body), "\
+0: LoadValue(n)
+1: Pop
+2: LoadValue(n)
+3: StoreValue(x)
+4: LoadConstTrue
+5: Branch
+  then_block:
+    0: LoadConstFloat(1)
+    1: StoreValue(x)
+  else_block:
+
+6: LoadValue(x)
+7: Return
+");
+}
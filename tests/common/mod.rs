@@ -0,0 +1,76 @@
+// Each test file uses only some of these.
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use rust_let_lang::{AppShapes, BitApplication, FunctionRef, Linker, Machine, Optimizer, OptLevel, SOURCE_MODULE, SOURCE_PACKAGE, Shape, Value, compile_source};
+use rust_let_lang::compiler::compile_package;
+use rust_let_lang::ast::Parameter;
+use rust_let_lang::ir::{Ir, IrFunction};
+use rust_let_lang::shapes::shape_float;
+
+/**
+* A machine with src compiled and loaded as SOURCE_MODULE. Panics if src doesn't compile.
+*/
+pub fn machine(src: &str) -> Machine {
+  let package = compile_source(src).unwrap();
+
+  let mut app = BitApplication::library();
+  app.packages.insert(String::from(SOURCE_PACKAGE), package);
+
+  Machine::new(app)
+}
+
+/**
+* Compiles src and calls name in it without arguments. Compile and runtime errors both come back as their message.
+*/
+pub fn call(src: &str, name: &str) -> Result<Value, String> {
+  call_with(src, name, vec![])
+}
+
+pub fn call_with(src: &str, name: &str, args: Vec<Value>) -> Result<Value, String> {
+  let package = compile_source(src).map_err(|err| err.to_string())?;
+
+  let mut app = BitApplication::library();
+  app.packages.insert(String::from(SOURCE_PACKAGE), package);
+
+  Machine::new(app).call(SOURCE_PACKAGE, SOURCE_MODULE, name, args).map_err(|err| err.to_string())
+}
+
+/**
+* Compiles and links the sample package in test/ as package test, then calls name in its module. Panics if it doesn't
+* build, runtime errors come back as their message.
+*/
+pub fn call_test_package(module: &str, name: &str, args: Vec<Value>) -> Result<Value, String> {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+  let package = compile_package("test", dir.to_str().unwrap(), &Optimizer::with_level(OptLevel::O2), &mut AppShapes::new()).unwrap();
+  let app = Linker::new().package("test", package).link().unwrap();
+
+  Machine::new(app).call("test", module, name, args).map_err(|err| err.to_string())
+}
+
+/**
+* A script::Main.test function returning a Float, taking a Float for each of args, with body as its IR.
+*/
+pub fn ir_function(args: &[&str], body: Vec<Ir>) -> IrFunction {
+  let shape = Shape::SimpleFunctionShape { args: args.iter().map(|_| shape_float()).collect(), result: Box::new(shape_float()) };
+
+  IrFunction {
+    func_ref: FunctionRef { package: String::from("script"), module: String::from("Main"), name: String::from("test"), shape: shape.clone() },
+    args: args.iter().map(|id| Parameter { id: String::from(*id), shape: shape_float() }).collect(),
+    body,
+    shape,
+  }
+}
+
+pub fn load(local: &str) -> Ir {
+  Ir::LoadValue { local: String::from(local) }
+}
+
+pub fn store(local: &str) -> Ir {
+  Ir::StoreValue { local: String::from(local) }
+}
+
+pub fn float(value: f64) -> Ir {
+  Ir::LoadConstFloat { value }
+}
@@ -0,0 +1,79 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::{Optimizer, OptLevel, Value};
+use rust_let_lang::ir::Ir;
+use rust_let_lang::optimize::Pass;
+
+use common::{call_with, ir_function, load, store};
+
+fn eliminate(body: Vec<Ir>) -> String {
+  let mut func = ir_function(&["a", "b"], body);
+
+  Optimizer::builder(OptLevel::O0).enable(Pass::CommonSubexpression).build().optimize(&mut func);
+
+  let mut out = Vec::new();
+  Ir::pretty_print(&func.body, "", &mut out).unwrap();
+  String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn repeats_load_the_first_result() {
+  let body = vec![
+    load("a"), load("b"), Ir::MulFloat,
+    load("a"), load("b"), Ir::MulFloat,
+    Ir::AddFloat,
+    load("a"), load("b"), Ir::MulFloat,
+    Ir::AddFloat,
+    Ir::Return,
+  ];
+
+  assert_eq!(eliminate(body), "\
+0: LoadValue(a)
+1: LoadValue(b)
+2: MulFloat
+3: Duplicate
+4: StoreValue(#cse1)
+5: LoadValue(#cse1)
+6: AddFloat
+7: LoadValue(#cse1)
+8: AddFloat
+9: Return
+");
+}
+
+#[test]
+fn stores_to_an_operand_end_the_reuse() {
+  let body = vec![
+    load("a"), load("b"), Ir::SubFloat,
+    store("a"),
+    load("a"), load("b"), Ir::SubFloat,
+    Ir::Return,
+  ];
+
+  assert_eq!(eliminate(body), "\
+0: LoadValue(a)
+1: LoadValue(b)
+2: SubFloat
+3: StoreValue(a)
+4: LoadValue(a)
+5: LoadValue(b)
+6: SubFloat
+7: Return
+");
+}
+
+#[test]
+fn optimized_code_gives_the_same_answers() {
+  let src = "
+fun norm(x: Float, y: Float): Float = {
+  let mut total = x * y + x * y
+  total = x * y + total
+  total
+}
+";
+
+  let result = call_with(src, "norm", vec![Value::Float(2.0), Value::Float(3.0)]).unwrap();
+  assert_eq!(result, Value::Float(18.0));
+}
@@ -0,0 +1,70 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::{Value, compile_source};
+
+use common::call_with;
+
+#[test]
+fn braced_ifs_need_no_parentheses() {
+  let src = "
+fun sign(x: Float): Float = if x < 0 {
+  -1
+} else if x == 0 {
+  0
+} else {
+  1
+}
+";
+
+  assert_eq!(call_with(src, "sign", vec![Value::Float(-5.0)]).unwrap(), Value::Float(-1.0));
+  assert_eq!(call_with(src, "sign", vec![Value::Float(0.0)]).unwrap(), Value::Float(0.0));
+  assert_eq!(call_with(src, "sign", vec![Value::Float(3.0)]).unwrap(), Value::Float(1.0));
+}
+
+#[test]
+fn braced_ifs_are_operands() {
+  let src = "
+fun bump(x: Float): Float = 10 + if x > 1 { x } else { 1 } * 2
+
+fun clamp(x: Float): Float = {
+  let mut result = x
+  if x > 10 { result = 10 }
+  (result)
+}
+";
+
+  assert_eq!(call_with(src, "bump", vec![Value::Float(3.0)]).unwrap(), Value::Float(16.0));
+  assert_eq!(call_with(src, "bump", vec![Value::Float(0.0)]).unwrap(), Value::Float(12.0));
+  assert_eq!(call_with(src, "clamp", vec![Value::Float(20.0)]).unwrap(), Value::Float(10.0));
+  assert_eq!(call_with(src, "clamp", vec![Value::Float(5.0)]).unwrap(), Value::Float(5.0));
+}
+
+#[test]
+fn branches_must_be_blocks() {
+  let errors = compile_source("fun pick(x: Boolean): Float = if x 1 else 2").err().unwrap();
+
+  assert!(errors.to_string().contains("Unexpected '1'"), "{}", errors);
+}
+
+#[test]
+fn arms_ending_in_reassignments_leave_unit() {
+  let src = "
+fun pick(): Float = {
+  let mut a = 1
+  if (a > 0) { a = 5 } else { a = 6 }
+  a
+}
+
+fun chained(x: Float): Float = {
+  let mut a = 1
+  if (x > 0) { a = 5 } else if (x < 0) { a = a + 1 } else { a = 7 }
+  a
+}
+";
+
+  assert_eq!(call_with(src, "pick", vec![]).unwrap(), Value::Float(5.0));
+  assert_eq!(call_with(src, "chained", vec![Value::Float(-1.0)]).unwrap(), Value::Float(2.0));
+  assert_eq!(call_with(src, "chained", vec![Value::Float(0.0)]).unwrap(), Value::Float(7.0));
+}
@@ -0,0 +1,35 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::rc::Rc;
+
+use rust_let_lang::{SOURCE_MODULE, SOURCE_PACKAGE, Value, compile_source};
+
+use common::machine;
+
+const SRC: &'static str = "
+fun greeting(): String = \"hello\"
+
+fun again(): String = \"hello\"
+";
+
+#[test]
+fn string_constants_are_stored_once() {
+  let package = compile_source(SRC).unwrap();
+  let module = &package.modules[SOURCE_MODULE];
+
+  assert_eq!(module.string_constants.iter().filter(|value| value.as_str() == "hello").count(), 1);
+}
+
+#[test]
+fn string_constants_are_shared_between_loads() {
+  let machine = machine(SRC);
+  let first = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "greeting", vec![]).unwrap();
+  let second = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "again", vec![]).unwrap();
+
+  match (first, second) {
+    (Value::String(first), Value::String(second)) => assert!(Rc::ptr_eq(&first, &second)),
+    other => panic!("Expected two strings, found {:?}", other),
+  }
+}
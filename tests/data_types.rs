@@ -0,0 +1,80 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::compile_source;
+
+use common::call_test_package;
+
+fn call(module: &str, name: &str) -> String {
+  call_test_package(module, name, vec![]).unwrap().to_string()
+}
+
+#[test]
+fn constructors_build_data() {
+  assert_eq!(call("boxes", "boxed"), "Box(1)");
+  assert_eq!(call("boxes", "paired"), "Pair(3, 'three')");
+  assert_eq!(call("boxes", "origin"), "Point(0, 0)");
+  assert_eq!(call("boxes", "same"), "true");
+}
+
+#[test]
+fn constructors_are_instantiated_at_each_use() {
+  assert_eq!(call("boxes", "labelled"), "Box('one')");
+  assert_eq!(call("boxes", "nested"), "Box(Box(2))");
+}
+
+#[test]
+fn data_types_cross_modules() {
+  assert_eq!(call("crates", "crated"), "Box(5)");
+  assert_eq!(call("crates", "stacked"), "[Box('a'), Box('one')]");
+}
+
+#[test]
+fn fields_read_back_what_was_built() {
+  assert_eq!(call("boxes", "unboxed"), "2");
+  assert_eq!(call("boxes", "second"), "'three'");
+  assert_eq!(call("boxes", "inner"), "2");
+  assert_eq!(call("boxes", "across"), "34");
+}
+
+#[test]
+fn fields_cross_modules() {
+  assert_eq!(call("crates", "uncrated"), "5");
+  assert_eq!(call("crates", "label"), "'one'");
+}
+
+#[test]
+fn fields_take_their_shape_from_the_type_arguments() {
+  let errors = compile_source("data Box[T](value: T)\n\nfun main(): Float = Box(\"one\").value").err().unwrap();
+  assert!(errors.to_string().contains("Declared: Float, but found: String"), "{}", errors);
+
+  let errors = compile_source("data Box[T](value: T)\n\nfun main(): Float = Box(1).other").err().unwrap();
+  assert!(errors.to_string().contains("No field other on type Main.Box[Float]"), "{}", errors);
+}
+
+#[test]
+fn type_arguments_must_agree() {
+  let errors = compile_source("data Box[T](value: T)\n\nfun main(): Box[String] = Box(1)").err().unwrap();
+  assert!(errors.to_string().contains("Declared: Main.Box[String], but found: Main.Box[Float]"), "{}", errors);
+}
+
+#[test]
+fn data_types_are_told_apart_by_name() {
+  let errors = compile_source("data Box[T](value: T)\ndata Crate[T](value: T)\n\nfun main(): Crate[Float] = Box(1)").err().unwrap();
+  assert!(errors.to_string().contains("Declared: Main.Crate[Float], but found: Main.Box[Float]"), "{}", errors);
+}
+
+#[test]
+fn data_types_take_exactly_their_parameters() {
+  let errors = compile_source("data Box[T](value: T)\n\nfun main(): Box[Float, Float] = Box(1)").err().unwrap();
+  assert!(errors.to_string().contains("Type Main.Box expects 1 type arguments but found 2"), "{}", errors);
+
+  let errors = compile_source("data Box[T](value: T)\n\nfun main(): Box = Box(1)").err().unwrap();
+  assert!(errors.to_string().contains("Type Main.Box expects 1 type arguments but found 0"), "{}", errors);
+}
+
+#[test]
+fn data_types_may_contain_themselves() {
+  assert!(compile_source("data Tree[T](value: T, children: List[Tree[T]])\n\nfun main(): Tree[Float] = Tree(1, [Tree(2, [])])").is_ok());
+}
@@ -0,0 +1,94 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rust_let_lang::{FunctionRef, RuntimeError, SOURCE_MODULE, SOURCE_PACKAGE, Value};
+use rust_let_lang::debugger::{Breakpoints, DebugAction, Debugger, Frame};
+use rust_let_lang::shapes::shape_unknown;
+use common::machine;
+
+const SRC: &'static str = "
+fun countdown(n: Float): Float = if (n <= 0) 0 else countdown(n - 1)
+
+fun main(): Float = countdown(3)
+";
+
+/**
+* Continues from every pause, remembering where it was and the first local at the time.
+*/
+struct Recorder {
+  pauses: Rc<RefCell<Vec<(String, usize, Value)>>>,
+  abort_after: Option<usize>,
+}
+
+impl Debugger for Recorder {
+  fn pause(&mut self, frame: &Frame, _breakpoints: &mut Breakpoints) -> DebugAction {
+    let mut pauses = self.pauses.borrow_mut();
+    pauses.push((frame.func.name.clone(), frame.index, frame.locals.get(0).cloned().unwrap_or(Value::Unit)));
+
+    match self.abort_after {
+      Some(count) if pauses.len() > count => DebugAction::Abort,
+      _ => DebugAction::Continue,
+    }
+  }
+}
+
+fn countdown() -> FunctionRef {
+  FunctionRef {
+    package: String::from(SOURCE_PACKAGE),
+    module: String::from(SOURCE_MODULE),
+    name: String::from("countdown"),
+    shape: shape_unknown(),
+  }
+}
+
+#[test]
+fn breakpoints_are_hit_on_every_call() {
+  let pauses = Rc::new(RefCell::new(Vec::new()));
+  let mut machine = machine(SRC);
+  machine.set_debugger(Box::new(Recorder { pauses: pauses.clone(), abort_after: None }));
+  machine.add_breakpoint(countdown(), 0);
+
+  assert_eq!(machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "main", vec![]).unwrap(), Value::Float(0.0));
+
+  let pauses = pauses.borrow();
+  // The first pause is the one a new debugger always gets before the first instruction.
+  assert_eq!(pauses[0].0, "main");
+  assert_eq!(pauses[1..].to_vec(), vec![
+    (String::from("countdown"), 0, Value::Float(3.0)),
+    (String::from("countdown"), 0, Value::Float(2.0)),
+    (String::from("countdown"), 0, Value::Float(1.0)),
+    (String::from("countdown"), 0, Value::Float(0.0)),
+  ]);
+}
+
+#[test]
+fn removed_breakpoints_are_not_hit() {
+  let pauses = Rc::new(RefCell::new(Vec::new()));
+  let mut machine = machine(SRC);
+  machine.set_debugger(Box::new(Recorder { pauses: pauses.clone(), abort_after: None }));
+  machine.add_breakpoint(countdown(), 0);
+  assert!(machine.remove_breakpoint(&countdown(), 0));
+
+  machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "main", vec![]).unwrap();
+
+  assert_eq!(pauses.borrow().len(), 1);
+}
+
+#[test]
+fn aborting_at_a_breakpoint_stops_the_program() {
+  let pauses = Rc::new(RefCell::new(Vec::new()));
+  let mut machine = machine(SRC);
+  machine.set_debugger(Box::new(Recorder { pauses: pauses.clone(), abort_after: Some(2) }));
+  machine.add_breakpoint(countdown(), 0);
+
+  match machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "main", vec![]) {
+    Err(RuntimeError::Aborted) => {}
+    other => panic!("Expected Aborted but got {:?}", other),
+  }
+
+  assert_eq!(pauses.borrow().len(), 3);
+}
@@ -0,0 +1,24 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn built_modules_disassemble() {
+  let dir = std::env::temp_dir().join(format!("letlang-disasm-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+
+  let script = dir.join("Shapes.let");
+  let output = dir.join("Shapes.letb");
+  fs::write(&script, "fun area(width: Float, height: Float): Float = width * height\n\nfun name(): String = \"square\"\n").unwrap();
+
+  let built = Command::new(env!("CARGO_BIN_EXE_rust_let_lang")).arg("build").arg(&script).arg("-o").arg(&output).output().unwrap();
+  assert_eq!(String::from_utf8_lossy(&built.stdout), "");
+
+  let listing = Command::new(env!("CARGO_BIN_EXE_rust_let_lang")).arg("disasm").arg(&output).output().unwrap();
+  let listing = String::from_utf8_lossy(&listing.stdout).into_owned();
+  fs::remove_dir_all(&dir).unwrap();
+
+  assert!(listing.contains("script::Shapes.area: { Float, Float -> Float }"), "{}", listing);
+  assert!(listing.contains("MulFloat"), "{}", listing);
+  assert!(listing.contains("\"square\""), "{}", listing);
+}
@@ -0,0 +1,42 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::collections::HashMap;
+
+use rust_let_lang::{SOURCE_MODULE, SOURCE_PACKAGE};
+use common::machine;
+
+const ENV: &'static str = "
+import Core::Env;
+
+fun second(): String = Env.args()[1]
+
+fun home(): String = Env.getVar(\"LETLANG_HOME\")
+
+fun missing(): String = Env.getVar(\"LETLANG_MISSING\")
+";
+
+#[test]
+fn args_are_what_the_embedder_set() {
+  let mut machine = machine(ENV);
+  machine.set_args(vec![String::from("first"), String::from("second")]);
+
+  let result = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "second", vec![]).unwrap();
+  assert_eq!(result.to_string(), "'second'");
+}
+
+#[test]
+fn get_var_reads_the_embedder_env() {
+  let mut env = HashMap::new();
+  env.insert(String::from("LETLANG_HOME"), String::from("/opt/letlang"));
+
+  let mut machine = machine(ENV);
+  machine.set_env(env);
+
+  let result = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "home", vec![]).unwrap();
+  assert_eq!(result.to_string(), "'/opt/letlang'");
+
+  let result = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "missing", vec![]).unwrap();
+  assert_eq!(result.to_string(), "''");
+}
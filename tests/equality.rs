@@ -0,0 +1,37 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+
+use common::call;
+
+const SOURCE: &'static str = "
+import Core::Core;
+
+fun tuples(): Boolean = (1, \"a\", (2, 3)) == (1, \"a\", (2, 3))
+
+fun strings(): Boolean = \"a\" != \"b\"
+
+fun variants(): Boolean = Some(Ok(1)) == Some(Err(\"no\"))
+
+fun sameFunction(): Boolean = {
+  let f = { x: Float -> Float => x }
+  Core.equals(f, f)
+}
+
+fun otherFunction(): Boolean = Core.equals({ x: Float -> Float => x }, { x: Float -> Float => x })
+";
+
+#[test]
+fn compares_composites_deeply() {
+  assert_eq!(call(SOURCE, "tuples").unwrap(), Value::True);
+  assert_eq!(call(SOURCE, "strings").unwrap(), Value::True);
+  assert_eq!(call(SOURCE, "variants").unwrap(), Value::False);
+}
+
+#[test]
+fn compares_functions_by_identity() {
+  assert_eq!(call(SOURCE, "sameFunction").unwrap(), Value::True);
+  assert_eq!(call(SOURCE, "otherFunction").unwrap(), Value::False);
+}
@@ -0,0 +1,55 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::{AppShapes, Optimizer, OptLevel, SOURCE_MODULE, SOURCE_PACKAGE, Value};
+use rust_let_lang::ir::{compile_ir_module, Ir};
+use rust_let_lang::parser::parse_str;
+use rust_let_lang::typechecker::check_module;
+
+use common::machine;
+
+const SRC: &'static str = "
+fun scaled(n: Float, by: Float): Float = {
+  fun scale(x: Float): Float = x * by
+  scale(n) + scale(1)
+}
+
+fun scaler(by: Float): { Float -> Float } = {
+  fun scaleBy(x: Float): Float = x * by
+  scaleBy
+}
+";
+
+fn builds_closure(name: &str) -> bool {
+  let shapes = AppShapes::new();
+  let checked = check_module(parse_str(SRC, "test", "escape").unwrap(), &shapes).unwrap();
+  let mut module = compile_ir_module(&checked, &shapes).unwrap();
+
+  Optimizer::with_level(OptLevel::O2).optimize_module(&mut module);
+
+  module.functions[name].body.iter().any(|ir| match ir {
+    Ir::BuildClosure { .. } => true,
+    _ => false,
+  })
+}
+
+#[test]
+fn closures_only_called_where_they_are_built_are_never_built() {
+  assert!(!builds_closure("scaled"));
+  assert!(builds_closure("scaler"));
+}
+
+#[test]
+fn calling_them_directly_gives_the_same_answers() {
+  let machine = machine(SRC);
+
+  assert_eq!(machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "scaled", vec![Value::Float(4.0), Value::Float(3.0)]).unwrap(), Value::Float(15.0));
+
+  if let Value::Function(scale) = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "scaler", vec![Value::Float(3.0)]).unwrap() {
+    assert_eq!(machine.execute_handle(scale, vec![Value::Float(2.0)]).unwrap(), Value::Float(6.0));
+  } else {
+    panic!("scaler should return a function");
+  }
+}
+
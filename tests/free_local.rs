@@ -0,0 +1,126 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::{Optimizer, OptLevel};
+use rust_let_lang::ir::Ir;
+use rust_let_lang::optimize::Pass;
+
+use common::{float, ir_function, load, store};
+
+/**
+* Runs only the FreeLocal pass over body, for a function taking n, and prints the result.
+*/
+fn free_locals(body: Vec<Ir>) -> String {
+  let mut func = ir_function(&["n"], body);
+
+  Optimizer::builder(OptLevel::O0).enable(Pass::FreeLocal).build().optimize(&mut func);
+
+  let mut out = Vec::new();
+  Ir::pretty_print(&func.body, "", &mut out).unwrap();
+  String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn locals_used_on_both_sides_are_freed_on_the_last_one() {
+  let body = vec![
+    load("n"),
+    float(2.0),
+    Ir::LessFloat,
+    Ir::Branch {
+      then_block: vec![load("n"), Ir::Return],
+      else_block: vec![load("n"), float(1.0), Ir::SubFloat, Ir::Return],
+    },
+  ];
+
+  assert_eq!(free_locals(body), "\
+0: LoadValue(n)
+1: LoadConstFloat(2)
+2: LessFloat
+3: Branch
+  then_block:
+    0: LoadValue(n)
+    1: Return
+  else_block:
+    0: LoadValue(n)
+    1: FreeLocal(n)
+    2: LoadConstFloat(1)
+    3: SubFloat
+    4: Return
+
+");
+}
+
+#[test]
+fn locals_live_after_a_join_wait_for_it() {
+  let body = vec![
+    float(1.0),
+    store("x"),
+    Ir::LoadConstTrue,
+    Ir::Branch {
+      then_block: vec![load("x"), store("y")],
+      else_block: vec![float(2.0), store("y")],
+    },
+    load("x"),
+    load("y"),
+    Ir::AddFloat,
+    Ir::Return,
+  ];
+
+  assert_eq!(free_locals(body), "\
+0: LoadConstFloat(1)
+1: StoreValue(x)
+2: LoadConstTrue
+3: Branch
+  then_block:
+    0: LoadValue(x)
+    1: StoreValue(y)
+  else_block:
+    0: LoadConstFloat(2)
+    1: StoreValue(y)
+
+4: LoadValue(x)
+5: FreeLocal(x)
+6: LoadValue(y)
+7: FreeLocal(y)
+8: AddFloat
+9: Return
+");
+}
+
+#[test]
+fn locals_read_by_a_loop_condition_outlive_the_loop() {
+  let body = vec![
+    float(0.0),
+    store("i"),
+    Ir::Loop {
+      condition: vec![load("i"), load("n"), Ir::LessFloat],
+      body: vec![load("i"), float(1.0), Ir::AddFloat, store("step"), load("step"), store("i")],
+    },
+    float(0.0),
+    Ir::Return,
+  ];
+
+  assert_eq!(free_locals(body), "\
+0: LoadConstFloat(0)
+1: StoreValue(i)
+2: Loop
+  condition:
+    0: LoadValue(i)
+    1: LoadValue(n)
+    2: LessFloat
+  body:
+    0: LoadValue(i)
+    1: LoadConstFloat(1)
+    2: AddFloat
+    3: StoreValue(step)
+    4: LoadValue(step)
+    5: FreeLocal(step)
+    6: StoreValue(i)
+
+3: FreeLocal(i)
+4: FreeLocal(n)
+5: LoadConstFloat(0)
+6: Return
+");
+}
@@ -0,0 +1,48 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::AppShapes;
+use rust_let_lang::ir::{compile_ir_module, Ir};
+use rust_let_lang::parser::parse_str;
+use rust_let_lang::typechecker::check_module;
+
+use common::call;
+
+const SRC: &'static str = "
+import Core::List;
+
+fun double(x: Float): Float = x * 2
+
+fun mapper(): { List[Float], { Float -> Float } -> List[Float] } = List.map
+
+fun mapped(): List[Float] = {
+  let map = List.map
+  let f = double
+  map([1, 2], f)
+}
+
+fun passed(): List[Float] = mapper()([3], double)
+";
+
+#[test]
+fn module_functions_are_values() {
+  assert_eq!(call(SRC, "mapped").unwrap().to_string(), "[2, 4]");
+  assert_eq!(call(SRC, "passed").unwrap().to_string(), "[6]");
+}
+
+#[test]
+fn qualified_names_load_the_function_they_name() {
+  let shapes = AppShapes::new();
+  let checked = check_module(parse_str(SRC, "test", "refs").unwrap(), &shapes).unwrap();
+  let module = compile_ir_module(&checked, &shapes).unwrap();
+
+  let loaded: Vec<String> = module.functions["mapper"].body.iter()
+    .filter_map(|ir| match ir {
+      Ir::LoadConstFunction { value } => Some(value.pretty()),
+      _ => None,
+    })
+    .collect();
+
+  assert_eq!(loaded, vec!["Core::List.map"]);
+}
@@ -0,0 +1,87 @@
+extern crate rust_let_lang;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_let_lang::{AppShapes, Optimizer, OptLevel};
+use rust_let_lang::compiler::compile;
+use rust_let_lang::interpreter::RunFunction;
+use rust_let_lang::ir::compile_ir_module;
+use rust_let_lang::parser::parse;
+use rust_let_lang::typechecker::check_module;
+
+/**
+* Compiles every .let fixture in tests/golden and compares the Ir, before optimizing, and the bytecode against the
+* .ir and .bytecode snapshots next to it. Run with UPDATE_GOLDEN=1 to rewrite the snapshots after a deliberate change.
+*
+* Functions are printed in name order and constants by value, so snapshots don't depend on HashMap order.
+*/
+#[test]
+fn golden_snapshots() {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+  let update = env::var("UPDATE_GOLDEN").is_ok();
+
+  let mut fixtures: Vec<PathBuf> = fs::read_dir(&dir).unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().and_then(|ex| ex.to_str()) == Some("let"))
+    .collect();
+
+  fixtures.sort();
+
+  let mut failures = Vec::new();
+
+  for fixture in &fixtures {
+    let (ir, bytecode) = render(fixture);
+
+    for (extension, actual) in [("ir", ir), ("bytecode", bytecode)] {
+      let snapshot = fixture.with_extension(extension);
+
+      if update {
+        fs::write(&snapshot, &actual).unwrap();
+        continue;
+      }
+
+      match fs::read_to_string(&snapshot) {
+        Ok(ref expected) if *expected == actual => {}
+        Ok(expected) => failures.push(format!("{} differs\n--- expected\n{}\n--- actual\n{}", snapshot.display(), expected, actual)),
+        Err(_) => failures.push(format!("{} is missing, run with UPDATE_GOLDEN=1 to create it", snapshot.display())),
+      }
+    }
+  }
+
+  assert!(!fixtures.is_empty(), "No fixtures found in {}", dir.display());
+  assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+fn render(fixture: &Path) -> (String, String) {
+  let name = fixture.file_stem().unwrap().to_str().unwrap();
+  let shapes = AppShapes::new();
+
+  let parsed = parse(fixture, "golden", name).unwrap_or_else(|errors| panic!("{}: {}", fixture.display(), errors[0]));
+  let checked = check_module(parsed, &shapes).unwrap_or_else(|errors| panic!("{}: {}", fixture.display(), errors[0]));
+  let ir_module = compile_ir_module(&checked, &shapes).unwrap();
+
+  let mut ir = Vec::new();
+  let mut names: Vec<&String> = ir_module.functions.keys().collect();
+  names.sort();
+
+  for name in names {
+    ir_module.functions[name].pretty_print(&mut ir).unwrap();
+  }
+
+  let module = compile(ir_module, &Optimizer::with_level(OptLevel::O2)).unwrap();
+
+  let mut bytecode = Vec::new();
+  let mut names: Vec<&String> = module.functions.keys().collect();
+  names.sort();
+
+  for name in names {
+    if let RunFunction::BitFunction(func) = &module.functions[name] {
+      func.disassemble(&module, &mut bytecode).unwrap();
+      bytecode.push(b'\n');
+    }
+  }
+
+  (String::from_utf8(ir).unwrap(), String::from_utf8(bytecode).unwrap())
+}
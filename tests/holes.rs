@@ -0,0 +1,28 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::compile_source;
+use common::call;
+
+const HOLES: &'static str = "
+fun names(): List[String] = ???
+
+fun half(x: Float): Float = x / 2
+
+fun halved(): Float = half(???)
+";
+
+#[test]
+fn holes_typecheck_to_the_expected_shape() {
+  assert!(compile_source(HOLES).is_ok());
+}
+
+#[test]
+fn running_a_hole_names_its_shape() {
+  let err = call(HOLES, "names").unwrap_err();
+  assert!(err.contains("Hole has type List[String]"), "{}", err);
+
+  let err = call(HOLES, "halved").unwrap_err();
+  assert!(err.contains("Hole has type Float"), "{}", err);
+}
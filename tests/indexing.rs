@@ -0,0 +1,61 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+
+use common::call;
+
+#[test]
+fn index_into_list() {
+  let src = "
+fun third(): Float = {
+  let xs = 10..20
+  xs[2] + xs[0]
+}
+";
+
+  assert_eq!(call(src, "third").unwrap(), Value::Float(22.0));
+}
+
+#[test]
+fn index_with_expressions() {
+  let src = "
+fun last(): Float = {
+  let n = 5
+  let xs = 0..n
+  xs[xs[n - 1] - 1]
+}
+";
+
+  assert_eq!(call(src, "last").unwrap(), Value::Float(3.0));
+}
+
+#[test]
+fn out_of_bounds_panics() {
+  let src = "
+fun past(): Float = (0..3)[3]
+";
+
+  assert_eq!(call(src, "past").unwrap_err(), "Panic: Index 3 out of bounds for list of length 3 in script::Main.past");
+}
+
+#[test]
+fn out_of_bounds_can_be_caught() {
+  let src = "
+import Core::Core;
+
+fun safe(): Result[Float, String] = Core.tryCall({ -> Float => (0..3)[0.5] })
+";
+
+  assert_eq!(call(src, "safe").unwrap().to_string(), "Err('Index 0.5 out of bounds for list of length 3')");
+}
+
+#[test]
+fn only_lists_can_be_indexed() {
+  let src = "
+fun first(): Float = (1, 2)[0]
+";
+
+  assert!(call(src, "first").unwrap_err().contains("Cannot index into non list type"));
+}
@@ -0,0 +1,141 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+use rust_let_lang::format::format_module;
+use rust_let_lang::parser::parse_str;
+
+use common::call;
+
+#[test]
+fn lambdas_take_parameter_shapes_from_the_call() {
+  let src = "
+import Core::List;
+
+fun doubled(): List[Float] = List.map([1, 2, 3], { x => x * 2 })
+
+fun total(): Float = List.fold(1..5, 0, { acc, x => acc + x })
+
+fun method(): List[Float] = {
+  let xs = [1, 2]
+  xs.map({ x => x + 1 })
+}
+";
+
+  assert_eq!(call(src, "doubled").unwrap().to_string(), "[2, 4, 6]");
+  assert_eq!(call(src, "total").unwrap(), Value::Float(10.0));
+  assert_eq!(call(src, "method").unwrap().to_string(), "[2, 3]");
+}
+
+#[test]
+fn lambdas_take_shapes_from_declared_types() {
+  let src = "
+type Step = { Float -> Float }
+
+fun aliased(): Float = {
+  let step: Step = { x => x + 1 }
+  step(1)
+}
+
+fun empty(): Float = {
+  let make: { Float -> List[Float] } = { x => [] }
+  let xs = make(1)
+  3
+}
+";
+
+  assert_eq!(call(src, "aliased").unwrap(), Value::Float(2.0));
+  assert_eq!(call(src, "empty").unwrap(), Value::Float(3.0));
+}
+
+#[test]
+fn functions_can_leave_out_every_shape() {
+  let src = "
+fun add(a, b) = a + b
+
+fun twice(f, x) = f(f(x))
+
+fun first(a, b) = a
+
+fun sum(): Float = add(1, 2)
+
+fun quadrupled(): Float = twice({ n => n * 2 }, 3)
+
+fun generic(): String = {
+  let n = first(1, 2) + 1
+  first(\"a\", n)
+}
+
+fun local(): Float = {
+  let pick = { a, b => b }
+  let s: String = pick(1, \"b\")
+  pick(s, 4)
+}
+";
+
+  assert_eq!(call(src, "sum").unwrap(), Value::Float(3.0));
+  assert_eq!(call(src, "quadrupled").unwrap(), Value::Float(12.0));
+  assert_eq!(call(src, "generic").unwrap().to_string(), "'a'");
+  assert_eq!(call(src, "local").unwrap(), Value::Float(4.0));
+}
+
+#[test]
+fn left_out_shapes_format_as_written() {
+  let src = "fun add(a, b: Float) = a + b\n";
+  let module = parse_str(src, "test", "inference").unwrap();
+
+  assert_eq!(format_module(&module), src);
+}
+
+#[test]
+fn inferred_shapes_show_up_in_errors() {
+  let src = "
+fun bad(x) = {
+  let y = x + 1
+  if (x) 1 else 2
+}
+";
+
+  assert!(call(src, "bad").unwrap_err().contains("Declared: Boolean, but found: Float"));
+
+  let src = "
+fun loop(f) = f(f)
+";
+
+  // A function can't take itself, its parameter would have to contain its own shape.
+  assert!(call(src, "loop").unwrap_err().contains("Invalid argument types for call"));
+}
+
+#[test]
+fn results_are_inferred_through_recursion() {
+  let src = "
+fun factorial(n: Float) = if (n > 1) n * factorial(n - 1) else 1
+
+fun countdown(): Float = {
+  fun down(n: Float) = if (n > 0) down(n - 1) else n
+  down(3)
+}
+
+fun even(n: Float) = if (n > 0) odd(n - 1) else true
+
+fun odd(n: Float) = if (n > 0) even(n - 1) else false
+
+fun six(): Float = factorial(3)
+
+fun three(): Boolean = odd(3)
+";
+
+  assert_eq!(call(src, "six").unwrap(), Value::Float(6.0));
+  assert_eq!(call(src, "countdown").unwrap(), Value::Float(0.0));
+  assert_eq!(call(src, "three").unwrap(), Value::True);
+}
+
+#[test]
+fn results_only_decided_by_recursion_are_ambiguous() {
+  let src = "
+fun spin(n: Float) = spin(n - 1)
+";
+
+  assert!(call(src, "spin").unwrap_err().contains("Can't infer what spin returns"));
+}
@@ -0,0 +1,78 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::CompileError;
+use rust_let_lang::bytecode::SourcePoint;
+use rust_let_lang::ir::{check_initialized, Ir};
+
+use common::{ir_function, load, store};
+
+fn uninitialized(body: Vec<Ir>) -> Option<String> {
+  match check_initialized(&ir_function(&["n"], body)) {
+    Ok(()) => None,
+    Err(CompileError::Uninitialized { local, .. }) => Some(local),
+    Err(err) => panic!("Unexpected error {}", err),
+  }
+}
+
+#[test]
+fn locals_stored_on_every_branch_can_be_read() {
+  let body = vec![
+    Ir::LoadConstTrue,
+    Ir::Branch {
+      then_block: vec![load("n"), store("x")],
+      else_block: vec![Ir::LoadConstFloat { value: 1.0 }, store("x")],
+    },
+    load("x"),
+    Ir::Return,
+  ];
+
+  assert_eq!(uninitialized(body), None);
+
+  let diverges = vec![
+    Ir::LoadConstTrue,
+    Ir::Branch {
+      then_block: vec![load("n"), store("x")],
+      else_block: vec![Ir::Panic { message: String::from("no") }],
+    },
+    load("x"),
+    Ir::Return,
+  ];
+
+  assert_eq!(uninitialized(diverges), None);
+}
+
+#[test]
+fn locals_missing_on_some_path_are_rejected() {
+  let one_branch = vec![
+    Ir::LoadConstTrue,
+    Ir::Branch {
+      then_block: vec![load("n"), store("x")],
+      else_block: vec![],
+    },
+    load("x"),
+    Ir::Return,
+  ];
+
+  assert_eq!(uninitialized(one_branch), Some(String::from("x")));
+
+  let loop_body = vec![
+    Ir::Loop {
+      condition: vec![Ir::LoadConstFalse],
+      body: vec![load("n"), store("x")],
+    },
+    load("x"),
+    Ir::Return,
+  ];
+
+  assert_eq!(uninitialized(loop_body), Some(String::from("x")));
+
+  let assert_only = vec![
+    Ir::Assert { condition: vec![load("n"), store("x"), Ir::LoadConstTrue], message: String::from("x"), point: SourcePoint { line: 1, column: 1 } },
+    load("x"),
+    Ir::Return,
+  ];
+
+  assert_eq!(uninitialized(assert_only), Some(String::from("x")));
+}
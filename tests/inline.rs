@@ -0,0 +1,79 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::{AppShapes, BitApplication, Machine, Optimizer, OptLevel, SOURCE_MODULE, SOURCE_PACKAGE, Value};
+use rust_let_lang::compiler::compile_source_module;
+use rust_let_lang::ir::{compile_ir_module, Ir};
+use rust_let_lang::optimize::Pass;
+use rust_let_lang::parser::parse_str;
+use rust_let_lang::typechecker::check_module;
+
+const SRC: &'static str = "
+fun minus(left: Float, right: Float): Float = left - right
+
+fun scaled(x: Float): Float = {
+  let factor = 3
+  x * factor
+}
+
+fun clamp(x: Float): Float = if (x > 10) 10 else x
+
+fun mixed(a: Float, b: Float): Float = {
+  let factor = 100
+  minus(a, b) * factor + scaled(a) + clamp(b)
+}
+
+fun nested(x: Float): Float = minus(minus(x, 1), minus(10, x))
+";
+
+fn calls(optimizer: &Optimizer, name: &str) -> Vec<String> {
+  let shapes = AppShapes::new();
+  let checked = check_module(parse_str(SRC, SOURCE_PACKAGE, SOURCE_MODULE).unwrap(), &shapes).unwrap();
+  let mut module = compile_ir_module(&checked, &shapes).unwrap();
+
+  optimizer.optimize_module(&mut module);
+
+  module.functions[name].body.iter()
+    .filter_map(|ir| match ir {
+      Ir::CallStatic { func } => Some(func.name.clone()),
+      _ => None,
+    })
+    .collect()
+}
+
+fn call(optimizer: &Optimizer, name: &str, args: Vec<Value>) -> Value {
+  let module = compile_source_module(SOURCE_PACKAGE, SOURCE_MODULE, SRC, optimizer, &AppShapes::new()).unwrap();
+
+  let mut machine = Machine::new(BitApplication::library());
+  machine.load_module(SOURCE_PACKAGE, SOURCE_MODULE, module);
+  machine.call(SOURCE_PACKAGE, SOURCE_MODULE, name, args).unwrap()
+}
+
+#[test]
+fn small_functions_are_inlined_into_their_callers() {
+  assert_eq!(calls(&Optimizer::with_level(OptLevel::O0), "mixed"), vec!["minus", "scaled", "clamp"]);
+
+  let inline = Optimizer::builder(OptLevel::O0).enable(Pass::Inline).build();
+  assert!(calls(&inline, "mixed").is_empty());
+  assert!(calls(&inline, "nested").is_empty());
+}
+
+#[test]
+fn inlined_calls_give_the_same_answers() {
+  let plain = Optimizer::with_level(OptLevel::O0);
+  let inline = Optimizer::builder(OptLevel::O0).enable(Pass::Inline).build();
+  let full = Optimizer::with_level(OptLevel::O2);
+
+  let cases = vec![
+    ("mixed", vec![Value::Float(7.0), Value::Float(2.0)]),
+    ("mixed", vec![Value::Float(2.0), Value::Float(20.0)]),
+    ("nested", vec![Value::Float(4.0)]),
+    ("nested", vec![Value::Float(-3.0)]),
+  ];
+
+  for (name, args) in cases {
+    let expected = call(&plain, name, args.clone());
+
+    assert_eq!(call(&inline, name, args.clone()), expected, "{} inlined", name);
+    assert_eq!(call(&full, name, args), expected, "{} at O2", name);
+  }
+}
@@ -0,0 +1,35 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::error::ParseError;
+use rust_let_lang::parser::{TokenKind, lex_str, parse_str};
+
+fn reserved(src: &str) -> (String, usize, usize) {
+  match parse_str(src, "test", "Keywords") {
+    Err(errors) => match &errors[0] {
+      ParseError::ReservedWord { loc, word } => (word.clone(), loc.y, loc.x),
+      other => panic!("Expected a reserved word, found {}", other),
+    },
+    Ok(_) => panic!("Expected {} not to parse", src),
+  }
+}
+
+#[test]
+fn keywords_are_their_own_kind() {
+  let kinds: Vec<TokenKind> = lex_str("let x = match", "Keywords").into_iter().map(|token| token.kind).collect();
+
+  assert_eq!(kinds, vec![TokenKind::Keyword, TokenKind::Id, TokenKind::Symbol, TokenKind::Keyword, TokenKind::EOF]);
+}
+
+#[test]
+fn keywords_are_not_names() {
+  assert_eq!(reserved("fun main(): Float = {\n  let let = 3\n  3\n}"), (String::from("let"), 2, 7));
+  assert_eq!(reserved("fun if(): Float = 3"), (String::from("if"), 1, 5));
+  assert_eq!(reserved("fun main(export: Float): Float = export"), (String::from("export"), 1, 10));
+}
+
+#[test]
+fn reserved_words_say_so() {
+  let errors = parse_str("fun main(): Float = {\n  let match = 3\n  3\n}", "test", "Keywords").err().unwrap();
+
+  assert_eq!(errors[0].to_string(), "'match' is a reserved word and can't be used as a name at file: Keywords, line: 2, columns: 7-11");
+}
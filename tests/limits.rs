@@ -0,0 +1,121 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use rust_let_lang::{Backend, DEFAULT_MAX_CALL_DEPTH, Dispatch, ExecutionLimits, Limit, Machine, RuntimeError, SOURCE_MODULE, SOURCE_PACKAGE, STACK_SIZE_PER_CALL, Value};
+
+use common::machine;
+
+const SRC: &'static str = "
+fun spin(n: Float): Float = {
+  let mut i = 0
+  while i < n {
+    i = i + 1
+  }
+  i
+}
+
+fun down(n: Float): Float = if (n == 0) { 0 } else { 1 + down(n - 1) }
+";
+
+const BACKENDS: [(Backend, Dispatch); 3] = [(Backend::Stack, Dispatch::Match), (Backend::Stack, Dispatch::Threaded), (Backend::Register, Dispatch::Match)];
+
+fn limited(backend: Backend, dispatch: Dispatch, limits: ExecutionLimits) -> Machine {
+  let mut machine = machine(SRC);
+  machine.set_backend(backend);
+  machine.set_dispatch(dispatch);
+  machine.set_limits(limits);
+  machine
+}
+
+fn run(machine: &Machine, name: &str, arg: f64) -> Result<Value, RuntimeError> {
+  machine.call(SOURCE_PACKAGE, SOURCE_MODULE, name, vec![Value::Float(arg)])
+}
+
+#[test]
+fn instruction_limit_stops_a_long_loop() {
+  for &(backend, dispatch) in BACKENDS.iter() {
+    let machine = limited(backend, dispatch, ExecutionLimits { max_instructions: Some(1000), ..ExecutionLimits::none() });
+
+    assert_eq!(run(&machine, "spin", 10.0).unwrap(), Value::Float(10.0));
+
+    match run(&machine, "spin", 1_000_000.0) {
+      Err(RuntimeError::LimitExceeded { func, limit: Limit::Instructions(1000) }) => assert_eq!(func.name, "spin"),
+      other => panic!("Expected the instruction limit on {:?} {:?}, got {:?}", backend, dispatch, other),
+    }
+  }
+}
+
+#[test]
+fn instruction_count_starts_over_with_each_call() {
+  for &(backend, dispatch) in BACKENDS.iter() {
+    let machine = limited(backend, dispatch, ExecutionLimits { max_instructions: Some(1000), ..ExecutionLimits::none() });
+
+    for _ in 0..10 {
+      assert_eq!(run(&machine, "spin", 50.0).unwrap(), Value::Float(50.0));
+    }
+  }
+}
+
+#[test]
+fn call_depth_limit_stops_deep_recursion() {
+  for &(backend, dispatch) in BACKENDS.iter() {
+    // Shallow enough for the default test thread's stack.
+    let machine = limited(backend, dispatch, ExecutionLimits { max_call_depth: Some(20), ..ExecutionLimits::none() });
+
+    assert_eq!(run(&machine, "down", 15.0).unwrap(), Value::Float(15.0));
+
+    match run(&machine, "down", 100.0) {
+      Err(RuntimeError::StackOverflow { limit: 20, chain }) => assert!(chain.iter().any(|(func, _)| func.name == "down")),
+      other => panic!("Expected the call depth limit on {:?} {:?}, got {:?}", backend, dispatch, other),
+    }
+  }
+}
+
+#[test]
+fn timeout_stops_a_loop_that_runs_too_long() {
+  for &(backend, dispatch) in BACKENDS.iter() {
+    let machine = limited(backend, dispatch, ExecutionLimits { timeout: Some(Duration::from_millis(10)), ..ExecutionLimits::none() });
+
+    match run(&machine, "spin", 1e12) {
+      Err(RuntimeError::LimitExceeded { func, limit: Limit::Timeout(max) }) => {
+        assert_eq!(func.name, "spin");
+        assert_eq!(max, Duration::from_millis(10));
+      }
+      other => panic!("Expected the timeout on {:?} {:?}, got {:?}", backend, dispatch, other),
+    }
+  }
+}
+
+#[test]
+fn limit_errors_name_the_limit() {
+  let machine = limited(Backend::Stack, Dispatch::Match, ExecutionLimits { max_instructions: Some(1000), ..ExecutionLimits::none() });
+
+  let message = run(&machine, "spin", 1_000_000.0).unwrap_err().to_string();
+  assert!(message.contains("Exceeded limit of 1000 instructions"), "{}", message);
+}
+
+#[test]
+fn call_depth_is_reached_before_the_stack_runs_out() {
+  for &(backend, dispatch) in BACKENDS.iter() {
+    // Sized just as main sizes the thread it runs everything on.
+    let result = thread::Builder::new()
+      .stack_size(DEFAULT_MAX_CALL_DEPTH * STACK_SIZE_PER_CALL)
+      .spawn(move || {
+        let machine = limited(backend, dispatch, ExecutionLimits::default());
+
+        match run(&machine, "down", (DEFAULT_MAX_CALL_DEPTH + 1) as f64) {
+          Err(RuntimeError::StackOverflow { limit, .. }) => limit,
+          other => panic!("Expected a stack overflow, got {:?}", other),
+        }
+      })
+      .unwrap()
+      .join()
+      .unwrap();
+
+    assert_eq!(result, DEFAULT_MAX_CALL_DEPTH);
+  }
+}
@@ -0,0 +1,55 @@
+extern crate rust_let_lang;
+
+use std::path::Path;
+
+use rust_let_lang::{AppShapes, BitPackage, FunctionRef, Linker, Machine, Optimizer, OptLevel, RuntimeError, Shape, Value};
+use rust_let_lang::compiler::compile_package;
+use rust_let_lang::shapes::shape_float;
+
+fn test_package() -> BitPackage {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+
+  compile_package("test", dir.to_str().unwrap(), &Optimizer::with_level(OptLevel::O0), &mut AppShapes::new()).unwrap()
+}
+
+fn main_with(args: Vec<Shape>) -> FunctionRef {
+  FunctionRef {
+    package: String::from("test"),
+    module: String::from("basic"),
+    name: String::from("main"),
+
+    shape: Shape::SimpleFunctionShape { args, result: Box::new(shape_float()) },
+  }
+}
+
+#[test]
+fn links_and_runs_main() {
+  let app = Linker::new().package("test", test_package()).main(main_with(vec![])).link().unwrap();
+
+  assert_eq!(Machine::new(app).run_main().unwrap(), Value::Float(30.0));
+}
+
+#[test]
+fn reports_every_missing_function_at_once() {
+  let mut package = test_package();
+  let basic = package.modules.get_mut("basic").unwrap();
+  basic.functions.remove("pow");
+  basic.functions.remove("listTest");
+
+  match Linker::new().package("test", package).link() {
+    Err(RuntimeError::LinkErrors(errs)) => {
+      let mut lines: Vec<String> = errs.iter().map(|err| err.to_string()).collect();
+      lines.sort();
+
+      assert_eq!(lines, vec!["No such function test::basic.listTest", "No such function test::basic.pow"]);
+    }
+    other => panic!("Expected link errors, got {:?}", other.err()),
+  }
+}
+
+#[test]
+fn main_must_have_its_declared_shape() {
+  let err = Linker::new().package("test", test_package()).main(main_with(vec![shape_float()])).link().err().unwrap();
+
+  assert_eq!(err.to_string(), "Signature mismatch for test::basic.main, compiled against { Float -> Float } but found { -> Float }");
+}
@@ -0,0 +1,47 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+use rust_let_lang::format::format_module;
+use rust_let_lang::parser::parse_str;
+
+use common::call;
+
+#[test]
+fn list_literals_build_lists() {
+  let src = "
+import Core::List;
+
+fun numbers(): List[Float] = [1, 2, 1 + 2]
+
+fun nested(): Float = {
+  let grid = [[1, 2], [3, 4]]
+  grid[1][0]
+}
+
+fun empty(): List[String] = []
+
+fun total(): Float = List.fold([10, 20, 30], 0, { acc: Float, x: Float -> Float => acc + x })
+";
+
+  assert_eq!(call(src, "numbers").unwrap().to_string(), "[1, 2, 3]");
+  assert_eq!(call(src, "nested").unwrap(), Value::Float(3.0));
+  assert_eq!(call(src, "empty").unwrap().to_string(), "[]");
+  assert_eq!(call(src, "total").unwrap(), Value::Float(60.0));
+  assert_eq!(call(src, "numbers").unwrap(), call("fun numbers(): List[Float] = 1..4\n", "numbers").unwrap());
+}
+
+#[test]
+fn list_items_must_agree() {
+  assert!(call("fun mixed(): List[Float] = [1, \"two\"]\n", "mixed").is_err());
+  assert!(call("fun unknown(): Float = { let xs = []\n 1 }\n", "unknown").is_err());
+}
+
+#[test]
+fn list_literals_format_as_written() {
+  let src = "fun grid(): List[List[Float]] = [[1, 2], [], [3]]\n";
+  let module = parse_str(src, "test", "lists").unwrap();
+
+  assert_eq!(format_module(&module), src);
+}
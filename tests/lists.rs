@@ -0,0 +1,64 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+use rust_let_lang::runtime::PersistentList;
+
+use common::call;
+
+#[test]
+fn push_keeps_every_element() {
+  // Enough to need three levels of the tree.
+  let size = 40_000;
+  let mut list = PersistentList::new();
+
+  for i in 0..size {
+    list = list.push(Value::Float(i as f64));
+  }
+
+  assert_eq!(list.len(), size);
+
+  for i in 0..size {
+    assert_eq!(list.get(i), Some(&Value::Float(i as f64)));
+  }
+
+  assert_eq!(list.get(size), None);
+  assert_eq!(list.iter().count(), size);
+  assert_eq!(list, (0..size).map(|i| Value::Float(i as f64)).collect());
+}
+
+#[test]
+fn push_leaves_the_original_alone() {
+  let base: PersistentList = (0..1056).map(|i| Value::Float(i as f64)).collect();
+
+  let left = base.push(Value::True);
+  let right = base.push(Value::False);
+
+  assert_eq!(base.len(), 1056);
+  assert_eq!(base.get(1056), None);
+  assert_eq!(left.get(1056), Some(&Value::True));
+  assert_eq!(right.get(1056), Some(&Value::False));
+  assert_eq!(left.get(1000), right.get(1000));
+}
+
+#[test]
+fn append_in_a_loop() {
+  let src = "
+import Core::List;
+
+fun build(): Float = {
+  let mut xs = List.new()
+  let mut i = 0
+
+  while (i < 5000) {
+    xs = List.append(xs, i)
+    i = i + 1
+  }
+
+  List.fold(xs, 0, { acc: Float, x: Float -> Float => acc + x })
+}
+";
+
+  assert_eq!(call(src, "build").unwrap(), Value::Float(4999.0 * 5000.0 / 2.0));
+}
@@ -0,0 +1,98 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::{Optimizer, OptLevel, Value};
+use rust_let_lang::ir::{Ir, IrFunction};
+use rust_let_lang::optimize::Pass;
+
+use common::{call_with, float, ir_function, load, store};
+
+/**
+* sum = 0, i = 0, while (i < n) { i = i + 1, sum = sum + scale * 2 }
+*/
+fn summing_loop() -> IrFunction {
+  ir_function(&["n", "scale"], vec![
+    float(0.0), store("sum"),
+    float(0.0), store("i"),
+    Ir::Loop {
+      condition: vec![load("i"), load("n"), Ir::LessFloat],
+      body: vec![
+        load("i"), float(1.0), Ir::AddFloat, store("i"),
+        load("sum"), load("scale"), float(2.0), Ir::MulFloat, Ir::AddFloat, store("sum"),
+      ],
+    },
+    load("sum"),
+    Ir::Return,
+  ])
+}
+
+fn per_iteration(func: &IrFunction) -> usize {
+  func.body.iter()
+    .filter_map(|ir| match ir {
+      Ir::Loop { condition, body } => Some(condition.len() + body.len()),
+      _ => None,
+    })
+    .sum()
+}
+
+fn render(body: &Vec<Ir>) -> String {
+  let mut out = Vec::new();
+  Ir::pretty_print(body, "", &mut out).unwrap();
+  String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn invariant_arithmetic_runs_once_before_the_loop() {
+  let mut func = summing_loop();
+  let before = per_iteration(&func);
+
+  Optimizer::builder(OptLevel::O0).enable(Pass::LoopInvariant).build().optimize(&mut func);
+
+  assert_eq!(per_iteration(&func), before - 2);
+  assert_eq!(render(&func.body), "\
+0: LoadConstFloat(0)
+1: StoreValue(sum)
+2: LoadConstFloat(0)
+3: StoreValue(i)
+4: LoadValue(scale)
+5: LoadConstFloat(2)
+6: MulFloat
+7: StoreValue(#hoist1)
+8: Loop
+  condition:
+    0: LoadValue(i)
+    1: LoadValue(n)
+    2: LessFloat
+  body:
+    0: LoadValue(i)
+    1: LoadConstFloat(1)
+    2: AddFloat
+    3: StoreValue(i)
+    4: LoadValue(sum)
+    5: LoadValue(#hoist1)
+    6: AddFloat
+    7: StoreValue(sum)
+
+9: LoadValue(sum)
+10: Return
+");
+}
+
+#[test]
+fn hoisted_loops_give_the_same_answers() {
+  let src = "
+fun total(n: Float, scale: Float): Float = {
+  let mut sum = 0
+  let mut i = 0
+  while (i < n) {
+    i = i + 1
+    sum = sum + scale * 2
+  }
+  sum
+}
+";
+
+  let result = call_with(src, "total", vec![Value::Float(4.0), Value::Float(1.5)]).unwrap();
+  assert_eq!(result, Value::Float(12.0));
+}
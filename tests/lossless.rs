@@ -0,0 +1,47 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::parser::{TokenKind, lex_lossless};
+
+const SRC: &'static str = "fun greet(): String = {
+  let name = \"say \\\"hi\\\"\"
+  name @ 12.5
+}
+";
+
+#[test]
+fn tokens_give_back_the_source() {
+  let tokens = lex_lossless(SRC, "Greet");
+
+  let joined: String = tokens.iter().map(|full| format!("{}{}", full.trivia, full.text)).collect();
+
+  assert_eq!(joined, SRC);
+  assert_eq!(tokens.last().unwrap().token.kind, TokenKind::EOF);
+  assert_eq!(tokens.last().unwrap().trivia, "\n");
+}
+
+#[test]
+fn tokens_know_where_they_end() {
+  let tokens = lex_lossless(SRC, "Greet");
+
+  let string = tokens.iter().find(|full| full.token.kind == TokenKind::String).unwrap();
+
+  assert_eq!(string.text, "\"say \\\"hi\\\"\"");
+  assert_eq!(string.token.value, "say \"hi\"");
+  assert_eq!((string.token.location.y, string.token.location.x), (2, 14));
+  assert_eq!((string.token.location.end_y, string.token.location.end_x), (2, 26));
+  assert_eq!(string.trivia, " ");
+
+  let number = tokens.iter().find(|full| full.token.kind == TokenKind::Number).unwrap();
+
+  assert_eq!((number.token.location.x, number.token.location.end_x), (10, 14));
+}
+
+#[test]
+fn unknown_characters_do_not_end_the_stream() {
+  let tokens = lex_lossless(SRC, "Greet");
+
+  let unknown = tokens.iter().find(|full| full.token.kind == TokenKind::Unknown).unwrap();
+
+  assert_eq!(unknown.text, "@");
+  assert_eq!((unknown.token.location.y, unknown.token.location.x), (3, 8));
+}
@@ -0,0 +1,106 @@
+extern crate rust_let_lang;
+
+use std::io::Cursor;
+
+use rust_let_lang::json::{Json, parse_json};
+use rust_let_lang::lsp::{check_document, run_server};
+
+const SRC: &'static str = "fun double(x: Float): Float = {
+  let twice = x * 2
+  twice
+}
+
+fun four(): Float = double(2)
+";
+
+#[test]
+fn reports_errors_where_they_are() {
+  let doc = check_document("fun broken(): Float = {\n  missing\n}\n", "Broken");
+
+  assert_eq!(doc.errors.len(), 1);
+
+  let (loc, message) = &doc.errors[0];
+  let loc = loc.as_ref().unwrap();
+
+  assert_eq!((loc.y, loc.x), (2, 3));
+  assert!(message.starts_with("Undeclared variable: missing"));
+}
+
+#[test]
+fn hovers_show_shapes() {
+  let doc = check_document(SRC, "Hover");
+
+  assert_eq!(doc.hover(3, 4), Some(String::from("twice: Float")));
+  assert_eq!(doc.hover(6, 22), Some(String::from("double: { Float -> Float }")));
+  assert_eq!(doc.hover(1, 5), Some(String::from("double: { Float -> Float }")));
+  assert_eq!(doc.hover(2, 1), None);
+}
+
+#[test]
+fn hovers_find_the_token_under_the_cursor() {
+  let doc = check_document(SRC, "Tokens");
+
+  assert_eq!(doc.hover(1, 1), Some(String::from("double: { Float -> Float }")));
+  assert_eq!(doc.hover(2, 15), Some(String::from("x: Float")));
+  assert_eq!(doc.hover(3, 7), Some(String::from("twice: Float")));
+  assert_eq!(doc.hover(3, 8), None);
+  assert_eq!(doc.hover(2, 17), None);
+  assert_eq!(doc.hover(6, 27), None);
+}
+
+#[test]
+fn documents_keep_every_token() {
+  let broken = "fun broken(): Float = {\n  1 +\n";
+  let doc = check_document(broken, "Broken");
+
+  assert!(!doc.errors.is_empty());
+  assert_eq!(doc.tokens.iter().map(|full| format!("{}{}", full.trivia, full.text)).collect::<String>(), broken);
+}
+
+#[test]
+fn definitions_point_at_declarations() {
+  let doc = check_document(SRC, "Definition");
+
+  let local = doc.definition(3, 3).unwrap();
+  assert_eq!((local.y, local.x), (2, 3));
+
+  let func = doc.definition(6, 21).unwrap();
+  assert_eq!((func.y, func.x), (1, 1));
+}
+
+fn frame(message: &str) -> String {
+  format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+}
+
+fn read_all(output: &str) -> Vec<Json> {
+  output.split("Content-Length: ")
+    .filter(|part| !part.is_empty())
+    .map(|part| parse_json(&part[part.find("\r\n\r\n").unwrap() + 4..]).unwrap())
+    .collect()
+}
+
+#[test]
+fn speaks_the_protocol() {
+  let text = Json::string("fun one(): Float = 1\n").to_string();
+
+  let input = [
+    frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#),
+    frame(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#),
+    frame(&format!(r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"file:///One.let","text":{}}}}}}}"#, text)),
+    frame(r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///One.let"},"position":{"line":0,"character":5}}}"#),
+    frame(r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#),
+    frame(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+  ].concat();
+
+  let mut output = Vec::new();
+  run_server(&mut Cursor::new(input), &mut output).unwrap();
+
+  let messages = read_all(&String::from_utf8(output).unwrap());
+
+  assert_eq!(messages.len(), 4);
+  assert_eq!(messages[0].get("result").get("capabilities").get("hoverProvider"), &Json::Bool(true));
+  assert_eq!(messages[1].get("method").as_str(), Some("textDocument/publishDiagnostics"));
+  assert_eq!(messages[1].get("params").get("diagnostics"), &Json::Array(vec![]));
+  assert_eq!(messages[2].get("result").get("contents").get("value").as_str(), Some("one: { -> Float }"));
+  assert_eq!(messages[3].get("id"), &Json::Number(3.0));
+}
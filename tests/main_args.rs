@@ -0,0 +1,47 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::{BitApplication, FunctionRef, Machine, SOURCE_MODULE, SOURCE_PACKAGE, Shape, Value, compile_source};
+use rust_let_lang::shapes::{shape_float, shape_list, shape_string, shape_unit};
+
+fn run(src: &str, args: Vec<Shape>, result: Shape, host_args: Vec<&str>) -> Result<Value, String> {
+  let package = compile_source(src).map_err(|err| err.to_string())?;
+
+  let main = FunctionRef {
+    package: String::from(SOURCE_PACKAGE),
+    module: String::from(SOURCE_MODULE),
+    name: String::from("main"),
+
+    shape: Shape::SimpleFunctionShape { args, result: Box::new(result) },
+  };
+
+  let mut app = BitApplication::new(main);
+  app.packages.insert(String::from(SOURCE_PACKAGE), package);
+
+  let mut machine = Machine::new(app);
+  machine.set_args(host_args.into_iter().map(String::from).collect());
+  machine.run_main().map_err(|err| err.to_string())
+}
+
+#[test]
+fn main_can_take_the_args() {
+  let src = "
+fun main(args: List[String]): String = args[1]
+";
+
+  let result = run(src, vec![shape_list(shape_string())], shape_string(), vec!["first", "second"]).unwrap();
+
+  assert_eq!(result.to_string(), "'second'");
+}
+
+#[test]
+fn main_can_return_anything() {
+  assert_eq!(run("fun main(): Unit = {}", vec![], shape_unit(), vec![]).unwrap(), Value::Unit);
+  assert_eq!(run("fun main(): Float = 3", vec![], shape_float(), vec![]).unwrap(), Value::Float(3.0));
+}
+
+#[test]
+fn main_can_only_take_the_args() {
+  let err = run("fun main(x: Float): Float = x", vec![shape_float()], shape_float(), vec![]).unwrap_err();
+
+  assert_eq!(err, "Main function script::Main.main must take no arguments or a List[String], not { Float -> Float }");
+}
@@ -0,0 +1,99 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+
+use common::call;
+
+#[test]
+fn tuples_are_keys() {
+  let src = "
+import Core::Map;
+
+fun lookup(): Option[Float] = {
+  let grid = Map.put(Map.put(Map.new(), (0, 1), 5), (1, 0), 7)
+  Map.get(grid, (1, 0))
+}
+";
+
+  assert_eq!(call(src, "lookup").unwrap().to_string(), "Some(7)");
+}
+
+#[test]
+fn missing_keys_are_none() {
+  let src = "
+import Core::Map;
+
+fun lookup(): Option[Float] = Map.get(Map.put(Map.new(), 1, 2), 3)
+";
+
+  assert_eq!(call(src, "lookup").unwrap().to_string(), "None");
+}
+
+#[test]
+fn values_are_any_shape() {
+  let src = "
+import Core::Map;
+
+fun names(): Map[Float, String] = Map.put(Map.put(Map.new(), 1, \"one\"), 2, \"two\")
+
+fun lookup(): Option[String] = Map.get(names(), 2)
+
+fun count(): Float = Map.fold(names(), 0, { total: Float, key: Float, name: String -> Float => total + key })
+
+fun both(): Option[Float] = {
+  let words = Map.put(Map.new(), 1, \"one\")
+  let numbers = Map.put(Map.new(), \"one\", 1)
+  Map.get(numbers, Map.get(words, 1)?)
+}
+";
+
+  assert_eq!(call(src, "lookup").unwrap().to_string(), "Some('two')");
+  assert_eq!(call(src, "count").unwrap(), Value::Float(3.0));
+  assert_eq!(call(src, "both").unwrap().to_string(), "Some(1)");
+}
+
+#[test]
+fn value_shape_is_checked() {
+  let src = "
+import Core::Map;
+
+fun build(): Map[Float, String] = Map.put(Map.put(Map.new(), 1, \"one\"), 2, 3)
+";
+
+  assert!(call(src, "build").unwrap_err().contains("Invalid argument types for call to Map.put, argument 3 expected String but found Float"));
+}
+
+#[test]
+fn key_shape_is_checked() {
+  let src = "
+import Core::Map;
+
+fun lookup(): Option[Float] = Map.get(Map.put(Map.new(), \"a\", 1), 2)
+";
+
+  assert!(call(src, "lookup").unwrap_err().contains("Invalid argument types for call to Map.get, argument 2 expected String but found Float"));
+}
+
+#[test]
+fn functions_are_not_keys() {
+  let src = "
+import Core::Map;
+
+fun lookup(): Option[Float] = Map.get(Map.new(), { x: Float -> Float => x })
+";
+
+  assert!(call(src, "lookup").unwrap_err().starts_with("Can't use { Float -> Float } as a map key, it isn't hashable"));
+}
+
+#[test]
+fn nan_is_not_a_key() {
+  let src = "
+import Core::Map;
+
+fun lookup(): Boolean = Map.contains(Map.new(), 0 / 0)
+";
+
+  assert_eq!(call(src, "lookup").unwrap_err(), "NaN can't be used as a map key");
+}
@@ -0,0 +1,91 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+use rust_let_lang::format::format_module;
+use rust_let_lang::parser::parse_str;
+
+use common::call;
+
+#[test]
+fn methods_call_the_owning_module() {
+  let src = "
+import Core::List;
+import Core::Map;
+
+fun doubled(): List[Float] = {
+  let xs = [1, 2, 3]
+  xs.map({ x: Float => x * 2 })
+}
+
+fun total(): Float = {
+  let xs = 1..5
+  let sum = { acc: Float, x: Float -> Float => acc + x }
+  xs.fold(0, sum)
+}
+
+fun captured(): Float = {
+  let xs = [4, 5]
+  let f = { n: Float => xs.append(n)[2] }
+  f(6)
+}
+";
+
+  assert_eq!(call(src, "doubled").unwrap().to_string(), "[2, 4, 6]");
+  assert_eq!(call(src, "total").unwrap(), Value::Float(10.0));
+  assert_eq!(call(src, "captured").unwrap(), Value::Float(6.0));
+}
+
+#[test]
+fn methods_need_the_module_imported() {
+  let src = "
+fun doubled(): List[Float] = {
+  let xs = [1, 2, 3]
+  xs.map({ x: Float => x * 2 })
+}
+";
+
+  assert!(call(src, "doubled").unwrap_err().contains("No function map for type List[Float]"));
+}
+
+#[test]
+fn methods_chain_on_any_value() {
+  let src = "
+import Core::List;
+
+fun chained(): List[Float] = [1, 2, 3].map({ x: Float => x * 2 }).append(7)
+
+fun indexed(): Float = {
+  let xs = [[1, 2], [3, 4]]
+  xs[1].fold(0, { acc: Float, x: Float -> Float => acc + x })
+}
+";
+
+  assert_eq!(call(src, "chained").unwrap().to_string(), "[2, 4, 6, 7]");
+  assert_eq!(call(src, "indexed").unwrap(), Value::Float(7.0));
+}
+
+#[test]
+fn calls_chain() {
+  let src = "
+fun adder(a: Float): { Float -> Float } = { b: Float => a + b }
+
+fun pickers(): List[{ Float -> Float }] = [adder(1), adder(2)]
+
+fun curried(): Float = adder(3)(4)
+
+fun picked(): Float = pickers()[1](10)
+";
+
+  assert_eq!(call(src, "curried").unwrap(), Value::Float(7.0));
+  assert_eq!(call(src, "picked").unwrap(), Value::Float(12.0));
+}
+
+#[test]
+fn method_chains_format_as_written() {
+  let src = "fun chained(xs: List[Float]): Float = (xs.append(1) + [2]).map(double).fold(0, add)\n";
+  let module = parse_str(src, "test", "methods").unwrap();
+
+  assert_eq!(format_module(&module), src);
+}
@@ -0,0 +1,59 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::{AppShapes, BitApplication, BitPackage, Machine, NativeModuleBuilder, RuntimeError, SOURCE_MODULE, SOURCE_PACKAGE, Shape, Value, compile_source_with};
+use rust_let_lang::shapes::shape_float;
+
+const SRC: &'static str = "
+import host::Math;
+
+fun run(): Float = Math.triple(4) + 1
+";
+
+fn triple_shape() -> Shape {
+  Shape::SimpleFunctionShape { args: vec![shape_float()], result: Box::new(shape_float()) }
+}
+
+fn triple(_: &Machine, args: Vec<Value>) -> Result<Value, RuntimeError> {
+  match args[0] {
+    Value::Float(value) => Ok(Value::Float(value * 3.0)),
+    _ => Err(RuntimeError::native("triple takes a Float")),
+  }
+}
+
+fn run(mut app: BitApplication) -> Value {
+  let shapes = AppShapes::for_application(&app).unwrap();
+  let package = compile_source_with(SRC, &shapes).unwrap();
+  app.packages.insert(String::from(SOURCE_PACKAGE), package);
+
+  Machine::new(app).call(SOURCE_PACKAGE, SOURCE_MODULE, "run", vec![]).unwrap()
+}
+
+#[test]
+fn registered_natives_can_be_called() {
+  let mut app = BitApplication::library();
+  app.register_native("host", "Math", "triple", triple_shape(), triple).unwrap();
+
+  assert_eq!(run(app), Value::Float(13.0));
+}
+
+#[test]
+fn built_native_modules_can_be_called() {
+  let mut package = BitPackage::new();
+  package.modules.insert(String::from("Math"), NativeModuleBuilder::new("host", "Math").function("triple", triple_shape(), triple).build());
+
+  let mut app = BitApplication::library();
+  app.packages.insert(String::from("host"), package);
+
+  assert_eq!(run(app), Value::Float(13.0));
+}
+
+#[test]
+fn natives_cannot_be_registered_twice() {
+  let mut app = BitApplication::library();
+  app.register_native("host", "Math", "triple", triple_shape(), triple).unwrap();
+
+  match app.register_native("host", "Math", "triple", triple_shape(), triple) {
+    Err(RuntimeError::AlreadyDeclared { name, .. }) => assert_eq!(name, "triple"),
+    other => panic!("Expected AlreadyDeclared, found {:?}", other.err()),
+  }
+}
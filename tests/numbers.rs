@@ -0,0 +1,62 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+use rust_let_lang::parser::{TokenKind, lex_str, parse_str};
+
+use common::call;
+
+fn value(literal: &str) -> Value {
+  call(&format!("fun literal(): Float = {}", literal), "literal").unwrap()
+}
+
+fn invalid(literal: &str) -> String {
+  let errors = parse_str(&format!("fun literal(): Float = {}", literal), "test", "Numbers").err().unwrap();
+  errors[0].to_string()
+}
+
+#[test]
+fn numbers_can_be_written_many_ways() {
+  assert_eq!(value("1e9"), Value::Float(1e9));
+  assert_eq!(value("2.5E-3"), Value::Float(2.5e-3));
+  assert_eq!(value(".5"), Value::Float(0.5));
+  assert_eq!(value("1_000_000"), Value::Float(1000000.0));
+  assert_eq!(value("-2.0"), Value::Float(-2.0));
+  assert_eq!(value("3 - -2"), Value::Float(5.0));
+}
+
+#[test]
+fn subtraction_and_ranges_still_split() {
+  let values = |src: &str| lex_str(src, "Numbers").into_iter().map(|token| token.value).collect::<Vec<String>>();
+
+  assert_eq!(values("1e-2-3"), vec!["1e-2", "-", "3", "<EOF>"]);
+  assert_eq!(values("0..10"), vec!["0", "..", "10", "<EOF>"]);
+  assert_eq!(lex_str(".5", "Numbers")[0].kind, TokenKind::Number);
+}
+
+#[test]
+fn bad_numbers_are_reported_whole() {
+  assert_eq!(invalid("1_"), "Invalid float literal '1_' at file: Numbers, line: 1, columns: 24-25");
+  assert_eq!(invalid("1__0"), "Invalid float literal '1__0' at file: Numbers, line: 1, columns: 24-27");
+  assert_eq!(invalid("1.2.3"), "Invalid float literal '1.2.3' at file: Numbers, line: 1, columns: 24-28");
+  assert_eq!(invalid("2x"), "Invalid float literal '2x' at file: Numbers, line: 1, columns: 24-25");
+}
+
+#[test]
+fn hex_and_binary_are_integers() {
+  assert_eq!(value("0xFF"), Value::Float(255.0));
+  assert_eq!(value("0xff_ff"), Value::Float(65535.0));
+  assert_eq!(value("0b1010"), Value::Float(10.0));
+  assert_eq!(value("-0b1"), Value::Float(-1.0));
+  assert_eq!(value("0x1e-5"), Value::Float(25.0));
+  assert_eq!(value("0x20_0000_0000_0000"), Value::Float(9007199254740992.0));
+}
+
+#[test]
+fn integers_must_fit_exactly() {
+  assert_eq!(invalid("0x20_0000_0000_0001"), "Integer literal '0x20_0000_0000_0001' is bigger than 2^53 so can't be an exact Float at file: Numbers, line: 1, columns: 24-42");
+  assert_eq!(invalid("0xFFFFFFFFFFFFFFFFFF"), "Integer literal '0xFFFFFFFFFFFFFFFFFF' is bigger than 2^53 so can't be an exact Float at file: Numbers, line: 1, columns: 24-43");
+  assert_eq!(invalid("0b102"), "Invalid float literal '0b102' at file: Numbers, line: 1, columns: 24-28");
+  assert_eq!(invalid("0x"), "Invalid float literal '0x' at file: Numbers, line: 1, columns: 24-25");
+}
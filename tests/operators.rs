@@ -0,0 +1,149 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::fs;
+
+use rust_let_lang::{AppShapes, Linker, Machine, Optimizer, OptLevel, Value};
+use rust_let_lang::compiler::compile_package;
+
+use common::call;
+
+const VECTORS: &'static str = "
+type Vec2 = (Float, Float)
+
+fun vec(x: Float, y: Float): Vec2 = (x, y)
+
+fun +(left: Vec2, right: Vec2): Vec2 = {
+  let (x1, y1) = left
+  let (x2, y2) = right
+  vec(x1 + x2, y1 + y2)
+}
+
+fun *(scale: Float, v: Vec2): Vec2 = {
+  let (x, y) = v
+  vec(scale * x, scale * y)
+}
+
+fun ==(left: Vec2, right: Vec2): Boolean = {
+  let (x1, y1) = left
+  let (x2, y2) = right
+  x1 - y1 == x2 - y2
+}
+
+fun sum(): Float = {
+  let (x, y) = (1, 2) + (3, 4)
+  x * 10 + y
+}
+
+fun scaled(): Float = {
+  let (x, y) = 3 * (1, 2)
+  x * 10 + y
+}
+
+fun same(): Boolean = (1, 2) == (5, 6)
+
+fun plain(): Float = 2 * 3 + 1
+";
+
+#[test]
+fn operators_dispatch_on_operand_shapes() {
+  assert_eq!(call(VECTORS, "sum").unwrap(), Value::Float(46.0));
+  assert_eq!(call(VECTORS, "scaled").unwrap(), Value::Float(36.0));
+  assert_eq!(call(VECTORS, "same").unwrap(), Value::True);
+  assert_eq!(call(VECTORS, "plain").unwrap(), Value::Float(7.0));
+}
+
+#[test]
+fn operands_without_an_overload_are_rejected() {
+  let src = "
+fun +(left: (Float, Float), right: (Float, Float)): (Float, Float) = left
+
+fun bad(): (Boolean, Boolean) = (true, false) + (false, true)
+";
+
+  assert!(call(src, "bad").unwrap_err().contains("Cannot perform operation '+'"));
+}
+
+#[test]
+fn operators_take_two_arguments() {
+  let src = "
+fun -(value: Float): Float = value
+";
+
+  assert!(call(src, "-").unwrap_err().contains("Operator - must take exactly two arguments but takes 1"));
+}
+
+#[test]
+fn operators_on_builtin_shapes_are_rejected() {
+  let src = "
+fun +(left: Float, right: Float): Float = left - right
+
+fun sum(): Float = 1 + 2
+";
+
+  assert!(call(src, "sum").unwrap_err().contains("Operator + can't be declared for builtin shapes Float and Float"));
+}
+
+#[test]
+fn overloads_stay_in_their_module() {
+  let dir = std::env::temp_dir().join(format!("letlang-operators-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+
+  fs::write(dir.join("vectors.let"), "
+type Vec2 = (Float, Float)
+
+fun +(left: Vec2, right: Vec2): Vec2 = {
+  let (x1, y1) = left
+  let (x2, y2) = right
+  (x1 + x2, y1 + y2)
+}
+
+public fun sum(): Vec2 = (1, 2) + (3, 4)
+").unwrap();
+  fs::write(dir.join("Main.let"), "
+import vectors::vectors
+
+fun sum(): (Float, Float) = (1, 2) + (3, 4)
+").unwrap();
+
+  let compiled = compile_package("vectors", dir.to_str().unwrap(), &Optimizer::with_level(OptLevel::O2), &mut AppShapes::new());
+  fs::remove_dir_all(&dir).unwrap();
+
+  let error = compiled.err().unwrap().to_string();
+  assert!(error.contains("Cannot perform operation '+'"), "{}", error);
+}
+
+#[test]
+fn overloads_apply_through_calls_into_their_module() {
+  let dir = std::env::temp_dir().join(format!("letlang-operators-calls-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+
+  fs::write(dir.join("vectors.let"), "
+type Vec2 = (Float, Float)
+
+fun +(left: Vec2, right: Vec2): Vec2 = {
+  let (x1, y1) = left
+  let (x2, y2) = right
+  (x1 + x2, y1 + y2)
+}
+
+public fun sum(): Vec2 = (1, 2) + (3, 4)
+").unwrap();
+  fs::write(dir.join("Main.let"), "
+import vectors::vectors
+
+fun sum(): Float = {
+  let (x, y) = vectors.sum()
+  x * 10 + y
+}
+").unwrap();
+
+  let compiled = compile_package("vectors", dir.to_str().unwrap(), &Optimizer::with_level(OptLevel::O2), &mut AppShapes::new());
+  fs::remove_dir_all(&dir).unwrap();
+
+  let app = Linker::new().package("vectors", compiled.unwrap()).link().unwrap();
+  assert_eq!(Machine::new(app).call("vectors", "Main", "sum", vec![]).unwrap(), Value::Float(46.0));
+}
@@ -0,0 +1,44 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::{Optimizer, OptLevel};
+use rust_let_lang::optimize::Pass;
+
+#[test]
+fn levels_run_their_own_passes() {
+  assert!(Optimizer::with_level(OptLevel::O0).passes().is_empty());
+  assert_eq!(Optimizer::with_level(OptLevel::O2).passes(), &OptLevel::O2.passes()[..]);
+}
+
+#[test]
+fn disabling_a_pass_removes_it() {
+  let optimizer = Optimizer::builder(OptLevel::O2).disable(Pass::Inline).disable(Pass::Peephole).build();
+
+  assert_eq!(optimizer.passes(), &[Pass::Escape, Pass::LiftReturn, Pass::CommonSubexpression, Pass::LoopInvariant,
+    Pass::DeadStore, Pass::FreeLocal, Pass::LoadStore]);
+  assert!(!optimizer.peephole());
+}
+
+#[test]
+fn enabled_passes_run_in_pipeline_order() {
+  let optimizer = Optimizer::builder(OptLevel::O0).enable(Pass::LoadStore).enable(Pass::StripAssert).enable(Pass::Inline).build();
+
+  assert_eq!(optimizer.passes(), &[Pass::Inline, Pass::StripAssert, Pass::LoadStore]);
+}
+
+#[test]
+fn disabling_a_pass_not_at_the_level_changes_nothing() {
+  let optimizer = Optimizer::builder(OptLevel::O1).disable(Pass::Inline).build();
+
+  assert_eq!(optimizer.passes(), &OptLevel::O1.passes()[..]);
+}
+
+#[test]
+fn inline_threshold_tracks_the_inline_pass() {
+  let mut optimizer = Optimizer::with_level(OptLevel::O2);
+
+  optimizer.set_inline_threshold(None);
+  assert!(!optimizer.passes().contains(&Pass::Inline));
+
+  optimizer.set_inline_threshold(Some(4));
+  assert_eq!(optimizer.passes(), &OptLevel::O2.passes()[..]);
+}
@@ -0,0 +1,63 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::bytecode::Instruction;
+use rust_let_lang::compiler::peephole_opt;
+
+fn describe(body: &[Instruction]) -> Vec<String> {
+  body.iter().map(|instruction| match instruction {
+    Instruction::LoadConstTrue => String::from("LoadConstTrue"),
+    Instruction::LoadConstUnit => String::from("LoadConstUnit"),
+    Instruction::LoadConstFloat { value } => format!("LoadConstFloat({})", value),
+    Instruction::LoadValue { local } => format!("LoadValue({})", local),
+    Instruction::StoreValue { local } => format!("StoreValue({})", local),
+    Instruction::AddFloat => String::from("AddFloat"),
+    Instruction::AddLocals { left, right } => format!("AddLocals({}, {})", left, right),
+    Instruction::StoreConstFloat { value, local } => format!("StoreConstFloat({}, {})", value, local),
+    Instruction::Branch { jump } => format!("Branch({})", jump),
+    Instruction::Jump { jump } => format!("Jump({})", jump),
+    Instruction::Return => String::from("Return"),
+    _ => String::from("?"),
+  }).collect()
+}
+
+#[test]
+fn fuses_and_moves_jumps() {
+  let body = vec![
+    Instruction::LoadConstFloat { value: 1.0 },
+    Instruction::StoreValue { local: 0 },
+    Instruction::LoadValue { local: 0 },
+    Instruction::LoadValue { local: 1 },
+    Instruction::AddFloat,
+    Instruction::StoreValue { local: 1 },
+    Instruction::Jump { jump: -7 },
+  ];
+
+  assert_eq!(describe(&peephole_opt(body)), vec!["StoreConstFloat(1, 0)", "AddLocals(0, 1)", "StoreValue(1)", "Jump(-4)"]);
+}
+
+#[test]
+fn never_fuses_over_a_jump_target() {
+  let body = vec![
+    Instruction::LoadConstTrue,
+    Instruction::Branch { jump: 1 },
+    Instruction::LoadConstFloat { value: 1.0 },
+    Instruction::StoreValue { local: 0 },
+    Instruction::Return,
+  ];
+
+  assert_eq!(describe(&peephole_opt(body)), vec!["LoadConstTrue", "Branch(1)", "LoadConstFloat(1)", "StoreValue(0)", "Return"]);
+}
+
+#[test]
+fn branch_to_a_jump_goes_straight_to_its_target() {
+  let body = vec![
+    Instruction::LoadConstTrue,
+    Instruction::Branch { jump: 1 },
+    Instruction::LoadConstUnit,
+    Instruction::Jump { jump: 1 },
+    Instruction::LoadConstUnit,
+    Instruction::Return,
+  ];
+
+  assert_eq!(describe(&peephole_opt(body)), vec!["LoadConstTrue", "Branch(3)", "LoadConstUnit", "Jump(1)", "LoadConstUnit", "Return"]);
+}
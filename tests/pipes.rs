@@ -0,0 +1,52 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+use rust_let_lang::format::format_module;
+use rust_let_lang::parser::parse_str;
+
+use common::call;
+
+#[test]
+fn pipe_into_functions() {
+  let src = "
+import Core::List;
+
+fun double(x: Float): Float = x * 2
+
+fun sum(xs: List[Float]): Float = List.fold(xs, 0, { acc: Float, x: Float -> Float => acc + x })
+
+fun piped(): Float = 1 + 2 |> double |> double
+
+fun withArgs(): Float = 0..4 |> List.map({ x: Float => x * 10 }) |> sum
+
+fun dynamic(): Float = {
+  let triple = { x: Float => x * 3 }
+  5 |> triple
+}
+";
+
+  assert_eq!(call(src, "piped").unwrap(), Value::Float(12.0));
+  assert_eq!(call(src, "withArgs").unwrap(), Value::Float(60.0));
+  assert_eq!(call(src, "dynamic").unwrap(), Value::Float(15.0));
+}
+
+#[test]
+fn pipe_checks_the_first_argument() {
+  let src = "
+fun double(x: Float): Float = x * 2
+
+fun wrong(): Float = \"two\" |> double
+";
+
+  assert!(call(src, "wrong").is_err());
+}
+
+#[test]
+fn pipes_format_as_written() {
+  let src = "fun piped(x: Float): Float = (x + 1 |> double |> scale(2)) * 3\n";
+  let module = parse_str(src, "test", "pipes").unwrap();
+
+  assert_eq!(format_module(&module), src);
+}
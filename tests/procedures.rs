@@ -0,0 +1,63 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::{AppShapes, SOURCE_MODULE, SOURCE_PACKAGE};
+use rust_let_lang::ir::{compile_ir_module, validate};
+use rust_let_lang::lsp::check_document;
+use rust_let_lang::parser::parse_str;
+use rust_let_lang::typechecker::check_module;
+
+use common::machine;
+
+const SRC: &'static str = "
+import Core::Console
+
+fun greet(): Unit = {
+  Console.println(\"hello\")
+  Console.println(\"world\")
+}
+
+fun declares(): Unit = {
+  let x = 1
+}
+
+fun nested(): Unit = {
+  fun helper(): Float = 1
+}
+
+fun counts(): Unit = {
+  let mut total = 0
+  while (total < 3) { total = total + 1 }
+  total
+}
+";
+
+#[test]
+fn unit_functions_return_unit_whatever_they_end_with() {
+  let machine = machine(SRC);
+
+  for name in &["greet", "declares", "nested", "counts"] {
+    assert_eq!(machine.call(SOURCE_PACKAGE, SOURCE_MODULE, name, vec![]).unwrap().to_string(), "()");
+  }
+}
+
+#[test]
+fn unit_functions_leave_only_unit_on_the_stack() {
+  let shapes = AppShapes::new();
+  let checked = check_module(parse_str(SRC, "test", "Main").unwrap(), &shapes).unwrap();
+  let module = compile_ir_module(&checked, &shapes).unwrap();
+
+  for func in module.functions.values() {
+    assert_eq!(validate(func), Ok(()));
+  }
+}
+
+#[test]
+fn only_a_thrown_away_value_is_warned_about() {
+  let document = check_document(SRC, "Main");
+  let warnings: Vec<String> = document.warnings.iter().map(|(_, message)| message.clone()).collect();
+
+  assert!(document.errors.is_empty());
+  assert_eq!(warnings, vec![String::from("Value of shape Float is never used at file: Main, line: 20, columns: 3-7")]);
+}
@@ -0,0 +1,69 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::{BitApplication, FunctionRef, Machine, SOURCE_MODULE, SOURCE_PACKAGE, Shape, Value, compile_source};
+use rust_let_lang::profiler::FunctionStats;
+use rust_let_lang::shapes::shape_float;
+
+fn func(name: &str, args: Vec<Shape>) -> FunctionRef {
+  FunctionRef {
+    package: String::from(SOURCE_PACKAGE),
+    module: String::from(SOURCE_MODULE),
+    name: String::from(name),
+
+    shape: Shape::SimpleFunctionShape { args, result: Box::new(shape_float()) },
+  }
+}
+
+/**
+* Profiles a main that counts down from depth, returning the stats for main and down.
+*/
+fn profile(depth: usize) -> (FunctionStats, FunctionStats) {
+  let src = format!("
+fun down(n: Float): Float = if (n <= 0) 0 else 1 + down(n - 1)
+
+fun main(): Float = down({})
+", depth);
+
+  let mut app = BitApplication::new(func("main", vec![]));
+  app.packages.insert(String::from(SOURCE_PACKAGE), compile_source(&src).unwrap());
+
+  let mut machine = Machine::new(app);
+  machine.enable_profiling();
+  assert_eq!(machine.run_main().unwrap(), Value::Float(depth as f64));
+
+  let profile = machine.profile().unwrap();
+  let main = profile.get(&func("main", vec![])).cloned().unwrap();
+  let down = profile.get(&func("down", vec![shape_float()])).cloned().unwrap();
+  (main, down)
+}
+
+#[test]
+fn counts_every_recursive_call() {
+  let (main, down) = profile(5);
+
+  assert_eq!(main.calls, 1);
+  assert_eq!(down.calls, 6);
+}
+
+#[test]
+fn counts_the_same_instructions_for_each_level() {
+  let (main, shallow) = profile(0);
+  let (_, middle) = profile(5);
+  let (_, deep) = profile(10);
+
+  assert!(main.instructions > 0);
+  assert!(shallow.instructions > 0);
+  assert!(middle.instructions > shallow.instructions);
+  assert_eq!(deep.instructions - middle.instructions, middle.instructions - shallow.instructions);
+}
+
+#[test]
+fn nothing_is_recorded_without_profiling() {
+  let mut app = BitApplication::new(func("main", vec![]));
+  app.packages.insert(String::from(SOURCE_PACKAGE), compile_source("fun main(): Float = 1").unwrap());
+
+  let machine = Machine::new(app);
+  machine.run_main().unwrap();
+
+  assert!(machine.profile().is_none());
+}
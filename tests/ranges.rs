@@ -0,0 +1,96 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::rc::Rc;
+
+use rust_let_lang::Value;
+use rust_let_lang::runtime::ListValue;
+use rust_let_lang::shapes::{shape_float, shape_list};
+
+use common::call;
+
+fn floats(values: &[f64]) -> Value {
+  Value::List(Rc::new(ListValue {
+    contents: values.iter().map(|value| Value::Float(*value)).collect(),
+    shape: shape_list(shape_float()),
+  }))
+}
+
+#[test]
+fn range_literal_excludes_end() {
+  let src = "
+fun digits(): List[Float] = 0..5
+";
+
+  assert_eq!(call(src, "digits").unwrap(), floats(&[0.0, 1.0, 2.0, 3.0, 4.0]));
+}
+
+#[test]
+fn range_binds_looser_than_sum() {
+  let src = "
+import Core::List;
+
+fun shifted(): Float = {
+  let n = 2
+  let xs = n+1..n*3
+  List.fold(xs, 0, { acc: Float, x: Float -> Float => acc + x })
+}
+";
+
+  assert_eq!(call(src, "shifted").unwrap(), Value::Float(3.0 + 4.0 + 5.0));
+}
+
+#[test]
+fn range_with_step() {
+  let src = "
+import Core::List;
+
+fun down(): List[Float] = List.range(10, 0, 0 - 4)
+";
+
+  assert_eq!(call(src, "down").unwrap(), floats(&[10.0, 6.0, 2.0]));
+}
+
+#[test]
+fn zero_step_fails() {
+  let src = "
+import Core::List;
+
+fun stuck(): List[Float] = List.range(0, 1, 0)
+";
+
+  assert!(call(src, "stuck").unwrap_err().contains("List.range step must not be zero"));
+}
+
+#[test]
+fn range_bounds_must_be_floats() {
+  let src = "
+fun words(): List[Float] = \"a\"..\"b\"
+";
+
+  assert!(call(src, "words").is_err());
+}
+
+#[test]
+fn huge_ranges_fail_before_they_are_built() {
+  let src = "
+import Core::List;
+
+fun huge(): List[Float] = 0..1000000000000
+
+fun endless(): List[Float] = List.range(0, 1 / 0, 1)
+";
+
+  assert!(call(src, "huge").unwrap_err().contains("List.range from 0 to 1000000000000 by 1 would have more than 10000000 items"));
+  assert!(call(src, "endless").unwrap_err().contains("List.range from 0 to inf by 1 would have more than 10000000 items"));
+}
+
+#[test]
+fn backwards_ranges_are_empty() {
+  let src = "
+fun backwards(): List[Float] = 5..0
+";
+
+  assert_eq!(call(src, "backwards").unwrap(), floats(&[]));
+}
@@ -0,0 +1,87 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::rc::Rc;
+
+use rust_let_lang::{SOURCE_MODULE, SOURCE_PACKAGE, Value};
+
+use common::machine;
+
+#[test]
+fn recursive_closures_are_not_kept_alive_by_themselves() {
+  let src = "
+fun countdown(step: Float): { Float -> Float } = {
+  fun down(n: Float): Float = if (n > 0) down(n - step) else n
+  down
+}
+";
+
+  let machine = machine(src);
+
+  if let Value::Function(down) = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "countdown", vec![Value::Float(2.0)]).unwrap() {
+    assert_eq!(machine.execute_handle(down.clone(), vec![Value::Float(9.0)]).unwrap(), Value::Float(-1.0));
+
+    // Only this test holds the handle, the calls didn't leave any cycle behind.
+    assert_eq!(Rc::strong_count(&down), 1);
+    assert_eq!(Rc::weak_count(&down), 1);
+  } else {
+    panic!("countdown should return a function");
+  }
+}
+
+#[test]
+fn captures_come_before_arguments() {
+  let src = "
+fun power(base: Float): { Float, Float -> Float } = {
+  fun go(n: Float, acc: Float): Float = if (n > 0) go(n - 1, acc * base) else acc
+  go
+}
+
+fun cube(base: Float): Float = {
+  let go = power(base)
+  go(3, 1)
+}
+";
+
+  let machine = machine(src);
+
+  assert_eq!(machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "cube", vec![Value::Float(2.0)]).unwrap(), Value::Float(8.0));
+
+  if let Value::Function(go) = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "power", vec![Value::Float(3.0)]).unwrap() {
+    assert_eq!(machine.execute_handle(go, vec![Value::Float(2.0), Value::Float(1.0)]).unwrap(), Value::Float(9.0));
+  } else {
+    panic!("power should return a function");
+  }
+}
+
+#[test]
+fn local_functions_can_call_each_other() {
+  let src = "
+fun parity(n: Float): Boolean = {
+  fun isEven(n: Float): Boolean = if (n == 0) true else isOdd(n - 1)
+  fun isOdd(n: Float): Boolean = if (n == 0) false else isEven(n - 1)
+  isEven(n)
+}
+";
+
+  let machine = machine(src);
+
+  assert_eq!(machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "parity", vec![Value::Float(10.0)]).unwrap(), Value::True);
+  assert_eq!(machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "parity", vec![Value::Float(7.0)]).unwrap(), Value::False);
+}
+
+#[test]
+fn mutually_recursive_functions_share_captures_and_infer_shapes() {
+  let src = "
+fun bounce(n: Float, step: Float): Float = {
+  fun ping(left) = if (left > 0) pong(left - step) else left
+  fun pong(left: Float) = if (left > 0) ping(left - step) + 10 else left
+  ping(n)
+}
+";
+
+  let machine = machine(src);
+
+  assert_eq!(machine.call(SOURCE_PACKAGE, SOURCE_MODULE, "bounce", vec![Value::Float(4.0), Value::Float(1.0)]).unwrap(), Value::Float(20.0));
+}
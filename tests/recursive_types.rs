@@ -0,0 +1,44 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::compile_source;
+
+use common::call_test_package;
+
+fn call(name: &str) -> String {
+  call_test_package("forest", name, vec![]).unwrap().to_string()
+}
+
+#[test]
+fn recursive_trees_fold() {
+  assert_eq!(call("total"), "Some(10)");
+  assert_eq!(call("deepest"), "Some(3)");
+}
+
+#[test]
+fn recursive_types_cross_modules() {
+  assert_eq!(call("rightTotal"), "Some(7)");
+}
+
+#[test]
+fn recursive_types_can_be_taken_apart() {
+  assert_eq!(call("grandchild"), "4");
+}
+
+#[test]
+fn only_references_inside_another_shape_may_recurse() {
+  let errors = compile_source("type Loop = Loop\n\nfun main(): Float = 1").err().unwrap();
+  assert!(errors.to_string().contains("Cyclic type alias: Loop -> Loop"), "{}", errors);
+
+  let errors = compile_source("type Ping = Pong\ntype Pong = Ping\n\nfun main(): Float = 1").err().unwrap();
+  assert!(errors.to_string().contains("Cyclic type alias: Ping -> Pong -> Ping"), "{}", errors);
+}
+
+#[test]
+fn recursive_types_keep_their_names() {
+  let src = "type Chain = Option[(Float, Chain)]\n\nfun main(): Chain = Some((1, Some(2)))";
+  let errors = compile_source(src).err().unwrap();
+
+  assert!(errors.to_string().contains("Option[(Float, Main.Chain)]"), "{}", errors);
+}
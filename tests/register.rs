@@ -0,0 +1,88 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::path::Path;
+
+use rust_let_lang::{AppShapes, Backend, BitApplication, Machine, Optimizer, OptLevel, SOURCE_MODULE, SOURCE_PACKAGE, Value, compile_source};
+use rust_let_lang::compiler::compile_package;
+use rust_let_lang::interpreter::RunFunction;
+use rust_let_lang::register::to_registers;
+use rust_let_lang::testing::find_tests;
+
+const SRC: &'static str = "
+fun sum(n: Float): Float = {
+  let mut total = 0
+  let mut i = 0
+
+  while (i < n) {
+    i = i + 1
+    total = total + i
+  }
+
+  total
+}
+
+fun countdown(n: Float): Float = if (n > 0) countdown(n - 1) else n
+
+fun sign(x: Float): Float = if (x < 0) 0 - 1 else if (x > 0) 1 else 0
+";
+
+fn machine(backend: Backend) -> Machine {
+  let mut machine = common::machine(SRC);
+  machine.set_backend(backend);
+  machine
+}
+
+#[test]
+fn backends_agree() {
+  let stack = machine(Backend::Stack);
+  let register = machine(Backend::Register);
+
+  for (name, arg) in &[("sum", 100.0), ("sign", -3.0), ("sign", 0.0), ("sign", 8.0), ("countdown", 20.0)] {
+    let expected = stack.call(SOURCE_PACKAGE, SOURCE_MODULE, name, vec![Value::Float(*arg)]).unwrap();
+    let found = register.call(SOURCE_PACKAGE, SOURCE_MODULE, name, vec![Value::Float(*arg)]).unwrap();
+
+    assert_eq!(found, expected, "{}({})", name, arg);
+  }
+}
+
+#[test]
+fn tail_calls_do_not_nest() {
+  let register = machine(Backend::Register);
+
+  assert_eq!(register.call(SOURCE_PACKAGE, SOURCE_MODULE, "countdown", vec![Value::Float(100_000.0)]).unwrap(), Value::Float(0.0));
+}
+
+#[test]
+fn loops_take_fewer_instructions() {
+  let package = compile_source(SRC).unwrap();
+  let module = &package.modules[SOURCE_MODULE];
+
+  if let RunFunction::BitFunction(func) = &module.functions["sum"] {
+    let converted = to_registers(func, module).unwrap();
+
+    assert!(converted.body.len() < func.body.len());
+  } else {
+    panic!("sum should be bytecode");
+  }
+}
+
+#[test]
+fn package_tests_pass_on_registers() {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+  let package = compile_package("test", dir.to_str().unwrap(), &Optimizer::with_level(OptLevel::O2), &mut AppShapes::new()).unwrap();
+  let tests = find_tests(&package);
+
+  let mut app = BitApplication::library();
+  app.packages.insert(String::from("test"), package);
+
+  let mut machine = Machine::new(app);
+  machine.set_backend(Backend::Register);
+
+  for func in tests {
+    if let Err(err) = machine.execute(func.clone(), vec![]) {
+      panic!("{}: {}", func.pretty(), err);
+    }
+  }
+}
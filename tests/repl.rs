@@ -0,0 +1,46 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::{OptLevel, Optimizer};
+use rust_let_lang::repl::Repl;
+
+#[test]
+fn let_persists_into_the_next_line() {
+  let optimizer = Optimizer::with_level(OptLevel::O2);
+  let mut repl = Repl::new(&optimizer);
+
+  assert_eq!(repl.eval("let x = 2").unwrap(), "x = 2: Float");
+  assert_eq!(repl.eval("x * 3").unwrap(), "6: Float");
+}
+
+#[test]
+fn rebinding_replaces_the_earlier_value() {
+  let optimizer = Optimizer::with_level(OptLevel::O2);
+  let mut repl = Repl::new(&optimizer);
+
+  repl.eval("let x = 2").unwrap();
+  repl.eval("let y = 5").unwrap();
+  repl.eval("let x = 10").unwrap();
+
+  assert_eq!(repl.eval("x + y").unwrap(), "15: Float");
+}
+
+#[test]
+fn failed_line_keeps_no_bindings() {
+  let optimizer = Optimizer::with_level(OptLevel::O2);
+  let mut repl = Repl::new(&optimizer);
+
+  repl.eval("let x = 2").unwrap();
+
+  assert!(repl.eval("let (x, y) = (5, ???)").is_err());
+  assert_eq!(repl.eval("x").unwrap(), "2: Float");
+  assert!(repl.eval("y").is_err());
+}
+
+#[test]
+fn tuple_let_binds_each_element() {
+  let optimizer = Optimizer::with_level(OptLevel::O2);
+  let mut repl = Repl::new(&optimizer);
+
+  assert_eq!(repl.eval("let (a, b) = (1, 2)").unwrap(), "a = 1: Float\nb = 2: Float");
+  assert_eq!(repl.eval("b - a").unwrap(), "1: Float");
+}
@@ -0,0 +1,101 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::{AppShapes, Optimizer, OptLevel, Value, compile_source};
+use rust_let_lang::format::format_module;
+use rust_let_lang::ir::{compile_ir_module, Ir};
+use rust_let_lang::parser::parse_str;
+use rust_let_lang::typechecker::check_module;
+
+use common::call_with;
+
+const SRC: &'static str = "
+fun abs(x: Float): Float = {
+  if (x < 0) {
+    return 0 - x
+  }
+  x
+}
+
+fun sign(x: Float): Float = if (x < 0) { return 0 - 1 } else { 1 }
+
+fun firstSquareOver(limit: Float): Float = {
+  let mut i = 0
+  while (i < limit) {
+    if (i * i > limit) return i
+    i = i + 1
+  }
+  limit
+}
+
+fun inferred(x: Float) = {
+  if (x > 1) { return x }
+  0
+}
+
+fun skip(x: Float): Unit = {
+  if (x > 1) { return }
+  let y = x
+}
+
+fun inner(x: Float): Float = {
+  let f = { y: Float => if (y > 1) { return 10 } else { y } }
+  f(x) + 1
+}
+
+fun midway(x: Float): Float = 1 + (return x)
+";
+
+#[test]
+fn returns_leave_the_function_early() {
+  assert_eq!(call_with(SRC, "abs", vec![Value::Float(-3.0)]).unwrap(), Value::Float(3.0));
+  assert_eq!(call_with(SRC, "abs", vec![Value::Float(3.0)]).unwrap(), Value::Float(3.0));
+  assert_eq!(call_with(SRC, "sign", vec![Value::Float(-2.0)]).unwrap(), Value::Float(-1.0));
+  assert_eq!(call_with(SRC, "sign", vec![Value::Float(2.0)]).unwrap(), Value::Float(1.0));
+  assert_eq!(call_with(SRC, "firstSquareOver", vec![Value::Float(10.0)]).unwrap(), Value::Float(4.0));
+  assert_eq!(call_with(SRC, "inferred", vec![Value::Float(5.0)]).unwrap(), Value::Float(5.0));
+  assert_eq!(call_with(SRC, "inferred", vec![Value::Float(0.5)]).unwrap(), Value::Float(0.0));
+  assert_eq!(call_with(SRC, "skip", vec![Value::Float(3.0)]).unwrap(), Value::Unit);
+  assert_eq!(call_with(SRC, "midway", vec![Value::Float(3.0)]).unwrap(), Value::Float(3.0));
+}
+
+#[test]
+fn returns_in_a_lambda_leave_only_the_lambda() {
+  assert_eq!(call_with(SRC, "inner", vec![Value::Float(5.0)]).unwrap(), Value::Float(11.0));
+  assert_eq!(call_with(SRC, "inner", vec![Value::Float(0.5)]).unwrap(), Value::Float(1.5));
+}
+
+#[test]
+fn returned_values_must_match_the_result() {
+  let errors = compile_source("fun f(x: Float): Float = {\n  if (x > 1) { return \"big\" }\n  x\n}").err().unwrap();
+  assert!(errors.to_string().contains("Declared: Float, but found: String"), "{}", errors);
+
+  let errors = compile_source("fun f(x: Float): Float = {\n  if (x > 1) { return }\n  x\n}").err().unwrap();
+  assert!(errors.to_string().contains("Declared: Float, but found: Unit"), "{}", errors);
+}
+
+#[test]
+fn lifted_returns_are_not_doubled() {
+  let shapes = AppShapes::new();
+  let checked = check_module(parse_str(SRC, "test", "Main").unwrap(), &shapes).unwrap();
+  let mut module = compile_ir_module(&checked, &shapes).unwrap();
+
+  Optimizer::with_level(OptLevel::O2).optimize_module(&mut module);
+
+  let then_block = module.functions["sign"].body.iter().find_map(|ir| match ir {
+    Ir::Branch { then_block, .. } => Some(then_block.clone()),
+    _ => None,
+  }).unwrap();
+
+  let returns = then_block.iter().filter(|ir| match ir { Ir::Return => true, _ => false }).count();
+  assert_eq!(returns, 1);
+}
+
+#[test]
+fn returns_format_as_written() {
+  let src = "fun f(x: Float): Unit = {\n  if (x > 1) {\n    return\n  }\n  let y = x\n}\n\nfun g(x: Float): Float = 1 + (return x * 2)\n";
+  let module = parse_str(src, "test", "returns").unwrap();
+
+  assert_eq!(format_module(&module), src);
+}
@@ -0,0 +1,76 @@
+extern crate rust_let_lang;
+
+use std::path::Path;
+
+use rust_let_lang::{AppShapes, BitApplication, BitPackage, FunctionRef, Machine, Optimizer, OptLevel, Shape, verify_roundtrip};
+use rust_let_lang::bytecode::serialize_bit_module;
+use rust_let_lang::compiler::compile_package;
+use rust_let_lang::shapes::shape_float;
+use rust_let_lang::testing::find_tests;
+
+fn test_package() -> BitPackage {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+
+  compile_package("test", dir.to_str().unwrap(), &Optimizer::with_level(OptLevel::O2), &mut AppShapes::new()).unwrap()
+}
+
+#[test]
+fn main_survives_roundtrip() {
+  let main = FunctionRef {
+    package: String::from("test"),
+    module: String::from("basic"),
+    name: String::from("main"),
+
+    shape: Shape::SimpleFunctionShape {
+      args: vec![],
+      result: Box::new(shape_float()),
+    },
+  };
+
+  let result = verify_roundtrip("test", test_package(), &main, vec![]).unwrap();
+
+  assert_eq!(format!("{:?}", result), "Float(30.0)");
+}
+
+#[test]
+fn tests_survive_roundtrip() {
+  for func in find_tests(&test_package()) {
+    if let Err(err) = verify_roundtrip("test", test_package(), &func, vec![]) {
+      panic!("{}: {}", func.pretty(), err);
+    }
+  }
+}
+
+#[test]
+fn builds_are_byte_for_byte_the_same() {
+  let bytes = |package: BitPackage| {
+    let mut names: Vec<&String> = package.modules.keys().collect();
+    names.sort();
+
+    let mut out = Vec::new();
+
+    for name in names {
+      serialize_bit_module(&mut out, &package.modules[name]).unwrap();
+    }
+
+    out
+  };
+
+  assert_eq!(bytes(test_package()), bytes(test_package()));
+}
+
+#[test]
+fn stale_signatures_fail_to_link() {
+  let mut package = test_package();
+
+  let basic = package.modules.get_mut("basic").unwrap();
+  let pow = basic.function_refs.iter_mut().find(|func| func.name == "pow").unwrap();
+  pow.shape = Shape::SimpleFunctionShape { args: vec![shape_float()], result: Box::new(shape_float()) };
+
+  let mut app = BitApplication::library();
+  app.packages.insert(String::from("test"), package);
+
+  let err = Machine::new(app).link().unwrap_err();
+
+  assert_eq!(err.to_string(), "Signature mismatch for test::basic.pow, compiled against { Float -> Float } but found { Float, Float -> Float }");
+}
@@ -0,0 +1,27 @@
+extern crate rust_let_lang;
+
+use std::fs;
+
+use rust_let_lang::{AppShapes, Machine, OptLevel, Optimizer, Value};
+use rust_let_lang::compiler::compile_script;
+
+#[test]
+fn top_level_statements_run_as_main() {
+  let dir = std::env::temp_dir().join(format!("letlang-script-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+
+  let path = dir.join("sum.let");
+  fs::write(&path, "
+fun double(x: Float): Float = x * 2
+
+let base = 5
+let doubled = double(base)
+doubled + 1
+").unwrap();
+
+  let app = compile_script(&path, &Optimizer::with_level(OptLevel::O2), &AppShapes::new()).unwrap();
+  assert_eq!(Machine::new(app).run_main().unwrap(), Value::Float(11.0));
+
+  fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,60 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::fs;
+use std::rc::Rc;
+
+use rust_let_lang::{RuntimeError, SOURCE_MODULE, SOURCE_PACKAGE, SecurityConfig, Value};
+use common::machine;
+
+const FILES: &'static str = "
+import Core::File;
+
+fun direct(path: String): String = File.readText(path)
+
+fun indirect(path: String): String = {
+  let read = File.readText
+  read(path)
+}
+";
+
+fn path_arg(name: &str) -> (std::path::PathBuf, Vec<Value>) {
+  let path = std::env::temp_dir().join(format!("letlang-{}-{}.txt", name, std::process::id()));
+  fs::write(&path, "secret").unwrap();
+
+  let arg = Value::String(Rc::new(String::from(path.to_str().unwrap())));
+  (path, vec![arg])
+}
+
+#[test]
+fn file_access_is_disabled_by_default() {
+  let machine = machine(FILES);
+  let (path, args) = path_arg("disabled");
+
+  for name in &["direct", "indirect"] {
+    match machine.call(SOURCE_PACKAGE, SOURCE_MODULE, name, args.clone()) {
+      Err(RuntimeError::Disabled { func, capability }) => {
+        assert_eq!(func.name, "readText");
+        assert_eq!(capability, "fs");
+      }
+      other => panic!("Expected {} to be disabled, found {:?}", name, other),
+    }
+  }
+
+  fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn trusted_programs_can_read_files() {
+  let mut machine = machine(FILES);
+  machine.set_security(SecurityConfig::trusted());
+  let (path, args) = path_arg("trusted");
+
+  for name in &["direct", "indirect"] {
+    let result = machine.call(SOURCE_PACKAGE, SOURCE_MODULE, name, args.clone()).unwrap();
+    assert_eq!(result.to_string(), "'secret'");
+  }
+
+  fs::remove_file(&path).unwrap();
+}
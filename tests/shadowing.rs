@@ -0,0 +1,77 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::{AppShapes, BitApplication, BitPackage, Machine, OptLevel, Optimizer, SOURCE_MODULE, SOURCE_PACKAGE, Shadowing, Value};
+use rust_let_lang::compiler::compile_source_module;
+
+fn call(src: &str, name: &str, shadowing: Shadowing) -> Result<Value, String> {
+  let mut shapes = AppShapes::new();
+  shapes.set_shadowing(shadowing);
+
+  let module = compile_source_module(SOURCE_PACKAGE, SOURCE_MODULE, src, &Optimizer::with_level(OptLevel::O2), &shapes)
+    .map_err(|err| err.to_string())?;
+
+  let mut package = BitPackage::new();
+  package.modules.insert(String::from(SOURCE_MODULE), module);
+
+  let mut app = BitApplication::library();
+  app.packages.insert(String::from(SOURCE_PACKAGE), package);
+
+  Machine::new(app).call(SOURCE_PACKAGE, SOURCE_MODULE, name, vec![]).map_err(|err| err.to_string())
+}
+
+const SHADOWS: &'static str = "
+fun same(): Float = {
+  let x = 1
+  let f = { n: Float => x + n }
+  let x = x + 10
+  x + f(0)
+}
+
+fun nested(x: Float): Float = {
+  let y = {
+    let x = x * 2
+    x
+  }
+  x + y
+}
+
+fun outer(): Float = nested(3)
+";
+
+#[test]
+fn shadowing_is_allowed_by_default() {
+  assert_eq!(call(SHADOWS, "same", Shadowing::Warn).unwrap(), Value::Float(12.0));
+  assert_eq!(call(SHADOWS, "outer", Shadowing::Warn).unwrap(), Value::Float(9.0));
+}
+
+#[test]
+fn denied_shadowing_points_at_the_original() {
+  let err = call(SHADOWS, "same", Shadowing::Deny).unwrap_err();
+
+  assert!(err.contains("Variable x shadows the one declared at file: Main, line: 3"), "{}", err);
+  assert!(err.contains("Variable x shadows the one declared at file: Main, line: 9"), "{}", err);
+}
+
+#[test]
+fn warnings_are_kept_for_the_caller() {
+  let mut shapes = AppShapes::new();
+  shapes.set_shadowing(Shadowing::Warn);
+
+  compile_source_module(SOURCE_PACKAGE, SOURCE_MODULE, SHADOWS, &Optimizer::with_level(OptLevel::O2), &shapes).unwrap();
+
+  let warnings: Vec<String> = shapes.take_warnings().iter().map(|warning| warning.to_string()).collect();
+
+  assert_eq!(warnings.len(), 2, "{:?}", warnings);
+  assert!(warnings[0].contains("Variable x shadows the one declared at file: Main, line: 3"), "{}", warnings[0]);
+  assert!(shapes.take_warnings().is_empty());
+}
+
+#[test]
+fn allowed_shadowing_warns_about_nothing() {
+  let mut shapes = AppShapes::new();
+  shapes.set_shadowing(Shadowing::Allow);
+
+  compile_source_module(SOURCE_PACKAGE, SOURCE_MODULE, SHADOWS, &Optimizer::with_level(OptLevel::O2), &shapes).unwrap();
+
+  assert!(shapes.take_warnings().is_empty());
+}
@@ -0,0 +1,43 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::Shape;
+use rust_let_lang::parser::parse_shape;
+use rust_let_lang::shapes::{shape_boolean, shape_float, shape_list, shape_map, shape_named, shape_option, shape_result, shape_string, shape_tuple, shape_unit, shape_unknown};
+
+fn function(args: Vec<Shape>, result: Shape) -> Shape {
+  Shape::SimpleFunctionShape { args, result: Box::new(result) }
+}
+
+#[test]
+fn pretty_shapes_parse_back() {
+  let shapes = vec![
+    shape_float(),
+    shape_unit(),
+    shape_unknown(),
+    shape_named(String::from("Geometry.Point")),
+    shape_list(shape_string()),
+    shape_map(shape_string(), shape_list(shape_float())),
+    shape_option(shape_float()),
+    shape_result(shape_list(shape_float()), shape_string()),
+    shape_tuple(vec![shape_float(), shape_tuple(vec![shape_boolean(), shape_string()])]),
+    function(vec![], shape_float()),
+    function(vec![shape_float(), function(vec![shape_float()], shape_unit())], shape_tuple(vec![shape_float(), shape_float()])),
+    shape_list(function(vec![shape_unknown()], shape_named(String::from("Point")))),
+  ];
+
+  for shape in shapes {
+    let printed = shape.pretty();
+
+    match parse_shape(&printed) {
+      Ok(parsed) => assert_eq!(parsed, shape, "{} parsed to {}", printed, parsed.pretty()),
+      Err(err) => panic!("{} didn't parse: {}", printed, err),
+    }
+  }
+}
+
+#[test]
+fn canonical_spacing() {
+  assert_eq!(function(vec![], shape_float()).pretty(), "{ -> Float }");
+  assert_eq!(parse_shape("{Float,Float->(Float,String)}").unwrap().pretty(), "{ Float, Float -> (Float, String) }");
+  assert!(parse_shape("Float Float").is_err());
+}
@@ -0,0 +1,24 @@
+extern crate rust_let_lang;
+
+mod common;
+
+
+use common::call;
+
+const SOURCE: &'static str = "
+import Core::Core;
+
+fun describe(): String = Core.show((1.5, 2, Some(\"two\"), None, ()))
+
+fun named(): String = Core.show({ x: Float -> Float => x })
+";
+
+#[test]
+fn shows_composite_values() {
+  assert_eq!(call(SOURCE, "describe").unwrap().to_string(), "'(1.5, 2, Some('two'), None, ())'");
+}
+
+#[test]
+fn shows_functions_by_ref() {
+  assert_eq!(call(SOURCE, "named").unwrap().to_string(), "'<script::Main.$closure_0>'");
+}
@@ -0,0 +1,60 @@
+extern crate rust_let_lang;
+
+use rust_let_lang::ast::{Expression, Location};
+use rust_let_lang::lsp::check_document;
+use rust_let_lang::parser::parse_str;
+
+const SRC: &'static str = "fun area(w: Float, h: Float): Float = {
+  w * h + double(1)
+}
+";
+
+fn span(loc: &Location) -> ((usize, usize), (usize, usize)) {
+  ((loc.y, loc.x), (loc.end_y, loc.end_x))
+}
+
+#[test]
+fn expressions_span_all_their_source() {
+  let module = parse_str(SRC, "test", "Area").unwrap();
+  let func = &module.functions[0].ex;
+
+  assert_eq!(span(&func.loc), ((1, 1), (3, 2)));
+
+  let statement = match &func.body {
+    Expression::Block(block) => &block.body[0],
+    _ => panic!("Expected a block"),
+  };
+
+  assert_eq!(span(statement.loc()), ((2, 3), (2, 20)));
+
+  match statement {
+    Expression::BinaryOp(sum) => {
+      assert_eq!(span(sum.left.loc()), ((2, 3), (2, 8)));
+      assert_eq!(span(sum.right.loc()), ((2, 11), (2, 20)));
+    }
+    _ => panic!("Expected a sum"),
+  }
+}
+
+#[test]
+fn pretty_shows_the_whole_span() {
+  let mut loc = Location::point("Area", 3, 2);
+  assert_eq!(loc.pretty(), "at file: Area, line: 2, column: 3");
+
+  loc.end_x = 8;
+  loc.end_y = 2;
+  assert_eq!(loc.pretty(), "at file: Area, line: 2, columns: 3-7");
+
+  loc.end_x = 2;
+  loc.end_y = 3;
+  assert_eq!(loc.pretty(), "at file: Area, line: 2, column: 3 to line: 3, column: 1");
+}
+
+#[test]
+fn errors_underline_the_expression() {
+  let doc = check_document("fun broken(): Float = {\n  missing\n}\n", "Broken");
+
+  let loc = doc.errors[0].0.as_ref().unwrap();
+
+  assert_eq!(span(loc), ((2, 3), (2, 10)));
+}
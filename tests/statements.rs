@@ -0,0 +1,88 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::lsp::check_document;
+use rust_let_lang::parser::parse_str;
+
+use common::call;
+
+#[test]
+fn brackets_on_a_new_line_start_a_new_statement() {
+  let src = "
+fun pair(): (Float, Float) = {
+  let x = 1
+  (x, 2)
+}
+
+fun list(): List[Float] = {
+  let x = 1
+  [x, 2]
+}
+
+fun call(): Float = {
+  let f = { x: Float => x + 1 }
+  f(2)
+}
+";
+
+  assert_eq!(call(src, "pair").unwrap().to_string(), "(1, 2)");
+  assert_eq!(call(src, "list").unwrap().to_string(), "[1, 2]");
+  assert_eq!(call(src, "call").unwrap().to_string(), "3");
+}
+
+#[test]
+fn operators_carry_on_to_the_next_line() {
+  let src = "
+fun sum(): Float = {
+  let x = 1
+    + 2
+  x
+    * 2
+}
+";
+
+  assert_eq!(call(src, "sum").unwrap().to_string(), "6");
+}
+
+#[test]
+fn negative_literals_on_a_new_line_start_a_new_statement() {
+  let src = "
+fun negative(): Float = {
+  let x = 5
+  -1
+}
+
+fun subtracted(): Float = {
+  let x = 5
+    - 1
+  x
+}
+";
+
+  assert_eq!(call(src, "negative").unwrap().to_string(), "-1");
+  assert_eq!(call(src, "subtracted").unwrap().to_string(), "4");
+}
+
+#[test]
+fn statements_on_one_line_need_semicolons() {
+  assert!(parse_str("fun ok(): Float = { let x = 1; let y = 2; x + y }", "test", "Statements").is_ok());
+
+  let errors = parse_str("fun bad(): Float = {\n  let x = 1 let y = 2\n  x + y\n}", "test", "Statements").err().unwrap();
+
+  assert_eq!(errors.len(), 1);
+  assert_eq!(errors[0].to_string(), "Unexpected 'let' found at file: Statements, line: 2, columns: 13-15. Expected: ; or a new line after the statement");
+}
+
+#[test]
+fn discarded_values_are_warned_about() {
+  let doc = check_document("fun double(x: Float): Float = x * 2\n\nfun wasteful(): Float = {\n  double(1)\n  let y = 2\n  y\n}\n", "Waste");
+
+  assert!(doc.errors.is_empty());
+  assert_eq!(doc.warnings.len(), 1);
+
+  let (loc, message) = &doc.warnings[0];
+
+  assert_eq!(loc.as_ref().map(|loc| (loc.y, loc.x)), Some((4, 3)));
+  assert!(message.starts_with("Value of shape Float is never used"), "{}", message);
+}
@@ -0,0 +1,95 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use std::path::Path;
+
+use rust_let_lang::{AppShapes, BitApplication, Dispatch, Machine, Optimizer, OptLevel, SOURCE_MODULE, SOURCE_PACKAGE, Value, compile_source};
+use rust_let_lang::compiler::compile_package;
+use rust_let_lang::interpreter::RunFunction;
+use rust_let_lang::testing::find_tests;
+use rust_let_lang::threaded::decode;
+
+const SRC: &'static str = "
+fun sum(n: Float): Float = {
+  let mut total = 0
+  let mut i = 0
+
+  while (i < n) {
+    i = i + 1
+    total = total + i
+  }
+
+  total
+}
+
+fun countdown(n: Float): Float = if (n > 0) countdown(n - 1) else n
+
+fun greet(n: Float): String = if (n > 0) \"hello\" else \"goodbye\"
+";
+
+fn machine(dispatch: Dispatch) -> Machine {
+  let mut machine = common::machine(SRC);
+  machine.set_dispatch(dispatch);
+  machine
+}
+
+#[test]
+fn dispatches_agree() {
+  let matched = machine(Dispatch::Match);
+  let threaded = machine(Dispatch::Threaded);
+
+  let calls = vec![
+    ("sum", Value::Float(100.0)),
+    ("countdown", Value::Float(20.0)),
+    ("greet", Value::Float(1.0)),
+    ("greet", Value::Float(0.0)),
+  ];
+
+  for (name, arg) in calls {
+    let expected = matched.call(SOURCE_PACKAGE, SOURCE_MODULE, name, vec![arg.clone()]).unwrap();
+    let found = threaded.call(SOURCE_PACKAGE, SOURCE_MODULE, name, vec![arg.clone()]).unwrap();
+
+    assert_eq!(found, expected, "{}({})", name, arg);
+  }
+}
+
+#[test]
+fn tail_calls_do_not_nest() {
+  let threaded = machine(Dispatch::Threaded);
+
+  assert_eq!(threaded.call(SOURCE_PACKAGE, SOURCE_MODULE, "countdown", vec![Value::Float(100_000.0)]).unwrap(), Value::Float(0.0));
+}
+
+#[test]
+fn loops_decode() {
+  let package = compile_source(SRC).unwrap();
+  let module = &package.modules[SOURCE_MODULE];
+
+  if let RunFunction::BitFunction(func) = &module.functions["sum"] {
+    let decoded = decode(func, module).unwrap();
+
+    assert_eq!(decoded.ops.len(), func.body.len());
+  } else {
+    panic!("sum should be bytecode");
+  }
+}
+
+#[test]
+fn package_tests_pass_threaded() {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+  let package = compile_package("test", dir.to_str().unwrap(), &Optimizer::with_level(OptLevel::O2), &mut AppShapes::new()).unwrap();
+  let tests = find_tests(&package);
+
+  let mut app = BitApplication::library();
+  app.packages.insert(String::from("test"), package);
+
+  let mut machine = Machine::new(app);
+  machine.set_dispatch(Dispatch::Threaded);
+
+  for func in tests {
+    if let Err(err) = machine.execute(func.clone(), vec![]) {
+      panic!("{}: {}", func.pretty(), err);
+    }
+  }
+}
@@ -0,0 +1,72 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::AppShapes;
+use rust_let_lang::ir::{compile_ir_module, validate, Ir};
+use rust_let_lang::parser::parse_str;
+use rust_let_lang::typechecker::check_module;
+
+use common::{float, ir_function};
+
+#[test]
+fn balanced_functions_are_valid() {
+  let body = vec![
+    Ir::LoadConstTrue,
+    Ir::Branch { then_block: vec![float(1.0)], else_block: vec![float(2.0), float(3.0), Ir::AddFloat] },
+    Ir::Loop { condition: vec![Ir::LoadConstFalse], body: vec![float(1.0), Ir::Pop] },
+    Ir::Return,
+  ];
+
+  assert_eq!(validate(&ir_function(&[], body)), Ok(()));
+}
+
+#[test]
+fn broken_invariants_say_where() {
+  let underflow = vec![float(1.0), Ir::AddFloat, Ir::Return];
+  assert_eq!(validate(&ir_function(&[], underflow)), Err(String::from("Pops 2 values with only 1 on the stack at 1")));
+
+  let uneven = vec![
+    Ir::LoadConstTrue,
+    Ir::Branch { then_block: vec![float(1.0)], else_block: vec![] },
+    Ir::Return,
+  ];
+  assert_eq!(validate(&ir_function(&[], uneven)), Err(String::from("Branches leave the stack at different depths [1, 0] at 1")));
+
+  let leaky_loop = vec![
+    Ir::Loop { condition: vec![Ir::LoadConstFalse], body: vec![float(1.0)] },
+    float(1.0),
+    Ir::Return,
+  ];
+  assert_eq!(validate(&ir_function(&[], leaky_loop)), Err(String::from("Loop body changes the stack by 1 at 0")));
+
+  let leftovers = vec![float(1.0), float(2.0), Ir::Return];
+  assert_eq!(validate(&ir_function(&[], leftovers)), Err(String::from("Return leaves 1 values behind on the stack at 2")));
+
+  let no_return = vec![float(1.0)];
+  assert_eq!(validate(&ir_function(&[], no_return)), Err(String::from("Body can finish without returning")));
+}
+
+#[test]
+fn discarded_block_values_are_popped() {
+  let src = "
+fun twice(x: Float): Float = x * 2
+
+fun main(): Float = {
+  let mut total = 1
+  twice(total)
+  total = twice(total)
+  while (total < 10) { total = twice(total) }
+  total
+}
+";
+  let shapes = AppShapes::new();
+  let checked = check_module(parse_str(src, "test", "Main").unwrap(), &shapes).unwrap();
+  let module = compile_ir_module(&checked, &shapes).unwrap();
+  let main = &module.functions["main"];
+
+  assert_eq!(validate(main), Ok(()));
+  // The while's () is dropped rather than pushed and popped.
+  assert_eq!(main.body.iter().filter(|ir| match ir { Ir::Pop => true, _ => false }).count(), 1);
+  assert_eq!(main.body.iter().filter(|ir| match ir { Ir::LoadConstUnit => true, _ => false }).count(), 0);
+}
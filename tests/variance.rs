@@ -0,0 +1,60 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::compile_source;
+
+use common::call;
+
+const SRC: &'static str = "
+fun twice(x: Float): Float = x * 2
+
+fun each(f: { Float -> Unit }): Unit = f(1)
+
+fun discarded(): Unit = each(twice)
+
+fun kept(): { Float -> Unit } = twice
+
+fun called(): Unit = kept()(2)
+
+fun applied(f: { { Float -> Float } -> Float }): Float = f(twice)
+
+fun runner(g: { Float -> Unit }): Float = { g(3)\n 4 }
+
+fun contravariant(): Float = applied(runner)
+";
+
+#[test]
+fn any_result_can_be_passed_as_unit() {
+  assert_eq!(call(SRC, "discarded").unwrap().to_string(), "()");
+}
+
+#[test]
+fn any_result_can_be_declared_as_unit() {
+  assert_eq!(call(SRC, "called").unwrap().to_string(), "()");
+}
+
+#[test]
+fn parameters_are_contravariant() {
+  assert_eq!(call(SRC, "contravariant").unwrap().to_string(), "4");
+}
+
+#[test]
+fn unit_results_are_not_taken_as_anything_else() {
+  let src = "fun log(x: Float): Unit = ()\n\nfun each(f: { Float -> Float }): Float = f(1)\n\nfun main(): Float = each(log)";
+  let errors = compile_source(src).err().unwrap();
+  assert!(errors.to_string().contains("expected { Float -> Float } but found { Float -> Unit }"), "{}", errors);
+}
+
+#[test]
+fn parameters_are_not_covariant() {
+  let src = "fun twice(x: Float): Float = x * 2\n\nfun apply(g: { Float -> Float }): Float = g(1)\n\nfun run(f: { { Float -> Unit } -> Float }): Float = f(twice)\n\nfun main(): Float = run(apply)";
+  let errors = compile_source(src).err().unwrap();
+  assert!(errors.to_string().contains("found { { Float -> Float } -> Float }"), "{}", errors);
+}
+
+#[test]
+fn unknown_still_agrees_with_anything() {
+  assert!(compile_source("fun twice(x: Float): Float = x * 2\n\nfun main(): { Float -> _ } = twice").is_ok());
+  assert!(compile_source("fun log(x: Float): Unit = ()\n\nfun main(): { _ -> Unit } = log").is_ok());
+}
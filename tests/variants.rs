@@ -0,0 +1,47 @@
+extern crate rust_let_lang;
+
+mod common;
+
+use rust_let_lang::Value;
+
+use common::call_test_package;
+
+fn call(name: &str, arg: f64) -> String {
+  format!("{:?}", call_test_package("options", name, vec![Value::Float(arg)]).unwrap())
+}
+
+#[test]
+fn try_unwraps_some() {
+  assert_eq!(call("quarter", 8.0), r#"Variant(VariantValue { tag: "Some", values: [Float(2.0)] })"#);
+}
+
+#[test]
+fn try_returns_none() {
+  assert_eq!(call("quarter", -8.0), r#"Variant(VariantValue { tag: "None", values: [] })"#);
+}
+
+#[test]
+fn try_unwraps_ok() {
+  assert_eq!(call("doubled", 4.0), r#"Variant(VariantValue { tag: "Ok", values: [Float(8.0)] })"#);
+}
+
+#[test]
+fn try_returns_err() {
+  assert_eq!(call("doubled", -4.0), r#"Variant(VariantValue { tag: "Err", values: [String("negative")] })"#);
+}
+
+#[test]
+fn try_returns_from_the_middle_of_an_expression() {
+  assert_eq!(call("incremented", 8.0), r#"Variant(VariantValue { tag: "Some", values: [Float(5.0)] })"#);
+  assert_eq!(call("incremented", -8.0), r#"Variant(VariantValue { tag: "None", values: [] })"#);
+}
+
+#[test]
+fn try_call_returns_ok() {
+  assert_eq!(call("recover", 3.0), r#"Variant(VariantValue { tag: "Ok", values: [Float(3.0)] })"#);
+}
+
+#[test]
+fn try_call_catches_panic() {
+  assert_eq!(call("recover", -3.0), r#"Variant(VariantValue { tag: "Err", values: [String("not positive")] })"#);
+}
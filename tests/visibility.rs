@@ -0,0 +1,45 @@
+extern crate rust_let_lang;
+
+use std::fs;
+use std::path::PathBuf;
+
+use rust_let_lang::{AppShapes, CompileError, OptLevel, Optimizer, TypeError};
+use rust_let_lang::compiler::compile_package;
+
+fn scratch_package(name: &str, main: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("letlang-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+
+  fs::write(dir.join("util.let"), "fun secret(): Float = 1\n\npublic fun open(): Float = secret() + 1\n").unwrap();
+  fs::write(dir.join("main.let"), main).unwrap();
+  dir
+}
+
+fn compile(dir: &PathBuf) -> Result<(), CompileError> {
+  compile_package("scratch", dir.to_str().unwrap(), &Optimizer::with_level(OptLevel::O2), &mut AppShapes::new()).map(|_| ())
+}
+
+#[test]
+fn importing_a_private_function_says_it_is_private() {
+  let dir = scratch_package("private", "import scratch::util\n\nfun run(): Float = util.secret()\n");
+
+  match compile(&dir) {
+    Err(CompileError::TypeErrors(errors)) => match &errors[..] {
+      [TypeError::PrivateFunction { id, .. }] => assert_eq!(id, "util.secret"),
+      other => panic!("Expected PrivateFunction, found {:?}", other),
+    },
+    other => panic!("Expected type errors, found {:?}", other),
+  }
+
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn public_functions_can_be_imported() {
+  let dir = scratch_package("public", "import scratch::util\n\nfun run(): Float = util.open()\n");
+
+  compile(&dir).unwrap();
+
+  fs::remove_dir_all(&dir).unwrap();
+}
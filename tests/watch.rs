@@ -0,0 +1,47 @@
+extern crate rust_let_lang;
+
+use std::fs;
+use std::path::PathBuf;
+
+use rust_let_lang::watch::Watcher;
+
+fn scratch_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("letlang-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(dir.join("nested")).unwrap();
+  dir
+}
+
+#[test]
+fn notices_sources_added_changed_and_removed() {
+  let dir = scratch_dir("watch");
+  fs::write(dir.join("Main.let"), "fun main(): Float = 1").unwrap();
+
+  let mut watcher = Watcher::new(&dir).unwrap();
+  assert!(!watcher.changed().unwrap());
+
+  fs::write(dir.join("nested").join("Util.let"), "fun two(): Float = 2").unwrap();
+  assert!(watcher.changed().unwrap());
+  assert!(!watcher.changed().unwrap());
+
+  fs::write(dir.join("Main.let"), "fun main(): Float = 100").unwrap();
+  assert!(watcher.changed().unwrap());
+
+  fs::remove_file(dir.join("nested").join("Util.let")).unwrap();
+  assert!(watcher.changed().unwrap());
+
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ignores_everything_but_sources() {
+  let dir = scratch_dir("ignore");
+  fs::write(dir.join("Main.let"), "fun main(): Float = 1").unwrap();
+
+  let mut watcher = Watcher::new(&dir).unwrap();
+
+  fs::write(dir.join("notes.txt"), "not letLang").unwrap();
+  assert!(!watcher.changed().unwrap());
+
+  fs::remove_dir_all(&dir).unwrap();
+}